@@ -0,0 +1,32 @@
+// bindless blit, the texture and the sampler are fetched from the descriptor heaps by the indices in the root constants.
+// needs shader model 6.6, compile it with DXC.
+
+// the indices of the bindless slots, given by bindless.rs
+struct BlitIndices
+{
+    uint GTextureIndex;
+    uint GSamplerIndex;
+};
+ConstantBuffer<BlitIndices> GBlitIndices : register(b0);
+
+struct BlitVSOutput
+{
+    float4 Pos : SV_POSITION;
+    float2 UV : TEXCOORD0;
+};
+
+BlitVSOutput BlitVS(uint VertexID : SV_VertexID)
+{
+    // one triangle that covers the whole screen, UV (0,0) (2,0) (0,2)
+    BlitVSOutput Out;
+    Out.UV = float2((VertexID << 1) & 2, VertexID & 2);
+    Out.Pos = float4(Out.UV * float2(2, -2) + float2(-1, 1), 0, 1);
+    return Out;
+}
+
+float4 BlitPS(BlitVSOutput In) : SV_TARGET
+{
+    Texture2D SourceTexture = ResourceDescriptorHeap[GBlitIndices.GTextureIndex];
+    SamplerState SourceSampler = SamplerDescriptorHeap[GBlitIndices.GSamplerIndex];
+    return SourceTexture.SampleLevel(SourceSampler, In.UV, 0);
+}
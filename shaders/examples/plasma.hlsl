@@ -0,0 +1,19 @@
+// an example shader to drag onto the window, it follows the HelloWorldPS conventions:
+// the entry point is HelloWorldPS, it's drawn as a full screen quad, and the constants are in b0.
+
+cbuffer HelloWorldConstants : register(b0)
+{
+    uint GTimeMS;
+    float2 GRenderSize;
+};
+
+float4 HelloWorldPS(float4 InPos : SV_POSITION) : SV_TARGET
+{
+    float2 UV = InPos.xy / GRenderSize;
+    float Time = GTimeMS * 0.001f;
+
+    float Value = sin(UV.x * 10.0f + Time) + sin(UV.y * 10.0f + Time * 1.3f) + sin((UV.x + UV.y) * 10.0f + Time * 0.7f);
+    float3 Color = 0.5f + 0.5f * cos(Value + float3(0, 2, 4));
+
+    return float4(Color, 1.0f);
+}
@@ -18,8 +18,12 @@ static const float2 GTrianglePointA = float2(960, 270);
 static const float2 GTrianglePointB = float2(480, 810);
 static const float2 GTrianglePointC = float2(1440, 810);
 
-// constant that holds a time parameter
-uint GTimeMS : register(b0);
+// constants that hold a time parameter and the size of render target, the triangle points above are in 1080p space
+cbuffer HelloWorldConstants : register(b0)
+{
+    uint GTimeMS;
+    float2 GRenderSize;
+};
 
 void HelloWorldVS(uint VertexID : SV_VertexID, out float4 OutPos : SV_POSITION)
 {
@@ -42,10 +46,13 @@ bool IsInsideTriangle(float2 p, float2 p1, float2 p2, float2 p3)
 
 float4 HelloWorldPS(float4 InPos : SV_POSITION) : SV_TARGET
 {
+    // map the pixel position to 1080p space, so the triangle covers the same area at any render resolution
+    float2 PixelPos = InPos.xy * float2(1920, 1080) / GRenderSize;
+
     // clip pixels that are not in triangle area
     float2 ShiftedAmount = float2(800, 0);
     float2 ShiftedPointA = lerp(GTrianglePointA - ShiftedAmount, GTrianglePointA + ShiftedAmount, cos(GTimeMS * 0.001f) * 0.5f + 0.5f);
-    if (!IsInsideTriangle(InPos.xy, ShiftedPointA, GTrianglePointB, GTrianglePointC))
+    if (!IsInsideTriangle(PixelPos, ShiftedPointA, GTrianglePointB, GTrianglePointC))
     {
         clip(-1);
     }
@@ -57,7 +64,7 @@ float4 HelloWorldPS(float4 InPos : SV_POSITION) : SV_TARGET
     float Offset = GTimeMS * 0.005f;
     float PosScale = 0.005f;
 
-    FakeNormal.z = sin(InPos.y * PosScale + Offset) * 0.5f + 0.5f;
+    FakeNormal.z = sin(PixelPos.y * PosScale + Offset) * 0.5f + 0.5f;
     FakeNormal.z = -FakeNormal.z;
     float Intensity = saturate(dot(-FakeLightDir, FakeNormal));
 
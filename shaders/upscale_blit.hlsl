@@ -0,0 +1,24 @@
+// upscale blit, stretches the internal render target over the whole back buffer
+
+Texture2D GSourceTexture : register(t0);
+SamplerState GLinearClampSampler : register(s0);
+
+struct BlitVSOutput
+{
+    float4 Pos : SV_POSITION;
+    float2 UV : TEXCOORD0;
+};
+
+BlitVSOutput BlitVS(uint VertexID : SV_VertexID)
+{
+    // one triangle that covers the whole screen, UV (0,0) (2,0) (0,2)
+    BlitVSOutput Out;
+    Out.UV = float2((VertexID << 1) & 2, VertexID & 2);
+    Out.Pos = float4(Out.UV * float2(2, -2) + float2(-1, 1), 0, 1);
+    return Out;
+}
+
+float4 BlitPS(BlitVSOutput In) : SV_TARGET
+{
+    return GSourceTexture.SampleLevel(GLinearClampSampler, In.UV, 0);
+}
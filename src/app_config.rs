@@ -0,0 +1,759 @@
+// app_config.rs - Startup settings from a TOML config file, overridden by command line switches.
+
+use std::path::{Path, PathBuf};
+
+use crate::image_io::ImageFormat;
+
+// the config file that is read when "--config <path>" isn't given, it's fine if it doesn't exist
+pub const GDEFAULT_CONFIG_PATH : &str = "config.toml";
+
+// the back buffer formats a flip model swapchain accepts
+const GBACK_BUFFER_FORMATS : [(&str, ImageFormat); 4] =
+[
+    ("rgba8_unorm", ImageFormat::Rgba8Unorm),
+    ("bgra8_unorm", ImageFormat::Bgra8Unorm),
+    ("rgb10a2_unorm", ImageFormat::Rgb10A2Unorm),
+    ("rgba16_float", ImageFormat::Rgba16Float),
+];
+
+// the formats of the depth buffers, D32 or a format with 8 stencil bits
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DepthFormat
+{
+    D32Float,
+    D24UnormS8Uint,
+    D32FloatS8X24Uint,
+}
+
+const GDEPTH_FORMATS : [(&str, DepthFormat); 3] =
+[
+    ("d32_float", DepthFormat::D32Float),
+    ("d24_unorm_s8_uint", DepthFormat::D24UnormS8Uint),
+    ("d32_float_s8x24_uint", DepthFormat::D32FloatS8X24Uint),
+];
+
+// the samples that can be run, main.rs dispatches on them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleChoice
+{
+    HelloWorldTriangle,
+}
+
+// the names "sample" accepts, new samples are added here
+const GSAMPLES : [(&str, SampleChoice); 1] =
+[
+    ("hello_world_triangle", SampleChoice::HelloWorldTriangle),
+];
+
+const GMAX_TEXTURE_SIZE : i64 = 16384;
+const GMAX_WINDOW_COUNT : i64 = 16;
+
+// which adapter D3D12CreateDevice uses
+#[derive(Clone, PartialEq, Debug)]
+pub enum AdapterChoice
+{
+    // the first hardware adapter with the highest feature level
+    Auto,
+    // the index of EnumAdapters1
+    Index(u32),
+    // a case insensitive part of the adapter description, e.g. "nvidia" or "basic render" for WARP
+    Name(String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AppConfig
+{
+    // client size of the main window, or the offscreen size when headless
+    pub width : u32,
+    pub height : u32,
+    pub back_buffer_format : ImageFormat,
+    pub depth_format : DepthFormat,
+    // clear depth to 0 and keep the nearer pixels with GREATER_EQUAL, for better precision far away
+    pub reverse_z : bool,
+    // samples per pixel of the scene targets, 1 disables MSAA. it's lowered to what the device supports
+    pub msaa : u32,
+    pub adapter : AdapterChoice,
+    pub vsync : bool,
+    pub debug_layer : bool,
+    pub sample : SampleChoice,
+    pub headless : bool,
+    // frames to render offscreen, None picks the default of the mode (1 headless, one second when recording, all for a replay)
+    pub frames : Option<u32>,
+    pub windows : u32,
+    pub render_scale : f32,
+    // target frame rates of the frame limiter, 0 means unlimited
+    pub focused_fps : f64,
+    pub unfocused_fps : f64,
+    pub minimized_fps : f64,
+}
+
+impl Default for AppConfig
+{
+    fn default() -> AppConfig
+    {
+        return AppConfig
+        {
+            width : 1920,
+            height : 1080,
+            back_buffer_format : ImageFormat::Rgba8Unorm,
+            depth_format : DepthFormat::D32Float,
+            reverse_z : false,
+            msaa : 1,
+            adapter : AdapterChoice::Auto,
+            vsync : false,
+            debug_layer : true,
+            sample : SampleChoice::HelloWorldTriangle,
+            headless : false,
+            frames : None,
+            windows : 1,
+            render_scale : 1.0,
+            focused_fps : 240.0,
+            unfocused_fps : 30.0,
+            minimized_fps : 10.0,
+        };
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConfigValue
+{
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+// a parsed key / value pair, origin tells where it came from for the error messages, e.g. "config.toml:3" or "--width"
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConfigEntry
+{
+    pub key : String,
+    pub value : ConfigValue,
+    pub origin : String,
+}
+
+// command line switches and the config key they set. the flags don't need a value, "--vsync" is the same as "--vsync=true",
+// but a true / false / on / off right after them is taken as their value, e.g. "--vsync false".
+struct CommandLineOption
+{
+    name : &'static str,
+    key : &'static str,
+    is_flag : bool,
+}
+
+const GCOMMAND_LINE_OPTIONS : [CommandLineOption; 17] =
+[
+    CommandLineOption { name : "--width", key : "width", is_flag : false },
+    CommandLineOption { name : "--height", key : "height", is_flag : false },
+    CommandLineOption { name : "--back-buffer-format", key : "back_buffer_format", is_flag : false },
+    CommandLineOption { name : "--depth-format", key : "depth_format", is_flag : false },
+    CommandLineOption { name : "--reverse-z", key : "reverse_z", is_flag : true },
+    CommandLineOption { name : "--msaa", key : "msaa", is_flag : false },
+    CommandLineOption { name : "--adapter", key : "adapter", is_flag : false },
+    CommandLineOption { name : "--vsync", key : "vsync", is_flag : true },
+    CommandLineOption { name : "--debug-layer", key : "debug_layer", is_flag : true },
+    CommandLineOption { name : "--sample", key : "sample", is_flag : false },
+    CommandLineOption { name : "--headless", key : "headless", is_flag : true },
+    CommandLineOption { name : "--frames", key : "frames", is_flag : false },
+    CommandLineOption { name : "--windows", key : "windows", is_flag : false },
+    CommandLineOption { name : "--render-scale", key : "render_scale", is_flag : false },
+    CommandLineOption { name : "--focused-fps", key : "focused_fps", is_flag : false },
+    CommandLineOption { name : "--unfocused-fps", key : "unfocused_fps", is_flag : false },
+    CommandLineOption { name : "--minimized-fps", key : "minimized_fps", is_flag : false },
+];
+
+// the switches main.rs reads itself and whether a value follows them, parse_command_line() skips them
+const GCALLER_SWITCHES : [(&str, bool); 7] =
+[
+    ("--config", true),
+    ("--print-config", false),
+    ("--record", true),
+    ("--record-format", true),
+    ("--record-fps", true),
+    ("--record-input", true),
+    ("--replay", true),
+];
+
+// function to parse a quoted TOML string, only the common escapes are supported
+fn parse_string(text : &str) -> Option<String>
+{
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(x) = chars.next()
+    {
+        match x
+        {
+            '\\' => match chars.next()?
+            {
+                '"' => result.push('"'),
+                '\\' => result.push('\\'),
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                _ => return None,
+            },
+            '"' => return None,
+            _ => result.push(x),
+        }
+    }
+
+    return Some(result);
+}
+
+// function to parse a TOML value: a bool, an integer, a float or a quoted string
+pub fn parse_value(text : &str) -> Option<ConfigValue>
+{
+    let text = text.trim();
+    if text.starts_with('"')
+    {
+        return parse_string(text).map(ConfigValue::Text);
+    }
+
+    match text
+    {
+        "true" => return Some(ConfigValue::Bool(true)),
+        "false" => return Some(ConfigValue::Bool(false)),
+        _ => {}
+    }
+
+    // TOML allows underscores between digits
+    let number = text.replace('_', "");
+    if let Ok(x) = number.parse::<i64>()
+    {
+        return Some(ConfigValue::Integer(x));
+    }
+    // str::parse also takes words like "inf", only plain decimal numbers are floats here
+    if number.chars().all(|x| x.is_ascii_digit() || "+-.eE".contains(x))
+    {
+        if let Ok(x) = number.parse::<f64>()
+        {
+            return Some(ConfigValue::Float(x));
+        }
+    }
+
+    return None;
+}
+
+// function to strip a trailing comment, a # inside a string isn't a comment
+fn strip_comment(line : &str) -> &str
+{
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, x) in line.char_indices()
+    {
+        match x
+        {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    return line;
+}
+
+// function to parse the config file text, each line is "key = value" and lines starting with # are comments.
+// it only checks the syntax, the keys and the value types are checked by validate().
+pub fn parse_config_text(text : &str, file_name : &str) -> Result<Vec<ConfigEntry>, String>
+{
+    let mut entries : Vec<ConfigEntry> = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate()
+    {
+        let origin = format!("{}:{}", file_name, line_index + 1);
+        let line = strip_comment(line).trim();
+        if line.is_empty()
+        {
+            continue;
+        }
+        if line.starts_with('[')
+        {
+            return Err(format!("{}: tables aren't supported, all settings are top level keys", origin));
+        }
+
+        let (key, value) = line.split_once('=').ok_or(format!("{}: expected 'key = value'", origin))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|x| x.is_ascii_alphanumeric() || x == '_')
+        {
+            return Err(format!("{}: invalid key '{}'", origin, key));
+        }
+
+        let value = parse_value(value).ok_or(format!("{}: invalid value '{}' for '{}'", origin, value.trim(), key))?;
+        entries.push(ConfigEntry { key : key.to_string(), value, origin });
+    }
+
+    return Ok(entries);
+}
+
+// function to parse a value given on the command line, it doesn't need quotes for strings
+fn parse_command_line_value(text : &str) -> ConfigValue
+{
+    return match parse_value(text)
+    {
+        Some(x) => x,
+        None => ConfigValue::Text(text.to_string()),
+    };
+}
+
+// function to pick the config switches from the command line, in the order they are given.
+// "--name value" and "--name=value" both work, the switches of main.rs are left for it and anything else is an error.
+pub fn parse_command_line(args : &[String]) -> Result<Vec<ConfigEntry>, String>
+{
+    let mut entries : Vec<ConfigEntry> = Vec::new();
+
+    let mut idx = 1;
+    while idx < args.len()
+    {
+        let (name, inline_value) = match args[idx].split_once('=')
+        {
+            Some((name, value)) => (name, Some(value)),
+            None => (args[idx].as_str(), None),
+        };
+        idx = idx + 1;
+
+        let option = match GCOMMAND_LINE_OPTIONS.iter().find(|x| x.name == name)
+        {
+            Some(x) => x,
+            None =>
+            {
+                match GCALLER_SWITCHES.iter().find(|x| x.0 == name)
+                {
+                    // main.rs only looks for the value in the next argument
+                    Some(_) if inline_value.is_some() => return Err(format!("{}: the value must be the next argument", name)),
+                    Some((_, takes_value)) if *takes_value => idx = idx + 1,
+                    Some(_) => {}
+                    None if name.starts_with("--") => return Err(format!("unknown switch '{}'", name)),
+                    None => return Err(format!("unexpected argument '{}'", args[idx - 1])),
+                }
+                continue;
+            }
+        };
+
+        let value = match inline_value
+        {
+            Some(x) => parse_command_line_value(x),
+            None if option.is_flag =>
+            {
+                match args.get(idx).map(|x| x.as_str())
+                {
+                    Some(x @ ("true" | "false" | "on" | "off")) =>
+                    {
+                        idx = idx + 1;
+                        parse_command_line_value(x)
+                    }
+                    _ => ConfigValue::Bool(true),
+                }
+            }
+            None =>
+            {
+                let x = args.get(idx).ok_or(format!("{}: missing value", name))?;
+                idx = idx + 1;
+                parse_command_line_value(x)
+            }
+        };
+
+        entries.push(ConfigEntry { key : option.key.to_string(), value, origin : name.to_string() });
+    }
+
+    return Ok(entries);
+}
+
+fn get_integer(entry : &ConfigEntry, min : i64, max : i64) -> Result<i64, String>
+{
+    return match entry.value
+    {
+        ConfigValue::Integer(x) if x >= min && x <= max => Ok(x),
+        _ => Err(format!("{}: '{}' must be an integer from {} to {}", entry.origin, entry.key, min, max)),
+    };
+}
+
+fn get_float(entry : &ConfigEntry, min : f64, max : f64) -> Result<f64, String>
+{
+    let value = match entry.value
+    {
+        ConfigValue::Integer(x) => Some(x as f64),
+        ConfigValue::Float(x) => Some(x),
+        _ => None,
+    };
+
+    return match value
+    {
+        Some(x) if x >= min && x <= max => Ok(x),
+        _ => Err(format!("{}: '{}' must be a number from {} to {}", entry.origin, entry.key, min, max)),
+    };
+}
+
+fn get_bool(entry : &ConfigEntry) -> Result<bool, String>
+{
+    return match &entry.value
+    {
+        ConfigValue::Bool(x) => Ok(*x),
+        // the command line also takes on / off
+        ConfigValue::Text(x) if x == "on" => Ok(true),
+        ConfigValue::Text(x) if x == "off" => Ok(false),
+        _ => Err(format!("{}: '{}' must be true or false", entry.origin, entry.key)),
+    };
+}
+
+fn get_text(entry : &ConfigEntry) -> Result<&str, String>
+{
+    return match &entry.value
+    {
+        ConfigValue::Text(x) => Ok(x.as_str()),
+        _ => Err(format!("{}: '{}' must be a string", entry.origin, entry.key)),
+    };
+}
+
+// function to set one entry on the config
+fn apply_entry(config : &mut AppConfig, entry : &ConfigEntry) -> Result<(), String>
+{
+    match entry.key.as_str()
+    {
+        "width" => config.width = get_integer(entry, 1, GMAX_TEXTURE_SIZE)? as u32,
+        "height" => config.height = get_integer(entry, 1, GMAX_TEXTURE_SIZE)? as u32,
+        "back_buffer_format" =>
+        {
+            let name = get_text(entry)?;
+            config.back_buffer_format = GBACK_BUFFER_FORMATS.iter().find(|x| x.0 == name).map(|x| x.1)
+                .ok_or(format!("{}: unknown back buffer format '{}', expected one of {}", entry.origin, name, get_format_names().join(", ")))?;
+        }
+        "depth_format" =>
+        {
+            let name = get_text(entry)?;
+            config.depth_format = GDEPTH_FORMATS.iter().find(|x| x.0 == name).map(|x| x.1)
+                .ok_or(format!("{}: unknown depth format '{}', expected one of {}", entry.origin, name, GDEPTH_FORMATS.map(|x| x.0).join(", ")))?;
+        }
+        "reverse_z" => config.reverse_z = get_bool(entry)?,
+        "msaa" =>
+        {
+            let sample_count = get_integer(entry, 1, 8)? as u32;
+            if !sample_count.is_power_of_two()
+            {
+                return Err(format!("{}: 'msaa' must be 1, 2, 4 or 8", entry.origin));
+            }
+            config.msaa = sample_count;
+        }
+        "adapter" =>
+        {
+            config.adapter = match &entry.value
+            {
+                ConfigValue::Integer(_) => AdapterChoice::Index(get_integer(entry, 0, u32::MAX as i64)? as u32),
+                ConfigValue::Text(x) if x == "auto" => AdapterChoice::Auto,
+                ConfigValue::Text(x) if !x.trim().is_empty() => AdapterChoice::Name(x.clone()),
+                _ => return Err(format!("{}: 'adapter' must be \"auto\", an adapter index or a part of the adapter name", entry.origin)),
+            };
+        }
+        "vsync" => config.vsync = get_bool(entry)?,
+        "debug_layer" => config.debug_layer = get_bool(entry)?,
+        "sample" =>
+        {
+            let name = get_text(entry)?;
+            config.sample = GSAMPLES.iter().find(|x| x.0 == name).map(|x| x.1)
+                .ok_or(format!("{}: unknown sample '{}', expected one of {}", entry.origin, name, GSAMPLES.map(|x| x.0).join(", ")))?;
+        }
+        "headless" => config.headless = get_bool(entry)?,
+        "frames" => config.frames = Some(get_integer(entry, 1, u32::MAX as i64)? as u32),
+        "windows" => config.windows = get_integer(entry, 1, GMAX_WINDOW_COUNT)? as u32,
+        // the same range render_scale::set_render_scale() clamps to
+        "render_scale" => config.render_scale = get_float(entry, 0.25, 2.0)? as f32,
+        "focused_fps" => config.focused_fps = get_float(entry, 0.0, 1000.0)?,
+        "unfocused_fps" => config.unfocused_fps = get_float(entry, 0.0, 1000.0)?,
+        "minimized_fps" => config.minimized_fps = get_float(entry, 0.0, 1000.0)?,
+        _ => return Err(format!("{}: unknown setting '{}'", entry.origin, entry.key)),
+    }
+
+    return Ok(());
+}
+
+fn get_format_names() -> Vec<&'static str>
+{
+    return GBACK_BUFFER_FORMATS.iter().map(|x| x.0).collect();
+}
+
+// function to build the config from the defaults and the entries, later entries override earlier ones.
+// every invalid entry is reported, not only the first one.
+pub fn validate(entries : &[ConfigEntry]) -> Result<AppConfig, Vec<String>>
+{
+    let mut config = AppConfig::default();
+    let mut errors : Vec<String> = Vec::new();
+
+    for x in entries.iter()
+    {
+        if let Err(error) = apply_entry(&mut config, x)
+        {
+            errors.push(error);
+        }
+    }
+
+    return if errors.is_empty() { Ok(config) } else { Err(errors) };
+}
+
+// function to get the config file path, "--config <path>" or the default one
+pub fn get_config_path(args : &[String]) -> (PathBuf, bool)
+{
+    return match args.iter().position(|x| x == "--config").and_then(|x| args.get(x + 1))
+    {
+        Some(x) => (PathBuf::from(x), true),
+        None => (PathBuf::from(GDEFAULT_CONFIG_PATH), false),
+    };
+}
+
+// function to load the config file and apply the command line on top of it.
+// a missing default config file is fine, a missing file given with --config is an error.
+pub fn load(args : &[String]) -> Result<AppConfig, Vec<String>>
+{
+    let (path, is_explicit) = get_config_path(args);
+
+    let mut entries : Vec<ConfigEntry> = Vec::new();
+    match std::fs::read_to_string(&path)
+    {
+        Ok(text) => entries = parse_config_text(&text, &get_file_name(&path)).map_err(|x| vec![x])?,
+        Err(x) if is_explicit => return Err(vec![format!("{}: {}", path.display(), x)]),
+        Err(_) => {}
+    }
+
+    entries.extend(parse_command_line(args).map_err(|x| vec![x])?);
+    return validate(&entries);
+}
+
+fn get_file_name(path : &Path) -> String
+{
+    return path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+}
+
+// function to quote a string for TOML
+fn quote(text : &str) -> String
+{
+    return format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t"));
+}
+
+impl AppConfig
+{
+    // function to write the config in the config file format, parsing it back gives the same config
+    pub fn to_toml(&self) -> String
+    {
+        let format_name = GBACK_BUFFER_FORMATS.iter().find(|x| x.1 == self.back_buffer_format).map(|x| x.0).unwrap_or("rgba8_unorm");
+        let depth_format_name = GDEPTH_FORMATS.iter().find(|x| x.1 == self.depth_format).map(|x| x.0).unwrap_or("d32_float");
+        let sample_name = GSAMPLES.iter().find(|x| x.1 == self.sample).map(|x| x.0).unwrap_or("hello_world_triangle");
+        let adapter = match &self.adapter
+        {
+            AdapterChoice::Auto => quote("auto"),
+            AdapterChoice::Index(x) => x.to_string(),
+            AdapterChoice::Name(x) => quote(x),
+        };
+
+        let mut lines : Vec<String> = Vec::new();
+        lines.push(format!("width = {}", self.width));
+        lines.push(format!("height = {}", self.height));
+        lines.push(format!("back_buffer_format = {}", quote(format_name)));
+        lines.push(format!("depth_format = {}", quote(depth_format_name)));
+        lines.push(format!("reverse_z = {}", self.reverse_z));
+        lines.push(format!("msaa = {}", self.msaa));
+        lines.push(format!("adapter = {}", adapter));
+        lines.push(format!("vsync = {}", self.vsync));
+        lines.push(format!("debug_layer = {}", self.debug_layer));
+        lines.push(format!("sample = {}", quote(sample_name)));
+        lines.push(format!("headless = {}", self.headless));
+        match self.frames
+        {
+            Some(x) => lines.push(format!("frames = {}", x)),
+            None => lines.push(String::from("# frames = <default of the mode>")),
+        }
+        lines.push(format!("windows = {}", self.windows));
+        lines.push(format!("render_scale = {:?}", self.render_scale));
+        lines.push(format!("focused_fps = {:?}", self.focused_fps));
+        lines.push(format!("unfocused_fps = {:?}", self.unfocused_fps));
+        lines.push(format!("minimized_fps = {:?}", self.minimized_fps));
+
+        return lines.join("\n") + "\n";
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn to_args(text : &str) -> Vec<String>
+    {
+        return std::iter::once("app.exe").chain(text.split_whitespace()).map(String::from).collect();
+    }
+
+    fn entry(key : &str, value : ConfigValue) -> ConfigEntry
+    {
+        return ConfigEntry { key : key.to_string(), value, origin : format!("--{}", key) };
+    }
+
+    #[test]
+    fn parse_value_reads_every_type()
+    {
+        assert_eq!(parse_value(" true "), Some(ConfigValue::Bool(true)));
+        assert_eq!(parse_value("false"), Some(ConfigValue::Bool(false)));
+        assert_eq!(parse_value("-42"), Some(ConfigValue::Integer(-42)));
+        assert_eq!(parse_value("1_000_000"), Some(ConfigValue::Integer(1000000)));
+        assert_eq!(parse_value("0.5"), Some(ConfigValue::Float(0.5)));
+        assert_eq!(parse_value("1e3"), Some(ConfigValue::Float(1000.0)));
+        assert_eq!(parse_value("\"a \\\"b\\\"\\n\""), Some(ConfigValue::Text(String::from("a \"b\"\n"))));
+        assert_eq!(parse_value("inf"), None);
+        assert_eq!(parse_value("nan"), None);
+        assert_eq!(parse_value("yes"), None);
+        assert_eq!(parse_value("\"unterminated"), None);
+        assert_eq!(parse_value("\"bad \\q escape\""), None);
+        assert_eq!(parse_value("\"two\" \"strings\""), None);
+    }
+
+    #[test]
+    fn comments_are_stripped_outside_of_strings()
+    {
+        assert_eq!(strip_comment("width = 800 # comment"), "width = 800 ");
+        assert_eq!(strip_comment("adapter = \"a # b\" # c"), "adapter = \"a # b\" ");
+        assert_eq!(strip_comment("adapter = \"a \\\" # b\""), "adapter = \"a \\\" # b\"");
+        assert_eq!(strip_comment("# only a comment"), "");
+    }
+
+    #[test]
+    fn parse_config_text_reports_the_line()
+    {
+        let entries = parse_config_text("# settings\n\nwidth = 800\nadapter = \"basic # render\" # WARP\n", "test.toml").unwrap();
+        assert_eq!(entries, vec!
+        [
+            ConfigEntry { key : String::from("width"), value : ConfigValue::Integer(800), origin : String::from("test.toml:3") },
+            ConfigEntry { key : String::from("adapter"), value : ConfigValue::Text(String::from("basic # render")), origin : String::from("test.toml:4") },
+        ]);
+
+        let error = |text : &str| parse_config_text(text, "test.toml").unwrap_err();
+        assert_eq!(error("width = 1\n[window]\n"), "test.toml:2: tables aren't supported, all settings are top level keys");
+        assert_eq!(error("width 800"), "test.toml:1: expected 'key = value'");
+        assert_eq!(error("win dth = 800"), "test.toml:1: invalid key 'win dth'");
+        assert_eq!(error(" = 800"), "test.toml:1: invalid key ''");
+        assert_eq!(error("\nadapter = nvidia"), "test.toml:2: invalid value 'nvidia' for 'adapter'");
+    }
+
+    #[test]
+    fn parse_command_line_takes_both_value_forms()
+    {
+        let entries = parse_command_line(&to_args("--width 800 --height=600 --adapter nvidia --render-scale 0.5")).unwrap();
+        let values : Vec<(&str, &ConfigValue, &str)> = entries.iter().map(|x| (x.key.as_str(), &x.value, x.origin.as_str())).collect();
+        assert_eq!(values, vec!
+        [
+            ("width", &ConfigValue::Integer(800), "--width"),
+            ("height", &ConfigValue::Integer(600), "--height"),
+            ("adapter", &ConfigValue::Text(String::from("nvidia")), "--adapter"),
+            ("render_scale", &ConfigValue::Float(0.5), "--render-scale"),
+        ]);
+
+        assert_eq!(parse_command_line(&to_args("--width")).unwrap_err(), "--width: missing value");
+    }
+
+    #[test]
+    fn flags_take_an_explicit_value_after_them()
+    {
+        let config = validate(&parse_command_line(&to_args("--vsync false --debug-layer off --headless --reverse-z on --width 640")).unwrap()).unwrap();
+        assert!(!config.vsync);
+        assert!(!config.debug_layer);
+        assert!(config.headless);
+        assert!(config.reverse_z);
+        assert_eq!(config.width, 640);
+
+        // a flag right before another switch or at the end is set
+        let config = validate(&parse_command_line(&to_args("--vsync --debug-layer=false --headless")).unwrap()).unwrap();
+        assert!(config.vsync);
+        assert!(!config.debug_layer);
+        assert!(config.headless);
+    }
+
+    #[test]
+    fn parse_command_line_rejects_unknown_arguments()
+    {
+        assert_eq!(parse_command_line(&to_args("--widht 800")).unwrap_err(), "unknown switch '--widht'");
+        assert_eq!(parse_command_line(&to_args("--vsync maybe")).unwrap_err(), "unexpected argument 'maybe'");
+        assert_eq!(parse_command_line(&to_args("--record=out.y4m")).unwrap_err(), "--record: the value must be the next argument");
+
+        // the switches of main.rs and their values are skipped
+        let entries = parse_command_line(&to_args("--config my.toml --record out --record-fps 30 --print-config --headless --replay input.rec")).unwrap();
+        assert_eq!(entries, vec![ConfigEntry { key : String::from("headless"), value : ConfigValue::Bool(true), origin : String::from("--headless") }]);
+    }
+
+    #[test]
+    fn sample_is_picked_by_name()
+    {
+        let entries = parse_command_line(&to_args("--sample hello_world_triangle")).unwrap();
+        assert_eq!(validate(&entries).unwrap().sample, SampleChoice::HelloWorldTriangle);
+
+        let entries = parse_config_text("sample = \"hello_world_triangle\"", "test.toml").unwrap();
+        assert_eq!(validate(&entries).unwrap().sample, SampleChoice::HelloWorldTriangle);
+
+        let entries = parse_command_line(&to_args("--sample=cube")).unwrap();
+        assert_eq!(validate(&entries).unwrap_err(), vec![String::from("--sample: unknown sample 'cube', expected one of hello_world_triangle")]);
+
+        let entries = parse_config_text("sample = 1", "test.toml").unwrap();
+        assert_eq!(validate(&entries).unwrap_err(), vec![String::from("test.toml:1: 'sample' must be a string")]);
+    }
+
+    #[test]
+    fn validate_reports_every_invalid_entry()
+    {
+        let entries = vec!
+        [
+            entry("width", ConfigValue::Integer(0)),
+            entry("height", ConfigValue::Integer(720)),
+            entry("msaa", ConfigValue::Integer(3)),
+            entry("vsync", ConfigValue::Integer(1)),
+            entry("back_buffer_format", ConfigValue::Text(String::from("rgb8"))),
+            entry("adapter", ConfigValue::Text(String::from(" "))),
+            entry("render_scale", ConfigValue::Float(4.0)),
+            entry("colour", ConfigValue::Bool(true)),
+        ];
+        let errors = validate(&entries).unwrap_err();
+        assert_eq!(errors, vec!
+        [
+            String::from("--width: 'width' must be an integer from 1 to 16384"),
+            String::from("--msaa: 'msaa' must be 1, 2, 4 or 8"),
+            String::from("--vsync: 'vsync' must be true or false"),
+            String::from("--back_buffer_format: unknown back buffer format 'rgb8', expected one of rgba8_unorm, bgra8_unorm, rgb10a2_unorm, rgba16_float"),
+            String::from("--adapter: 'adapter' must be \"auto\", an adapter index or a part of the adapter name"),
+            String::from("--render_scale: 'render_scale' must be a number from 0.25 to 2"),
+            String::from("--colour: unknown setting 'colour'"),
+        ]);
+
+        // later entries override earlier ones
+        let config = validate(&[entry("width", ConfigValue::Integer(800)), entry("width", ConfigValue::Integer(1024))]).unwrap();
+        assert_eq!(config.width, 1024);
+        assert_eq!(config.height, AppConfig::default().height);
+    }
+
+    #[test]
+    fn to_toml_parses_back_to_the_same_config()
+    {
+        let config = AppConfig
+        {
+            width : 1280,
+            height : 720,
+            back_buffer_format : ImageFormat::Rgb10A2Unorm,
+            depth_format : DepthFormat::D24UnormS8Uint,
+            reverse_z : true,
+            msaa : 4,
+            adapter : AdapterChoice::Name(String::from("Basic \"Render\" Driver")),
+            vsync : true,
+            debug_layer : false,
+            sample : SampleChoice::HelloWorldTriangle,
+            headless : true,
+            frames : Some(12),
+            windows : 3,
+            render_scale : 0.75,
+            focused_fps : 144.0,
+            unfocused_fps : 0.0,
+            minimized_fps : 7.5,
+        };
+        let parsed = validate(&parse_config_text(&config.to_toml(), "config.toml").unwrap()).unwrap();
+        assert_eq!(parsed, config);
+
+        let default_config = AppConfig { adapter : AdapterChoice::Index(2), ..AppConfig::default() };
+        assert_eq!(validate(&parse_config_text(&default_config.to_toml(), "config.toml").unwrap()).unwrap(), default_config);
+
+        // the config file next to the manifest holds the defaults
+        assert_eq!(validate(&parse_config_text(include_str!("../config.toml"), "config.toml").unwrap()).unwrap(), AppConfig::default());
+    }
+}
@@ -0,0 +1,293 @@
+// asset_drop.rs - Files dropped onto a window are routed to a loader by their extension.
+// a .hlsl file replaces the pixel shader of the demo, images are shown fullscreen and meshes are loaded for later use.
+// the result of each drop is reported in the title of the window it was dropped on.
+
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::bindless;
+use crate::descriptor_allocator::{DescriptorHandle, DescriptorHeapType};
+use crate::descriptor_heap;
+use crate::graphic_device;
+use crate::graphic_device::RenderTarget;
+use crate::hello_world_triangle;
+use crate::image_io;
+use crate::mesh_io;
+use crate::mesh_io::Mesh;
+use crate::render_scale;
+use crate::resource_states;
+use crate::window_events;
+use crate::window_events::{EventResponse, WindowEvent, WindowEventKind};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AssetKind
+{
+    Shader,
+    Image,
+    Mesh,
+}
+
+// a loader returns a short status for the window title, or the error
+pub type AssetLoader = Box<dyn FnMut(&Path) -> std::result::Result<String, String>>;
+
+// a dropped image uploaded to a texture, with a SRV for the blit. it's also registered as a bindless texture when bindless is on.
+struct ImageView
+{
+    texture : ID3D12Resource,
+    srv : DescriptorHandle,
+    bindless_texture : Option<DescriptorHandle>,
+    width : u32,
+    height : u32,
+}
+
+impl Drop for ImageView
+{
+    fn drop(&mut self)
+    {
+        resource_states::unregister_resource(&self.texture);
+        descriptor_heap::free(&self.srv);
+        if let Some(x) = self.bindless_texture.as_ref()
+        {
+            bindless::release(x);
+        }
+    }
+}
+
+static mut GLOADERS : Option<HashMap<AssetKind, AssetLoader>> = None;
+static mut GIMAGE_VIEW : Option<ImageView> = None;
+static mut GLOADED_MESH : Option<Mesh> = None;
+
+// the original window titles, the drop status is appended to them
+static mut GWINDOW_TITLES : Option<HashMap<isize, String>> = None;
+
+// function to get the kind of asset from the file extension, None if there is no loader for it
+pub fn get_asset_kind(path : &Path) -> Option<AssetKind>
+{
+    let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("").to_ascii_lowercase();
+    return match extension.as_str()
+    {
+        "hlsl" => Some(AssetKind::Shader),
+        "png" | "dds" => Some(AssetKind::Image),
+        "obj" => Some(AssetKind::Mesh),
+        _ => None,
+    };
+}
+
+// function to register the loader of an asset kind, it replaces the previous loader of that kind
+pub fn register_loader(kind : AssetKind, loader : AssetLoader)
+{
+    unsafe
+    {
+        (*std::ptr::addr_of_mut!(GLOADERS)).get_or_insert_with(HashMap::new).insert(kind, loader);
+    }
+}
+
+// function to load a file with the loader of its kind
+pub fn load_file(path : &Path) -> std::result::Result<String, String>
+{
+    unsafe
+    {
+        let kind = get_asset_kind(path).ok_or(format!("no loader for {}", path.display()))?;
+        let loader = (*std::ptr::addr_of_mut!(GLOADERS)).as_mut().and_then(|x| x.get_mut(&kind)).ok_or(format!("no loader for {:?} files", kind))?;
+        return loader(path);
+    }
+}
+
+// function to get the file name for the status messages
+fn get_file_name(path : &Path) -> String
+{
+    return path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+}
+
+// function to upload an image and create the SRV to show it
+fn create_image_view(image : &image_io::Image) -> Option<ImageView>
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        let texture = graphic_device::create_texture_from_image(image)?;
+
+        let srv = match descriptor_heap::allocate(DescriptorHeapType::CbvSrvUav, 1)
+        {
+            Some(x) => x,
+            None =>
+            {
+                resource_states::unregister_resource(&texture);
+                return None;
+            }
+        };
+        device.CreateShaderResourceView(&texture, None, descriptor_heap::get_cpu_handle(&srv, 0));
+
+        let bindless_texture = if bindless::is_blit_ready() { bindless::register_texture(&texture) } else { None };
+
+        return Some(ImageView
+        {
+            texture,
+            srv,
+            bindless_texture,
+            width : image.width,
+            height : image.height,
+        });
+    }
+}
+
+// function to register the built-in loaders of shaders, images and meshes
+pub fn register_default_loaders()
+{
+    register_loader(AssetKind::Shader, Box::new(|path|
+    {
+        hello_world_triangle::load_pixel_shader(path)?;
+
+        // show the shader again if an image was on screen
+        clear_image_view();
+        return Ok(format!("{} compiled", get_file_name(path)));
+    }));
+
+    register_loader(AssetKind::Image, Box::new(|path|
+    {
+        if !render_scale::is_pipeline_ready()
+        {
+            return Err(String::from("the blit pipeline isn't created"));
+        }
+
+        let image = image_io::read_image(path)?;
+        let image_view = create_image_view(&image).ok_or(format!("texture creation failed for {}", get_file_name(path)))?;
+
+        clear_image_view();
+        unsafe
+        {
+            GIMAGE_VIEW = Some(image_view);
+        }
+        return Ok(format!("{} {}x{}", get_file_name(path), image.width, image.height));
+    }));
+
+    register_loader(AssetKind::Mesh, Box::new(|path|
+    {
+        let mesh = mesh_io::read_mesh(path)?;
+        let status = format!("{} {} vertices, {} triangles", get_file_name(path), mesh.positions.len(), mesh.get_triangle_count());
+        unsafe
+        {
+            GLOADED_MESH = Some(mesh);
+        }
+        return Ok(status);
+    }));
+}
+
+// function to show a status in the title of a window, after its original title
+fn set_window_status(h_wnd : HWND, status : &str)
+{
+    unsafe
+    {
+        let titles = (*std::ptr::addr_of_mut!(GWINDOW_TITLES)).get_or_insert_with(HashMap::new);
+        let title = titles.entry(h_wnd.0 as isize).or_insert_with(||
+        {
+            let mut buffer = [0u16; 256];
+            let length = GetWindowTextW(h_wnd, &mut buffer) as usize;
+            String::from_utf16_lossy(&buffer[..length])
+        });
+
+        let _ = SetWindowTextW(h_wnd, &HSTRING::from(format!("{} - {}", title, status)));
+    }
+}
+
+// function to route the dropped files to their loaders. the full error goes to the console,
+// the first line of it is shown in the window title.
+pub fn subscribe_window_events()
+{
+    window_events::subscribe(WindowEventKind::FileDropped, Box::new(|h_wnd, event|
+    {
+        if let WindowEvent::FileDropped { paths } = event
+        {
+            for x in paths.iter()
+            {
+                match load_file(x)
+                {
+                    Ok(status) =>
+                    {
+                        println!("Loaded {}", x.display());
+                        set_window_status(h_wnd, &status);
+                    }
+                    Err(error) =>
+                    {
+                        println!("Error during loading {}:\n{}", x.display(), error);
+                        let first_line = error.lines().next().unwrap_or("").chars().take(200).collect::<String>();
+                        set_window_status(h_wnd, &format!("Error: {}", first_line));
+                    }
+                }
+            }
+        }
+        return EventResponse::Consumed;
+    }));
+}
+
+// function to draw the dropped image into the target, keeping its aspect ratio. returns false if no image is shown.
+pub fn render_image_view(target : &RenderTarget) -> bool
+{
+    unsafe
+    {
+        let image_view = match (*std::ptr::addr_of!(GIMAGE_VIEW)).as_ref()
+        {
+            Some(x) => x,
+            None => return false,
+        };
+
+        // fit the image into the target, the rest is letterboxed
+        let scale = (target.width as f32 / image_view.width as f32).min(target.height as f32 / image_view.height as f32);
+        let width = (image_view.width as f32 * scale).round() as i32;
+        let height = (image_view.height as f32 * scale).round() as i32;
+        let left = (target.width as i32 - width) / 2;
+        let top = (target.height as i32 - height) / 2;
+        let dest_rect = RECT
+        {
+            left,
+            top,
+            right : left + width,
+            bottom : top + height,
+        };
+
+        match image_view.bindless_texture.as_ref()
+        {
+            Some(x) => bindless::blit_texture(&image_view.texture, x, target, &dest_rect),
+            None => render_scale::blit_texture(&image_view.texture, &image_view.srv, target, &dest_rect),
+        }
+        return true;
+    }
+}
+
+// function to stop showing the dropped image
+pub fn clear_image_view()
+{
+    unsafe
+    {
+        if GIMAGE_VIEW.is_some()
+        {
+            // the texture might still be used by the GPU
+            graphic_device::wait_for_gpu();
+            GIMAGE_VIEW = None;
+        }
+    }
+}
+
+pub fn get_loaded_mesh() -> Option<&'static Mesh>
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GLOADED_MESH)).as_ref();
+    }
+}
+
+// function to release the loaded assets, call it while the GPU is idle
+pub fn shutdown()
+{
+    unsafe
+    {
+        GIMAGE_VIEW = None;
+        GLOADED_MESH = None;
+        GLOADERS = None;
+        GWINDOW_TITLES = None;
+    }
+}
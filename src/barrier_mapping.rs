@@ -0,0 +1,299 @@
+// barrier_mapping.rs - The mapping of legacy resource states to Enhanced Barriers sync, access and layout
+
+use crate::state_tracker::{ResourceId, ResourceState, StateBarrier};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BarrierSync(pub u32);
+
+impl BarrierSync
+{
+    pub const NONE : BarrierSync = BarrierSync(0);
+    pub const ALL : BarrierSync = BarrierSync(0x1);
+    pub const DRAW : BarrierSync = BarrierSync(0x2);
+    pub const INDEX_INPUT : BarrierSync = BarrierSync(0x4);
+    pub const VERTEX_SHADING : BarrierSync = BarrierSync(0x8);
+    pub const PIXEL_SHADING : BarrierSync = BarrierSync(0x10);
+    pub const DEPTH_STENCIL : BarrierSync = BarrierSync(0x20);
+    pub const RENDER_TARGET : BarrierSync = BarrierSync(0x40);
+    pub const COMPUTE_SHADING : BarrierSync = BarrierSync(0x80);
+    pub const COPY : BarrierSync = BarrierSync(0x200);
+    pub const RESOLVE : BarrierSync = BarrierSync(0x400);
+    pub const EXECUTE_INDIRECT : BarrierSync = BarrierSync(0x800);
+    pub const ALL_SHADING : BarrierSync = BarrierSync(0x1000);
+    pub const NON_PIXEL_SHADING : BarrierSync = BarrierSync(0x2000);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BarrierAccess(pub u32);
+
+impl BarrierAccess
+{
+    pub const COMMON : BarrierAccess = BarrierAccess(0);
+    pub const VERTEX_BUFFER : BarrierAccess = BarrierAccess(0x1);
+    pub const CONSTANT_BUFFER : BarrierAccess = BarrierAccess(0x2);
+    pub const INDEX_BUFFER : BarrierAccess = BarrierAccess(0x4);
+    pub const RENDER_TARGET : BarrierAccess = BarrierAccess(0x8);
+    pub const UNORDERED_ACCESS : BarrierAccess = BarrierAccess(0x10);
+    pub const DEPTH_STENCIL_WRITE : BarrierAccess = BarrierAccess(0x20);
+    pub const DEPTH_STENCIL_READ : BarrierAccess = BarrierAccess(0x40);
+    pub const SHADER_RESOURCE : BarrierAccess = BarrierAccess(0x80);
+    pub const STREAM_OUTPUT : BarrierAccess = BarrierAccess(0x100);
+    pub const INDIRECT_ARGUMENT : BarrierAccess = BarrierAccess(0x200);
+    pub const COPY_DEST : BarrierAccess = BarrierAccess(0x400);
+    pub const COPY_SOURCE : BarrierAccess = BarrierAccess(0x800);
+    pub const RESOLVE_DEST : BarrierAccess = BarrierAccess(0x1000);
+    pub const RESOLVE_SOURCE : BarrierAccess = BarrierAccess(0x2000);
+    pub const NO_ACCESS : BarrierAccess = BarrierAccess(0x8000_0000);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BarrierLayout(pub u32);
+
+impl BarrierLayout
+{
+    pub const COMMON : BarrierLayout = BarrierLayout(0);
+    pub const PRESENT : BarrierLayout = BarrierLayout(0);
+    pub const GENERIC_READ : BarrierLayout = BarrierLayout(1);
+    pub const RENDER_TARGET : BarrierLayout = BarrierLayout(2);
+    pub const UNORDERED_ACCESS : BarrierLayout = BarrierLayout(3);
+    pub const DEPTH_STENCIL_WRITE : BarrierLayout = BarrierLayout(4);
+    pub const DEPTH_STENCIL_READ : BarrierLayout = BarrierLayout(5);
+    pub const SHADER_RESOURCE : BarrierLayout = BarrierLayout(6);
+    pub const COPY_SOURCE : BarrierLayout = BarrierLayout(7);
+    pub const COPY_DEST : BarrierLayout = BarrierLayout(8);
+    pub const RESOLVE_SOURCE : BarrierLayout = BarrierLayout(9);
+    pub const RESOLVE_DEST : BarrierLayout = BarrierLayout(10);
+    pub const UNDEFINED : BarrierLayout = BarrierLayout(0xffff_ffff);
+}
+
+// the table of every legacy state bit, with the sync scope, the access and the texture layout it maps to. COMMON has no bits, it isn't in here.
+const GSTATE_MAPPING : [(ResourceState, BarrierSync, BarrierAccess, BarrierLayout); 14] =
+[
+    (ResourceState::VERTEX_AND_CONSTANT_BUFFER, BarrierSync(BarrierSync::VERTEX_SHADING.0 | BarrierSync::ALL_SHADING.0)
+        , BarrierAccess(BarrierAccess::VERTEX_BUFFER.0 | BarrierAccess::CONSTANT_BUFFER.0), BarrierLayout::GENERIC_READ),
+    (ResourceState::INDEX_BUFFER, BarrierSync::INDEX_INPUT, BarrierAccess::INDEX_BUFFER, BarrierLayout::GENERIC_READ),
+    (ResourceState::RENDER_TARGET, BarrierSync::RENDER_TARGET, BarrierAccess::RENDER_TARGET, BarrierLayout::RENDER_TARGET),
+    (ResourceState::UNORDERED_ACCESS, BarrierSync::ALL_SHADING, BarrierAccess::UNORDERED_ACCESS, BarrierLayout::UNORDERED_ACCESS),
+    (ResourceState::DEPTH_WRITE, BarrierSync::DEPTH_STENCIL, BarrierAccess::DEPTH_STENCIL_WRITE, BarrierLayout::DEPTH_STENCIL_WRITE),
+    (ResourceState::DEPTH_READ, BarrierSync::DEPTH_STENCIL, BarrierAccess::DEPTH_STENCIL_READ, BarrierLayout::DEPTH_STENCIL_READ),
+    (ResourceState::NON_PIXEL_SHADER_RESOURCE, BarrierSync::NON_PIXEL_SHADING, BarrierAccess::SHADER_RESOURCE, BarrierLayout::SHADER_RESOURCE),
+    (ResourceState::PIXEL_SHADER_RESOURCE, BarrierSync::PIXEL_SHADING, BarrierAccess::SHADER_RESOURCE, BarrierLayout::SHADER_RESOURCE),
+    (ResourceState::STREAM_OUT, BarrierSync::VERTEX_SHADING, BarrierAccess::STREAM_OUTPUT, BarrierLayout::COMMON),
+    (ResourceState::INDIRECT_ARGUMENT, BarrierSync::EXECUTE_INDIRECT, BarrierAccess::INDIRECT_ARGUMENT, BarrierLayout::GENERIC_READ),
+    (ResourceState::COPY_DEST, BarrierSync::COPY, BarrierAccess::COPY_DEST, BarrierLayout::COPY_DEST),
+    (ResourceState::COPY_SOURCE, BarrierSync::COPY, BarrierAccess::COPY_SOURCE, BarrierLayout::COPY_SOURCE),
+    (ResourceState::RESOLVE_DEST, BarrierSync::RESOLVE, BarrierAccess::RESOLVE_DEST, BarrierLayout::RESOLVE_DEST),
+    (ResourceState::RESOLVE_SOURCE, BarrierSync::RESOLVE, BarrierAccess::RESOLVE_SOURCE, BarrierLayout::RESOLVE_SOURCE),
+];
+
+// function to get the sync scope, access and texture layout of a legacy state. a combined read state gets the union of
+// the syncs and accesses of its bits, its layout is GENERIC_READ unless all the bits share one layout or it's a depth read
+// that shaders read as well.
+pub fn get_state_mapping(state : ResourceState) -> (BarrierSync, BarrierAccess, BarrierLayout)
+{
+    // COMMON and PRESENT can be accessed by anything, so the GPU work before and after must be synchronized completely
+    if state == ResourceState::COMMON
+    {
+        return (BarrierSync::ALL, BarrierAccess::COMMON, BarrierLayout::COMMON);
+    }
+
+    let mut sync = 0;
+    let mut access = 0;
+    let mut layout : Option<BarrierLayout> = None;
+    for (legacy_state, x, y, z) in GSTATE_MAPPING.iter()
+    {
+        if !state.contains(*legacy_state)
+        {
+            continue;
+        }
+
+        sync = sync | x.0;
+        access = access | y.0;
+        layout = match layout
+        {
+            None => Some(*z),
+            Some(w) if w == *z => Some(w),
+            // depth read is the only layout that can be read as a depth buffer, it's also readable by shaders but not by copies or resolves
+            Some(BarrierLayout::DEPTH_STENCIL_READ) if *z == BarrierLayout::SHADER_RESOURCE => Some(BarrierLayout::DEPTH_STENCIL_READ),
+            Some(BarrierLayout::SHADER_RESOURCE) if *z == BarrierLayout::DEPTH_STENCIL_READ => Some(BarrierLayout::DEPTH_STENCIL_READ),
+            Some(_) => Some(BarrierLayout::GENERIC_READ),
+        };
+    }
+
+    return (BarrierSync(sync), BarrierAccess(access), layout.unwrap_or(BarrierLayout::COMMON));
+}
+
+// an enhanced barrier, subresource None means all subresources
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnhancedBarrier
+{
+    Texture
+    {
+        resource : ResourceId,
+        subresource : Option<u32>,
+        sync_before : BarrierSync,
+        sync_after : BarrierSync,
+        access_before : BarrierAccess,
+        access_after : BarrierAccess,
+        layout_before : BarrierLayout,
+        layout_after : BarrierLayout,
+    },
+    Buffer
+    {
+        resource : ResourceId,
+        sync_before : BarrierSync,
+        sync_after : BarrierSync,
+        access_before : BarrierAccess,
+        access_after : BarrierAccess,
+    },
+    Global
+    {
+        sync_before : BarrierSync,
+        sync_after : BarrierSync,
+        access_before : BarrierAccess,
+        access_after : BarrierAccess,
+    },
+}
+
+// function to translate a tracked barrier, is_texture tells whether the resource has layouts
+pub fn translate_barrier(barrier : &StateBarrier, is_texture : bool) -> EnhancedBarrier
+{
+    return match *barrier
+    {
+        StateBarrier::Transition { resource, subresource, before, after } =>
+        {
+            let (sync_before, access_before, layout_before) = get_state_mapping(before);
+            let (sync_after, access_after, layout_after) = get_state_mapping(after);
+            if is_texture
+            {
+                EnhancedBarrier::Texture { resource, subresource, sync_before, sync_after, access_before, access_after, layout_before, layout_after }
+            }
+            else
+            {
+                EnhancedBarrier::Buffer { resource, sync_before, sync_after, access_before, access_after }
+            }
+        }
+        // the UAV writes before must finish before the UAV accesses after, the layout stays the same
+        StateBarrier::UnorderedAccess { .. } => EnhancedBarrier::Global
+        {
+            sync_before : BarrierSync::ALL_SHADING,
+            sync_after : BarrierSync::ALL_SHADING,
+            access_before : BarrierAccess::UNORDERED_ACCESS,
+            access_after : BarrierAccess::UNORDERED_ACCESS,
+        },
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const RESOURCE : ResourceId = ResourceId(0x1000);
+
+    #[test]
+    fn each_state_bit_has_its_own_mapping()
+    {
+        let expected =
+        [
+            (ResourceState::VERTEX_AND_CONSTANT_BUFFER, BarrierSync(0x8 | 0x1000), BarrierAccess(0x1 | 0x2), BarrierLayout::GENERIC_READ),
+            (ResourceState::INDEX_BUFFER, BarrierSync::INDEX_INPUT, BarrierAccess::INDEX_BUFFER, BarrierLayout::GENERIC_READ),
+            (ResourceState::RENDER_TARGET, BarrierSync::RENDER_TARGET, BarrierAccess::RENDER_TARGET, BarrierLayout::RENDER_TARGET),
+            (ResourceState::UNORDERED_ACCESS, BarrierSync::ALL_SHADING, BarrierAccess::UNORDERED_ACCESS, BarrierLayout::UNORDERED_ACCESS),
+            (ResourceState::DEPTH_WRITE, BarrierSync::DEPTH_STENCIL, BarrierAccess::DEPTH_STENCIL_WRITE, BarrierLayout::DEPTH_STENCIL_WRITE),
+            (ResourceState::DEPTH_READ, BarrierSync::DEPTH_STENCIL, BarrierAccess::DEPTH_STENCIL_READ, BarrierLayout::DEPTH_STENCIL_READ),
+            (ResourceState::NON_PIXEL_SHADER_RESOURCE, BarrierSync::NON_PIXEL_SHADING, BarrierAccess::SHADER_RESOURCE, BarrierLayout::SHADER_RESOURCE),
+            (ResourceState::PIXEL_SHADER_RESOURCE, BarrierSync::PIXEL_SHADING, BarrierAccess::SHADER_RESOURCE, BarrierLayout::SHADER_RESOURCE),
+            (ResourceState::STREAM_OUT, BarrierSync::VERTEX_SHADING, BarrierAccess::STREAM_OUTPUT, BarrierLayout::COMMON),
+            (ResourceState::INDIRECT_ARGUMENT, BarrierSync::EXECUTE_INDIRECT, BarrierAccess::INDIRECT_ARGUMENT, BarrierLayout::GENERIC_READ),
+            (ResourceState::COPY_DEST, BarrierSync::COPY, BarrierAccess::COPY_DEST, BarrierLayout::COPY_DEST),
+            (ResourceState::COPY_SOURCE, BarrierSync::COPY, BarrierAccess::COPY_SOURCE, BarrierLayout::COPY_SOURCE),
+            (ResourceState::RESOLVE_DEST, BarrierSync::RESOLVE, BarrierAccess::RESOLVE_DEST, BarrierLayout::RESOLVE_DEST),
+            (ResourceState::RESOLVE_SOURCE, BarrierSync::RESOLVE, BarrierAccess::RESOLVE_SOURCE, BarrierLayout::RESOLVE_SOURCE),
+        ];
+        for (state, sync, access, layout) in expected.iter()
+        {
+            assert_eq!(get_state_mapping(*state), (*sync, *access, *layout), "{:?}", state);
+        }
+
+        // every bit of D3D12_RESOURCE_STATES up to RESOLVE_SOURCE is covered
+        let covered = expected.iter().fold(0, |bits, x| bits | x.0.0);
+        assert_eq!(covered, 0x3fff);
+    }
+
+    #[test]
+    fn common_and_present_synchronize_everything()
+    {
+        let common = (BarrierSync::ALL, BarrierAccess::COMMON, BarrierLayout::COMMON);
+        assert_eq!(get_state_mapping(ResourceState::COMMON), common);
+        assert_eq!(get_state_mapping(ResourceState::PRESENT), common);
+        assert_eq!(BarrierLayout::PRESENT, BarrierLayout::COMMON);
+    }
+
+    #[test]
+    fn combined_read_states_pick_a_shared_layout()
+    {
+        let shader_resource = ResourceState(ResourceState::PIXEL_SHADER_RESOURCE.0 | ResourceState::NON_PIXEL_SHADER_RESOURCE.0);
+        assert_eq!(get_state_mapping(shader_resource), (BarrierSync(0x10 | 0x2000), BarrierAccess::SHADER_RESOURCE, BarrierLayout::SHADER_RESOURCE));
+
+        // a depth buffer read by the depth test and a pixel shader stays in the depth read layout
+        let depth_and_shader = ResourceState(ResourceState::DEPTH_READ.0 | ResourceState::PIXEL_SHADER_RESOURCE.0);
+        assert_eq!(get_state_mapping(depth_and_shader), (BarrierSync(0x20 | 0x10), BarrierAccess(0x40 | 0x80), BarrierLayout::DEPTH_STENCIL_READ));
+
+        // copies and resolves can't use the depth read layout
+        let depth_and_copy = ResourceState(ResourceState::DEPTH_READ.0 | ResourceState::COPY_SOURCE.0);
+        assert_eq!(get_state_mapping(depth_and_copy), (BarrierSync(0x20 | 0x200), BarrierAccess(0x40 | 0x800), BarrierLayout::GENERIC_READ));
+        let depth_shader_and_resolve = ResourceState(ResourceState::DEPTH_READ.0 | ResourceState::PIXEL_SHADER_RESOURCE.0 | ResourceState::RESOLVE_SOURCE.0);
+        assert_eq!(get_state_mapping(depth_shader_and_resolve).2, BarrierLayout::GENERIC_READ);
+
+        let copy_and_shader = ResourceState(ResourceState::COPY_SOURCE.0 | ResourceState::PIXEL_SHADER_RESOURCE.0);
+        assert_eq!(get_state_mapping(copy_and_shader), (BarrierSync(0x200 | 0x10), BarrierAccess(0x800 | 0x80), BarrierLayout::GENERIC_READ));
+
+        let (sync, access, layout) = get_state_mapping(ResourceState::GENERIC_READ);
+        assert_eq!(sync, BarrierSync(0x8 | 0x1000 | 0x4 | 0x2000 | 0x10 | 0x800 | 0x200));
+        assert_eq!(access, BarrierAccess(0x1 | 0x2 | 0x4 | 0x80 | 0x200 | 0x800));
+        assert_eq!(layout, BarrierLayout::GENERIC_READ);
+    }
+
+    #[test]
+    fn transitions_become_texture_or_buffer_barriers()
+    {
+        let barrier = StateBarrier::Transition { resource : RESOURCE, subresource : Some(2), before : ResourceState::RENDER_TARGET, after : ResourceState::PIXEL_SHADER_RESOURCE };
+        assert_eq!(translate_barrier(&barrier, true), EnhancedBarrier::Texture
+        {
+            resource : RESOURCE,
+            subresource : Some(2),
+            sync_before : BarrierSync::RENDER_TARGET,
+            sync_after : BarrierSync::PIXEL_SHADING,
+            access_before : BarrierAccess::RENDER_TARGET,
+            access_after : BarrierAccess::SHADER_RESOURCE,
+            layout_before : BarrierLayout::RENDER_TARGET,
+            layout_after : BarrierLayout::SHADER_RESOURCE,
+        });
+
+        // buffers have no layout and no subresources
+        let barrier = StateBarrier::Transition { resource : RESOURCE, subresource : None, before : ResourceState::COMMON, after : ResourceState::COPY_DEST };
+        assert_eq!(translate_barrier(&barrier, false), EnhancedBarrier::Buffer
+        {
+            resource : RESOURCE,
+            sync_before : BarrierSync::ALL,
+            sync_after : BarrierSync::COPY,
+            access_before : BarrierAccess::COMMON,
+            access_after : BarrierAccess::COPY_DEST,
+        });
+    }
+
+    #[test]
+    fn unordered_access_barriers_become_global_barriers()
+    {
+        let expected = EnhancedBarrier::Global
+        {
+            sync_before : BarrierSync::ALL_SHADING,
+            sync_after : BarrierSync::ALL_SHADING,
+            access_before : BarrierAccess::UNORDERED_ACCESS,
+            access_after : BarrierAccess::UNORDERED_ACCESS,
+        };
+        assert_eq!(translate_barrier(&StateBarrier::UnorderedAccess { resource : RESOURCE }, true), expected);
+        assert_eq!(translate_barrier(&StateBarrier::UnorderedAccess { resource : RESOURCE }, false), expected);
+    }
+}
@@ -0,0 +1,230 @@
+// barriers.rs - Recording of the tracked barriers. on devices that report Enhanced Barriers the transitions are translated
+// to texture, buffer and global barriers by barrier_mapping.rs and recorded with ID3D12GraphicsCommandList7::Barrier,
+// otherwise they are recorded as legacy transition and UAV barriers. the callers only see StateBarrier.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::mem;
+use std::mem::ManuallyDrop;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows_core::Interface;
+
+use crate::barrier_mapping::{translate_barrier, EnhancedBarrier};
+use crate::graphic_device;
+use crate::resource_states::to_d3d12_state;
+use crate::state_tracker::{ResourceId, StateBarrier};
+
+static mut GENHANCED_BARRIERS_SUPPORTED : bool = false;
+static mut GUSE_ENHANCED_BARRIERS : bool = true;
+
+// function to check whether the driver supports Enhanced Barriers, it's called by resource_states::initialize()
+pub fn initialize()
+{
+    unsafe
+    {
+        let mut options12 = D3D12_FEATURE_DATA_D3D12_OPTIONS12::default();
+        let result = graphic_device::get_device().CheckFeatureSupport(D3D12_FEATURE_D3D12_OPTIONS12, &mut options12 as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_D3D12_OPTIONS12>() as u32);
+
+        // older runtimes don't know OPTIONS12 at all, that's the same as not supported
+        GENHANCED_BARRIERS_SUPPORTED = result.is_ok() && options12.EnhancedBarriersSupported.as_bool();
+        println!("Enhanced Barriers: {}", if GENHANCED_BARRIERS_SUPPORTED { "supported" } else { "not supported, using legacy barriers" });
+    }
+}
+
+pub fn is_enhanced_barriers_supported() -> bool
+{
+    unsafe
+    {
+        return GENHANCED_BARRIERS_SUPPORTED;
+    }
+}
+
+// function to force the legacy barriers even if Enhanced Barriers are supported, it's useful to compare both paths
+pub fn set_use_enhanced_barriers(enable : bool)
+{
+    unsafe
+    {
+        GUSE_ENHANCED_BARRIERS = enable;
+    }
+}
+
+pub fn is_using_enhanced_barriers() -> bool
+{
+    unsafe
+    {
+        return GENHANCED_BARRIERS_SUPPORTED && GUSE_ENHANCED_BARRIERS;
+    }
+}
+
+// function to record tracked barriers in one call, the resources are borrowed from the map without AddRef
+pub fn record_barriers(command_list : &ID3D12GraphicsCommandList, barriers : &[StateBarrier], resources : &HashMap<ResourceId, ID3D12Resource>)
+{
+    if barriers.is_empty()
+    {
+        return;
+    }
+
+    unsafe
+    {
+        if is_using_enhanced_barriers()
+        {
+            if let Ok(x) = command_list.cast::<ID3D12GraphicsCommandList7>()
+            {
+                record_enhanced_barriers(&x, barriers, resources);
+                return;
+            }
+        }
+
+        command_list.ResourceBarrier(&create_legacy_barriers(barriers, resources));
+    }
+}
+
+// function to convert tracked barriers to legacy D3D12 barriers
+fn create_legacy_barriers(barriers : &[StateBarrier], resources : &HashMap<ResourceId, ID3D12Resource>) -> Vec<D3D12_RESOURCE_BARRIER>
+{
+    return barriers.iter().map(|x| match *x
+    {
+        StateBarrier::Transition { resource, subresource, before, after } =>
+        {
+            let transition_barrier = D3D12_RESOURCE_TRANSITION_BARRIER
+            {
+                pResource : unsafe { mem::transmute_copy(&resources[&resource]) },
+                StateBefore : to_d3d12_state(before),
+                StateAfter : to_d3d12_state(after),
+                Subresource : subresource.unwrap_or(D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES),
+            };
+
+            D3D12_RESOURCE_BARRIER
+            {
+                Type : D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                Anonymous : D3D12_RESOURCE_BARRIER_0
+                {
+                    Transition : ManuallyDrop::new(transition_barrier),
+                },
+                ..D3D12_RESOURCE_BARRIER::default()
+            }
+        }
+        StateBarrier::UnorderedAccess { resource } =>
+        {
+            let uav_barrier = D3D12_RESOURCE_UAV_BARRIER
+            {
+                pResource : unsafe { mem::transmute_copy(&resources[&resource]) },
+            };
+
+            D3D12_RESOURCE_BARRIER
+            {
+                Type : D3D12_RESOURCE_BARRIER_TYPE_UAV,
+                Anonymous : D3D12_RESOURCE_BARRIER_0
+                {
+                    UAV : ManuallyDrop::new(uav_barrier),
+                },
+                ..D3D12_RESOURCE_BARRIER::default()
+            }
+        }
+    }).collect();
+}
+
+// function to translate tracked barriers and record them as one texture, one buffer and one global barrier group
+fn record_enhanced_barriers(command_list : &ID3D12GraphicsCommandList7, barriers : &[StateBarrier], resources : &HashMap<ResourceId, ID3D12Resource>)
+{
+    let mut texture_barriers : Vec<D3D12_TEXTURE_BARRIER> = Vec::new();
+    let mut buffer_barriers : Vec<D3D12_BUFFER_BARRIER> = Vec::new();
+    let mut global_barriers : Vec<D3D12_GLOBAL_BARRIER> = Vec::new();
+
+    unsafe
+    {
+        for x in barriers
+        {
+            // only textures have layouts, the buffers are transitioned with their sync and access only
+            let is_texture = match *x
+            {
+                StateBarrier::Transition { resource, .. } => resources[&resource].GetDesc().Dimension != D3D12_RESOURCE_DIMENSION_BUFFER,
+                StateBarrier::UnorderedAccess { .. } => false,
+            };
+
+            match translate_barrier(x, is_texture)
+            {
+                EnhancedBarrier::Texture { resource, subresource, sync_before, sync_after, access_before, access_after, layout_before, layout_after } =>
+                {
+                    // NumMipLevels 0 makes IndexOrFirstMipLevel a subresource index, 0xffffffff is all subresources
+                    let subresource_range = D3D12_BARRIER_SUBRESOURCE_RANGE
+                    {
+                        IndexOrFirstMipLevel : subresource.unwrap_or(D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES),
+                        ..D3D12_BARRIER_SUBRESOURCE_RANGE::default()
+                    };
+
+                    texture_barriers.push(D3D12_TEXTURE_BARRIER
+                    {
+                        SyncBefore : D3D12_BARRIER_SYNC(sync_before.0 as i32),
+                        SyncAfter : D3D12_BARRIER_SYNC(sync_after.0 as i32),
+                        AccessBefore : D3D12_BARRIER_ACCESS(access_before.0 as i32),
+                        AccessAfter : D3D12_BARRIER_ACCESS(access_after.0 as i32),
+                        LayoutBefore : D3D12_BARRIER_LAYOUT(layout_before.0 as i32),
+                        LayoutAfter : D3D12_BARRIER_LAYOUT(layout_after.0 as i32),
+                        pResource : mem::transmute_copy(&resources[&resource]),
+                        Subresources : subresource_range,
+                        Flags : D3D12_TEXTURE_BARRIER_FLAG_NONE,
+                    });
+                }
+                EnhancedBarrier::Buffer { resource, sync_before, sync_after, access_before, access_after } =>
+                {
+                    buffer_barriers.push(D3D12_BUFFER_BARRIER
+                    {
+                        SyncBefore : D3D12_BARRIER_SYNC(sync_before.0 as i32),
+                        SyncAfter : D3D12_BARRIER_SYNC(sync_after.0 as i32),
+                        AccessBefore : D3D12_BARRIER_ACCESS(access_before.0 as i32),
+                        AccessAfter : D3D12_BARRIER_ACCESS(access_after.0 as i32),
+                        pResource : mem::transmute_copy(&resources[&resource]),
+                        // buffer barriers always cover the whole buffer
+                        Offset : 0,
+                        Size : u64::MAX,
+                    });
+                }
+                EnhancedBarrier::Global { sync_before, sync_after, access_before, access_after } =>
+                {
+                    global_barriers.push(D3D12_GLOBAL_BARRIER
+                    {
+                        SyncBefore : D3D12_BARRIER_SYNC(sync_before.0 as i32),
+                        SyncAfter : D3D12_BARRIER_SYNC(sync_after.0 as i32),
+                        AccessBefore : D3D12_BARRIER_ACCESS(access_before.0 as i32),
+                        AccessAfter : D3D12_BARRIER_ACCESS(access_after.0 as i32),
+                    });
+                }
+            }
+        }
+
+        let mut barrier_groups : Vec<D3D12_BARRIER_GROUP> = Vec::new();
+        if !global_barriers.is_empty()
+        {
+            barrier_groups.push(D3D12_BARRIER_GROUP
+            {
+                Type : D3D12_BARRIER_TYPE_GLOBAL,
+                NumBarriers : global_barriers.len() as u32,
+                Anonymous : D3D12_BARRIER_GROUP_0 { pGlobalBarriers : global_barriers.as_ptr() },
+            });
+        }
+
+        if !texture_barriers.is_empty()
+        {
+            barrier_groups.push(D3D12_BARRIER_GROUP
+            {
+                Type : D3D12_BARRIER_TYPE_TEXTURE,
+                NumBarriers : texture_barriers.len() as u32,
+                Anonymous : D3D12_BARRIER_GROUP_0 { pTextureBarriers : texture_barriers.as_ptr() },
+            });
+        }
+
+        if !buffer_barriers.is_empty()
+        {
+            barrier_groups.push(D3D12_BARRIER_GROUP
+            {
+                Type : D3D12_BARRIER_TYPE_BUFFER,
+                NumBarriers : buffer_barriers.len() as u32,
+                Anonymous : D3D12_BARRIER_GROUP_0 { pBufferBarriers : buffer_barriers.as_ptr() },
+            });
+        }
+
+        command_list.Barrier(&barrier_groups);
+    }
+}
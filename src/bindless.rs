@@ -0,0 +1,611 @@
+// bindless.rs - The bindless resource model of shader model 6.6. there is one large shader visible CBV_SRV_UAV heap and one sampler heap,
+// every texture or buffer registered here gets a stable index in them, and shaders fetch it with ResourceDescriptorHeap[index]
+// or SamplerDescriptorHeap[index]. the indices are passed in root constants, the same way hello_world_triangle passes GTimeMS.
+// a released index is only handed out again after the frames that could still read it are done on the GPU.
+// it needs a device with SM 6.6 and resource binding tier 3, the rest of the app keeps working without it.
+
+use std::mem::ManuallyDrop;
+use std::ffi::c_void;
+use std::mem;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Direct3D::*;
+use windows::Win32::Graphics::Direct3D::Dxc::*;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows::Win32::Graphics::Dxgi::Common::*;
+use windows::Win32::System::LibraryLoader::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+
+use crate::buffer::Buffer;
+use crate::descriptor_allocator::{DeferredFreeList, DescriptorHandle, DescriptorHeapType};
+use crate::descriptor_heap;
+use crate::graphic_device;
+use crate::graphic_device::RenderTarget;
+use crate::gpu_memory;
+use crate::residency;
+use crate::residency_policy::ResidencyId;
+use crate::resource_states::CommandListStates;
+
+// a shader visible heap whose slots are indexed by the shaders directly
+struct BindlessHeap
+{
+    heap : ID3D12DescriptorHeap,
+    cpu_start : D3D12_CPU_DESCRIPTOR_HANDLE,
+    descriptor_size : u32,
+    indices : DeferredFreeList,
+    // the residency objects pinned by the live slots, the shaders can read them without any touch
+    pinned : HashMap<u32, ResidencyId>,
+}
+
+// DXC is loaded at runtime, so the app still starts on machines without dxcompiler.dll
+struct DxcCompiler
+{
+    utils : IDxcUtils,
+    compiler : IDxcCompiler3,
+}
+
+static mut GRESOURCE_HEAP : Option<BindlessHeap> = None;
+static mut GSAMPLER_HEAP : Option<BindlessHeap> = None;
+static mut GBINDLESS_ROOT_SIGNATURE : Option<ID3D12RootSignature> = None;
+static mut GLINEAR_CLAMP_SAMPLER : Option<DescriptorHandle> = None;
+static mut GBLIT_STATE : Option<ID3D12PipelineState> = None;
+static mut GDXC_COMPILER : Option<DxcCompiler> = None;
+
+// the size of the heaps, tier 3 allows much more resources, the sampler heap is at the limit of D3D12
+const GRESOURCE_HEAP_CAPACITY : u32 = 65536;
+const GSAMPLER_HEAP_CAPACITY : u32 = D3D12_MAX_SHADER_VISIBLE_SAMPLER_HEAP_SIZE;
+
+// the number of 32-bit root constants at b0, for the indices of a pass
+pub const GROOT_CONSTANT_COUNT : u32 = 8;
+
+// function to check whether the device can index the descriptor heaps directly
+pub fn is_supported() -> bool
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+
+        // the highest shader model is an in/out parameter, ask for the one we need
+        let mut shader_model = D3D12_FEATURE_DATA_SHADER_MODEL
+        {
+            HighestShaderModel : D3D_SHADER_MODEL_6_6,
+        };
+        if device.CheckFeatureSupport(D3D12_FEATURE_SHADER_MODEL, &mut shader_model as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_SHADER_MODEL>() as u32).is_err() || shader_model.HighestShaderModel.0 < D3D_SHADER_MODEL_6_6.0
+        {
+            return false;
+        }
+
+        let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS::default();
+        if device.CheckFeatureSupport(D3D12_FEATURE_D3D12_OPTIONS, &mut options as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_D3D12_OPTIONS>() as u32).is_err()
+        {
+            return false;
+        }
+
+        return options.ResourceBindingTier.0 >= D3D12_RESOURCE_BINDING_TIER_3.0;
+    }
+}
+
+fn create_bindless_heap(heap_type : DescriptorHeapType, capacity : u32) -> Option<BindlessHeap>
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        let heap_desc = D3D12_DESCRIPTOR_HEAP_DESC
+        {
+            NumDescriptors : capacity,
+            Type : descriptor_heap::get_d3d12_heap_type(heap_type),
+            Flags : D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
+            ..D3D12_DESCRIPTOR_HEAP_DESC::default()
+        };
+
+        let heap = match device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&heap_desc)
+        {
+            Ok(x) => x,
+            Err(_) =>
+            {
+                println!("Error during bindless {:?} heap creation!", heap_type);
+                return None;
+            }
+        };
+
+        return Some(BindlessHeap
+        {
+            cpu_start : heap.GetCPUDescriptorHandleForHeapStart(),
+            heap,
+            descriptor_size : device.GetDescriptorHandleIncrementSize(heap_desc.Type),
+            indices : DeferredFreeList::new(heap_type, capacity),
+            pinned : HashMap::new(),
+        });
+    }
+}
+
+// function to create the root signature shared by the bindless passes, root constants only
+fn create_root_signature() -> Option<ID3D12RootSignature>
+{
+    unsafe
+    {
+        let root_parameter_constant = D3D12_ROOT_PARAMETER1
+        {
+            ParameterType : D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+            Anonymous : D3D12_ROOT_PARAMETER1_0
+            {
+                Constants : D3D12_ROOT_CONSTANTS
+                {
+                    ShaderRegister : 0,
+                    RegisterSpace : 0,
+                    Num32BitValues : GROOT_CONSTANT_COUNT,
+                }
+            },
+            ShaderVisibility : D3D12_SHADER_VISIBILITY_ALL,
+        };
+
+        // the heap flags need root signature 1.1
+        let root_signature_desc = D3D12_VERSIONED_ROOT_SIGNATURE_DESC
+        {
+            Version : D3D_ROOT_SIGNATURE_VERSION_1_1,
+            Anonymous : D3D12_VERSIONED_ROOT_SIGNATURE_DESC_0
+            {
+                Desc_1_1 : D3D12_ROOT_SIGNATURE_DESC1
+                {
+                    NumParameters : 1,
+                    pParameters : &root_parameter_constant,
+                    NumStaticSamplers : 0,
+                    pStaticSamplers : std::ptr::null(),
+                    Flags : D3D12_ROOT_SIGNATURE_FLAG_CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED | D3D12_ROOT_SIGNATURE_FLAG_SAMPLER_HEAP_DIRECTLY_INDEXED,
+                }
+            },
+        };
+
+        let mut root_signature_blob : Option<ID3DBlob> = None;
+        let _ = D3D12SerializeVersionedRootSignature(&root_signature_desc, &mut root_signature_blob, None);
+        if root_signature_blob.is_none()
+        {
+            println!("Error during bindless root signature serialization!");
+            return None;
+        }
+
+        let root_blob_data = std::slice::from_raw_parts(root_signature_blob.as_ref().unwrap().GetBufferPointer() as *const u8, root_signature_blob.as_ref().unwrap().GetBufferSize());
+        return graphic_device::get_device().CreateRootSignature::<ID3D12RootSignature>(0, root_blob_data).ok();
+    }
+}
+
+// function to create the bindless heaps and root signature, call it after graphic_device::initialize_d3d12().
+// returns false if the device doesn't support it, bindless stays off then.
+pub fn initialize() -> bool
+{
+    unsafe
+    {
+        if !is_supported()
+        {
+            println!("Bindless resources need shader model 6.6 and resource binding tier 3, they are disabled.");
+            return false;
+        }
+
+        GRESOURCE_HEAP = create_bindless_heap(DescriptorHeapType::CbvSrvUav, GRESOURCE_HEAP_CAPACITY);
+        GSAMPLER_HEAP = create_bindless_heap(DescriptorHeapType::Sampler, GSAMPLER_HEAP_CAPACITY);
+        GBINDLESS_ROOT_SIGNATURE = create_root_signature();
+        if GRESOURCE_HEAP.is_none() || GSAMPLER_HEAP.is_none() || GBINDLESS_ROOT_SIGNATURE.is_none()
+        {
+            println!("Error during bindless initialization!");
+            shutdown();
+            return false;
+        }
+
+        let linear_clamp_sampler = D3D12_SAMPLER_DESC
+        {
+            Filter : D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+            AddressU : D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+            AddressV : D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+            AddressW : D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+            MaxAnisotropy : 1,
+            ComparisonFunc : D3D12_COMPARISON_FUNC_NEVER,
+            MaxLOD : f32::MAX,
+            ..D3D12_SAMPLER_DESC::default()
+        };
+        GLINEAR_CLAMP_SAMPLER = create_sampler(&linear_clamp_sampler);
+
+        return true;
+    }
+}
+
+// function to release the heaps and pipelines, call it while the GPU is idle
+pub fn shutdown()
+{
+    unsafe
+    {
+        GBLIT_STATE = None;
+        GLINEAR_CLAMP_SAMPLER = None;
+        GBINDLESS_ROOT_SIGNATURE = None;
+        GRESOURCE_HEAP = None;
+        GSAMPLER_HEAP = None;
+        GDXC_COMPILER = None;
+    }
+}
+
+pub fn is_enabled() -> bool
+{
+    unsafe
+    {
+        return GBINDLESS_ROOT_SIGNATURE.is_some();
+    }
+}
+
+fn get_heap(heap_type : DescriptorHeapType) -> Option<&'static mut BindlessHeap>
+{
+    unsafe
+    {
+        return match heap_type
+        {
+            DescriptorHeapType::CbvSrvUav => (*std::ptr::addr_of_mut!(GRESOURCE_HEAP)).as_mut(),
+            DescriptorHeapType::Sampler => (*std::ptr::addr_of_mut!(GSAMPLER_HEAP)).as_mut(),
+            _ => None,
+        };
+    }
+}
+
+// function to allocate a slot in the resource heap, create the view at get_cpu_handle() of it.
+// the shader index is handle.index, it stays the same until the handle is released.
+pub fn allocate_resource_index() -> Option<DescriptorHandle>
+{
+    let heap = get_heap(DescriptorHeapType::CbvSrvUav)?;
+    let handle = heap.indices.allocate(1);
+    if handle.is_none()
+    {
+        println!("The bindless resource heap is full! {} slots wait for the GPU", heap.indices.get_retired_count());
+    }
+    return handle;
+}
+
+// function to get the CPU handle of a bindless slot to write its descriptor
+pub fn get_cpu_handle(handle : &DescriptorHandle) -> D3D12_CPU_DESCRIPTOR_HANDLE
+{
+    let heap = get_heap(handle.heap_type).expect("Bindless isn't initialized, or the handle isn't a bindless one!");
+    if cfg!(debug_assertions)
+    {
+        if let Err(x) = heap.indices.validate(handle)
+        {
+            println!("Invalid bindless handle! {}", x);
+            debug_assert!(false, "Invalid bindless handle! {}", x);
+        }
+    }
+
+    return D3D12_CPU_DESCRIPTOR_HANDLE
+    {
+        ptr : heap.cpu_start.ptr + (handle.index * heap.descriptor_size) as usize,
+    };
+}
+
+// function to register a texture with a default SRV of the whole resource
+pub fn register_texture(texture : &ID3D12Resource) -> Option<DescriptorHandle>
+{
+    let handle = allocate_resource_index()?;
+    unsafe
+    {
+        graphic_device::get_device().CreateShaderResourceView(texture, None, get_cpu_handle(&handle));
+    }
+    pin_resource(&handle, texture);
+    return Some(handle);
+}
+
+// function to register a structured or raw buffer with a SRV, use ByteAddressBuffer or StructuredBuffer<T> in the shader
+pub fn register_buffer<T : Copy>(buffer : &Buffer<T>) -> Option<DescriptorHandle>
+{
+    let handle = allocate_resource_index()?;
+    if !buffer.create_shader_resource_view(get_cpu_handle(&handle))
+    {
+        println!("Only structured and raw buffers can be registered as bindless SRVs!");
+        release(&handle);
+        return None;
+    }
+    pin_resource(&handle, buffer.get_resource());
+    return Some(handle);
+}
+
+// function to keep the memory of a registered resource resident while its slot is live
+fn pin_resource(handle : &DescriptorHandle, resource : &ID3D12Resource)
+{
+    if let (Some(heap), Some(x)) = (get_heap(handle.heap_type), gpu_memory::pin_resource(resource))
+    {
+        heap.pinned.insert(handle.index, x);
+    }
+}
+
+// function to create a sampler in the sampler heap, for SamplerDescriptorHeap[handle.index]
+pub fn create_sampler(sampler_desc : &D3D12_SAMPLER_DESC) -> Option<DescriptorHandle>
+{
+    let heap = get_heap(DescriptorHeapType::Sampler)?;
+    let handle = match heap.indices.allocate(1)
+    {
+        Some(x) => x,
+        None =>
+        {
+            println!("The bindless sampler heap is full!");
+            return None;
+        }
+    };
+
+    unsafe
+    {
+        graphic_device::get_device().CreateSampler(sampler_desc, get_cpu_handle(&handle));
+    }
+    return Some(handle);
+}
+
+// function to release a bindless slot. the frames submitted so far may still read it,
+// so the index is reused only after the next fence signal of the main queue is completed.
+pub fn release(handle : &DescriptorHandle)
+{
+    if let Some(heap) = get_heap(handle.heap_type)
+    {
+        if let Err(x) = heap.indices.retire(handle, graphic_device::get_next_fence_value())
+        {
+            println!("Invalid bindless handle! {}", x);
+            debug_assert!(false, "Invalid bindless handle! {}", x);
+            return;
+        }
+
+        // the frames submitted so far may still read it, unpin() keeps it until the next one is completed
+        if let Some(x) = heap.pinned.remove(&handle.index)
+        {
+            residency::unpin(x);
+        }
+    }
+}
+
+// function to recycle the released slots of the finished frames, called once per frame
+pub fn begin_frame()
+{
+    let completed_fence_value = graphic_device::get_completed_fence_value();
+    for x in [DescriptorHeapType::CbvSrvUav, DescriptorHeapType::Sampler]
+    {
+        if let Some(heap) = get_heap(x)
+        {
+            heap.indices.reclaim(completed_fence_value);
+        }
+    }
+}
+
+// the sampler created at initialization, for the common case
+pub fn get_linear_clamp_sampler() -> Option<DescriptorHandle>
+{
+    unsafe
+    {
+        return GLINEAR_CLAMP_SAMPLER;
+    }
+}
+
+pub fn get_root_signature() -> Option<&'static ID3D12RootSignature>
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GBINDLESS_ROOT_SIGNATURE)).as_ref();
+    }
+}
+
+// function to bind the bindless heaps and the graphics root signature, the heaps must be set before the root signature
+pub fn bind_graphics(command_list : &ID3D12GraphicsCommandList)
+{
+    unsafe
+    {
+        let resource_heap = (*std::ptr::addr_of!(GRESOURCE_HEAP)).as_ref().map(|x| x.heap.clone());
+        let sampler_heap = (*std::ptr::addr_of!(GSAMPLER_HEAP)).as_ref().map(|x| x.heap.clone());
+        command_list.SetDescriptorHeaps(&[resource_heap, sampler_heap]);
+        command_list.SetGraphicsRootSignature(get_root_signature());
+    }
+}
+
+// function to load dxcompiler.dll and create the compiler on first use
+fn get_dxc_compiler() -> std::result::Result<&'static DxcCompiler, String>
+{
+    unsafe
+    {
+        if GDXC_COMPILER.is_none()
+        {
+            let module = LoadLibraryW(w!("dxcompiler.dll")).map_err(|_| "dxcompiler.dll is not found".to_string())?;
+            let create_instance : DxcCreateInstanceProc = mem::transmute(GetProcAddress(module, s!("DxcCreateInstance")));
+            let create_instance = create_instance.ok_or("DxcCreateInstance is not found in dxcompiler.dll")?;
+
+            let mut utils : *mut c_void = std::ptr::null_mut();
+            let mut compiler : *mut c_void = std::ptr::null_mut();
+            create_instance(&CLSID_DxcUtils, &IDxcUtils::IID, &mut utils).ok().map_err(|x| x.message())?;
+            let utils = IDxcUtils::from_raw(utils);
+            create_instance(&CLSID_DxcCompiler, &IDxcCompiler3::IID, &mut compiler).ok().map_err(|x| x.message())?;
+            let compiler = IDxcCompiler3::from_raw(compiler);
+
+            GDXC_COMPILER = Some(DxcCompiler { utils, compiler });
+        }
+
+        return Ok((*std::ptr::addr_of!(GDXC_COMPILER)).as_ref().unwrap());
+    }
+}
+
+// function to compile a shader file with DXC, e.g. profile "ps_6_6". the compile errors are returned on failure.
+pub fn compile_shader(path : &Path, entry_point : &str, profile : &str) -> std::result::Result<IDxcBlob, String>
+{
+    unsafe
+    {
+        let dxc = get_dxc_compiler()?;
+        let shader_path = fs::canonicalize(path).map_err(|x| format!("{}: {}", path.display(), x))?;
+        let source = dxc.utils.LoadFile(&HSTRING::from(shader_path.as_path()), None).map_err(|x| x.message())?;
+        let source_buffer = DxcBuffer
+        {
+            Ptr : source.GetBufferPointer(),
+            Size : source.GetBufferSize(),
+            Encoding : DXC_CP_ACP.0,
+        };
+
+        // the file name goes first so #include is resolved relative to it
+        let shader_file_name = HSTRING::from(shader_path.as_path());
+        let entry_point = HSTRING::from(entry_point);
+        let profile = HSTRING::from(profile);
+        let mut arguments = vec![PCWSTR(shader_file_name.as_ptr()), w!("-E"), PCWSTR(entry_point.as_ptr()), w!("-T"), PCWSTR(profile.as_ptr())];
+        // debug builds keep the shaders debuggable, release builds get the optimized ones
+        if cfg!(debug_assertions)
+        {
+            arguments.extend_from_slice(&[DXC_ARG_DEBUG, DXC_ARG_SKIP_OPTIMIZATIONS]);
+        }
+        let include_handler = dxc.utils.CreateDefaultIncludeHandler().map_err(|x| x.message())?;
+
+        let result = dxc.compiler.Compile::<_, IDxcResult>(&source_buffer, Some(arguments.as_slice()), &include_handler).map_err(|x| x.message())?;
+        let status = result.GetStatus().map_err(|x| x.message())?;
+        if status.is_err()
+        {
+            let message = match result.GetErrorBuffer()
+            {
+                Ok(x) => String::from_utf8_lossy(std::slice::from_raw_parts(x.GetBufferPointer() as *const u8, x.GetBufferSize())).trim_end_matches('\0').trim().to_string(),
+                Err(_) => status.message(),
+            };
+            return Err(message);
+        }
+
+        return result.GetResult().map_err(|x| x.message());
+    }
+}
+
+// function to create the bindless blit pipeline, the texture and sampler indices are the root constants 0 and 1
+pub fn create_blit_pipeline() -> bool
+{
+    unsafe
+    {
+        if !is_enabled()
+        {
+            return false;
+        }
+
+        let shader_path = PathBuf::from("./shaders/bindless_blit.hlsl");
+        let blobs = compile_shader(&shader_path, "BlitVS", "vs_6_6").and_then(|vs| compile_shader(&shader_path, "BlitPS", "ps_6_6").map(|ps| (vs, ps)));
+        let (vs_blob, ps_blob) = match blobs
+        {
+            Ok(x) => x,
+            Err(x) =>
+            {
+                println!("Error during bindless blit shader creation! {}", x);
+                return false;
+            }
+        };
+
+        let render_target_blend_desc = D3D12_RENDER_TARGET_BLEND_DESC
+        {
+            RenderTargetWriteMask : D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8,
+            ..D3D12_RENDER_TARGET_BLEND_DESC::default()
+        };
+
+        let mut rtv_format_list = [DXGI_FORMAT_UNKNOWN; 8];
+        rtv_format_list[0] = graphic_device::get_back_buffer_format();
+
+        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC
+        {
+            pRootSignature : ManuallyDrop::new(GBINDLESS_ROOT_SIGNATURE.clone()),
+            VS : D3D12_SHADER_BYTECODE
+            {
+                pShaderBytecode : vs_blob.GetBufferPointer(),
+                BytecodeLength : vs_blob.GetBufferSize(),
+            },
+            PS : D3D12_SHADER_BYTECODE
+            {
+                pShaderBytecode : ps_blob.GetBufferPointer(),
+                BytecodeLength : ps_blob.GetBufferSize(),
+            },
+            RasterizerState : D3D12_RASTERIZER_DESC
+            {
+                FillMode : D3D12_FILL_MODE_SOLID,
+                CullMode : D3D12_CULL_MODE_NONE,
+                ..D3D12_RASTERIZER_DESC::default()
+            },
+            BlendState : D3D12_BLEND_DESC
+            {
+                RenderTarget : [render_target_blend_desc; 8],
+                ..D3D12_BLEND_DESC::default()
+            },
+            DepthStencilState : D3D12_DEPTH_STENCIL_DESC::default(),
+            SampleMask : u32::MAX,
+            PrimitiveTopologyType : D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets : 1,
+            RTVFormats : rtv_format_list,
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+
+        GBLIT_STATE = graphic_device::get_device().CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc).ok();
+        if GBLIT_STATE.is_none()
+        {
+            println!("Error during bindless blit pipeline state creation!");
+            return false;
+        }
+
+        return true;
+    }
+}
+
+pub fn is_blit_ready() -> bool
+{
+    unsafe
+    {
+        return GBLIT_STATE.is_some();
+    }
+}
+
+// function to draw a registered texture into a rect of the output target with the linear clamp sampler.
+// the area outside of the rect is cleared to black, the same as render_scale::blit_texture().
+pub fn blit_texture(source : &ID3D12Resource, texture : &DescriptorHandle, dest : &RenderTarget, dest_rect : &RECT)
+{
+    unsafe
+    {
+        let (blit_state, sampler) = match ((*std::ptr::addr_of!(GBLIT_STATE)).as_ref(), GLINEAR_CLAMP_SAMPLER)
+        {
+            (Some(x), Some(y)) => (x, y),
+            _ => return,
+        };
+
+        let command_list = graphic_device::get_command_list();
+        let _ = command_list.Reset(graphic_device::get_command_allocator(), None);
+
+        let dest_resource = dest.resource.as_ref().unwrap();
+        let mut resource_states = CommandListStates::new();
+        resource_states.use_as_shader_resource(source);
+        resource_states.use_as_render_target(dest_resource);
+        resource_states.flush(command_list);
+        command_list.OMSetRenderTargets(1, Some(&dest.rtv), FALSE, None);
+
+        let covers_target = dest_rect.left <= 0 && dest_rect.top <= 0 && dest_rect.right >= dest.width as i32 && dest_rect.bottom >= dest.height as i32;
+        if !covers_target
+        {
+            command_list.ClearRenderTargetView(dest.rtv, &[0.0, 0.0, 0.0, 1.0], None);
+        }
+
+        command_list.SetPipelineState(blit_state);
+        bind_graphics(command_list);
+
+        // GTextureIndex and GSamplerIndex of bindless_blit.hlsl
+        let blit_indices : [u32; 2] = [texture.index, sampler.index];
+        command_list.SetGraphicsRoot32BitConstants(0, 2, blit_indices.as_ptr() as *const c_void, 0);
+
+        let viewport_desc = D3D12_VIEWPORT
+        {
+            Width : (dest_rect.right - dest_rect.left) as f32,
+            Height : (dest_rect.bottom - dest_rect.top) as f32,
+            MinDepth : 0.0,
+            MaxDepth : 1.0,
+            TopLeftX : dest_rect.left as f32,
+            TopLeftY : dest_rect.top as f32,
+        };
+        command_list.RSSetViewports(&[viewport_desc; 1]);
+        command_list.RSSetScissorRects(&[*dest_rect; 1]);
+
+        // full screen triangle
+        command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        command_list.DrawInstanced(3, 1, 0, 0);
+
+        // back to the resting state, e.g. present state for back buffer
+        resource_states.require(dest_resource, None, dest.resting_state);
+        resource_states.execute(command_list);
+    }
+}
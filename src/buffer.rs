@@ -0,0 +1,529 @@
+// buffer.rs - Typed GPU buffers: vertex, index, constant, structured and raw buffers in upload, default or readback heaps.
+// T is the element type, it must be plain data with a C layout (#[repr(C)]) since it's copied to the GPU byte by byte.
+// views are created on demand into descriptors owned by the caller, a buffer doesn't own any descriptor heap.
+// every buffer is registered for state tracking, its transitions are recorded through a CommandListStates.
+
+use windows::Win32::Graphics::Direct3D12::*;
+use windows::Win32::Graphics::Dxgi::Common::*;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+
+use crate::gpu_memory;
+use crate::gpu_memory::GpuAllocation;
+use crate::graphic_device;
+use crate::resource_states;
+use crate::resource_states::CommandListStates;
+
+// what the buffer is bound as, it decides the element stride and the views it can create
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferKind
+{
+    Vertex,
+    // T must be u16 or u32
+    Index,
+    // every element is padded to D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT (256 bytes), so each one can be bound as a CBV
+    Constant,
+    // StructuredBuffer<T> / RWStructuredBuffer<T>
+    Structured,
+    // ByteAddressBuffer / RWByteAddressBuffer, the size must be a multiple of 4 bytes
+    Raw,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferHeap
+{
+    // CPU writable and persistently mapped, it's read by the GPU over PCIe on discrete GPUs
+    Upload,
+    // GPU memory, the data goes through a temporary upload buffer
+    Default,
+    // CPU readable, filled by copy_from()
+    Readback,
+}
+
+pub struct Buffer<T : Copy>
+{
+    resource : ID3D12Resource,
+    // the heap memory behind the resource, it's declared after it so it's released after it
+    _memory : GpuAllocation,
+    kind : BufferKind,
+    heap : BufferHeap,
+    element_count : u32,
+    // bytes between two elements, larger than size_of::<T>() for constant buffers
+    element_stride : u32,
+    // the persistent mapping of upload buffers, null for the other heaps
+    mapped_data : *mut u8,
+    _element : PhantomData<T>,
+}
+
+fn align_up(value : u64, alignment : u64) -> u64
+{
+    return value.div_ceil(alignment) * alignment;
+}
+
+// function to get the stride of an element of given kind
+fn get_element_stride<T>(kind : BufferKind) -> u32
+{
+    let size = std::mem::size_of::<T>() as u64;
+    return match kind
+    {
+        BufferKind::Constant => align_up(size, D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as u64) as u32,
+        _ => size as u32,
+    };
+}
+
+fn get_buffer_desc(size_in_bytes : u64, flags : D3D12_RESOURCE_FLAGS) -> D3D12_RESOURCE_DESC
+{
+    return D3D12_RESOURCE_DESC
+    {
+        Dimension : D3D12_RESOURCE_DIMENSION_BUFFER,
+        Width : size_in_bytes,
+        Height : 1,
+        DepthOrArraySize : 1,
+        MipLevels : 1,
+        Format : DXGI_FORMAT_UNKNOWN,
+        SampleDesc : DXGI_SAMPLE_DESC
+        {
+            Count : 1,
+            Quality : 0,
+        },
+        Layout : D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        Flags : flags,
+        ..D3D12_RESOURCE_DESC::default()
+    };
+}
+
+// function to create a committed buffer resource
+pub fn create_buffer_resource(size_in_bytes : u64, heap_type : D3D12_HEAP_TYPE, flags : D3D12_RESOURCE_FLAGS, initial_state : D3D12_RESOURCE_STATES) -> Option<ID3D12Resource>
+{
+    unsafe
+    {
+        let buffer_desc = get_buffer_desc(size_in_bytes, flags);
+
+        let heap_properties = D3D12_HEAP_PROPERTIES
+        {
+            Type : heap_type,
+            ..D3D12_HEAP_PROPERTIES::default()
+        };
+
+        let mut resource : Option<ID3D12Resource> = None;
+        if graphic_device::get_device().CreateCommittedResource(&heap_properties, D3D12_HEAP_FLAG_NONE, &buffer_desc, initial_state, None, &mut resource).is_err()
+        {
+            println!("Error during buffer creation! {} bytes", size_in_bytes);
+            return None;
+        }
+
+        return resource;
+    }
+}
+
+impl<T : Copy> Buffer<T>
+{
+    // function to create a buffer of element_count elements, the content is undefined.
+    // structured and raw buffers in the default heap also allow unordered access.
+    pub fn new(kind : BufferKind, heap : BufferHeap, element_count : u32) -> Option<Buffer<T>>
+    {
+        let element_size = std::mem::size_of::<T>();
+        if element_count == 0 || element_size == 0
+        {
+            println!("Error during buffer creation! A buffer needs at least one element with a size.");
+            return None;
+        }
+        if kind == BufferKind::Index && element_size != 2 && element_size != 4
+        {
+            println!("Error during buffer creation! Index buffers need u16 or u32 elements.");
+            return None;
+        }
+        if kind == BufferKind::Raw && (element_size * element_count as usize) % 4 != 0
+        {
+            println!("Error during buffer creation! The size of a raw buffer must be a multiple of 4 bytes.");
+            return None;
+        }
+
+        let element_stride = get_element_stride::<T>(kind);
+        let size_in_bytes = element_stride as u64 * element_count as u64;
+
+        // upload and readback heaps require these states for their whole life. buffers in the default heap are always created in COMMON,
+        // the state tracking transitions them to the state of their first use.
+        let (heap_type, state) = match heap
+        {
+            BufferHeap::Upload => (D3D12_HEAP_TYPE_UPLOAD, D3D12_RESOURCE_STATE_GENERIC_READ),
+            BufferHeap::Default => (D3D12_HEAP_TYPE_DEFAULT, D3D12_RESOURCE_STATE_COMMON),
+            BufferHeap::Readback => (D3D12_HEAP_TYPE_READBACK, D3D12_RESOURCE_STATE_COPY_DEST),
+        };
+        let flags = match (heap, kind)
+        {
+            (BufferHeap::Default, BufferKind::Structured | BufferKind::Raw) => D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+            _ => D3D12_RESOURCE_FLAG_NONE,
+        };
+
+        // placed in the pooled heaps, so small buffers don't each cost a committed allocation
+        let (resource, memory) = match gpu_memory::create_resource(heap_type, &get_buffer_desc(size_in_bytes, flags), state, None)
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("Error during buffer creation! {} bytes", size_in_bytes);
+                return None;
+            }
+        };
+
+        // upload buffers stay mapped, the CPU writes go straight to the GPU visible memory
+        let mut mapped_data : *mut c_void = std::ptr::null_mut();
+        if heap == BufferHeap::Upload
+        {
+            unsafe
+            {
+                if resource.Map(0, None, Some(&mut mapped_data)).is_err()
+                {
+                    println!("Error during upload buffer mapping!");
+                    return None;
+                }
+            }
+        }
+
+        resource_states::register_resource(&resource, state);
+
+        return Some(Buffer
+        {
+            resource,
+            _memory : memory,
+            kind,
+            heap,
+            element_count,
+            element_stride,
+            mapped_data : mapped_data as *mut u8,
+            _element : PhantomData,
+        });
+    }
+
+    // function to create a buffer with its initial data. default heap buffers are filled through a temporary upload buffer
+    // and wait for the GPU, so it's meant for loading and not for every frame.
+    pub fn new_with_data(kind : BufferKind, heap : BufferHeap, data : &[T]) -> Option<Buffer<T>>
+    {
+        if heap == BufferHeap::Readback
+        {
+            println!("Error during buffer creation! Readback buffers are filled by the GPU.");
+            return None;
+        }
+
+        let mut buffer = Buffer::<T>::new(kind, heap, data.len() as u32)?;
+        match heap
+        {
+            BufferHeap::Upload => buffer.write(0, data),
+            _ =>
+            {
+                let staging = Buffer::<T>::new_with_data(kind, BufferHeap::Upload, data)?;
+                buffer.copy_from(&staging);
+            }
+        }
+
+        return Some(buffer);
+    }
+
+    // function to write elements from first_element on, only for upload buffers.
+    // the GPU may still read the old data of the previous frame, the caller is responsible for not overwriting it in flight.
+    pub fn write(&mut self, first_element : u32, data : &[T])
+    {
+        if self.mapped_data.is_null()
+        {
+            println!("Only upload buffers can be written by the CPU!");
+            return;
+        }
+        if first_element as usize + data.len() > self.element_count as usize
+        {
+            println!("Buffer write out of range! {} elements from {} in a buffer of {}", data.len(), first_element, self.element_count);
+            return;
+        }
+
+        unsafe
+        {
+            let element_size = std::mem::size_of::<T>();
+            for (idx, x) in data.iter().enumerate()
+            {
+                let dest = self.mapped_data.add((first_element as usize + idx) * self.element_stride as usize);
+                std::ptr::copy_nonoverlapping(x as *const T as *const u8, dest, element_size);
+            }
+        }
+    }
+
+    // function to copy the whole content of another buffer with the same element type and count, it waits for the GPU.
+    // e.g. fill a default buffer from an upload buffer, or copy a default buffer to a readback buffer.
+    // default heap buffers are left in the copy states, the next list using them transitions them.
+    pub fn copy_from(&mut self, source : &Buffer<T>)
+    {
+        if source.element_count != self.element_count || source.element_stride != self.element_stride
+        {
+            println!("Buffer copy needs the same element count and stride!");
+            return;
+        }
+        if source.heap == BufferHeap::Readback || self.heap == BufferHeap::Upload
+        {
+            println!("Buffer copy needs a source that isn't a readback buffer and a dest that isn't an upload buffer!");
+            return;
+        }
+
+        unsafe
+        {
+            let command_list = graphic_device::get_command_list();
+            let _ = command_list.Reset(graphic_device::get_command_allocator(), None);
+
+            // upload buffers stay in GENERIC_READ, which includes COPY_SOURCE. readback buffers are always in COPY_DEST
+            let mut resource_states = CommandListStates::new();
+            match source.heap
+            {
+                BufferHeap::Upload => resource_states.require(&source.resource, None, D3D12_RESOURCE_STATE_GENERIC_READ),
+                _ => resource_states.use_as_copy_source(&source.resource),
+            }
+            resource_states.use_as_copy_dest(&self.resource);
+            resource_states.flush(command_list);
+
+            command_list.CopyBufferRegion(&self.resource, 0, &source.resource, 0, self.get_size_in_bytes());
+
+            resource_states.execute(command_list);
+            graphic_device::wait_for_gpu();
+        }
+    }
+
+    // function to read the elements of a readback buffer, after copy_from() finished
+    pub fn read(&self) -> Option<Vec<T>>
+    {
+        if self.heap != BufferHeap::Readback
+        {
+            println!("Only readback buffers can be read by the CPU!");
+            return None;
+        }
+
+        unsafe
+        {
+            let mut mapped_data : *mut c_void = std::ptr::null_mut();
+            if self.resource.Map(0, None, Some(&mut mapped_data)).is_err()
+            {
+                println!("Error during readback buffer mapping!");
+                return None;
+            }
+
+            let mut elements : Vec<T> = Vec::with_capacity(self.element_count as usize);
+            for idx in 0..self.element_count as usize
+            {
+                let source = (mapped_data as *const u8).add(idx * self.element_stride as usize) as *const T;
+                elements.push(std::ptr::read_unaligned(source));
+            }
+
+            // nothing is written by CPU, pass an empty range
+            let written_range = D3D12_RANGE::default();
+            self.resource.Unmap(0, Some(&written_range));
+
+            return Some(elements);
+        }
+    }
+
+    // function to use a default heap buffer in a state from now on in the list of resource_states, e.g. UNORDERED_ACCESS
+    // before a compute pass. the transition is recorded at the next flush, the other heaps keep their state.
+    pub fn transition(&self, resource_states : &mut CommandListStates, state_after : D3D12_RESOURCE_STATES)
+    {
+        if self.heap != BufferHeap::Default
+        {
+            return;
+        }
+
+        resource_states.require(&self.resource, None, state_after);
+    }
+
+    // function to get the vertex buffer view, None if it isn't a vertex buffer
+    pub fn get_vertex_buffer_view(&self) -> Option<D3D12_VERTEX_BUFFER_VIEW>
+    {
+        if self.kind != BufferKind::Vertex
+        {
+            return None;
+        }
+
+        return Some(D3D12_VERTEX_BUFFER_VIEW
+        {
+            BufferLocation : self.get_gpu_address(0),
+            SizeInBytes : self.get_size_in_bytes() as u32,
+            StrideInBytes : self.element_stride,
+        });
+    }
+
+    // function to get the index buffer view, the format comes from the element size
+    pub fn get_index_buffer_view(&self) -> Option<D3D12_INDEX_BUFFER_VIEW>
+    {
+        if self.kind != BufferKind::Index
+        {
+            return None;
+        }
+
+        return Some(D3D12_INDEX_BUFFER_VIEW
+        {
+            BufferLocation : self.get_gpu_address(0),
+            SizeInBytes : self.get_size_in_bytes() as u32,
+            Format : if self.element_stride == 2 { DXGI_FORMAT_R16_UINT } else { DXGI_FORMAT_R32_UINT },
+        });
+    }
+
+    // function to create a CBV of one element, returns false if it isn't a constant buffer
+    pub fn create_constant_buffer_view(&self, element_index : u32, dest : D3D12_CPU_DESCRIPTOR_HANDLE) -> bool
+    {
+        if self.kind != BufferKind::Constant || element_index >= self.element_count
+        {
+            return false;
+        }
+
+        let cbv_desc = D3D12_CONSTANT_BUFFER_VIEW_DESC
+        {
+            BufferLocation : self.get_gpu_address(element_index),
+            SizeInBytes : self.element_stride,
+        };
+
+        unsafe
+        {
+            graphic_device::get_device().CreateConstantBufferView(Some(&cbv_desc), dest);
+        }
+        return true;
+    }
+
+    // function to create a SRV of the whole buffer, returns false if it isn't a structured or raw buffer
+    pub fn create_shader_resource_view(&self, dest : D3D12_CPU_DESCRIPTOR_HANDLE) -> bool
+    {
+        let (format, first_element, element_count, stride, flags) = match self.get_view_layout()
+        {
+            Some(x) => x,
+            None => return false,
+        };
+
+        let srv_desc = D3D12_SHADER_RESOURCE_VIEW_DESC
+        {
+            Format : format,
+            ViewDimension : D3D12_SRV_DIMENSION_BUFFER,
+            Shader4ComponentMapping : D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+            Anonymous : D3D12_SHADER_RESOURCE_VIEW_DESC_0
+            {
+                Buffer : D3D12_BUFFER_SRV
+                {
+                    FirstElement : first_element,
+                    NumElements : element_count,
+                    StructureByteStride : stride,
+                    Flags : if flags { D3D12_BUFFER_SRV_FLAG_RAW } else { D3D12_BUFFER_SRV_FLAG_NONE },
+                },
+            },
+        };
+
+        self.touch_residency();
+        unsafe
+        {
+            graphic_device::get_device().CreateShaderResourceView(&self.resource, Some(&srv_desc), dest);
+        }
+        return true;
+    }
+
+    // function to create a UAV of the whole buffer, returns false if it isn't a structured or raw buffer in the default heap
+    pub fn create_unordered_access_view(&self, dest : D3D12_CPU_DESCRIPTOR_HANDLE) -> bool
+    {
+        if self.heap != BufferHeap::Default
+        {
+            return false;
+        }
+        let (format, first_element, element_count, stride, flags) = match self.get_view_layout()
+        {
+            Some(x) => x,
+            None => return false,
+        };
+
+        let uav_desc = D3D12_UNORDERED_ACCESS_VIEW_DESC
+        {
+            Format : format,
+            ViewDimension : D3D12_UAV_DIMENSION_BUFFER,
+            Anonymous : D3D12_UNORDERED_ACCESS_VIEW_DESC_0
+            {
+                Buffer : D3D12_BUFFER_UAV
+                {
+                    FirstElement : first_element,
+                    NumElements : element_count,
+                    StructureByteStride : stride,
+                    CounterOffsetInBytes : 0,
+                    Flags : if flags { D3D12_BUFFER_UAV_FLAG_RAW } else { D3D12_BUFFER_UAV_FLAG_NONE },
+                },
+            },
+        };
+
+        self.touch_residency();
+        unsafe
+        {
+            graphic_device::get_device().CreateUnorderedAccessView(&self.resource, None, Some(&uav_desc), dest);
+        }
+        return true;
+    }
+
+    // the format, first element, element count, stride and raw flag of the SRV and UAV.
+    // raw views address 32-bit words, structured views address elements.
+    fn get_view_layout(&self) -> Option<(DXGI_FORMAT, u64, u32, u32, bool)>
+    {
+        return match self.kind
+        {
+            BufferKind::Structured => Some((DXGI_FORMAT_UNKNOWN, 0, self.element_count, self.element_stride, false)),
+            BufferKind::Raw => Some((DXGI_FORMAT_R32_TYPELESS, 0, (self.get_size_in_bytes() / 4) as u32, 0, true)),
+            _ => None,
+        };
+    }
+
+    // function to get the GPU address of an element, e.g. for a root CBV
+    pub fn get_gpu_address(&self, element_index : u32) -> u64
+    {
+        self.touch_residency();
+        unsafe
+        {
+            return self.resource.GetGPUVirtualAddress() + element_index as u64 * self.element_stride as u64;
+        }
+    }
+
+    // function to keep the memory of the buffer resident for the next submission. the views don't declare a state
+    // on a CommandListStates, so they touch it when they're created, a view kept for later frames must touch it again.
+    pub fn touch_residency(&self)
+    {
+        gpu_memory::touch_resources(&[&self.resource]);
+    }
+
+    pub fn get_resource(&self) -> &ID3D12Resource
+    {
+        return &self.resource;
+    }
+
+    pub fn get_kind(&self) -> BufferKind
+    {
+        return self.kind;
+    }
+
+    pub fn get_heap(&self) -> BufferHeap
+    {
+        return self.heap;
+    }
+
+    pub fn get_element_count(&self) -> u32
+    {
+        return self.element_count;
+    }
+
+    pub fn get_element_stride(&self) -> u32
+    {
+        return self.element_stride;
+    }
+
+    pub fn get_size_in_bytes(&self) -> u64
+    {
+        return self.element_stride as u64 * self.element_count as u64;
+    }
+
+    // the state the buffer is left in by the submitted command lists
+    pub fn get_state(&self) -> Option<D3D12_RESOURCE_STATES>
+    {
+        return resource_states::get_resource_state(&self.resource, 0);
+    }
+}
+
+impl<T : Copy> Drop for Buffer<T>
+{
+    fn drop(&mut self)
+    {
+        resource_states::unregister_resource(&self.resource);
+    }
+}
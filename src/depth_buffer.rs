@@ -0,0 +1,284 @@
+// depth_buffer.rs - Depth stencil buffers that go with the render targets, with a DSV and an SRV for post effects.
+// the format and the depth convention are picked once before the targets are created. with reverse-Z the depth is
+// cleared to 0 and the nearer pixels pass with GREATER_EQUAL, the float precision is then spread better over the distance.
+// the resource is created typeless so the same memory can be viewed as depth and as a shader readable float.
+
+use windows::Win32::Graphics::Direct3D12::*;
+use windows::Win32::Graphics::Dxgi::Common::*;
+
+use crate::app_config::DepthFormat;
+use crate::descriptor_allocator::{DescriptorHandle, DescriptorHeapType};
+use crate::descriptor_heap;
+use crate::gpu_memory;
+use crate::gpu_memory::GpuAllocation;
+use crate::graphic_device;
+use crate::msaa;
+use crate::resource_states;
+
+static mut GDEPTH_FORMAT : DepthFormat = DepthFormat::D32Float;
+static mut GREVERSE_Z : bool = false;
+
+// function to pick the depth format, call it before the render targets are created
+pub fn set_depth_format(depth_format : DepthFormat)
+{
+    unsafe
+    {
+        GDEPTH_FORMAT = depth_format;
+    }
+}
+
+pub fn get_depth_format() -> DepthFormat
+{
+    unsafe
+    {
+        return GDEPTH_FORMAT;
+    }
+}
+
+// function to pick reverse-Z, call it before the pipelines are created since the depth test depends on it
+pub fn set_reverse_z(reverse_z : bool)
+{
+    unsafe
+    {
+        GREVERSE_Z = reverse_z;
+    }
+}
+
+pub fn is_reverse_z() -> bool
+{
+    unsafe
+    {
+        return GREVERSE_Z;
+    }
+}
+
+// function to get the formats of a depth format as (resource, DSV, SRV), the SRV only reads the depth plane
+fn get_formats(depth_format : DepthFormat) -> (DXGI_FORMAT, DXGI_FORMAT, DXGI_FORMAT)
+{
+    return match depth_format
+    {
+        DepthFormat::D32Float => (DXGI_FORMAT_R32_TYPELESS, DXGI_FORMAT_D32_FLOAT, DXGI_FORMAT_R32_FLOAT),
+        DepthFormat::D24UnormS8Uint => (DXGI_FORMAT_R24G8_TYPELESS, DXGI_FORMAT_D24_UNORM_S8_UINT, DXGI_FORMAT_R24_UNORM_X8_TYPELESS),
+        DepthFormat::D32FloatS8X24Uint => (DXGI_FORMAT_R32G8X24_TYPELESS, DXGI_FORMAT_D32_FLOAT_S8X24_UINT, DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS),
+    };
+}
+
+// the format for DSVFormat of the pipelines
+pub fn get_dsv_format() -> DXGI_FORMAT
+{
+    return get_formats(get_depth_format()).1;
+}
+
+pub fn has_stencil() -> bool
+{
+    return get_depth_format() != DepthFormat::D32Float;
+}
+
+pub fn get_clear_depth() -> f32
+{
+    return if is_reverse_z() { 0.0 } else { 1.0 };
+}
+
+// function to get the depth test of the current convention, the nearer or equal pixels pass and write their depth
+pub fn get_depth_stencil_desc() -> D3D12_DEPTH_STENCIL_DESC
+{
+    return D3D12_DEPTH_STENCIL_DESC
+    {
+        DepthEnable : true.into(),
+        DepthWriteMask : D3D12_DEPTH_WRITE_MASK_ALL,
+        DepthFunc : if is_reverse_z() { D3D12_COMPARISON_FUNC_GREATER_EQUAL } else { D3D12_COMPARISON_FUNC_LESS_EQUAL },
+        ..D3D12_DEPTH_STENCIL_DESC::default()
+    };
+}
+
+// a depth buffer, it rests in DEPTH_WRITE state. the SRV is staged in the CPU heap like the other SRVs,
+// read it in DEPTH_READ | PIXEL_SHADER_RESOURCE | NON_PIXEL_SHADER_RESOURCE and return it to DEPTH_WRITE afterwards.
+pub struct DepthBuffer
+{
+    resource : ID3D12Resource,
+    // it's declared after the resource so it's released after it
+    _memory : GpuAllocation,
+    dsv : DescriptorHandle,
+    srv : DescriptorHandle,
+    width : u32,
+    height : u32,
+}
+
+// function to create a depth buffer with the current depth format and the MSAA sample count
+pub fn create_depth_buffer(width : u32, height : u32) -> Option<DepthBuffer>
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        let (resource_format, dsv_format, srv_format) = get_formats(get_depth_format());
+
+        let texture_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width : width as u64,
+            Height : height,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : resource_format,
+            // it's drawn together with the scene color, so it has the same samples
+            SampleDesc : msaa::get_sample_desc(),
+            Layout : D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags : D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        // clears with the optimized value are the fast ones
+        let clear_value = D3D12_CLEAR_VALUE
+        {
+            Format : dsv_format,
+            Anonymous : D3D12_CLEAR_VALUE_0
+            {
+                DepthStencil : D3D12_DEPTH_STENCIL_VALUE
+                {
+                    Depth : get_clear_depth(),
+                    Stencil : 0,
+                },
+            },
+        };
+
+        let (resource, memory) = match gpu_memory::create_resource(D3D12_HEAP_TYPE_DEFAULT, &texture_desc, D3D12_RESOURCE_STATE_DEPTH_WRITE, Some(&clear_value))
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("Error during depth buffer creation!");
+                return None;
+            }
+        };
+
+        let dsv = descriptor_heap::allocate(DescriptorHeapType::Dsv, 1)?;
+        let dsv_desc = if msaa::is_enabled()
+        {
+            D3D12_DEPTH_STENCIL_VIEW_DESC
+            {
+                Format : dsv_format,
+                ViewDimension : D3D12_DSV_DIMENSION_TEXTURE2DMS,
+                Flags : D3D12_DSV_FLAG_NONE,
+                Anonymous : D3D12_DEPTH_STENCIL_VIEW_DESC_0
+                {
+                    Texture2DMS : D3D12_TEX2DMS_DSV::default(),
+                },
+            }
+        }
+        else
+        {
+            D3D12_DEPTH_STENCIL_VIEW_DESC
+            {
+                Format : dsv_format,
+                ViewDimension : D3D12_DSV_DIMENSION_TEXTURE2D,
+                Flags : D3D12_DSV_FLAG_NONE,
+                Anonymous : D3D12_DEPTH_STENCIL_VIEW_DESC_0
+                {
+                    Texture2D : D3D12_TEX2D_DSV { MipSlice : 0 },
+                },
+            }
+        };
+        device.CreateDepthStencilView(&resource, Some(&dsv_desc), descriptor_heap::get_cpu_handle(&dsv, 0));
+
+        let srv = match descriptor_heap::allocate(DescriptorHeapType::CbvSrvUav, 1)
+        {
+            Some(x) => x,
+            None =>
+            {
+                descriptor_heap::free(&dsv);
+                return None;
+            }
+        };
+        // a multisampled depth is read per sample with Texture2DMS in the shaders
+        let srv_desc = if msaa::is_enabled()
+        {
+            D3D12_SHADER_RESOURCE_VIEW_DESC
+            {
+                Format : srv_format,
+                ViewDimension : D3D12_SRV_DIMENSION_TEXTURE2DMS,
+                Shader4ComponentMapping : D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+                Anonymous : D3D12_SHADER_RESOURCE_VIEW_DESC_0
+                {
+                    Texture2DMS : D3D12_TEX2DMS_SRV::default(),
+                },
+            }
+        }
+        else
+        {
+            D3D12_SHADER_RESOURCE_VIEW_DESC
+            {
+                Format : srv_format,
+                ViewDimension : D3D12_SRV_DIMENSION_TEXTURE2D,
+                Shader4ComponentMapping : D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
+                Anonymous : D3D12_SHADER_RESOURCE_VIEW_DESC_0
+                {
+                    Texture2D : D3D12_TEX2D_SRV
+                    {
+                        MipLevels : 1,
+                        ..D3D12_TEX2D_SRV::default()
+                    },
+                },
+            }
+        };
+        device.CreateShaderResourceView(&resource, Some(&srv_desc), descriptor_heap::get_cpu_handle(&srv, 0));
+        resource_states::register_resource(&resource, D3D12_RESOURCE_STATE_DEPTH_WRITE);
+
+        return Some(DepthBuffer
+        {
+            resource,
+            _memory : memory,
+            dsv,
+            srv,
+            width,
+            height,
+        });
+    }
+}
+
+impl Drop for DepthBuffer
+{
+    fn drop(&mut self)
+    {
+        resource_states::unregister_resource(&self.resource);
+        descriptor_heap::free(&self.dsv);
+        descriptor_heap::free(&self.srv);
+    }
+}
+
+impl DepthBuffer
+{
+    pub fn get_resource(&self) -> &ID3D12Resource
+    {
+        return &self.resource;
+    }
+
+    pub fn get_dsv(&self) -> D3D12_CPU_DESCRIPTOR_HANDLE
+    {
+        return descriptor_heap::get_cpu_handle(&self.dsv, 0);
+    }
+
+    pub fn get_srv(&self) -> &DescriptorHandle
+    {
+        return &self.srv;
+    }
+
+    pub fn get_width(&self) -> u32
+    {
+        return self.width;
+    }
+
+    pub fn get_height(&self) -> u32
+    {
+        return self.height;
+    }
+}
+
+// function to clear a depth stencil view to the clear depth of the convention and stencil 0
+pub fn clear(command_list : &ID3D12GraphicsCommandList, dsv : D3D12_CPU_DESCRIPTOR_HANDLE)
+{
+    unsafe
+    {
+        let clear_flags = if has_stencil() { D3D12_CLEAR_FLAG_DEPTH | D3D12_CLEAR_FLAG_STENCIL } else { D3D12_CLEAR_FLAG_DEPTH };
+        command_list.ClearDepthStencilView(dsv, clear_flags, get_clear_depth(), 0, &[]);
+    }
+}
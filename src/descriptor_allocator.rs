@@ -0,0 +1,321 @@
+// descriptor_allocator.rs - The free list bookkeeping behind the CPU descriptor heaps of descriptor_heap.rs
+
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DescriptorHeapType
+{
+    Rtv,
+    Dsv,
+    CbvSrvUav,
+    Sampler,
+}
+
+pub const GDESCRIPTOR_HEAP_TYPES : [DescriptorHeapType; 4] = [DescriptorHeapType::Rtv, DescriptorHeapType::Dsv, DescriptorHeapType::CbvSrvUav, DescriptorHeapType::Sampler];
+
+// a range of descriptors in a CPU heap, count descriptors from index on
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct DescriptorHandle
+{
+    pub heap_type : DescriptorHeapType,
+    pub index : u32,
+    pub count : u32,
+    // the generation of the first slot when it was allocated, it changes when the range is freed
+    pub generation : u32,
+}
+
+// a free list over the slots of one heap, ranges are allocated first fit and merged with their neighbours when freed
+pub struct FreeListAllocator
+{
+    heap_type : DescriptorHeapType,
+    capacity : u32,
+    // free ranges as (start, count), sorted by start and never adjacent to each other
+    free_ranges : Vec<(u32, u32)>,
+    // generation of every slot, only the one of the first slot of a range is checked
+    generations : Vec<u32>,
+    // the allocated ranges by their first slot, to catch a handle with the wrong count
+    allocated_counts : Vec<u32>,
+}
+
+impl FreeListAllocator
+{
+    pub fn new(heap_type : DescriptorHeapType, capacity : u32) -> FreeListAllocator
+    {
+        return FreeListAllocator
+        {
+            heap_type,
+            capacity,
+            free_ranges : if capacity > 0 { vec![(0, capacity)] } else { Vec::new() },
+            generations : vec![0; capacity as usize],
+            allocated_counts : vec![0; capacity as usize],
+        };
+    }
+
+    // function to allocate count contiguous descriptors, None if there is no free range that large
+    pub fn allocate(&mut self, count : u32) -> Option<DescriptorHandle>
+    {
+        if count == 0
+        {
+            return None;
+        }
+
+        let range_index = self.free_ranges.iter().position(|x| x.1 >= count)?;
+        let (start, free_count) = self.free_ranges[range_index];
+        if free_count == count
+        {
+            self.free_ranges.remove(range_index);
+        }
+        else
+        {
+            self.free_ranges[range_index] = (start + count, free_count - count);
+        }
+
+        self.allocated_counts[start as usize] = count;
+        return Some(DescriptorHandle
+        {
+            heap_type : self.heap_type,
+            index : start,
+            count,
+            generation : self.generations[start as usize],
+        });
+    }
+
+    // function to check that a handle is a live allocation of this heap
+    pub fn validate(&self, handle : &DescriptorHandle) -> Result<(), String>
+    {
+        if handle.heap_type != self.heap_type
+        {
+            return Err(format!("{:?} handle used with the {:?} heap", handle.heap_type, self.heap_type));
+        }
+        if handle.count == 0 || handle.index as u64 + handle.count as u64 > self.capacity as u64
+        {
+            return Err(format!("{:?} handle {}..{} is out of the heap of {}", handle.heap_type, handle.index, handle.index as u64 + handle.count as u64, self.capacity));
+        }
+        if self.generations[handle.index as usize] != handle.generation || self.allocated_counts[handle.index as usize] != handle.count
+        {
+            return Err(format!("stale {:?} handle {} (generation {}), it was freed already", handle.heap_type, handle.index, handle.generation));
+        }
+        return Ok(());
+    }
+
+    // function to release a range, the handle and its copies are stale afterwards
+    pub fn free(&mut self, handle : &DescriptorHandle) -> Result<(), String>
+    {
+        self.validate(handle)?;
+
+        self.generations[handle.index as usize] = self.generations[handle.index as usize].wrapping_add(1);
+        self.allocated_counts[handle.index as usize] = 0;
+
+        // insert sorted, then merge with the range after and before
+        let start = handle.index;
+        let end = handle.index + handle.count;
+        let insert_index = self.free_ranges.partition_point(|x| x.0 < start);
+        self.free_ranges.insert(insert_index, (start, handle.count));
+
+        if insert_index + 1 < self.free_ranges.len() && self.free_ranges[insert_index + 1].0 == end
+        {
+            self.free_ranges[insert_index].1 = self.free_ranges[insert_index].1 + self.free_ranges[insert_index + 1].1;
+            self.free_ranges.remove(insert_index + 1);
+        }
+        if insert_index > 0 && self.free_ranges[insert_index - 1].0 + self.free_ranges[insert_index - 1].1 == start
+        {
+            self.free_ranges[insert_index - 1].1 = self.free_ranges[insert_index - 1].1 + self.free_ranges[insert_index].1;
+            self.free_ranges.remove(insert_index);
+        }
+
+        return Ok(());
+    }
+
+    pub fn get_heap_type(&self) -> DescriptorHeapType
+    {
+        return self.heap_type;
+    }
+
+    pub fn get_capacity(&self) -> u32
+    {
+        return self.capacity;
+    }
+
+    pub fn get_free_count(&self) -> u32
+    {
+        return self.free_ranges.iter().map(|x| x.1).sum();
+    }
+
+    // the largest range that can be allocated right now
+    pub fn get_largest_free_range(&self) -> u32
+    {
+        return self.free_ranges.iter().map(|x| x.1).max().unwrap_or(0);
+    }
+}
+
+// a free list whose released ranges are only reused once the frames that could still reference them are done on the GPU.
+// it keeps the indices stable for bindless access, where a shader reads a slot by its index long after the CPU side moved on.
+pub struct DeferredFreeList
+{
+    allocator : FreeListAllocator,
+    // released handles with the fence value that must be completed before they are free again
+    retired : VecDeque<(u64, DescriptorHandle)>,
+}
+
+impl DeferredFreeList
+{
+    pub fn new(heap_type : DescriptorHeapType, capacity : u32) -> DeferredFreeList
+    {
+        return DeferredFreeList
+        {
+            allocator : FreeListAllocator::new(heap_type, capacity),
+            retired : VecDeque::new(),
+        };
+    }
+
+    pub fn allocate(&mut self, count : u32) -> Option<DescriptorHandle>
+    {
+        return self.allocator.allocate(count);
+    }
+
+    // function to check that a handle is allocated and not released yet
+    pub fn validate(&self, handle : &DescriptorHandle) -> Result<(), String>
+    {
+        self.allocator.validate(handle)?;
+        if self.retired.iter().any(|x| x.1.index == handle.index)
+        {
+            return Err(format!("{:?} handle {} is released already", handle.heap_type, handle.index));
+        }
+        return Ok(());
+    }
+
+    // function to release a range, it's free again once fence_value is completed
+    pub fn retire(&mut self, handle : &DescriptorHandle, fence_value : u64) -> Result<(), String>
+    {
+        self.validate(handle)?;
+        self.retired.push_back((fence_value, *handle));
+        return Ok(());
+    }
+
+    // function to free the retired ranges whose fence value is completed, they are retired in fence order
+    pub fn reclaim(&mut self, completed_fence_value : u64)
+    {
+        while let Some((fence_value, handle)) = self.retired.front().copied()
+        {
+            if fence_value > completed_fence_value
+            {
+                break;
+            }
+            // validated when it was retired
+            let _ = self.allocator.free(&handle);
+            self.retired.pop_front();
+        }
+    }
+
+    pub fn get_free_count(&self) -> u32
+    {
+        return self.allocator.get_free_count();
+    }
+
+    // the number of ranges waiting for the GPU
+    pub fn get_retired_count(&self) -> usize
+    {
+        return self.retired.len();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn free_list_detects_stale_and_foreign_handles()
+    {
+        let mut allocator = FreeListAllocator::new(DescriptorHeapType::CbvSrvUav, 16);
+        let handle = allocator.allocate(4).unwrap();
+        assert!(allocator.validate(&handle).is_ok());
+
+        // same slot, but from another heap type
+        let foreign = DescriptorHandle { heap_type : DescriptorHeapType::Sampler, ..handle };
+        assert!(allocator.validate(&foreign).is_err());
+        assert!(allocator.free(&foreign).is_err());
+
+        // the wrong count is caught as well
+        let wrong_count = DescriptorHandle { count : 3, ..handle };
+        assert!(allocator.validate(&wrong_count).is_err());
+
+        assert!(allocator.free(&handle).is_ok());
+        assert!(allocator.free(&handle).is_err());
+
+        // the slot is reused with a new generation, the old copy stays stale
+        let reused = allocator.allocate(4).unwrap();
+        assert_eq!(reused.index, handle.index);
+        assert_ne!(reused.generation, handle.generation);
+        assert!(allocator.validate(&handle).is_err());
+        assert!(allocator.validate(&reused).is_ok());
+    }
+
+    #[test]
+    fn free_list_merges_ranges_on_free()
+    {
+        let mut allocator = FreeListAllocator::new(DescriptorHeapType::Rtv, 12);
+        let first = allocator.allocate(4).unwrap();
+        let second = allocator.allocate(4).unwrap();
+        let third = allocator.allocate(4).unwrap();
+        assert_eq!((first.index, second.index, third.index), (0, 4, 8));
+        assert!(allocator.allocate(1).is_none());
+
+        // freeing the outer ones leaves two ranges of 4, nothing larger fits
+        allocator.free(&first).unwrap();
+        allocator.free(&third).unwrap();
+        assert_eq!(allocator.get_free_count(), 8);
+        assert_eq!(allocator.get_largest_free_range(), 4);
+        assert!(allocator.allocate(5).is_none());
+
+        // the middle one merges with both neighbours
+        allocator.free(&second).unwrap();
+        assert_eq!(allocator.get_largest_free_range(), 12);
+        assert_eq!(allocator.allocate(12).unwrap().index, 0);
+    }
+
+    #[test]
+    fn deferred_free_list_reclaims_by_fence_value()
+    {
+        let mut free_list = DeferredFreeList::new(DescriptorHeapType::CbvSrvUav, 8);
+        let first = free_list.allocate(2).unwrap();
+        let second = free_list.allocate(2).unwrap();
+        free_list.retire(&first, 5).unwrap();
+        free_list.retire(&second, 6).unwrap();
+
+        // a retired handle can't be retired twice
+        assert!(free_list.retire(&first, 7).is_err());
+        assert_eq!(free_list.get_retired_count(), 2);
+        assert_eq!(free_list.get_free_count(), 4);
+
+        free_list.reclaim(5);
+        assert_eq!(free_list.get_retired_count(), 1);
+        assert_eq!(free_list.get_free_count(), 6);
+        free_list.reclaim(6);
+        assert_eq!(free_list.get_retired_count(), 0);
+        assert_eq!(free_list.get_free_count(), 8);
+        assert!(free_list.validate(&second).is_err());
+    }
+
+    #[test]
+    fn deferred_free_list_doesnt_reuse_an_index_before_its_fence()
+    {
+        // a full heap, so the only index that can come back is the retired one
+        let mut free_list = DeferredFreeList::new(DescriptorHeapType::CbvSrvUav, 4);
+        let handles : Vec<DescriptorHandle> = (0..4).map(|_| free_list.allocate(1).unwrap()).collect();
+        free_list.retire(&handles[2], 10).unwrap();
+
+        // a shader of a frame in flight may still read the slot, it isn't handed out again
+        assert!(free_list.allocate(1).is_none());
+        free_list.reclaim(9);
+        assert!(free_list.allocate(1).is_none());
+        assert!(free_list.validate(&handles[2]).is_err());
+
+        free_list.reclaim(10);
+        let reused = free_list.allocate(1).unwrap();
+        assert_eq!(reused.index, handles[2].index);
+        assert_ne!(reused.generation, handles[2].generation);
+        assert!(free_list.validate(&handles[2]).is_err());
+        assert!(free_list.validate(&reused).is_ok());
+    }
+}
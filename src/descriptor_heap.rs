@@ -0,0 +1,289 @@
+// descriptor_heap.rs - The descriptor heaps of the app. every heap type has a CPU heap with a free list, views are created there
+// and live as long as their owner. the shader visible CBV_SRV_UAV heap is a ring, the tables of a frame are copied into it
+// from the CPU heap and reused once the frame is done on the GPU. the bookkeeping is in descriptor_allocator.rs and frame_ring.rs.
+
+use windows::Win32::Graphics::Direct3D12::*;
+
+use crate::descriptor_allocator::{DescriptorHandle, DescriptorHeapType, FreeListAllocator, GDESCRIPTOR_HEAP_TYPES};
+use crate::frame_ring::FrameRing;
+use crate::graphic_device;
+
+// a non shader visible heap, it's the staging place of the views
+struct CpuDescriptorHeap
+{
+    // kept alive for the descriptors
+    _heap : ID3D12DescriptorHeap,
+    cpu_start : D3D12_CPU_DESCRIPTOR_HANDLE,
+    descriptor_size : u32,
+    allocator : FreeListAllocator,
+}
+
+// the shader visible heap with the tables of the frames in flight
+struct ShaderVisibleRing
+{
+    heap : ID3D12DescriptorHeap,
+    cpu_start : D3D12_CPU_DESCRIPTOR_HANDLE,
+    gpu_start : D3D12_GPU_DESCRIPTOR_HANDLE,
+    descriptor_size : u32,
+    ring : FrameRing,
+}
+
+// a table in the shader visible heap, valid for the current frame only
+#[derive(Clone, Copy, Debug)]
+pub struct DescriptorTable
+{
+    pub cpu_handle : D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub gpu_handle : D3D12_GPU_DESCRIPTOR_HANDLE,
+    pub count : u32,
+}
+
+// CPU heaps indexed by DescriptorHeapType
+static mut GCPU_HEAPS : Vec<CpuDescriptorHeap> = Vec::new();
+static mut GSHADER_VISIBLE_RING : Option<ShaderVisibleRing> = None;
+
+// the sizes of the CPU heaps, in descriptors
+const GCPU_HEAP_CAPACITIES : [u32; 4] = [256, 64, 4096, 256];
+// the shader visible ring, it holds the tables of all frames in flight
+const GSHADER_VISIBLE_CAPACITY : u32 = 16384;
+
+pub fn get_d3d12_heap_type(heap_type : DescriptorHeapType) -> D3D12_DESCRIPTOR_HEAP_TYPE
+{
+    return match heap_type
+    {
+        DescriptorHeapType::Rtv => D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
+        DescriptorHeapType::Dsv => D3D12_DESCRIPTOR_HEAP_TYPE_DSV,
+        DescriptorHeapType::CbvSrvUav => D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+        DescriptorHeapType::Sampler => D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+    };
+}
+
+fn create_heap(heap_type : DescriptorHeapType, capacity : u32, shader_visible : bool) -> Option<ID3D12DescriptorHeap>
+{
+    unsafe
+    {
+        let heap_desc = D3D12_DESCRIPTOR_HEAP_DESC
+        {
+            NumDescriptors : capacity,
+            Type : get_d3d12_heap_type(heap_type),
+            Flags : if shader_visible { D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE } else { D3D12_DESCRIPTOR_HEAP_FLAG_NONE },
+            ..D3D12_DESCRIPTOR_HEAP_DESC::default()
+        };
+
+        return match graphic_device::get_device().CreateDescriptorHeap::<ID3D12DescriptorHeap>(&heap_desc)
+        {
+            Ok(x) => Some(x),
+            Err(_) =>
+            {
+                println!("Error during {:?} descriptor heap creation!", heap_type);
+                None
+            }
+        };
+    }
+}
+
+// function to create the heaps, it's called by graphic_device::initialize_d3d12()
+pub fn initialize() -> bool
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        let cpu_heaps = &mut *std::ptr::addr_of_mut!(GCPU_HEAPS);
+        cpu_heaps.clear();
+
+        for (idx, x) in GDESCRIPTOR_HEAP_TYPES.iter().enumerate()
+        {
+            let heap = match create_heap(*x, GCPU_HEAP_CAPACITIES[idx], false)
+            {
+                Some(heap) => heap,
+                None => return false,
+            };
+
+            cpu_heaps.push(CpuDescriptorHeap
+            {
+                cpu_start : heap.GetCPUDescriptorHandleForHeapStart(),
+                _heap : heap,
+                descriptor_size : device.GetDescriptorHandleIncrementSize(get_d3d12_heap_type(*x)),
+                allocator : FreeListAllocator::new(*x, GCPU_HEAP_CAPACITIES[idx]),
+            });
+        }
+
+        let heap = match create_heap(DescriptorHeapType::CbvSrvUav, GSHADER_VISIBLE_CAPACITY, true)
+        {
+            Some(x) => x,
+            None => return false,
+        };
+        GSHADER_VISIBLE_RING = Some(ShaderVisibleRing
+        {
+            cpu_start : heap.GetCPUDescriptorHandleForHeapStart(),
+            gpu_start : heap.GetGPUDescriptorHandleForHeapStart(),
+            heap,
+            descriptor_size : device.GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV),
+            ring : FrameRing::new(GSHADER_VISIBLE_CAPACITY as u64),
+        });
+
+        return true;
+    }
+}
+
+// function to release the heaps, the handles that are freed afterwards are ignored
+pub fn shutdown()
+{
+    unsafe
+    {
+        (*std::ptr::addr_of_mut!(GCPU_HEAPS)).clear();
+        GSHADER_VISIBLE_RING = None;
+    }
+}
+
+fn get_cpu_heap(heap_type : DescriptorHeapType) -> Option<&'static mut CpuDescriptorHeap>
+{
+    unsafe
+    {
+        let cpu_heaps = &mut *std::ptr::addr_of_mut!(GCPU_HEAPS);
+        return cpu_heaps.get_mut(heap_type as usize);
+    }
+}
+
+// function to report a misused handle, it's a bug in the caller so debug builds stop right there
+fn report_invalid_handle(error : &str)
+{
+    println!("Invalid descriptor handle! {}", error);
+    debug_assert!(false, "Invalid descriptor handle! {}", error);
+}
+
+// function to allocate count contiguous descriptors in the CPU heap of a type
+pub fn allocate(heap_type : DescriptorHeapType, count : u32) -> Option<DescriptorHandle>
+{
+    let heap = get_cpu_heap(heap_type)?;
+    let handle = heap.allocator.allocate(count);
+    if handle.is_none()
+    {
+        println!("The {:?} descriptor heap is full! {} descriptors requested, {} free", heap_type, count, heap.allocator.get_free_count());
+    }
+    return handle;
+}
+
+// function to release descriptors, the views in them must not be used by the GPU anymore
+pub fn free(handle : &DescriptorHandle)
+{
+    if let Some(heap) = get_cpu_heap(handle.heap_type)
+    {
+        if let Err(x) = heap.allocator.free(handle)
+        {
+            report_invalid_handle(&x);
+        }
+    }
+}
+
+// function to get the CPU handle of the descriptor at offset in the range, the handle is checked in debug builds
+pub fn get_cpu_handle(handle : &DescriptorHandle, offset : u32) -> D3D12_CPU_DESCRIPTOR_HANDLE
+{
+    let heap = get_cpu_heap(handle.heap_type).expect("The descriptor heaps aren't initialized!");
+    if cfg!(debug_assertions)
+    {
+        if let Err(x) = heap.allocator.validate(handle)
+        {
+            report_invalid_handle(&x);
+        }
+        if offset >= handle.count
+        {
+            report_invalid_handle(&format!("offset {} in a range of {}", offset, handle.count));
+        }
+    }
+
+    return D3D12_CPU_DESCRIPTOR_HANDLE
+    {
+        ptr : heap.cpu_start.ptr + ((handle.index + offset) * heap.descriptor_size) as usize,
+    };
+}
+
+pub fn get_descriptor_size(heap_type : DescriptorHeapType) -> u32
+{
+    return get_cpu_heap(heap_type).map(|x| x.descriptor_size).unwrap_or(0);
+}
+
+// function to reclaim the tables of the finished frames, called once per frame before any table is allocated
+pub fn begin_frame()
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GSHADER_VISIBLE_RING)).as_mut()
+        {
+            x.ring.reclaim(graphic_device::get_completed_fence_value());
+        }
+    }
+}
+
+// function to close the tables of this frame, called after the command lists of the frame are executed
+pub fn end_frame()
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GSHADER_VISIBLE_RING)).as_mut()
+        {
+            x.ring.end_frame(graphic_device::get_next_fence_value());
+        }
+    }
+}
+
+// function to allocate a table of count descriptors in the shader visible heap for this frame
+pub fn allocate_table(count : u32) -> Option<DescriptorTable>
+{
+    unsafe
+    {
+        let shader_visible_ring = (*std::ptr::addr_of_mut!(GSHADER_VISIBLE_RING)).as_mut()?;
+        let slot = match shader_visible_ring.ring.allocate(count as u64, 1)
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("The shader visible descriptor heap is full! {} descriptors requested", count);
+                return None;
+            }
+        };
+
+        let offset = slot * shader_visible_ring.descriptor_size as u64;
+        return Some(DescriptorTable
+        {
+            cpu_handle : D3D12_CPU_DESCRIPTOR_HANDLE { ptr : shader_visible_ring.cpu_start.ptr + offset as usize },
+            gpu_handle : D3D12_GPU_DESCRIPTOR_HANDLE { ptr : shader_visible_ring.gpu_start.ptr + offset },
+            count,
+        });
+    }
+}
+
+// function to build a table for this frame from CBV_SRV_UAV ranges of the CPU heap, they are copied in the given order
+pub fn stage_table(ranges : &[DescriptorHandle]) -> Option<DescriptorTable>
+{
+    let count = ranges.iter().map(|x| x.count).sum::<u32>();
+    let table = allocate_table(count)?;
+    let descriptor_size = get_descriptor_size(DescriptorHeapType::CbvSrvUav);
+
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        let mut dest = table.cpu_handle;
+        for x in ranges.iter()
+        {
+            if x.heap_type != DescriptorHeapType::CbvSrvUav
+            {
+                report_invalid_handle(&format!("{:?} handle in a shader visible CBV_SRV_UAV table", x.heap_type));
+                return None;
+            }
+
+            device.CopyDescriptorsSimple(x.count, dest, get_cpu_handle(x, 0), D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
+            dest.ptr = dest.ptr + (x.count * descriptor_size) as usize;
+        }
+    }
+
+    return Some(table);
+}
+
+// the shader visible heap to bind with SetDescriptorHeaps before any table of it is used
+pub fn get_shader_visible_heap() -> Option<&'static ID3D12DescriptorHeap>
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GSHADER_VISIBLE_RING)).as_ref().map(|x| &x.heap);
+    }
+}
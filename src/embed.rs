@@ -0,0 +1,239 @@
+// embed.rs - The library entry point for hosting the renderer inside a window owned by another application (e.g. an editor viewport).
+// the host owns the window and the message loop, it forwards messages to us and asks for frames whenever it wants.
+// the forwarded keyboard and mouse messages feed input.rs, so the host can read the input state and actions between frames.
+
+use windows::Win32::Foundation::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::graphic_device;
+use crate::graphic_device::Surface;
+use crate::hello_world_triangle;
+use crate::input;
+use crate::window;
+use crate::upload_allocator;
+use crate::descriptor_heap;
+
+// a host window we render into
+struct EmbeddedSurface
+{
+    surface : Surface,
+    minimized : bool,
+    occluded : bool,
+}
+
+static mut GEMBED_INITIALIZED : bool = false;
+static mut GEMBED_SURFACES : Vec<EmbeddedSurface> = Vec::new();
+
+// function to find the surface of a host window
+unsafe fn find_surface(h_wnd : HWND) -> Option<&'static mut EmbeddedSurface>
+{
+    return (*std::ptr::addr_of_mut!(GEMBED_SURFACES)).iter_mut().find(|x| x.surface.get_hwnd() == h_wnd);
+}
+
+// function to attach the renderer to a host window, the device and demo resources are created with the first attached window.
+// the swapchain is sized from the client rect of the window, so a child HWND of any size works.
+pub fn attach(h_wnd : HWND) -> bool
+{
+    unsafe
+    {
+        if find_surface(h_wnd).is_some()
+        {
+            return true;
+        }
+
+        // check the host window before anything is created for it
+        if !IsWindow(h_wnd).as_bool()
+        {
+            println!("Invalid host window!");
+            return false;
+        }
+
+        if !GEMBED_INITIALIZED
+        {
+            if !graphic_device::initialize_d3d12() || !upload_allocator::initialize(upload_allocator::GDEFAULT_CAPACITY)
+            {
+                return false;
+            }
+
+            hello_world_triangle::create_pipeline();
+            input::initialize(std::path::Path::new("input_bindings.toml"));
+            GEMBED_INITIALIZED = true;
+        }
+
+        // a swapchain can't be 0 sized, the real size comes with the next WM_SIZE
+        let (width, height) = window::get_client_size(h_wnd);
+        let width = width.max(1);
+        let height = height.max(1);
+        let surface = match graphic_device::create_surface(h_wnd, width, height, true)
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("Error during swapchain creation!");
+                return false;
+            }
+        };
+
+        (*std::ptr::addr_of_mut!(GEMBED_SURFACES)).push(EmbeddedSurface
+        {
+            surface,
+            minimized : false,
+            occluded : false,
+        });
+
+        return true;
+    }
+}
+
+// function to detach the renderer from a host window, must be called before the host destroys it
+pub fn detach(h_wnd : HWND)
+{
+    unsafe
+    {
+        if find_surface(h_wnd).is_some()
+        {
+            graphic_device::wait_for_gpu();
+            (*std::ptr::addr_of_mut!(GEMBED_SURFACES)).retain(|x| x.surface.get_hwnd() != h_wnd);
+        }
+    }
+}
+
+// function for the host to forward its window messages, returns true if the message was handled by the renderer.
+// the host should still pass unhandled messages to its own window proc. input messages are only observed, they return false
+// so the host keeps its own handling (and WM_INPUT reaches the default proc). raw mouse motion only arrives when the host
+// registered the mouse for raw input, e.g. with input::register_raw_mouse().
+pub fn handle_message(h_wnd : HWND, message : u32, w_param : WPARAM, l_param : LPARAM) -> bool
+{
+    unsafe
+    {
+        let embedded = match find_surface(h_wnd)
+        {
+            Some(x) => x,
+            None => return false,
+        };
+
+        match message
+        {
+            WM_SIZE =>
+            {
+                embedded.minimized = w_param.0 as u32 == SIZE_MINIMIZED;
+
+                // low word and high word of l_param are the new client width and height
+                let width = (l_param.0 & 0xffff) as u32;
+                let height = ((l_param.0 >> 16) & 0xffff) as u32;
+                if !embedded.minimized
+                {
+                    embedded.surface.resize(width, height);
+                }
+                return true;
+            }
+            _ =>
+            {
+                if let Some(x) = input::translate_message(message, w_param, l_param)
+                {
+                    input::inject_event(x);
+                }
+                return false;
+            }
+        }
+    }
+}
+
+// function to render and present a frame to every attached window, called by the host whenever it wants a new frame
+pub fn render_frame()
+{
+    unsafe
+    {
+        if !GEMBED_INITIALIZED
+        {
+            return;
+        }
+
+        let surfaces = &mut *std::ptr::addr_of_mut!(GEMBED_SURFACES);
+        for x in surfaces.iter_mut()
+        {
+            if x.occluded
+            {
+                x.occluded = x.surface.is_occluded();
+            }
+        }
+
+        if !surfaces.iter().any(|x| !x.minimized && !x.occluded)
+        {
+            return;
+        }
+
+        graphic_device::update();
+        graphic_device::begin_frame();
+        upload_allocator::begin_frame();
+        descriptor_heap::begin_frame();
+        for x in surfaces.iter_mut()
+        {
+            if x.minimized || x.occluded
+            {
+                continue;
+            }
+
+            hello_world_triangle::render(&x.surface.get_render_target());
+            x.occluded = !x.surface.present();
+        }
+        upload_allocator::end_frame();
+        descriptor_heap::end_frame();
+        graphic_device::wait_for_gpu();
+
+        // the frame consumed the pressed and released states, the held ones stay
+        input::end_frame();
+    }
+}
+
+// function to release everything, the host windows are left untouched
+pub fn shutdown()
+{
+    unsafe
+    {
+        if !GEMBED_INITIALIZED
+        {
+            return;
+        }
+
+        // the surfaces hold placed resources and RTVs, they are released before the heaps and the state tracking are shut down.
+        // the descriptor heaps hold the device as well, so it's only released once they are gone too.
+        graphic_device::wait_for_gpu();
+        (*std::ptr::addr_of_mut!(GEMBED_SURFACES)).clear();
+        upload_allocator::shutdown();
+        graphic_device::shutdown();
+        descriptor_heap::shutdown();
+        GEMBED_INITIALIZED = false;
+    }
+}
+
+// C ABI exports for native hosts loading the cdylib, they just forward to the functions above
+#[no_mangle]
+pub extern "system" fn rust_d3d12_attach(h_wnd : HWND) -> BOOL
+{
+    return BOOL::from(attach(h_wnd));
+}
+
+#[no_mangle]
+pub extern "system" fn rust_d3d12_detach(h_wnd : HWND)
+{
+    detach(h_wnd);
+}
+
+#[no_mangle]
+pub extern "system" fn rust_d3d12_handle_message(h_wnd : HWND, message : u32, w_param : WPARAM, l_param : LPARAM) -> BOOL
+{
+    return BOOL::from(handle_message(h_wnd, message, w_param, l_param));
+}
+
+#[no_mangle]
+pub extern "system" fn rust_d3d12_render_frame()
+{
+    render_frame();
+}
+
+#[no_mangle]
+pub extern "system" fn rust_d3d12_shutdown()
+{
+    shutdown();
+}
@@ -0,0 +1,489 @@
+// frame_limiter.rs - The frame rate limiter, paces the game loop with a high-resolution waitable timer and a short spin at the end
+
+#[cfg(windows)]
+use windows::Win32::Foundation::*;
+#[cfg(windows)]
+use windows::Win32::System::Threading::*;
+use std::time::Duration;
+#[cfg(windows)]
+use std::time::Instant;
+
+// limiter modes, each of them can have its own target frame rate
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LimiterMode
+{
+    Focused,
+    Unfocused,
+    Minimized,
+}
+
+// the clock used by the pacing algorithm, the Win32 one is below and the tests inject a fake one
+pub trait FrameClock
+{
+    // time elapsed since an arbitrary but fixed origin
+    fn now(&mut self) -> Duration;
+
+    // coarse sleep, it's allowed to wake up a little bit late
+    fn sleep(&mut self, duration : Duration);
+
+    // called in every iteration of the final busy wait
+    fn spin(&mut self);
+}
+
+// accuracy report of the pacing, errors are how late the frames were released compared to their deadlines
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PacingStats
+{
+    pub frame_count : u64,
+    pub average_error : Duration,
+    pub max_error : Duration,
+    pub average_frame_time : Duration,
+}
+
+// the pacing algorithm itself, it doesn't touch any OS API so it can run with any FrameClock
+pub struct FramePacer
+{
+    target_fps : [f64; 3],
+    spin_threshold : Duration,
+    // the mode of the last frame, the schedule restarts when it changes
+    mode : Option<LimiterMode>,
+    next_deadline : Option<Duration>,
+    last_release : Option<Duration>,
+    frame_count : u64,
+    total_error : Duration,
+    max_error : Duration,
+    total_frame_time : Duration,
+}
+
+impl FramePacer
+{
+    pub fn new(focused_fps : f64, unfocused_fps : f64, minimized_fps : f64) -> FramePacer
+    {
+        return FramePacer
+        {
+            target_fps : [focused_fps, unfocused_fps, minimized_fps],
+            // sleeping is only trusted until this much time is left, the rest is spent in a busy wait
+            spin_threshold : Duration::from_micros(1500),
+            mode : None,
+            next_deadline : None,
+            last_release : None,
+            frame_count : 0,
+            total_error : Duration::ZERO,
+            max_error : Duration::ZERO,
+            total_frame_time : Duration::ZERO,
+        };
+    }
+
+    // set the target frame rate of a mode, 0 means unlimited
+    pub fn set_target_fps(&mut self, mode : LimiterMode, fps : f64)
+    {
+        self.target_fps[mode as usize] = fps.max(0.0);
+    }
+
+    // block until the next frame is due, returns the time at which the frame is released
+    pub fn wait(&mut self, clock : &mut dyn FrameClock, mode : LimiterMode) -> Duration
+    {
+        let fps = self.target_fps[mode as usize];
+        let mut now = clock.now();
+
+        // the deadline was scheduled with the interval of the old mode, e.g. 100 ms ahead when it was minimized at 10 fps
+        if self.mode != Some(mode)
+        {
+            self.mode = Some(mode);
+            self.next_deadline = None;
+        }
+
+        if fps <= 0.0
+        {
+            // unlimited, restart the schedule whenever a limit comes back
+            self.next_deadline = None;
+            self.record_release(now, None);
+            return now;
+        }
+
+        let interval = Duration::from_secs_f64(1.0 / fps);
+        let mut deadline = match self.next_deadline
+        {
+            Some(x) => x,
+            None => now + interval,
+        };
+
+        // if we fell behind by more than a frame (e.g. a hitch), don't try to catch up
+        // by releasing a burst of frames, restart the schedule from now instead.
+        if now > deadline + interval
+        {
+            deadline = now;
+        }
+
+        // coarse sleep for most of the remaining time
+        if deadline > now + self.spin_threshold
+        {
+            clock.sleep(deadline - now - self.spin_threshold);
+            now = clock.now();
+        }
+
+        // spin for the last part, the timer resolution isn't good enough for it
+        while now < deadline
+        {
+            clock.spin();
+            now = clock.now();
+        }
+
+        self.next_deadline = Some(deadline + interval);
+        self.record_release(now, Some(deadline));
+        return now;
+    }
+
+    fn record_release(&mut self, now : Duration, deadline : Option<Duration>)
+    {
+        if let Some(x) = self.last_release
+        {
+            self.total_frame_time = self.total_frame_time + now.saturating_sub(x);
+        }
+        self.last_release = Some(now);
+
+        if let Some(x) = deadline
+        {
+            let error = now.saturating_sub(x);
+            self.total_error = self.total_error + error;
+            self.max_error = self.max_error.max(error);
+        }
+        self.frame_count = self.frame_count + 1;
+    }
+
+    pub fn get_stats(&self) -> PacingStats
+    {
+        let mut stats = PacingStats
+        {
+            frame_count : self.frame_count,
+            max_error : self.max_error,
+            ..PacingStats::default()
+        };
+
+        if self.frame_count > 0
+        {
+            stats.average_error = self.total_error / self.frame_count as u32;
+        }
+        if self.frame_count > 1
+        {
+            stats.average_frame_time = self.total_frame_time / (self.frame_count - 1) as u32;
+        }
+
+        return stats;
+    }
+
+    pub fn reset_stats(&mut self)
+    {
+        self.frame_count = 0;
+        self.total_error = Duration::ZERO;
+        self.max_error = Duration::ZERO;
+        self.total_frame_time = Duration::ZERO;
+        self.last_release = None;
+    }
+}
+
+// the real clock, Instant for reading the time and a waitable timer for sleeping
+#[cfg(windows)]
+pub struct Win32FrameClock
+{
+    origin : Instant,
+    timer : Option<HANDLE>,
+}
+
+#[cfg(windows)]
+impl Win32FrameClock
+{
+    pub fn new() -> Win32FrameClock
+    {
+        unsafe
+        {
+            // prefer the high resolution timer (Windows 10 1803+), fallback to a normal one
+            let mut timer = CreateWaitableTimerExW(None, None, CREATE_WAITABLE_TIMER_HIGH_RESOLUTION, TIMER_ALL_ACCESS.0).ok();
+            if timer.is_none()
+            {
+                println!("High resolution waitable timer is not supported, fallback to the default one.");
+                timer = CreateWaitableTimerExW(None, None, 0, TIMER_ALL_ACCESS.0).ok();
+            }
+
+            return Win32FrameClock
+            {
+                origin : Instant::now(),
+                timer,
+            };
+        }
+    }
+}
+
+#[cfg(windows)]
+impl FrameClock for Win32FrameClock
+{
+    fn now(&mut self) -> Duration
+    {
+        return self.origin.elapsed();
+    }
+
+    fn sleep(&mut self, duration : Duration)
+    {
+        unsafe
+        {
+            if let Some(x) = self.timer
+            {
+                // negative due time means relative time, in 100 nanoseconds unit
+                let due_time : i64 = -((duration.as_nanos() / 100) as i64);
+                if SetWaitableTimer(x, &due_time, 0, None, None, FALSE).is_ok()
+                {
+                    WaitForSingleObject(x, INFINITE);
+                    return;
+                }
+            }
+
+            std::thread::sleep(duration);
+        }
+    }
+
+    fn spin(&mut self)
+    {
+        std::hint::spin_loop();
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Win32FrameClock
+{
+    fn drop(&mut self)
+    {
+        unsafe
+        {
+            if let Some(x) = self.timer
+            {
+                let _ = CloseHandle(x);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+static mut GFRAME_PACER : Option<FramePacer> = None;
+#[cfg(windows)]
+static mut GFRAME_CLOCK : Option<Win32FrameClock> = None;
+#[cfg(windows)]
+static mut GLAST_REPORT_TIME : Duration = Duration::ZERO;
+
+// how often the pacing accuracy is printed
+#[cfg(windows)]
+const GREPORT_INTERVAL : Duration = Duration::from_secs(5);
+
+// function to initialize the limiter with the target frame rates of each mode
+#[cfg(windows)]
+pub fn initialize(focused_fps : f64, unfocused_fps : f64, minimized_fps : f64)
+{
+    unsafe
+    {
+        GFRAME_PACER = Some(FramePacer::new(focused_fps, unfocused_fps, minimized_fps));
+        GFRAME_CLOCK = Some(Win32FrameClock::new());
+        GLAST_REPORT_TIME = Duration::ZERO;
+    }
+}
+
+// function to shutdown, prints the final report
+#[cfg(windows)]
+pub fn shutdown()
+{
+    unsafe
+    {
+        if GFRAME_PACER.is_some()
+        {
+            print_stats();
+        }
+
+        GFRAME_PACER = None;
+        GFRAME_CLOCK = None;
+    }
+}
+
+// wait until the next frame of given mode is due, called once per loop iteration
+#[cfg(windows)]
+pub fn wait_for_next_frame(mode : LimiterMode)
+{
+    unsafe
+    {
+        if GFRAME_PACER.is_none() || GFRAME_CLOCK.is_none()
+        {
+            return;
+        }
+
+        let pacer = GFRAME_PACER.as_mut().unwrap();
+        let clock = GFRAME_CLOCK.as_mut().unwrap();
+        let now = pacer.wait(clock, mode);
+
+        // report the accuracy periodically
+        if now - GLAST_REPORT_TIME >= GREPORT_INTERVAL
+        {
+            GLAST_REPORT_TIME = now;
+            print_stats();
+            GFRAME_PACER.as_mut().unwrap().reset_stats();
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn set_target_fps(mode : LimiterMode, fps : f64)
+{
+    unsafe
+    {
+        if let Some(x) = GFRAME_PACER.as_mut()
+        {
+            x.set_target_fps(mode, fps);
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn get_stats() -> PacingStats
+{
+    unsafe
+    {
+        return match GFRAME_PACER.as_ref()
+        {
+            Some(x) => x.get_stats(),
+            None => PacingStats::default(),
+        };
+    }
+}
+
+#[cfg(windows)]
+fn print_stats()
+{
+    let stats = get_stats();
+    if stats.frame_count == 0
+    {
+        return;
+    }
+
+    let fps = if stats.average_frame_time.is_zero() { 0.0 } else { 1.0 / stats.average_frame_time.as_secs_f64() };
+    println!("Frame pacing: {} frames, {:.1} fps, average error {:.3} ms, max error {:.3} ms", stats.frame_count, fps
+        , stats.average_error.as_secs_f64() * 1000.0, stats.max_error.as_secs_f64() * 1000.0);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // a clock that only advances when it's asked to sleep or spin, so the pacing is exact and instant
+    struct FakeClock
+    {
+        now : Duration,
+        // how late every sleep wakes up
+        oversleep : Duration,
+        spin_step : Duration,
+        sleep_count : u32,
+        spin_count : u32,
+    }
+
+    impl FakeClock
+    {
+        fn new(oversleep : Duration) -> FakeClock
+        {
+            return FakeClock
+            {
+                now : Duration::ZERO,
+                oversleep,
+                spin_step : Duration::from_micros(10),
+                sleep_count : 0,
+                spin_count : 0,
+            };
+        }
+
+        // the time spent by the frame itself
+        fn advance(&mut self, duration : Duration)
+        {
+            self.now += duration;
+        }
+    }
+
+    impl FrameClock for FakeClock
+    {
+        fn now(&mut self) -> Duration
+        {
+            return self.now;
+        }
+
+        fn sleep(&mut self, duration : Duration)
+        {
+            self.now += duration + self.oversleep;
+            self.sleep_count += 1;
+        }
+
+        fn spin(&mut self)
+        {
+            self.now += self.spin_step;
+            self.spin_count += 1;
+        }
+    }
+
+    #[test]
+    fn releases_frames_on_their_deadlines()
+    {
+        let mut clock = FakeClock::new(Duration::from_micros(500));
+        let mut pacer = FramePacer::new(100.0, 30.0, 10.0);
+        let interval = Duration::from_millis(10);
+
+        let mut last_release = pacer.wait(&mut clock, LimiterMode::Focused);
+        for _ in 0..10
+        {
+            clock.advance(Duration::from_millis(2));
+            let release = pacer.wait(&mut clock, LimiterMode::Focused);
+            // the spin makes up for the late wake up, at most one spin step late
+            assert!(release - last_release >= interval);
+            assert!(release - last_release < interval + clock.spin_step * 2);
+            last_release = release;
+        }
+
+        assert!(clock.sleep_count >= 10);
+        assert!(clock.spin_count > 0);
+        assert_eq!(pacer.get_stats().frame_count, 11);
+        assert!(pacer.get_stats().max_error < clock.spin_step * 2);
+    }
+
+    #[test]
+    fn unlimited_mode_never_waits()
+    {
+        let mut clock = FakeClock::new(Duration::ZERO);
+        let mut pacer = FramePacer::new(0.0, 30.0, 10.0);
+        for _ in 0..5
+        {
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(pacer.wait(&mut clock, LimiterMode::Focused), clock.now);
+        }
+        assert_eq!(clock.sleep_count + clock.spin_count, 0);
+    }
+
+    #[test]
+    fn falling_behind_restarts_the_schedule()
+    {
+        let mut clock = FakeClock::new(Duration::ZERO);
+        let mut pacer = FramePacer::new(100.0, 30.0, 10.0);
+        pacer.wait(&mut clock, LimiterMode::Focused);
+
+        // a hitch of 50 ms, the next frame is released right away instead of a burst of catch up frames
+        clock.advance(Duration::from_millis(50));
+        let release = pacer.wait(&mut clock, LimiterMode::Focused);
+        assert_eq!(release, clock.now);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(pacer.wait(&mut clock, LimiterMode::Focused) - release, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn mode_change_restarts_the_schedule()
+    {
+        let mut clock = FakeClock::new(Duration::ZERO);
+        let mut pacer = FramePacer::new(240.0, 30.0, 10.0);
+        pacer.wait(&mut clock, LimiterMode::Minimized);
+        let release = pacer.wait(&mut clock, LimiterMode::Minimized);
+
+        // the minimized schedule was 100 ms ahead, the focused frame is due one focused interval from now
+        let restored = pacer.wait(&mut clock, LimiterMode::Focused);
+        assert!(restored - release <= Duration::from_secs_f64(1.0 / 240.0) + clock.spin_step);
+    }
+}
@@ -0,0 +1,211 @@
+// frame_ring.rs - A ring allocator whose space is reclaimed frame by frame once the fence value of the frame is completed
+
+use std::collections::VecDeque;
+
+// the ring of the upload allocator and of the shader visible descriptor heap. positions only grow, the offset is the
+// position modulo the capacity, so head - tail is always the space in use including the padding and the skipped end.
+pub struct FrameRing
+{
+    capacity : u64,
+    head : u64,
+    tail : u64,
+    // the head at the end of each frame that isn't reclaimed yet, with the fence value that frame completes with
+    frame_ends : VecDeque<(u64, u64)>,
+}
+
+pub fn align_up(value : u64, alignment : u64) -> u64
+{
+    return value.div_ceil(alignment) * alignment;
+}
+
+impl FrameRing
+{
+    pub fn new(capacity : u64) -> FrameRing
+    {
+        return FrameRing
+        {
+            capacity,
+            head : 0,
+            tail : 0,
+            frame_ends : VecDeque::new(),
+        };
+    }
+
+    // function to allocate size units aligned to alignment (a power of 2), returns the offset in the ring.
+    // an allocation never straddles the end of the ring, the rest of the ring is skipped instead.
+    // returns None if the frames in flight still use the space.
+    pub fn allocate(&mut self, size : u64, alignment : u64) -> Option<u64>
+    {
+        if size == 0 || size > self.capacity || !alignment.is_power_of_two()
+        {
+            return None;
+        }
+
+        let offset = self.head % self.capacity;
+        let mut aligned_offset = align_up(offset, alignment);
+        if aligned_offset + size > self.capacity
+        {
+            // wrap around, the offset 0 is aligned to anything
+            aligned_offset = self.capacity;
+        }
+
+        let new_head = self.head + (aligned_offset - offset) + size;
+        if new_head - self.tail > self.capacity
+        {
+            return None;
+        }
+
+        self.head = new_head;
+        return Some(aligned_offset % self.capacity);
+    }
+
+    // function to mark the end of a frame, its allocations are free once fence_value is completed
+    pub fn end_frame(&mut self, fence_value : u64)
+    {
+        // nothing allocated since the last frame, no need for another entry
+        if self.frame_ends.back().map_or(self.tail, |x| x.1) == self.head
+        {
+            return;
+        }
+        self.frame_ends.push_back((fence_value, self.head));
+    }
+
+    // function to release the space of the frames whose fence value is completed
+    pub fn reclaim(&mut self, completed_fence_value : u64)
+    {
+        while let Some((fence_value, frame_end)) = self.frame_ends.front().copied()
+        {
+            if fence_value > completed_fence_value
+            {
+                break;
+            }
+            self.tail = frame_end;
+            self.frame_ends.pop_front();
+        }
+    }
+
+    pub fn get_capacity(&self) -> u64
+    {
+        return self.capacity;
+    }
+
+    // the space in use, including the padding
+    pub fn get_used_size(&self) -> u64
+    {
+        return self.head - self.tail;
+    }
+
+    pub fn get_frames_in_flight(&self) -> usize
+    {
+        return self.frame_ends.len();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn allocations_are_aligned_and_padded()
+    {
+        let mut ring = FrameRing::new(1024);
+        assert_eq!(ring.allocate(10, 1), Some(0));
+        assert_eq!(ring.allocate(16, 256), Some(256));
+        assert_eq!(ring.allocate(1, 4), Some(272));
+        // the padding before the aligned allocation counts as used
+        assert_eq!(ring.get_used_size(), 273);
+
+        assert_eq!(ring.allocate(0, 4), None);
+        assert_eq!(ring.allocate(1025, 4), None);
+        assert_eq!(ring.allocate(4, 3), None);
+        assert_eq!(ring.get_used_size(), 273);
+    }
+
+    #[test]
+    fn wrapping_skips_the_end_of_the_ring()
+    {
+        let mut ring = FrameRing::new(1024);
+        assert_eq!(ring.allocate(900, 1), Some(0));
+        ring.end_frame(1);
+        ring.reclaim(1);
+        assert_eq!(ring.get_used_size(), 0);
+
+        // 200 bytes don't fit in the 124 left at the end, they start at offset 0 and the end is skipped
+        assert_eq!(ring.allocate(200, 1), Some(0));
+        assert_eq!(ring.get_used_size(), 124 + 200);
+        assert_eq!(ring.allocate(100, 64), Some(256));
+    }
+
+    #[test]
+    fn a_full_ring_fails_until_a_frame_is_reclaimed()
+    {
+        let mut ring = FrameRing::new(1024);
+        assert_eq!(ring.allocate(600, 1), Some(0));
+        ring.end_frame(1);
+
+        // it would wrap into the space the frame still uses
+        assert_eq!(ring.allocate(600, 1), None);
+        ring.reclaim(0);
+        assert_eq!(ring.allocate(600, 1), None);
+
+        ring.reclaim(1);
+        assert_eq!(ring.allocate(600, 1), Some(0));
+        assert_eq!(ring.get_used_size(), 1024);
+        assert_eq!(ring.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn empty_frames_are_not_recorded()
+    {
+        let mut ring = FrameRing::new(1024);
+        ring.end_frame(1);
+        assert_eq!(ring.get_frames_in_flight(), 0);
+
+        ring.allocate(16, 16);
+        ring.end_frame(2);
+        ring.end_frame(3);
+        assert_eq!(ring.get_frames_in_flight(), 1);
+
+        // a reclaimed frame with nothing allocated after it doesn't need a new entry either
+        ring.reclaim(2);
+        ring.end_frame(4);
+        assert_eq!(ring.get_frames_in_flight(), 0);
+    }
+
+    #[test]
+    fn frames_are_reclaimed_by_fence_value()
+    {
+        let mut ring = FrameRing::new(1024);
+        for fence_value in 1..=3
+        {
+            ring.allocate(100, 1);
+            ring.end_frame(fence_value);
+        }
+        assert_eq!((ring.get_used_size(), ring.get_frames_in_flight()), (300, 3));
+
+        ring.reclaim(2);
+        assert_eq!((ring.get_used_size(), ring.get_frames_in_flight()), (100, 1));
+        ring.reclaim(2);
+        assert_eq!((ring.get_used_size(), ring.get_frames_in_flight()), (100, 1));
+        ring.reclaim(5);
+        assert_eq!((ring.get_used_size(), ring.get_frames_in_flight()), (0, 0));
+    }
+
+    #[test]
+    fn unit_allocations_wrap_without_straddling_the_end()
+    {
+        let mut ring = FrameRing::new(10);
+        assert_eq!(ring.allocate(6, 1), Some(0));
+        ring.end_frame(1);
+
+        // 4 slots are left at the end, a table of 5 would straddle it
+        assert_eq!(ring.allocate(5, 1), None);
+        ring.reclaim(1);
+        assert_eq!(ring.allocate(5, 1), Some(0));
+        // the skipped end counts as used until the frame is reclaimed
+        assert_eq!(ring.get_used_size(), 9);
+        assert_eq!(ring.allocate(1, 1), Some(5));
+        assert_eq!(ring.allocate(1, 1), None);
+    }
+}
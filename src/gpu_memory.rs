@@ -0,0 +1,496 @@
+// gpu_memory.rs - Placed resources sub-allocated from large ID3D12Heaps with memory_allocator.rs. the heaps are pooled by
+// heap type and, on resource heap tier 1, by what they can hold (buffers, textures, render target and depth textures).
+// resources larger than half a heap get a committed allocation of their own. freed memory is only reused once the GPU
+// has passed the fence of the frame it was freed in. the heaps and dedicated resources in local video memory are
+// managed by residency.rs.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::mem;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows_core::Interface;
+
+use crate::graphic_device;
+use crate::memory_allocator::{MemoryAllocation, TlsfAllocator};
+use crate::residency;
+use crate::residency_policy::ResidencyId;
+use crate::resource_states;
+use crate::state_tracker::ResourceId;
+
+// the size of a heap of a pool, a multiple of the MSAA placement alignment
+pub const GHEAP_SIZE : u64 = 64 * 1024 * 1024;
+
+// the resources larger than it are dedicated allocations
+pub const GDEDICATED_THRESHOLD : u64 = GHEAP_SIZE / 2;
+
+// what a heap can hold, tier 1 needs a heap for each of the first three, tier 2 puts everything in one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MemoryCategory
+{
+    Buffer,
+    Texture,
+    RenderTargetOrDepth,
+    All,
+}
+
+struct MemoryHeap
+{
+    heap : ID3D12Heap,
+    allocator : TlsfAllocator,
+    // only the heaps in local video memory, DEFAULT, are evicted
+    residency_id : Option<ResidencyId>,
+}
+
+struct MemoryPool
+{
+    heap_type : D3D12_HEAP_TYPE,
+    category : MemoryCategory,
+    // heaps are released when they are empty, the slots are kept so the allocations can refer to them by index
+    heaps : Vec<Option<MemoryHeap>>,
+}
+
+struct GpuMemory
+{
+    resource_heap_tier : D3D12_RESOURCE_HEAP_TIER,
+    pools : Vec<MemoryPool>,
+    // the freed placements as (fence value, pool, heap, allocation), in the order they were freed
+    retired : VecDeque<(u64, usize, usize, MemoryAllocation)>,
+    dedicated_count : u32,
+    dedicated_size : u64,
+    // the heap or the dedicated resource that has to be resident for each resource
+    residency_ids : HashMap<ResourceId, ResidencyId>,
+}
+
+static mut GGPU_MEMORY : Option<GpuMemory> = None;
+
+// the memory behind a resource, keep it next to the resource and drop it after the resource
+pub struct GpuAllocation
+{
+    // (pool, heap, allocation) of a placed resource, None for a dedicated allocation
+    placement : Option<(usize, usize, MemoryAllocation)>,
+    size : u64,
+    resource_id : ResourceId,
+}
+
+impl GpuAllocation
+{
+    pub fn get_size(&self) -> u64
+    {
+        return self.size;
+    }
+
+    pub fn is_dedicated(&self) -> bool
+    {
+        return self.placement.is_none();
+    }
+}
+
+impl Drop for GpuAllocation
+{
+    fn drop(&mut self)
+    {
+        unsafe
+        {
+            let gpu_memory = match (*std::ptr::addr_of_mut!(GGPU_MEMORY)).as_mut()
+            {
+                Some(x) => x,
+                None => return,
+            };
+
+            // a dedicated resource is its own residency object
+            if let Some(x) = gpu_memory.residency_ids.remove(&self.resource_id)
+            {
+                if self.placement.is_none()
+                {
+                    residency::unregister(x);
+                }
+            }
+
+            match self.placement
+            {
+                // the GPU may still use the memory in the frame being recorded
+                Some((pool, heap, allocation)) => gpu_memory.retired.push_back((graphic_device::get_next_fence_value(), pool, heap, allocation)),
+                None =>
+                {
+                    gpu_memory.dedicated_count -= 1;
+                    gpu_memory.dedicated_size -= self.size;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct GpuMemoryStatistics
+{
+    pub heap_count : u32,
+    pub heap_size : u64,
+    pub used_size : u64,
+    pub allocation_count : u32,
+    pub free_block_count : u32,
+    // how much of the free memory of the heaps is outside of the largest free block of its heap, from 0 to 1
+    pub fragmentation : f32,
+    pub dedicated_count : u32,
+    pub dedicated_size : u64,
+}
+
+// function to query the resource heap tier, it's called by graphic_device::initialize_d3d12()
+pub fn initialize() -> bool
+{
+    unsafe
+    {
+        let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS::default();
+        if graphic_device::get_device().CheckFeatureSupport(D3D12_FEATURE_D3D12_OPTIONS, &mut options as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_D3D12_OPTIONS>() as u32).is_err()
+        {
+            return false;
+        }
+
+        GGPU_MEMORY = Some(GpuMemory
+        {
+            resource_heap_tier : options.ResourceHeapTier,
+            pools : Vec::new(),
+            retired : VecDeque::new(),
+            dedicated_count : 0,
+            dedicated_size : 0,
+            residency_ids : HashMap::new(),
+        });
+        return true;
+    }
+}
+
+// function to release the heaps, the allocations dropped afterwards are ignored
+pub fn shutdown()
+{
+    unsafe
+    {
+        GGPU_MEMORY = None;
+    }
+}
+
+// function to get the category of a resource, every resource goes in one heap on tier 2
+fn get_category(resource_heap_tier : D3D12_RESOURCE_HEAP_TIER, resource_desc : &D3D12_RESOURCE_DESC) -> MemoryCategory
+{
+    if resource_heap_tier.0 >= D3D12_RESOURCE_HEAP_TIER_2.0
+    {
+        return MemoryCategory::All;
+    }
+
+    if resource_desc.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER
+    {
+        return MemoryCategory::Buffer;
+    }
+
+    if (resource_desc.Flags & (D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET | D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL)).0 != 0
+    {
+        return MemoryCategory::RenderTargetOrDepth;
+    }
+
+    return MemoryCategory::Texture;
+}
+
+// function to get the flags and the alignment of the heaps of a pool, only default heaps can hold MSAA textures
+fn get_heap_flags_and_alignment(heap_type : D3D12_HEAP_TYPE, category : MemoryCategory) -> (D3D12_HEAP_FLAGS, u64)
+{
+    let heap_flags = match category
+    {
+        MemoryCategory::Buffer => D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS,
+        MemoryCategory::Texture => D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES,
+        MemoryCategory::RenderTargetOrDepth => D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES,
+        MemoryCategory::All => D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES,
+    };
+
+    let alignment = if heap_type == D3D12_HEAP_TYPE_DEFAULT && category != MemoryCategory::Buffer
+    {
+        D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as u64
+    }
+    else
+    {
+        D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64
+    };
+
+    return (heap_flags, alignment);
+}
+
+// function to create a heap for a pool
+fn create_heap(heap_type : D3D12_HEAP_TYPE, category : MemoryCategory) -> Option<MemoryHeap>
+{
+    unsafe
+    {
+        let (heap_flags, alignment) = get_heap_flags_and_alignment(heap_type, category);
+        let heap_desc = D3D12_HEAP_DESC
+        {
+            SizeInBytes : GHEAP_SIZE,
+            Properties : D3D12_HEAP_PROPERTIES
+            {
+                Type : heap_type,
+                ..D3D12_HEAP_PROPERTIES::default()
+            },
+            Alignment : alignment,
+            Flags : heap_flags,
+        };
+
+        let mut heap : Option<ID3D12Heap> = None;
+        if graphic_device::get_device().CreateHeap(&heap_desc, &mut heap).is_err()
+        {
+            println!("Error during memory heap creation! {} bytes", GHEAP_SIZE);
+            return None;
+        }
+
+        let heap = heap.unwrap();
+        let residency_id = if heap_type == D3D12_HEAP_TYPE_DEFAULT { Some(residency::register(&heap.cast().unwrap(), GHEAP_SIZE)) } else { None };
+
+        return Some(MemoryHeap
+        {
+            heap,
+            allocator : TlsfAllocator::new(GHEAP_SIZE, D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64),
+            residency_id,
+        });
+    }
+}
+
+// function to create a committed resource, which has its own implicit heap
+fn create_committed_resource(heap_type : D3D12_HEAP_TYPE, resource_desc : &D3D12_RESOURCE_DESC, initial_state : D3D12_RESOURCE_STATES
+    , clear_value : Option<*const D3D12_CLEAR_VALUE>) -> Option<ID3D12Resource>
+{
+    unsafe
+    {
+        let heap_properties = D3D12_HEAP_PROPERTIES
+        {
+            Type : heap_type,
+            ..D3D12_HEAP_PROPERTIES::default()
+        };
+
+        let mut resource : Option<ID3D12Resource> = None;
+        if graphic_device::get_device().CreateCommittedResource(&heap_properties, D3D12_HEAP_FLAG_NONE, resource_desc, initial_state, clear_value, &mut resource).is_err()
+        {
+            println!("Error during committed resource creation!");
+            return None;
+        }
+
+        return resource;
+    }
+}
+
+// function to create a resource in a pool of heap_type, it's placed in one of the heaps unless it's too large for them.
+// the returned allocation must be dropped after the resource.
+pub fn create_resource(heap_type : D3D12_HEAP_TYPE, resource_desc : &D3D12_RESOURCE_DESC, initial_state : D3D12_RESOURCE_STATES
+    , clear_value : Option<*const D3D12_CLEAR_VALUE>) -> Option<(ID3D12Resource, GpuAllocation)>
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        let allocation_info = device.GetResourceAllocationInfo(0, &[*resource_desc]);
+        if allocation_info.SizeInBytes == u64::MAX
+        {
+            println!("Error during resource creation! The resource description is invalid.");
+            return None;
+        }
+
+        let gpu_memory = (*std::ptr::addr_of_mut!(GGPU_MEMORY)).as_mut();
+        let category = get_category(gpu_memory.as_ref().map_or(D3D12_RESOURCE_HEAP_TIER_1, |x| x.resource_heap_tier), resource_desc);
+        let (_, heap_alignment) = get_heap_flags_and_alignment(heap_type, category);
+
+        let gpu_memory = match gpu_memory
+        {
+            Some(x) if allocation_info.SizeInBytes <= GDEDICATED_THRESHOLD && allocation_info.Alignment <= heap_alignment => x,
+            x =>
+            {
+                let resource = create_committed_resource(heap_type, resource_desc, initial_state, clear_value)?;
+                let resource_id = resource_states::get_resource_id(&resource);
+                if let Some(y) = x
+                {
+                    y.dedicated_count += 1;
+                    y.dedicated_size += allocation_info.SizeInBytes;
+                    if heap_type == D3D12_HEAP_TYPE_DEFAULT
+                    {
+                        y.residency_ids.insert(resource_id, residency::register(&resource.cast().unwrap(), allocation_info.SizeInBytes));
+                    }
+                }
+
+                let allocation = GpuAllocation
+                {
+                    placement : None,
+                    size : allocation_info.SizeInBytes,
+                    resource_id,
+                };
+                return Some((resource, allocation));
+            }
+        };
+
+        let pool_index = match gpu_memory.pools.iter().position(|x| x.heap_type == heap_type && x.category == category)
+        {
+            Some(x) => x,
+            None =>
+            {
+                gpu_memory.pools.push(MemoryPool { heap_type, category, heaps : Vec::new() });
+                gpu_memory.pools.len() - 1
+            }
+        };
+        let pool = &mut gpu_memory.pools[pool_index];
+
+        // first fit over the heaps, a new heap goes in the first released slot
+        let mut placement : Option<(usize, MemoryAllocation)> = None;
+        for (i, x) in pool.heaps.iter_mut().enumerate()
+        {
+            if let Some(y) = x.as_mut().and_then(|x| x.allocator.allocate(allocation_info.SizeInBytes, allocation_info.Alignment))
+            {
+                placement = Some((i, y));
+                break;
+            }
+        }
+
+        let (heap_index, allocation) = match placement
+        {
+            Some(x) => x,
+            None =>
+            {
+                let mut heap = create_heap(heap_type, category)?;
+                let allocation = heap.allocator.allocate(allocation_info.SizeInBytes, allocation_info.Alignment)?;
+                let heap_index = match pool.heaps.iter().position(|x| x.is_none())
+                {
+                    Some(x) => x,
+                    None =>
+                    {
+                        pool.heaps.push(None);
+                        pool.heaps.len() - 1
+                    }
+                };
+                pool.heaps[heap_index] = Some(heap);
+                (heap_index, allocation)
+            }
+        };
+
+        let memory_heap = pool.heaps[heap_index].as_mut().unwrap();
+        let mut resource : Option<ID3D12Resource> = None;
+        if device.CreatePlacedResource(&memory_heap.heap, allocation.offset, resource_desc, initial_state, clear_value, &mut resource).is_err()
+        {
+            println!("Error during placed resource creation! {} bytes", allocation_info.SizeInBytes);
+            // the GPU never saw it, so it can be freed right away
+            let _ = memory_heap.allocator.free(&allocation);
+            return None;
+        }
+
+        let resource = resource.unwrap();
+        let resource_id = resource_states::get_resource_id(&resource);
+        if let Some(x) = memory_heap.residency_id
+        {
+            gpu_memory.residency_ids.insert(resource_id, x);
+        }
+
+        let allocation = GpuAllocation
+        {
+            placement : Some((pool_index, heap_index, allocation)),
+            size : allocation.size,
+            resource_id,
+        };
+        return Some((resource, allocation));
+    }
+}
+
+// function to free the placements the GPU is done with and release the heaps that became empty, one heap per pool is kept.
+// it's called by graphic_device::begin_frame()
+pub fn begin_frame()
+{
+    unsafe
+    {
+        let gpu_memory = match (*std::ptr::addr_of_mut!(GGPU_MEMORY)).as_mut()
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        let completed_fence_value = graphic_device::get_completed_fence_value();
+        while let Some(&(fence_value, pool_index, heap_index, allocation)) = gpu_memory.retired.front()
+        {
+            if fence_value > completed_fence_value
+            {
+                break;
+            }
+            gpu_memory.retired.pop_front();
+
+            let pool = &mut gpu_memory.pools[pool_index];
+            let heap = match pool.heaps[heap_index].as_mut()
+            {
+                Some(x) => x,
+                None => continue,
+            };
+            if let Err(x) = heap.allocator.free(&allocation)
+            {
+                println!("Error during memory release! {}", x);
+                continue;
+            }
+
+            let (is_empty, residency_id) = (heap.allocator.is_empty(), heap.residency_id);
+            if is_empty && pool.heaps.iter().filter(|x| x.is_some()).count() > 1
+            {
+                if let Some(x) = residency_id
+                {
+                    residency::unregister(x);
+                }
+                pool.heaps[heap_index] = None;
+            }
+        }
+    }
+}
+
+// function to get the residency object a resource lives in, None if it's not managed
+pub fn get_residency_id(resource_id : ResourceId) -> Option<ResidencyId>
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GGPU_MEMORY)).as_ref()?.residency_ids.get(&resource_id).copied();
+    }
+}
+
+// function to keep the memory of resources resident for the next submission
+pub fn touch_resources(resources : &[&ID3D12Resource])
+{
+    let residency_ids : Vec<ResidencyId> = resources.iter().filter_map(|x| get_residency_id(resource_states::get_resource_id(x))).collect();
+    residency::touch(&residency_ids);
+}
+
+// function to keep the memory of a resource resident until residency::unpin() is called with the returned id,
+// None if the resource isn't managed
+pub fn pin_resource(resource : &ID3D12Resource) -> Option<ResidencyId>
+{
+    let residency_id = get_residency_id(resource_states::get_resource_id(resource))?;
+    residency::pin(residency_id);
+    return Some(residency_id);
+}
+
+pub fn get_statistics() -> GpuMemoryStatistics
+{
+    unsafe
+    {
+        let gpu_memory = match (*std::ptr::addr_of!(GGPU_MEMORY)).as_ref()
+        {
+            Some(x) => x,
+            None => return GpuMemoryStatistics::default(),
+        };
+
+        let mut statistics = GpuMemoryStatistics
+        {
+            dedicated_count : gpu_memory.dedicated_count,
+            dedicated_size : gpu_memory.dedicated_size,
+            ..GpuMemoryStatistics::default()
+        };
+
+        let mut fragmented_size = 0;
+        for x in gpu_memory.pools.iter().flat_map(|x| x.heaps.iter().flatten())
+        {
+            let heap_statistics = x.allocator.get_statistics();
+            statistics.heap_count += 1;
+            statistics.heap_size += heap_statistics.capacity;
+            statistics.used_size += heap_statistics.used_size;
+            statistics.allocation_count += heap_statistics.allocation_count;
+            statistics.free_block_count += heap_statistics.free_block_count;
+            fragmented_size += heap_statistics.get_free_size() - heap_statistics.largest_free_block;
+        }
+
+        let free_size = statistics.heap_size - statistics.used_size;
+        statistics.fragmentation = if free_size > 0 { fragmented_size as f32 / free_size as f32 } else { 0.0 };
+        return statistics;
+    }
+}
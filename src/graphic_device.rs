@@ -12,6 +12,20 @@ use std::mem;
 use std::ffi::c_void;
 use libc;
 
+use crate::app_config::AdapterChoice;
+use crate::descriptor_allocator::{DescriptorHandle, DescriptorHeapType};
+use crate::descriptor_heap;
+use crate::depth_buffer;
+use crate::depth_buffer::DepthBuffer;
+use crate::msaa;
+use crate::msaa::MsaaTarget;
+use crate::gpu_memory;
+use crate::residency;
+use crate::image_io;
+use crate::resource_states;
+use crate::resource_states::CommandListStates;
+use crate::image_io::{Footprint, Image, ImageFormat};
+
 // global D3D12 interfaces
 static mut GDXGI_FACTORY : Option<IDXGIFactory4> = None;
 static mut GD3D12_DEVICE : Option<ID3D12Device> = None;
@@ -21,18 +35,44 @@ static mut GMAIN_COMMAND_LIST : Option<ID3D12GraphicsCommandList> = None;
 static mut GDEBUG_INFO_QUEUE : Option<ID3D12InfoQueue> = None;
 
 const GMAXFRAME : usize = 2;
-static GBACK_BUFFER_FORMAT : DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
+static mut GBACK_BUFFER_FORMAT : DXGI_FORMAT = DXGI_FORMAT_R8G8B8A8_UNORM;
 static mut GSUPPORT_SCREEN_TEARING : bool = false;
-static mut GSWAPCHAIN : Option<IDXGISwapChain3> = None;
-static mut GSWAPCHAIN_HEAP : Option<ID3D12DescriptorHeap> = None;
-static mut GRTV_DESCRIPTOR_SIZE : u32 = 0;
-static mut GSWAPCHAIN_RESOURCE : [Option<ID3D12Resource>; GMAXFRAME] = [None,None];
-static mut GCURRENT_FRAME_INDEX : u32 = 0;
+static mut GVSYNC : bool = false;
+
+// device creation settings, they must be set before initialize_d3d12()
+static mut GDEBUG_LAYER : bool = true;
+static mut GADAPTER_CHOICE : AdapterChoice = AdapterChoice::Auto;
 
 static mut GMAIN_FENCE_VALUE : u64 = 0;
 static mut GMAIN_FENCE : Option<ID3D12Fence> = None;
 static mut GMAIN_FENCE_EVENT : Option<HANDLE> = None;
 
+// the adapter of the device, its video memory usage and budget are polled by update()
+static mut GDXGI_ADAPTER : Option<IDXGIAdapter3> = None;
+static mut GVIDEO_MEMORY_BUDGET_EVENT : Option<HANDLE> = None;
+static mut GVIDEO_MEMORY_BUDGET_COOKIE : Option<u32> = None;
+static mut GVIDEO_MEMORY_STATS : VideoMemoryStats = VideoMemoryStats
+{
+    local_usage : 0,
+    local_budget : 0,
+    non_local_usage : 0,
+    non_local_budget : 0,
+    budget_change_count : 0,
+};
+
+// the video memory of the adapter, local is the memory of the GPU and non local is the system memory it can access.
+// the budget is what the OS lets the application use, it changes with the other applications.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct VideoMemoryStats
+{
+    pub local_usage : u64,
+    pub local_budget : u64,
+    pub non_local_usage : u64,
+    pub non_local_budget : u64,
+    // how many budget change notifications were received
+    pub budget_change_count : u32,
+}
+
 // function to create device
 fn create_device()
 {
@@ -42,10 +82,13 @@ fn create_device()
 
         // enable debug layer
         let mut debug_controller : Option<ID3D12Debug> = None;
-        if let Ok(()) = D3D12GetDebugInterface(&mut debug_controller)
+        if GDEBUG_LAYER
         {
-            debug_controller.as_ref().unwrap().EnableDebugLayer();
-            dxgi_factory_flag = dxgi_factory_flag | DXGI_CREATE_FACTORY_DEBUG;
+            if let Ok(()) = D3D12GetDebugInterface(&mut debug_controller)
+            {
+                debug_controller.as_ref().unwrap().EnableDebugLayer();
+                dxgi_factory_flag = dxgi_factory_flag | DXGI_CREATE_FACTORY_DEBUG;
+            }
         }
 
         // create DXGI factory
@@ -61,10 +104,11 @@ fn create_device()
             // try adapters from the highest feature level to lowest
             let feature_levels : [D3D_FEATURE_LEVEL; 3] = [D3D_FEATURE_LEVEL_12_2, D3D_FEATURE_LEVEL_12_1, D3D_FEATURE_LEVEL_12_0];
             let feature_levels_name : [&str;3] = ["12_2","12_1","12_0"];
+            let adapter_choice = &*std::ptr::addr_of!(GADAPTER_CHOICE);
             let mut feature_index = 0;
             let mut adapter_index;
 
-            'FeatureLevelLoop : loop
+            'FeatureLevelLoop : while feature_index < feature_levels.len()
             {
                 adapter_index = 0;
                 loop
@@ -72,19 +116,23 @@ fn create_device()
                     if let Ok(x) = GDXGI_FACTORY.as_ref().unwrap().EnumAdapters1(adapter_index)
                     {
                         let adapter_desc = x.GetDesc1().unwrap();
-                        if (adapter_desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 as u32) > 0
+                        let adapter_name = String::from_utf16_lossy(&adapter_desc.Description).trim_end_matches('\0').to_string();
+
+                        // skip software adapter unless it's picked explicitly, e.g. "basic render" for WARP
+                        let is_candidate = match adapter_choice
                         {
-                            // skip software adapter
-                            adapter_index = adapter_index + 1;
-                            continue;
-                        }
+                            AdapterChoice::Auto => (adapter_desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 as u32) == 0,
+                            AdapterChoice::Index(index) => adapter_index == *index,
+                            AdapterChoice::Name(name) => adapter_name.to_lowercase().contains(&name.to_lowercase()),
+                        };
 
                         // whenever an adapter with the highest feature level succeeds a intialization, jump out
-                        if let Ok(_) = D3D12CreateDevice(&x, feature_levels[feature_index], &mut d3d12_device)
+                        if is_candidate && D3D12CreateDevice(&x, feature_levels[feature_index], &mut d3d12_device).is_ok()
                         {
-                            println!("Selected adapter for D3D12CreateDevice: {}", String::from_utf16(&adapter_desc.Description).unwrap());
+                            println!("Selected adapter for D3D12CreateDevice: {}", adapter_name);
                             println!("Intialized with feature level: {}", feature_levels_name[feature_index]);
                             GD3D12_DEVICE = d3d12_device;
+                            GDXGI_ADAPTER = x.cast().ok();
                             break 'FeatureLevelLoop;
                         }
                     }
@@ -99,6 +147,18 @@ fn create_device()
 
                 feature_index = feature_index + 1;
             }
+
+            if GD3D12_DEVICE.is_none() && *adapter_choice != AdapterChoice::Auto
+            {
+                println!("No adapter matches {:?} or it doesn't support D3D12, the adapters are:", adapter_choice);
+                let mut adapter_index = 0;
+                while let Ok(x) = GDXGI_FACTORY.as_ref().unwrap().EnumAdapters1(adapter_index)
+                {
+                    let adapter_desc = x.GetDesc1().unwrap();
+                    println!("  {}: {}", adapter_index, String::from_utf16_lossy(&adapter_desc.Description).trim_end_matches('\0'));
+                    adapter_index = adapter_index + 1;
+                }
+            }
         }
 
         // cache an ID3D12InfoQueue interface for use if device creation and debug layer are ready.
@@ -146,28 +206,62 @@ fn create_command_buffers()
     }
 }
 
-// function to create swapchain
-fn create_swapchain(h_wnd : HWND, render_width : u32, render_height : u32)
+// a presentable surface of a window, every window owns its swapchain, back buffer RTVs, back buffers, depth buffer and
+// MSAA target while all of them share the same device and command queue.
+pub struct Surface
+{
+    h_wnd : HWND,
+    swapchain : IDXGISwapChain3,
+    // one RTV per back buffer
+    rtvs : DescriptorHandle,
+    back_buffers : [Option<ID3D12Resource>; GMAXFRAME],
+    back_buffer_index : u32,
+    // whether the scene is drawn into the back buffer. with a render scale it's drawn into a ScaledTarget and only blitted here,
+    // then the surface doesn't need a depth buffer or an MSAA target of its own
+    is_scene_target : bool,
+    // one depth buffer is enough since the frames don't overlap on the GPU
+    depth_buffer : Option<DepthBuffer>,
+    // the same goes for the multisampled target, it's resolved into the current back buffer. None without MSAA or a scene
+    msaa_target : Option<MsaaTarget>,
+    width : u32,
+    height : u32,
+}
+
+// function to check whether the tearing is supported, with ALLOW_TEARING we can render above monitor's refresh rate
+fn check_screen_tearing_support()
 {
     unsafe
     {
-        let device = GD3D12_DEVICE.as_ref().unwrap();
         // use the cast() function in windows_core::Interface, it's basically the equivalent of QueryInterface in c++ COM
         let factory : IDXGIFactory5 = GDXGI_FACTORY.as_ref().unwrap().cast().unwrap();
 
-        // check screen tearing support, with ALLOW_TEARING we can render above monitor's refresh rate
         let mut support_tearing : bool = false;
         // to force-convert as c_void pointer, make it as _ first then the c_void
         let _ = factory.CheckFeatureSupport(DXGI_FEATURE_PRESENT_ALLOW_TEARING, &mut support_tearing as *mut _ as *mut c_void
             , mem::size_of::<DXGI_FEATURE>().try_into().unwrap());
         GSUPPORT_SCREEN_TEARING = support_tearing;
+    }
+}
 
-        let mut swapchain_flags : u32 = 0;
+fn get_swapchain_flags() -> DXGI_SWAP_CHAIN_FLAG
+{
+    unsafe
+    {
+        let mut swapchain_flags : DXGI_SWAP_CHAIN_FLAG = DXGI_SWAP_CHAIN_FLAG::default();
         if GSUPPORT_SCREEN_TEARING
         {
-            swapchain_flags = swapchain_flags | DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING.0 as u32;
+            swapchain_flags = swapchain_flags | DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING;
         }
 
+        return swapchain_flags;
+    }
+}
+
+// function to create a surface for a window, is_scene_target tells whether the scene is drawn into the back buffers
+pub fn create_surface(h_wnd : HWND, render_width : u32, render_height : u32, is_scene_target : bool) -> Option<Surface>
+{
+    unsafe
+    {
         // create swapchain
         let swapchain_desc = DXGI_SWAP_CHAIN_DESC1
         {
@@ -182,45 +276,632 @@ fn create_swapchain(h_wnd : HWND, render_width : u32, render_height : u32)
                 Count : 1,
                 Quality : 0,
             },
-            Flags : swapchain_flags,
+            Flags : get_swapchain_flags().0 as u32,
             ..DXGI_SWAP_CHAIN_DESC1::default()
         };
 
-        if let Ok(x) = GDXGI_FACTORY.as_ref().unwrap().CreateSwapChainForHwnd(GMAIN_COMMAND_QUEUE.as_ref().unwrap(), h_wnd, &swapchain_desc, None, None)
+        let swapchain : IDXGISwapChain3 = match GDXGI_FACTORY.as_ref().unwrap().CreateSwapChainForHwnd(GMAIN_COMMAND_QUEUE.as_ref().unwrap(), h_wnd, &swapchain_desc, None, None)
         {
-            GSWAPCHAIN = Some(x.cast().unwrap());
-        }
+            Ok(x) => x.cast().unwrap(),
+            Err(_) => return None,
+        };
 
         // disable alt+enter behavior for now
         let _ = GDXGI_FACTORY.as_ref().unwrap().MakeWindowAssociation(h_wnd, DXGI_MWA_NO_ALT_ENTER);
 
-        // create swapchain descriptor heap
-        let swapchain_descriptor_heap_desc = D3D12_DESCRIPTOR_HEAP_DESC
+        // RTVs of the back buffers
+        let rtvs = descriptor_heap::allocate(DescriptorHeapType::Rtv, GMAXFRAME as u32)?;
+
+        let mut surface = Surface
         {
-            NumDescriptors : GMAXFRAME as u32,
-            Type : D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-            Flags : D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
-            ..D3D12_DESCRIPTOR_HEAP_DESC::default()
+            h_wnd,
+            swapchain,
+            rtvs,
+            back_buffers : [None, None],
+            back_buffer_index : 0,
+            is_scene_target,
+            depth_buffer : if is_scene_target { depth_buffer::create_depth_buffer(render_width, render_height) } else { None },
+            msaa_target : if is_scene_target { msaa::create_msaa_target(render_width, render_height) } else { None },
+            width : render_width,
+            height : render_height,
         };
+        surface.create_back_buffer_views();
+
+        return Some(surface);
+    }
+}
+
+impl Drop for Surface
+{
+    fn drop(&mut self)
+    {
+        self.unregister_back_buffers();
+        descriptor_heap::free(&self.rtvs);
+    }
+}
+
+impl Surface
+{
+    // function to get the back buffers from swapchain and create their RTVs
+    fn create_back_buffer_views(&mut self)
+    {
+        unsafe
+        {
+            let device = GD3D12_DEVICE.as_ref().unwrap();
+            for idx in 0..GMAXFRAME
+            {
+                if let Ok(x) = self.swapchain.GetBuffer::<ID3D12Resource>(idx as u32)
+                {
+                    device.CreateRenderTargetView(&x, None, descriptor_heap::get_cpu_handle(&self.rtvs, idx as u32));
+                    resource_states::register_resource(&x, D3D12_RESOURCE_STATE_PRESENT);
+                    self.back_buffers[idx] = Some(x);
+                }
+            }
+
+            self.back_buffer_index = self.swapchain.GetCurrentBackBufferIndex();
+        }
+    }
+
+    fn unregister_back_buffers(&self)
+    {
+        for x in self.back_buffers.iter().flatten()
+        {
+            resource_states::unregister_resource(x);
+        }
+    }
+
+    // function to resize the back buffers, GPU must not be using them so it waits for the GPU first
+    pub fn resize(&mut self, render_width : u32, render_height : u32)
+    {
+        // minimized windows report 0 size, keep the old buffers in that case
+        if render_width == 0 || render_height == 0 || (render_width == self.width && render_height == self.height)
+        {
+            return;
+        }
+
+        unsafe
+        {
+            wait_for_gpu();
+
+            // all references to the back buffers must be released before ResizeBuffers
+            self.unregister_back_buffers();
+            self.back_buffers = [None, None];
+            if let Err(x) = self.swapchain.ResizeBuffers(GMAXFRAME as u32, render_width, render_height, DXGI_FORMAT_UNKNOWN, get_swapchain_flags())
+            {
+                println!("Error during swapchain resizing! {}", x.message());
+            }
+            else
+            {
+                self.width = render_width;
+                self.height = render_height;
+                if self.is_scene_target
+                {
+                    self.depth_buffer = depth_buffer::create_depth_buffer(render_width, render_height);
+                    self.msaa_target = msaa::create_msaa_target(render_width, render_height);
+                }
+            }
+
+            self.create_back_buffer_views();
+        }
+    }
 
-        if let Ok(x) = device.CreateDescriptorHeap::<ID3D12DescriptorHeap>(&swapchain_descriptor_heap_desc)
+    // function to switch whether the scene is drawn into the back buffer, the depth buffer and MSAA target are created or released with it
+    pub fn set_scene_target(&mut self, is_scene_target : bool)
+    {
+        if is_scene_target == self.is_scene_target
         {
-            GSWAPCHAIN_HEAP = Some(x);
+            return;
         }
-        GRTV_DESCRIPTOR_SIZE = device.GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_RTV);
 
-        let mut rtv_handle : D3D12_CPU_DESCRIPTOR_HANDLE = GSWAPCHAIN_HEAP.as_ref().unwrap().GetCPUDescriptorHandleForHeapStart();
-        for idx in 0..GMAXFRAME
+        // the old buffers might still be used by the GPU
+        wait_for_gpu();
+        self.is_scene_target = is_scene_target;
+        self.depth_buffer = if is_scene_target { depth_buffer::create_depth_buffer(self.width, self.height) } else { None };
+        self.msaa_target = if is_scene_target { msaa::create_msaa_target(self.width, self.height) } else { None };
+    }
+
+    // present the backbuffer, returns false if the window is occluded and nothing is visible
+    pub fn present(&mut self) -> bool
+    {
+        unsafe
         {
-            if let Ok(x) = GSWAPCHAIN.as_ref().unwrap().GetBuffer::<ID3D12Resource>(idx as u32)
+            // tearing is only allowed with sync interval 0
+            let mut present_flags : DXGI_PRESENT = DXGI_PRESENT::default();
+            if GSUPPORT_SCREEN_TEARING && !GVSYNC
             {
-                GSWAPCHAIN_RESOURCE[idx] = Some(x);
-                device.CreateRenderTargetView(GSWAPCHAIN_RESOURCE[idx].as_ref().unwrap(), None, rtv_handle);
-                rtv_handle.ptr = rtv_handle.ptr + GRTV_DESCRIPTOR_SIZE as usize;
+                present_flags = present_flags | DXGI_PRESENT_ALLOW_TEARING;
             }
+
+            let sync_interval = if GVSYNC { 1 } else { 0 };
+            let result = self.swapchain.Present(sync_interval, present_flags);
+
+            // advance frame index
+            self.back_buffer_index = self.swapchain.GetCurrentBackBufferIndex();
+
+            return result != DXGI_STATUS_OCCLUDED;
+        }
+    }
+
+    // test whether the window is still occluded without presenting anything, used to resume rendering after an occlusion
+    pub fn is_occluded(&self) -> bool
+    {
+        unsafe
+        {
+            return self.swapchain.Present(0, DXGI_PRESENT_TEST) == DXGI_STATUS_OCCLUDED;
+        }
+    }
+
+    pub fn get_hwnd(&self) -> HWND
+    {
+        return self.h_wnd;
+    }
+
+    pub fn get_width(&self) -> u32
+    {
+        return self.width;
+    }
+
+    pub fn get_height(&self) -> u32
+    {
+        return self.height;
+    }
+
+    pub fn get_back_buffer_rtv(&self) -> D3D12_CPU_DESCRIPTOR_HANDLE
+    {
+        // the RTV of the current back buffer
+        return descriptor_heap::get_cpu_handle(&self.rtvs, self.back_buffer_index);
+    }
+
+    pub fn get_back_buffer_resource(&self) -> &Option<ID3D12Resource>
+    {
+        return &self.back_buffers[self.back_buffer_index as usize];
+    }
+
+    pub fn get_render_target(&self) -> RenderTarget
+    {
+        return RenderTarget
+        {
+            resource : self.get_back_buffer_resource().clone(),
+            rtv : self.get_back_buffer_rtv(),
+            depth_resource : self.depth_buffer.as_ref().map(|x| x.get_resource().clone()),
+            dsv : self.depth_buffer.as_ref().map(|x| x.get_dsv()),
+            msaa_resource : self.msaa_target.as_ref().map(|x| x.get_resource().clone()),
+            msaa_rtv : self.msaa_target.as_ref().map(|x| x.get_rtv()),
+            width : self.width,
+            height : self.height,
+            resting_state : D3D12_RESOURCE_STATE_PRESENT,
+        };
+    }
+}
+
+// a render target the demo can draw into, it can be a back buffer of a surface or an offscreen texture.
+// resting_state is the state the resource stays in outside of rendering, PRESENT for back buffers for example.
+#[derive(Clone)]
+pub struct RenderTarget
+{
+    pub resource : Option<ID3D12Resource>,
+    pub rtv : D3D12_CPU_DESCRIPTOR_HANDLE,
+    // the depth buffer of the same size, it rests in DEPTH_WRITE state. None when the target has no depth
+    pub depth_resource : Option<ID3D12Resource>,
+    pub dsv : Option<D3D12_CPU_DESCRIPTOR_HANDLE>,
+    // the multisampled color target of the same size, the scene is drawn into it and resolved into resource at the end.
+    // the depth buffer has its sample count then. it rests in RESOLVE_SOURCE state, None without MSAA
+    pub msaa_resource : Option<ID3D12Resource>,
+    pub msaa_rtv : Option<D3D12_CPU_DESCRIPTOR_HANDLE>,
+    pub width : u32,
+    pub height : u32,
+    pub resting_state : D3D12_RESOURCE_STATES,
+}
+
+// an offscreen render target for rendering without a window, it rests in COPY_SOURCE state so it can be read back any time
+pub struct OffscreenTarget
+{
+    resource : ID3D12Resource,
+    rtv : DescriptorHandle,
+    depth_buffer : Option<DepthBuffer>,
+    msaa_target : Option<MsaaTarget>,
+    readback : TextureReadback,
+    width : u32,
+    height : u32,
+}
+
+// a readback buffer that is able to hold a copy of a 2D texture, rows are padded to D3D12_TEXTURE_DATA_PITCH_ALIGNMENT in it
+pub struct TextureReadback
+{
+    buffer : ID3D12Resource,
+    footprint : Footprint,
+    texture_format : DXGI_FORMAT,
+    image_format : ImageFormat,
+}
+
+// function to create an offscreen render target with the back buffer format
+pub fn create_offscreen_target(render_width : u32, render_height : u32) -> Option<OffscreenTarget>
+{
+    unsafe
+    {
+        let device = GD3D12_DEVICE.as_ref().unwrap();
+
+        let texture_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width : render_width as u64,
+            Height : render_height,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : GBACK_BUFFER_FORMAT,
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            Layout : D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags : D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        let heap_properties = D3D12_HEAP_PROPERTIES
+        {
+            Type : D3D12_HEAP_TYPE_DEFAULT,
+            ..D3D12_HEAP_PROPERTIES::default()
+        };
+
+        let mut resource : Option<ID3D12Resource> = None;
+        if device.CreateCommittedResource(&heap_properties, D3D12_HEAP_FLAG_NONE, &texture_desc, D3D12_RESOURCE_STATE_COPY_SOURCE, None, &mut resource).is_err()
+        {
+            println!("Error during offscreen target creation!");
+            return None;
         }
 
-        GCURRENT_FRAME_INDEX = GSWAPCHAIN.as_ref().unwrap().GetCurrentBackBufferIndex();
+        let readback = create_texture_readback(resource.as_ref().unwrap())?;
+
+        let rtv = descriptor_heap::allocate(DescriptorHeapType::Rtv, 1)?;
+        device.CreateRenderTargetView(resource.as_ref().unwrap(), None, descriptor_heap::get_cpu_handle(&rtv, 0));
+        resource_states::register_resource(resource.as_ref().unwrap(), D3D12_RESOURCE_STATE_COPY_SOURCE);
+
+        return Some(OffscreenTarget
+        {
+            resource : resource.unwrap(),
+            rtv,
+            depth_buffer : depth_buffer::create_depth_buffer(render_width, render_height),
+            msaa_target : msaa::create_msaa_target(render_width, render_height),
+            readback,
+            width : render_width,
+            height : render_height,
+        });
+    }
+}
+
+impl Drop for OffscreenTarget
+{
+    fn drop(&mut self)
+    {
+        resource_states::unregister_resource(&self.resource);
+        descriptor_heap::free(&self.rtv);
+    }
+}
+
+impl OffscreenTarget
+{
+    pub fn get_render_target(&self) -> RenderTarget
+    {
+        return RenderTarget
+        {
+            resource : Some(self.resource.clone()),
+            rtv : descriptor_heap::get_cpu_handle(&self.rtv, 0),
+            depth_resource : self.depth_buffer.as_ref().map(|x| x.get_resource().clone()),
+            dsv : self.depth_buffer.as_ref().map(|x| x.get_dsv()),
+            msaa_resource : self.msaa_target.as_ref().map(|x| x.get_resource().clone()),
+            msaa_rtv : self.msaa_target.as_ref().map(|x| x.get_rtv()),
+            width : self.width,
+            height : self.height,
+            resting_state : D3D12_RESOURCE_STATE_COPY_SOURCE,
+        };
+    }
+
+    // function to copy the rendered image to CPU memory, it waits for the GPU so it's meant for tools and tests rather than real time use.
+    pub fn read_back(&self) -> Image
+    {
+        self.readback.copy_from(&self.resource, D3D12_RESOURCE_STATE_COPY_SOURCE);
+        wait_for_gpu();
+        return self.readback.read();
+    }
+
+    pub fn get_width(&self) -> u32
+    {
+        return self.width;
+    }
+
+    pub fn get_height(&self) -> u32
+    {
+        return self.height;
+    }
+}
+
+// function to create a readback buffer for the first subresource of a texture
+pub fn create_texture_readback(texture : &ID3D12Resource) -> Option<TextureReadback>
+{
+    unsafe
+    {
+        let device = GD3D12_DEVICE.as_ref().unwrap();
+        let texture_desc = texture.GetDesc();
+        let image_format = match ImageFormat::from_dxgi_format(texture_desc.Format.0 as u32)
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("Readback of texture format {} is not supported!", texture_desc.Format.0);
+                return None;
+            }
+        };
+
+        // how the texture is laid out in a buffer, rows are padded to the pitch alignment
+        let footprint = image_io::compute_footprint(texture_desc.Width as u32, texture_desc.Height, image_format);
+
+        let buffer_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width : footprint.total_bytes,
+            Height : 1,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : DXGI_FORMAT_UNKNOWN,
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            Layout : D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags : D3D12_RESOURCE_FLAG_NONE,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        let heap_properties = D3D12_HEAP_PROPERTIES
+        {
+            Type : D3D12_HEAP_TYPE_READBACK,
+            ..D3D12_HEAP_PROPERTIES::default()
+        };
+
+        let mut buffer : Option<ID3D12Resource> = None;
+        if device.CreateCommittedResource(&heap_properties, D3D12_HEAP_FLAG_NONE, &buffer_desc, D3D12_RESOURCE_STATE_COPY_DEST, None, &mut buffer).is_err()
+        {
+            println!("Error during readback buffer creation!");
+            return None;
+        }
+
+        return Some(TextureReadback
+        {
+            buffer : buffer.unwrap(),
+            footprint,
+            texture_format : texture_desc.Format,
+            image_format,
+        });
+    }
+}
+
+impl TextureReadback
+{
+    // function to record and execute the copy from texture to the readback buffer, the texture is left in resting_state afterwards.
+    // the data is available after the GPU finished the copy, see wait_for_gpu().
+    pub fn copy_from(&self, texture : &ID3D12Resource, resting_state : D3D12_RESOURCE_STATES)
+    {
+        unsafe
+        {
+            let command_list = GMAIN_COMMAND_LIST.as_ref().unwrap();
+            let _ = command_list.Reset(GMAIN_COMMAND_ALLOCATOR.as_ref().unwrap(), None);
+
+            // the readback buffer is always in COPY_DEST, only the texture is tracked
+            let mut resource_states = CommandListStates::new();
+            resource_states.use_as_copy_source(texture);
+            resource_states.flush(command_list);
+
+            let dest_location = D3D12_TEXTURE_COPY_LOCATION
+            {
+                pResource : mem::transmute_copy(&self.buffer),
+                Type : D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+                Anonymous : D3D12_TEXTURE_COPY_LOCATION_0
+                {
+                    PlacedFootprint : D3D12_PLACED_SUBRESOURCE_FOOTPRINT
+                    {
+                        Offset : 0,
+                        Footprint : D3D12_SUBRESOURCE_FOOTPRINT
+                        {
+                            Format : self.texture_format,
+                            Width : self.footprint.width,
+                            Height : self.footprint.height,
+                            Depth : 1,
+                            RowPitch : self.footprint.row_pitch as u32,
+                        },
+                    },
+                },
+            };
+
+            let source_location = D3D12_TEXTURE_COPY_LOCATION
+            {
+                pResource : mem::transmute_copy(texture),
+                Type : D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+                Anonymous : D3D12_TEXTURE_COPY_LOCATION_0
+                {
+                    SubresourceIndex : 0,
+                },
+            };
+            command_list.CopyTextureRegion(&dest_location, 0, 0, 0, &source_location, None);
+
+            resource_states.require(texture, None, resting_state);
+            resource_states.execute(command_list);
+        }
+    }
+
+    // function to read the copied data, padded rows are packed tightly in the returned image
+    pub fn read(&self) -> Image
+    {
+        unsafe
+        {
+            let mut image = Image
+            {
+                width : self.footprint.width,
+                height : self.footprint.height,
+                format : self.image_format,
+                pixels : Vec::new(),
+            };
+
+            let mut mapped_data : *mut c_void = std::ptr::null_mut();
+            if self.buffer.Map(0, None, Some(&mut mapped_data)).is_err()
+            {
+                println!("Error during readback buffer mapping!");
+                return image;
+            }
+
+            let padded_data = std::slice::from_raw_parts(mapped_data as *const u8, self.footprint.total_bytes as usize);
+            image.pixels = image_io::pack_rows(padded_data, &self.footprint);
+
+            // nothing is written by CPU, pass an empty range
+            let written_range = D3D12_RANGE::default();
+            self.buffer.Unmap(0, Some(&written_range));
+
+            return image;
+        }
+    }
+}
+
+// function to create a texture from a CPU image, the pixels are uploaded and the texture rests in PIXEL_SHADER_RESOURCE state.
+// it's registered for state tracking, unregister it before it's released. it waits for the GPU, so it's meant for loading and not for every frame.
+pub fn create_texture_from_image(image : &Image) -> Option<ID3D12Resource>
+{
+    unsafe
+    {
+        let device = GD3D12_DEVICE.as_ref().unwrap();
+        let texture_format = DXGI_FORMAT(image.format.dxgi_format() as i32);
+        let footprint = image_io::compute_footprint(image.width, image.height, image.format);
+
+        let texture_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width : image.width as u64,
+            Height : image.height,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : texture_format,
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            Layout : D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags : D3D12_RESOURCE_FLAG_NONE,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        let default_heap_properties = D3D12_HEAP_PROPERTIES
+        {
+            Type : D3D12_HEAP_TYPE_DEFAULT,
+            ..D3D12_HEAP_PROPERTIES::default()
+        };
+
+        let mut texture : Option<ID3D12Resource> = None;
+        if device.CreateCommittedResource(&default_heap_properties, D3D12_HEAP_FLAG_NONE, &texture_desc, D3D12_RESOURCE_STATE_COPY_DEST, None, &mut texture).is_err()
+        {
+            println!("Error during texture creation!");
+            return None;
+        }
+        let texture = texture.unwrap();
+
+        let buffer_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_BUFFER,
+            Width : footprint.total_bytes,
+            Height : 1,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : DXGI_FORMAT_UNKNOWN,
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            Layout : D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags : D3D12_RESOURCE_FLAG_NONE,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        let upload_heap_properties = D3D12_HEAP_PROPERTIES
+        {
+            Type : D3D12_HEAP_TYPE_UPLOAD,
+            ..D3D12_HEAP_PROPERTIES::default()
+        };
+
+        let mut upload_buffer : Option<ID3D12Resource> = None;
+        if device.CreateCommittedResource(&upload_heap_properties, D3D12_HEAP_FLAG_NONE, &buffer_desc, D3D12_RESOURCE_STATE_GENERIC_READ, None, &mut upload_buffer).is_err()
+        {
+            println!("Error during upload buffer creation!");
+            return None;
+        }
+        let upload_buffer = upload_buffer.unwrap();
+
+        // copy the tightly packed rows to the padded rows of the buffer
+        let mut mapped_data : *mut c_void = std::ptr::null_mut();
+        if upload_buffer.Map(0, None, Some(&mut mapped_data)).is_err()
+        {
+            println!("Error during upload buffer mapping!");
+            return None;
+        }
+        let padded_data = std::slice::from_raw_parts_mut(mapped_data as *mut u8, footprint.total_bytes as usize);
+        let row_size = footprint.row_size as usize;
+        for row in 0..image.height as usize
+        {
+            let dest_offset = row * footprint.row_pitch as usize;
+            padded_data[dest_offset..dest_offset + row_size].copy_from_slice(&image.pixels[row * row_size..(row + 1) * row_size]);
+        }
+        upload_buffer.Unmap(0, None);
+
+        // registered only once nothing can fail anymore, the ids are the resource pointers and a stale one would be inherited
+        resource_states::register_resource(&texture, D3D12_RESOURCE_STATE_COPY_DEST);
+
+        let command_list = GMAIN_COMMAND_LIST.as_ref().unwrap();
+        let _ = command_list.Reset(GMAIN_COMMAND_ALLOCATOR.as_ref().unwrap(), None);
+
+        // the upload buffer stays in GENERIC_READ and is released right after the copy, only the texture is tracked
+        let mut resource_states = CommandListStates::new();
+        resource_states.use_as_copy_dest(&texture);
+        resource_states.flush(command_list);
+
+        let dest_location = D3D12_TEXTURE_COPY_LOCATION
+        {
+            pResource : mem::transmute_copy(&texture),
+            Type : D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            Anonymous : D3D12_TEXTURE_COPY_LOCATION_0
+            {
+                SubresourceIndex : 0,
+            },
+        };
+
+        let source_location = D3D12_TEXTURE_COPY_LOCATION
+        {
+            pResource : mem::transmute_copy(&upload_buffer),
+            Type : D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            Anonymous : D3D12_TEXTURE_COPY_LOCATION_0
+            {
+                PlacedFootprint : D3D12_PLACED_SUBRESOURCE_FOOTPRINT
+                {
+                    Offset : 0,
+                    Footprint : D3D12_SUBRESOURCE_FOOTPRINT
+                    {
+                        Format : texture_format,
+                        Width : footprint.width,
+                        Height : footprint.height,
+                        Depth : 1,
+                        RowPitch : footprint.row_pitch as u32,
+                    },
+                },
+            },
+        };
+        command_list.CopyTextureRegion(&dest_location, 0, 0, 0, &source_location, None);
+
+        resource_states.require(&texture, None, D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE);
+        resource_states.execute(command_list);
+
+        // the upload buffer must stay alive until the copy is done
+        wait_for_gpu();
+        return Some(texture);
     }
 }
 
@@ -244,8 +925,66 @@ fn create_fence()
     }
 }
 
-// function to initialize d3d12
-pub fn initialize_d3d12(h_wnd : HWND, render_width : u32, render_height : u32) -> bool
+// function to register the event the OS signals when the video memory budget changes
+fn create_video_memory_budget_notification()
+{
+    unsafe
+    {
+        let adapter = match GDXGI_ADAPTER.as_ref()
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        if let Ok(x) = CreateEventW(None, FALSE, FALSE, None)
+        {
+            GVIDEO_MEMORY_BUDGET_EVENT = Some(x);
+            GVIDEO_MEMORY_BUDGET_COOKIE = adapter.RegisterVideoMemoryBudgetChangeNotificationEvent(x).ok();
+        }
+    }
+}
+
+// function to query the video memory usage and budget, the residency manager evicts when the usage is over the budget
+fn poll_video_memory()
+{
+    unsafe
+    {
+        let adapter = match GDXGI_ADAPTER.as_ref()
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        let mut local_info = DXGI_QUERY_VIDEO_MEMORY_INFO::default();
+        let mut non_local_info = DXGI_QUERY_VIDEO_MEMORY_INFO::default();
+        if adapter.QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_LOCAL, &mut local_info).is_err()
+            || adapter.QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_NON_LOCAL, &mut non_local_info).is_err()
+        {
+            return;
+        }
+
+        let video_memory_stats = &mut *std::ptr::addr_of_mut!(GVIDEO_MEMORY_STATS);
+        video_memory_stats.local_usage = local_info.CurrentUsage;
+        video_memory_stats.local_budget = local_info.Budget;
+        video_memory_stats.non_local_usage = non_local_info.CurrentUsage;
+        video_memory_stats.non_local_budget = non_local_info.Budget;
+
+        // the event is signaled once per change, don't wait for it
+        if let Some(x) = GVIDEO_MEMORY_BUDGET_EVENT
+        {
+            if WaitForSingleObject(x, 0) == WAIT_OBJECT_0
+            {
+                video_memory_stats.budget_change_count += 1;
+                println!("Video memory budget changed: {} MB used of {} MB.", local_info.CurrentUsage / (1024 * 1024), local_info.Budget / (1024 * 1024));
+            }
+        }
+
+        residency::update_budget(local_info.CurrentUsage, local_info.Budget);
+    }
+}
+
+// function to initialize d3d12, surfaces for windows are created with create_surface() afterwards
+pub fn initialize_d3d12() -> bool
 {
     unsafe 
     {
@@ -263,10 +1002,24 @@ pub fn initialize_d3d12(h_wnd : HWND, render_width : u32, render_height : u32) -
             return false;
         }
 
-        create_swapchain(h_wnd, render_width, render_height);
-        if GSWAPCHAIN.is_none() || GSWAPCHAIN_HEAP.is_none()
+        check_screen_tearing_support();
+        msaa::initialize();
+        if !descriptor_heap::initialize()
         {
-            println!("Error during swapchain creation!");
+            println!("Error during descriptor heaps creation!");
+            return false;
+        }
+
+        if !resource_states::initialize()
+        {
+            println!("Error during resource state tracking creation!");
+            return false;
+        }
+
+        residency::initialize();
+        if !gpu_memory::initialize()
+        {
+            println!("Error during GPU memory allocator creation!");
             return false;
         }
 
@@ -276,6 +1029,9 @@ pub fn initialize_d3d12(h_wnd : HWND, render_width : u32, render_height : u32) -
             println!("Error during fence creation!");
             return false;
         }
+
+        create_video_memory_budget_notification();
+        poll_video_memory();
     }
 
     return true;
@@ -287,6 +1043,18 @@ pub fn shutdown()
     unsafe
     {
         wait_for_gpu();
+        resource_states::shutdown();
+        gpu_memory::shutdown();
+        residency::shutdown();
+
+        if let (Some(x), Some(y)) = (GDXGI_ADAPTER.as_ref(), GVIDEO_MEMORY_BUDGET_COOKIE.take())
+        {
+            x.UnregisterVideoMemoryBudgetChangeNotification(y);
+        }
+        if let Some(x) = GVIDEO_MEMORY_BUDGET_EVENT.take()
+        {
+            let _ = CloseHandle(x);
+        }
         let _ = CloseHandle(GMAIN_FENCE_EVENT.as_ref());
     }
 }
@@ -307,9 +1075,23 @@ pub fn wait_for_gpu()
             let _ = main_fence.SetEventOnCompletion(prev_fence_value, fence_event);
             WaitForSingleObject(fence_event, INFINITE);
         }
+    }
+}
 
-        // advance frame index
-        GCURRENT_FRAME_INDEX = GSWAPCHAIN.as_ref().unwrap().GetCurrentBackBufferIndex();
+// the fence value the next signal of the main queue uses, everything executed before it completes with that value
+pub fn get_next_fence_value() -> u64
+{
+    unsafe
+    {
+        return GMAIN_FENCE_VALUE;
+    }
+}
+
+pub fn get_completed_fence_value() -> u64
+{
+    unsafe
+    {
+        return GMAIN_FENCE.as_ref().unwrap().GetCompletedValue();
     }
 }
 
@@ -350,21 +1132,20 @@ pub fn update()
             }
         }
     }
+
+    poll_video_memory();
 }
 
-// present the backbuffer
-pub fn present()
+// reset the command allocator, called once per frame before any command list is recorded.
+// the GPU must have finished the previous frame, which is guaranteed by wait_for_gpu() at the end of the frame.
+pub fn begin_frame()
 {
     unsafe
     {
-        let mut present_flags : DXGI_PRESENT = DXGI_PRESENT::default();
-        if GSUPPORT_SCREEN_TEARING
-        {
-            present_flags = present_flags | DXGI_PRESENT_ALLOW_TEARING;
-        }
-
-        let _ = GSWAPCHAIN.as_ref().unwrap().Present(0, present_flags);
+        let _ = GMAIN_COMMAND_ALLOCATOR.as_ref().unwrap().Reset();
     }
+    resource_states::begin_frame();
+    gpu_memory::begin_frame();
 }
 
 // getter functions
@@ -400,27 +1181,62 @@ pub fn get_command_queue() -> &'static ID3D12CommandQueue
     }
 }
 
-pub fn get_back_buffer_rtv() -> D3D12_CPU_DESCRIPTOR_HANDLE
+// function to turn vsync on or off, it applies to all surfaces from the next present
+pub fn set_vsync(vsync : bool)
 {
     unsafe
     {
-        // offset the handle based on frame index
-        let mut rtv_handle : D3D12_CPU_DESCRIPTOR_HANDLE = GSWAPCHAIN_HEAP.as_ref().unwrap().GetCPUDescriptorHandleForHeapStart();
-        rtv_handle.ptr = rtv_handle.ptr + (GRTV_DESCRIPTOR_SIZE * GCURRENT_FRAME_INDEX) as usize;
+        GVSYNC = vsync;
+    }
+}
 
-        return rtv_handle;
+pub fn get_vsync() -> bool
+{
+    unsafe
+    {
+        return GVSYNC;
+    }
+}
+
+pub fn get_video_memory_stats() -> VideoMemoryStats
+{
+    unsafe
+    {
+        return GVIDEO_MEMORY_STATS;
     }
 }
 
 pub fn get_back_buffer_format() -> DXGI_FORMAT
 {
-    return GBACK_BUFFER_FORMAT;
+    unsafe
+    {
+        return GBACK_BUFFER_FORMAT;
+    }
+}
+
+// function to pick the format of the swapchains and the offscreen targets, call it before initialize_d3d12()
+pub fn set_back_buffer_format(format : ImageFormat)
+{
+    unsafe
+    {
+        GBACK_BUFFER_FORMAT = DXGI_FORMAT(format.dxgi_format() as i32);
+    }
 }
 
-pub fn get_back_buffer_resource() -> &'static Option<ID3D12Resource>
+// function to enable or disable the D3D12 debug layer, call it before initialize_d3d12()
+pub fn set_debug_layer(enabled : bool)
 {
     unsafe
     {
-        return &GSWAPCHAIN_RESOURCE[GCURRENT_FRAME_INDEX as usize];
+        GDEBUG_LAYER = enabled;
     }
-}
\ No newline at end of file
+}
+
+// function to pick the adapter for the device, call it before initialize_d3d12()
+pub fn set_adapter_choice(adapter_choice : AdapterChoice)
+{
+    unsafe
+    {
+        GADAPTER_CHOICE = adapter_choice;
+    }
+}
@@ -0,0 +1,66 @@
+// headless.rs - Rendering without any window, frames are rendered into an offscreen target and read back to CPU memory.
+// useful for CI and batch rendering, there is no swapchain involved at all.
+
+use crate::graphic_device;
+use crate::graphic_device::RenderTarget;
+use crate::image_io::Image;
+use crate::upload_allocator;
+use crate::descriptor_heap;
+
+// the entry points of the sample to render, the caller picks them
+pub struct SampleFunctions
+{
+    pub create_pipeline : fn(),
+    pub render : fn(&RenderTarget),
+}
+
+// function to render given number of frames offscreen, on_frame receives the frame index and the read back image of each frame.
+// returns false if the device or the offscreen target couldn't be created.
+pub fn run(sample : &SampleFunctions, render_width : u32, render_height : u32, frame_count : u32, on_frame : impl FnMut(u32, &Image)) -> bool
+{
+    return run_with_update(sample, render_width, render_height, frame_count, |_| {}, on_frame);
+}
+
+// same as run(), but before_frame is called with the frame index before each frame renders, e.g. to feed a replay
+pub fn run_with_update(sample : &SampleFunctions, render_width : u32, render_height : u32, frame_count : u32, mut before_frame : impl FnMut(u32)
+    , mut on_frame : impl FnMut(u32, &Image)) -> bool
+{
+    if !graphic_device::initialize_d3d12() || !upload_allocator::initialize(upload_allocator::GDEFAULT_CAPACITY)
+    {
+        return false;
+    }
+
+    let offscreen_target = match graphic_device::create_offscreen_target(render_width, render_height)
+    {
+        Some(x) => x,
+        None => return false,
+    };
+
+    (sample.create_pipeline)();
+
+    let render_target = offscreen_target.get_render_target();
+    for frame_index in 0..frame_count
+    {
+        before_frame(frame_index);
+
+        graphic_device::update();
+        graphic_device::begin_frame();
+        upload_allocator::begin_frame();
+        descriptor_heap::begin_frame();
+        (sample.render)(&render_target);
+        upload_allocator::end_frame();
+        descriptor_heap::end_frame();
+
+        // read_back() waits for the GPU, so no extra wait_for_gpu() here
+        let image = offscreen_target.read_back();
+        on_frame(frame_index, &image);
+    }
+
+    graphic_device::update();
+    // the target holds placed resources and frees its RTV, so it goes before the memory, the state tracking and the heaps
+    drop(offscreen_target);
+    upload_allocator::shutdown();
+    graphic_device::shutdown();
+    descriptor_heap::shutdown();
+    return true;
+}
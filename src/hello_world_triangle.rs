@@ -12,12 +12,26 @@ use windows::core::*;
 use std::path::PathBuf;
 use std::fs;
 use std::time::*;
+use std::ffi::c_void;
 
 static mut GOVERLAY_STATE : Option<ID3D12PipelineState> = None;
 static mut GHELLO_ROOT_SIGNATURE : Option<ID3D12RootSignature> = None;
+static mut GHELLO_VS_BLOB : Option<ID3DBlob> = None;
 static mut GSTART_TIME : Option<SystemTime> = None;
+static mut GPAUSE_TIME : Option<SystemTime> = None;
 
+// when a fixed time step is set, the demo clock advances by exactly one step per rendered frame instead of following the wall clock
+static mut GFIXED_TIME_STEP : Option<Duration> = None;
+static mut GFIXED_FRAME_INDEX : u32 = 0;
+
+// when a manual time is set, the demo clock shows exactly that time, it's advanced by the caller (e.g. an input replay)
+static mut GMANUAL_TIME : Option<Duration> = None;
+
+use crate::depth_buffer;
 use crate::graphic_device;
+use crate::graphic_device::RenderTarget;
+use crate::msaa;
+use crate::resource_states::CommandListStates;
 
 const fn decode_utf8_char(bytes: &[u8], mut pos: usize) -> Option<(u32, usize)> {
     if bytes.len() == pos {
@@ -122,7 +136,7 @@ pub fn create_pipeline()
     {
         let device = graphic_device::get_device();
 
-        // create a root signature with a pixel-only 32-bit constant.
+        // create a root signature with pixel-only 32-bit constants, the time and the render target size.
         let root_parameter_constant = D3D12_ROOT_PARAMETER
         {
             ParameterType : D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
@@ -133,7 +147,7 @@ pub fn create_pipeline()
                 {
                     ShaderRegister : 0,
                     RegisterSpace : 0,
-                    Num32BitValues : 1,
+                    Num32BitValues : 3,
                 }
             },
             ShaderVisibility : D3D12_SHADER_VISIBILITY_PIXEL,
@@ -180,17 +194,36 @@ pub fn create_pipeline()
             return;
         }
 
+        GHELLO_VS_BLOB = vs_blob.clone();
+        GOVERLAY_STATE = create_overlay_state(vs_blob.as_ref().unwrap(), ps_blob.as_ref().unwrap());
+        if GOVERLAY_STATE.is_none()
+        {
+            println!("Error during pipeline state creation!");
+        }
+
+        // store the start time
+        GSTART_TIME = Some(std::time::SystemTime::now());
+    }
+}
+
+// function to create the overlay pipeline state with the hello world root signature, the pixel shader can be a dropped one
+fn create_overlay_state(vs_blob : &ID3DBlob, ps_blob : &ID3DBlob) -> Option<ID3D12PipelineState>
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+
         // setup byte code structure
         let vs_bytecode = D3D12_SHADER_BYTECODE
         {
-            pShaderBytecode : vs_blob.as_ref().unwrap().GetBufferPointer(),
-            BytecodeLength : vs_blob.as_ref().unwrap().GetBufferSize(),
+            pShaderBytecode : vs_blob.GetBufferPointer(),
+            BytecodeLength : vs_blob.GetBufferSize(),
         };
 
         let ps_bytecode = D3D12_SHADER_BYTECODE
         {
-            pShaderBytecode : ps_blob.as_ref().unwrap().GetBufferPointer(),
-            BytecodeLength : ps_blob.as_ref().unwrap().GetBufferSize(),
+            pShaderBytecode : ps_blob.GetBufferPointer(),
+            BytecodeLength : ps_blob.GetBufferSize(),
         };
 
         // setup an overlay rasterizer
@@ -226,72 +259,160 @@ pub fn create_pipeline()
                 RenderTarget : [render_target_blend_desc; 8],
                 ..D3D12_BLEND_DESC::default()
             },
-            DepthStencilState : D3D12_DEPTH_STENCIL_DESC::default(),
+            // the quad is at depth 0, it passes the depth test of either convention against the cleared depth
+            DepthStencilState : depth_buffer::get_depth_stencil_desc(),
             SampleMask : u32::MAX,
             PrimitiveTopologyType : D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
             NumRenderTargets : 1,
             RTVFormats : rtv_format_list,
-            SampleDesc : DXGI_SAMPLE_DESC
-            {
-                Count : 1,             
-                Quality : 0,  
-            },
+            DSVFormat : depth_buffer::get_dsv_format(),
+            // the scene targets are multisampled with MSAA
+            SampleDesc : msaa::get_sample_desc(),
             ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
         };
 
-        if let Ok(x) = device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc)
+        return device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc).ok();
+    }
+}
+
+// function to compile a pixel shader file with the conventions of HelloWorldPS and use it for the overlay.
+// the shader must have "float4 HelloWorldPS(float4 InPos : SV_POSITION) : SV_TARGET" as the entry point,
+// and can read the HelloWorldConstants cbuffer (b0). the compile errors are returned and the current shader is kept then.
+pub fn load_pixel_shader(path : &std::path::Path) -> std::result::Result<(), String>
+{
+    unsafe
+    {
+        let vs_blob = GHELLO_VS_BLOB.as_ref().ok_or("the hello world pipeline isn't created")?;
+        let shader_path = fs::canonicalize(path).map_err(|x| x.to_string())?;
+
+        // D3D_COMPILE_STANDARD_FILE_INCLUDE is the magic pointer value 1, it resolves #include relative to the shader file.
+        // it isn't a real object, so never let it be dropped.
+        let standard_include = ManuallyDrop::new(ID3DInclude::from_raw(1 as *mut c_void));
+
+        let mut ps_blob : Option<ID3DBlob> = None;
+        let mut error_blob : Option<ID3DBlob> = None;
+        let compile_result = D3DCompileFromFile(&HSTRING::from(shader_path.as_path()), None, &*standard_include, s!("HelloWorldPS"), s!("ps_5_1")
+            , D3DCOMPILE_DEBUG | D3DCOMPILE_SKIP_OPTIMIZATION, 0, &mut ps_blob, Some(&mut error_blob));
+
+        if compile_result.is_err() || ps_blob.is_none()
         {
-            GOVERLAY_STATE = Some(x);
+            let message = match error_blob
+            {
+                Some(x) => String::from_utf8_lossy(std::slice::from_raw_parts(x.GetBufferPointer() as *const u8, x.GetBufferSize())).trim_end_matches('\0').trim().to_string(),
+                None => compile_result.err().map(|x| x.message()).unwrap_or_default(),
+            };
+            return Err(message);
         }
 
-        if GOVERLAY_STATE.is_none()
+        let pipeline_state = create_overlay_state(vs_blob, ps_blob.as_ref().unwrap()).ok_or("pipeline state creation failed")?;
+
+        // the old state might still be used by the GPU
+        graphic_device::wait_for_gpu();
+        GOVERLAY_STATE = Some(pipeline_state);
+        return Ok(());
+    }
+}
+
+// function to pause or resume the demo clock, the animation continues from where it stopped after resuming
+pub fn set_paused(paused : bool)
+{
+    unsafe
+    {
+        if paused && GPAUSE_TIME.is_none()
         {
-            println!("Error during pipeline state creation!");
+            GPAUSE_TIME = Some(SystemTime::now());
         }
+        else if !paused && GPAUSE_TIME.is_some()
+        {
+            // shift the start time by the paused duration
+            if let (Some(start_time), Ok(x)) = (GSTART_TIME, GPAUSE_TIME.unwrap().elapsed())
+            {
+                GSTART_TIME = Some(start_time + x);
+            }
+            GPAUSE_TIME = None;
+        }
+    }
+}
 
-        // store the start time
-        GSTART_TIME = Some(std::time::SystemTime::now());
+// function to drive the demo clock with a fixed step per frame, e.g. 1/60 s for a frame-exact recording.
+// None goes back to the wall clock. the fixed clock restarts from 0 whenever it's set.
+pub fn set_fixed_time_step(time_step : Option<Duration>)
+{
+    unsafe
+    {
+        GFIXED_TIME_STEP = time_step;
+        GFIXED_FRAME_INDEX = 0;
+    }
+}
+
+// function to set the demo time directly, it has priority over the fixed step and the wall clock. None goes back to them.
+pub fn set_manual_time(time : Option<Duration>)
+{
+    unsafe
+    {
+        GMANUAL_TIME = time;
+    }
+}
+
+// function to get the demo time of the next rendered frame
+fn get_demo_time() -> Option<Duration>
+{
+    unsafe
+    {
+        if GMANUAL_TIME.is_some()
+        {
+            return GMANUAL_TIME;
+        }
+
+        if let Some(x) = GFIXED_TIME_STEP
+        {
+            let demo_time = x * GFIXED_FRAME_INDEX;
+            GFIXED_FRAME_INDEX = GFIXED_FRAME_INDEX + 1;
+            return Some(demo_time);
+        }
+
+        let start_time = GSTART_TIME.as_ref().unwrap();
+        return SystemTime::now().duration_since(*start_time).ok();
     }
 }
 
-// function to render for hello world triangle
-pub fn render(width : u32, height : u32)
+// function to render for hello world triangle, the target can be a surface back buffer or an offscreen texture
+pub fn render(target : &RenderTarget)
 {
     unsafe 
     {
-        // reset command buffers
+        // reset command list, the allocator is reset once per frame by graphic_device::begin_frame()
         let command_allocator = graphic_device::get_command_allocator();
         let command_list = graphic_device::get_command_list();
-        let _ = command_allocator.Reset();
         let _ = command_list.Reset(command_allocator, None);
 
-        // transition and clear backbuffer
-        let back_buffer_handle = graphic_device::get_back_buffer_rtv();
-        let clear_color : [f32; 4] = [0.0, 0.2, 0.4, 1.0 ];
+        let width = target.width;
+        let height = target.height;
 
-        // D3D12_RESOURCE_TRANSITION_BARRIER desc
-        let rtv_transition_barrier = D3D12_RESOURCE_TRANSITION_BARRIER
+        // transition and clear render target, the state tracker inserts the barrier from whatever state the target is in.
+        // with MSAA it draws into the multisampled target, which is resolved into the target at the end
+        let target_resource = target.resource.as_ref().unwrap();
+        let (draw_resource, back_buffer_handle) = match (target.msaa_resource.as_ref(), target.msaa_rtv)
         {
-            pResource : ManuallyDrop::new(graphic_device::get_back_buffer_resource().clone()),
-            StateBefore : D3D12_RESOURCE_STATE_PRESENT,
-            StateAfter : D3D12_RESOURCE_STATE_RENDER_TARGET,
-            Subresource : 0,
+            (Some(x), Some(y)) => (x, y),
+            _ => (target_resource, target.rtv),
         };
+        let clear_color : [f32; 4] = [0.0, 0.2, 0.4, 1.0 ];
 
-        // D3D12_RESOURCE_BARRIER desc
-        let rtv_resource_barrier = D3D12_RESOURCE_BARRIER
+        let mut resource_states = CommandListStates::new();
+        resource_states.use_as_render_target(draw_resource);
+        if let Some(x) = target.depth_resource.as_ref()
         {
-            Type : D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-            Anonymous : D3D12_RESOURCE_BARRIER_0
-            {
-                Transition : ManuallyDrop::new(rtv_transition_barrier),
-            },
-            ..D3D12_RESOURCE_BARRIER::default()
-        };
-        command_list.ResourceBarrier(&[rtv_resource_barrier; 1]);
-        
-        command_list.OMSetRenderTargets(1, Some(&back_buffer_handle), FALSE, None);
+            resource_states.require(x, None, D3D12_RESOURCE_STATE_DEPTH_WRITE);
+        }
+        resource_states.flush(command_list);
+
+        command_list.OMSetRenderTargets(1, Some(&back_buffer_handle), FALSE, target.dsv.as_ref().map(|x| x as *const _));
         command_list.ClearRenderTargetView(back_buffer_handle, &clear_color, None);
+        if let Some(x) = target.dsv
+        {
+            depth_buffer::clear(command_list, x);
+        }
 
         // bind graphic state, root signature, viewport and scissor rect
         command_list.SetPipelineState(GOVERLAY_STATE.as_ref().unwrap());
@@ -318,39 +439,28 @@ pub fn render(width : u32, height : u32)
         command_list.RSSetScissorRects(&[scissor_desc; 1]);
 
         // set constant number as elapsed time
-        let start_time = GSTART_TIME.as_ref().unwrap();
-        let current_time = SystemTime::now();
-        if let Ok(x) = current_time.duration_since(*start_time)
+        if let Some(x) = get_demo_time()
         {
             command_list.SetGraphicsRoot32BitConstant(0,x.as_millis() as u32,0);
         }
 
+        // render target size for mapping the pixel position, the float bits are passed as they are
+        let render_size : [f32; 2] = [width as f32, height as f32];
+        command_list.SetGraphicsRoot32BitConstants(0, 2, render_size.as_ptr() as *const c_void, 1);
+
         // set topology and draw full screen quad
         command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
         command_list.DrawInstanced(6, 1, 0, 0);
 
-        // transition back buffer to present state
-        let present_transition_barrier = D3D12_RESOURCE_TRANSITION_BARRIER
+        if let Some(x) = target.msaa_resource.as_ref()
         {
-            pResource : ManuallyDrop::new(graphic_device::get_back_buffer_resource().clone()),
-            StateBefore : D3D12_RESOURCE_STATE_RENDER_TARGET,
-            StateAfter : D3D12_RESOURCE_STATE_PRESENT,
-            Subresource : 0,
-        };
+            msaa::resolve(command_list, &mut resource_states, x, target_resource);
+        }
 
-        let present_resource_barrier = D3D12_RESOURCE_BARRIER
-        {
-            Type : D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-            Anonymous : D3D12_RESOURCE_BARRIER_0
-            {
-                Transition : ManuallyDrop::new(present_transition_barrier),
-            },
-            ..D3D12_RESOURCE_BARRIER::default()
-        };
-        command_list.ResourceBarrier(&[present_resource_barrier; 1]);
+        // transition render target back to its resting state, e.g. present state for back buffer
+        resource_states.require(target_resource, None, target.resting_state);
 
         // close command list and execute
-        let _ = command_list.Close();
-        graphic_device::get_command_queue().ExecuteCommandLists(&[Some(command_list.cast().unwrap())]);
+        resource_states.execute(command_list);
     }
 }
\ No newline at end of file
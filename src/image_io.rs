@@ -0,0 +1,1200 @@
+// image_io.rs - CPU side image helpers: readback footprints, format conversion, PNG / DDS encoding and decoding.
+
+use std::io::Write;
+
+// pixel formats that we know how to read back and encode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat
+{
+    Rgba8Unorm,
+    Rgba8Srgb,
+    Bgra8Unorm,
+    Bgra8Srgb,
+    Rgb10A2Unorm,
+    Rgba16Float,
+}
+
+// layout of a 2D texture copied into a buffer, rows in the buffer are padded to the pitch alignment
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Footprint
+{
+    pub width : u32,
+    pub height : u32,
+    pub row_size : u64,
+    pub row_pitch : u64,
+    pub total_bytes : u64,
+}
+
+// the same value as D3D12_TEXTURE_DATA_PITCH_ALIGNMENT
+pub const GTEXTURE_PITCH_ALIGNMENT : u64 = 256;
+
+// a CPU image, pixels are tightly packed rows in the given format
+pub struct Image
+{
+    pub width : u32,
+    pub height : u32,
+    pub format : ImageFormat,
+    pub pixels : Vec<u8>,
+}
+
+impl ImageFormat
+{
+    pub fn bytes_per_pixel(&self) -> u32
+    {
+        return match self
+        {
+            ImageFormat::Rgba16Float => 8,
+            _ => 4,
+        };
+    }
+
+    pub fn is_srgb(&self) -> bool
+    {
+        return *self == ImageFormat::Rgba8Srgb || *self == ImageFormat::Bgra8Srgb;
+    }
+
+    // the DXGI_FORMAT value, used by the DDS DX10 header
+    pub fn dxgi_format(&self) -> u32
+    {
+        return match self
+        {
+            ImageFormat::Rgba8Unorm => 28,
+            ImageFormat::Rgba8Srgb => 29,
+            ImageFormat::Bgra8Unorm => 87,
+            ImageFormat::Bgra8Srgb => 91,
+            ImageFormat::Rgb10A2Unorm => 24,
+            ImageFormat::Rgba16Float => 10,
+        };
+    }
+
+    pub fn from_dxgi_format(dxgi_format : u32) -> Option<ImageFormat>
+    {
+        return match dxgi_format
+        {
+            28 => Some(ImageFormat::Rgba8Unorm),
+            29 => Some(ImageFormat::Rgba8Srgb),
+            87 => Some(ImageFormat::Bgra8Unorm),
+            91 => Some(ImageFormat::Bgra8Srgb),
+            24 => Some(ImageFormat::Rgb10A2Unorm),
+            10 => Some(ImageFormat::Rgba16Float),
+            _ => None,
+        };
+    }
+}
+
+fn align_up(value : u64, alignment : u64) -> u64
+{
+    return value.div_ceil(alignment) * alignment;
+}
+
+// function to compute how a single 2D subresource is laid out in a readback buffer
+pub fn compute_footprint(width : u32, height : u32, format : ImageFormat) -> Footprint
+{
+    let row_size = width as u64 * format.bytes_per_pixel() as u64;
+    let row_pitch = align_up(row_size, GTEXTURE_PITCH_ALIGNMENT);
+
+    // the last row doesn't need the padding
+    let total_bytes = if height == 0 { 0 } else { row_pitch * (height as u64 - 1) + row_size };
+
+    return Footprint
+    {
+        width,
+        height,
+        row_size,
+        row_pitch,
+        total_bytes,
+    };
+}
+
+// function to pack the padded rows of a readback buffer tightly
+pub fn pack_rows(padded_data : &[u8], footprint : &Footprint) -> Vec<u8>
+{
+    let mut data : Vec<u8> = Vec::with_capacity((footprint.row_size * footprint.height as u64) as usize);
+    for row in 0..footprint.height as usize
+    {
+        let row_start = row * footprint.row_pitch as usize;
+        data.extend_from_slice(&padded_data[row_start..row_start + footprint.row_size as usize]);
+    }
+
+    return data;
+}
+
+// function to convert a 16-bit float to f32
+pub fn half_to_f32(half : u16) -> f32
+{
+    let sign = ((half >> 15) & 0x1) as u32;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = if exponent == 0
+    {
+        if mantissa == 0
+        {
+            sign << 31
+        }
+        else
+        {
+            // subnormal, value = mantissa * 2^-24
+            let value = mantissa as f32 * (1.0 / 16777216.0);
+            return if sign == 1 { -value } else { value };
+        }
+    }
+    else if exponent == 0x1f
+    {
+        // inf or nan
+        (sign << 31) | 0x7f800000 | (mantissa << 13)
+    }
+    else
+    {
+        (sign << 31) | ((exponent + 127 - 15) << 23) | (mantissa << 13)
+    };
+
+    return f32::from_bits(bits);
+}
+
+// function to encode a linear value to sRGB
+pub fn linear_to_srgb(value : f32) -> f32
+{
+    if value <= 0.0031308
+    {
+        return value * 12.92;
+    }
+    return 1.055 * value.powf(1.0 / 2.4) - 0.055;
+}
+
+fn unit_to_u8(value : f32) -> u8
+{
+    if value.is_nan()
+    {
+        return 0;
+    }
+    return (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+}
+
+// function to convert an image to 8-bit RGBA for PNG. 8-bit formats are kept as they are, HDR float is tonemapped with
+// Reinhard and encoded to sRGB.
+pub fn convert_to_rgba8(image : &Image) -> Vec<u8>
+{
+    let pixel_count = (image.width * image.height) as usize;
+    let mut rgba8 : Vec<u8> = Vec::with_capacity(pixel_count * 4);
+
+    match image.format
+    {
+        ImageFormat::Rgba8Unorm | ImageFormat::Rgba8Srgb =>
+        {
+            rgba8.extend_from_slice(&image.pixels[..pixel_count * 4]);
+        }
+        ImageFormat::Bgra8Unorm | ImageFormat::Bgra8Srgb =>
+        {
+            for x in image.pixels[..pixel_count * 4].chunks_exact(4)
+            {
+                rgba8.extend_from_slice(&[x[2], x[1], x[0], x[3]]);
+            }
+        }
+        ImageFormat::Rgb10A2Unorm =>
+        {
+            for x in image.pixels[..pixel_count * 4].chunks_exact(4)
+            {
+                let packed = u32::from_le_bytes([x[0], x[1], x[2], x[3]]);
+                let r = (packed & 0x3ff) as f32 / 1023.0;
+                let g = ((packed >> 10) & 0x3ff) as f32 / 1023.0;
+                let b = ((packed >> 20) & 0x3ff) as f32 / 1023.0;
+                let a = ((packed >> 30) & 0x3) as f32 / 3.0;
+                rgba8.extend_from_slice(&[unit_to_u8(r), unit_to_u8(g), unit_to_u8(b), unit_to_u8(a)]);
+            }
+        }
+        ImageFormat::Rgba16Float =>
+        {
+            for x in image.pixels[..pixel_count * 8].chunks_exact(8)
+            {
+                let mut color = [0u8; 4];
+                for channel in 0..4
+                {
+                    let value = half_to_f32(u16::from_le_bytes([x[channel * 2], x[channel * 2 + 1]]));
+                    color[channel] = if channel == 3
+                    {
+                        unit_to_u8(value)
+                    }
+                    else
+                    {
+                        // inf / inf would be NaN, infinity maps to white
+                        let value = value.max(0.0);
+                        let mapped = if value.is_infinite() { 1.0 } else { value / (1.0 + value) };
+                        unit_to_u8(linear_to_srgb(mapped))
+                    };
+                }
+                rgba8.extend_from_slice(&color);
+            }
+        }
+    }
+
+    return rgba8;
+}
+
+// CRC-32 used by PNG chunks
+fn crc32(data : &[u8]) -> u32
+{
+    let mut crc : u32 = 0xffffffff;
+    for x in data
+    {
+        crc = crc ^ *x as u32;
+        for _ in 0..8
+        {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    return !crc;
+}
+
+// Adler-32 used by the zlib stream
+fn adler32(data : &[u8]) -> u32
+{
+    let mut a : u32 = 1;
+    let mut b : u32 = 0;
+    for chunk in data.chunks(5552)
+    {
+        for x in chunk
+        {
+            a = a + *x as u32;
+            b = b + a;
+        }
+        a = a % 65521;
+        b = b % 65521;
+    }
+    return (b << 16) | a;
+}
+
+fn write_png_chunk(output : &mut Vec<u8>, chunk_type : &[u8; 4], data : &[u8])
+{
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = output.len();
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+    let crc = crc32(&output[crc_start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+// function to encode 8-bit RGBA pixels as PNG. the zlib stream uses stored (uncompressed) deflate blocks,
+// which keeps the encoder tiny at the cost of file size.
+pub fn encode_png(width : u32, height : u32, rgba8 : &[u8], srgb : bool) -> Vec<u8>
+{
+    let mut output : Vec<u8> = Vec::new();
+    output.extend_from_slice(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+
+    // IHDR: 8 bits per channel, color type 6 (RGBA), no interlace
+    let mut header : Vec<u8> = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_png_chunk(&mut output, b"IHDR", &header);
+
+    // sRGB chunk with perceptual rendering intent
+    if srgb
+    {
+        write_png_chunk(&mut output, b"sRGB", &[0]);
+    }
+
+    // every scanline starts with a filter type byte, 0 means no filter
+    let row_size = width as usize * 4;
+    let mut scanlines : Vec<u8> = Vec::with_capacity((row_size + 1) * height as usize);
+    for row in 0..height as usize
+    {
+        scanlines.push(0);
+        scanlines.extend_from_slice(&rgba8[row * row_size..(row + 1) * row_size]);
+    }
+
+    // zlib header (deflate, 32K window, no preset dictionary), stored blocks, then adler32 of the raw data
+    let mut zlib : Vec<u8> = Vec::with_capacity(scanlines.len() + scanlines.len() / 65535 * 5 + 16);
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    let mut blocks : Vec<&[u8]> = scanlines.chunks(65535).collect();
+    if blocks.is_empty()
+    {
+        blocks.push(&[]);
+    }
+    for (idx, block) in blocks.iter().enumerate()
+    {
+        let is_final = if idx + 1 == blocks.len() { 1u8 } else { 0u8 };
+        let block_length = block.len() as u16;
+        zlib.push(is_final);
+        zlib.extend_from_slice(&block_length.to_le_bytes());
+        zlib.extend_from_slice(&(!block_length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+    write_png_chunk(&mut output, b"IDAT", &zlib);
+
+    write_png_chunk(&mut output, b"IEND", &[]);
+    return output;
+}
+
+fn write_u32(output : &mut Vec<u8>, value : u32)
+{
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+// function to encode an image as DDS with a DX10 header, pixels are written as they are without any conversion
+pub fn encode_dds(image : &Image) -> Vec<u8>
+{
+    let row_size = image.width * image.format.bytes_per_pixel();
+    let mut output : Vec<u8> = Vec::with_capacity(4 + 124 + 20 + image.pixels.len());
+
+    output.extend_from_slice(b"DDS ");
+
+    // DDS_HEADER, flags are CAPS | HEIGHT | WIDTH | PITCH | PIXELFORMAT
+    write_u32(&mut output, 124);
+    write_u32(&mut output, 0x1 | 0x2 | 0x4 | 0x8 | 0x1000);
+    write_u32(&mut output, image.height);
+    write_u32(&mut output, image.width);
+    write_u32(&mut output, row_size);
+    write_u32(&mut output, 0);
+    write_u32(&mut output, 1);
+    for _ in 0..11
+    {
+        write_u32(&mut output, 0);
+    }
+
+    // DDS_PIXELFORMAT with FOURCC flag and "DX10" fourcc, the real format is in the DX10 header
+    write_u32(&mut output, 32);
+    write_u32(&mut output, 0x4);
+    output.extend_from_slice(b"DX10");
+    for _ in 0..5
+    {
+        write_u32(&mut output, 0);
+    }
+
+    // caps is DDSCAPS_TEXTURE, caps2 / caps3 / caps4 / reserved2 are zero
+    write_u32(&mut output, 0x1000);
+    for _ in 0..4
+    {
+        write_u32(&mut output, 0);
+    }
+
+    // DDS_HEADER_DXT10, dimension 3 is TEXTURE2D
+    write_u32(&mut output, image.format.dxgi_format());
+    write_u32(&mut output, 3);
+    write_u32(&mut output, 0);
+    write_u32(&mut output, 1);
+    write_u32(&mut output, 0);
+
+    output.extend_from_slice(&image.pixels[..(row_size * image.height) as usize]);
+    return output;
+}
+
+// function to write a PNG file, the image is converted to 8-bit RGBA first
+pub fn write_png(path : &std::path::Path, image : &Image) -> std::io::Result<()>
+{
+    let rgba8 = convert_to_rgba8(image);
+    let png = encode_png(image.width, image.height, &rgba8, image.format.is_srgb() || image.format == ImageFormat::Rgba16Float);
+    let mut file = std::fs::File::create(path)?;
+    return file.write_all(&png);
+}
+
+// function to write a DDS file
+pub fn write_dds(path : &std::path::Path, image : &Image) -> std::io::Result<()>
+{
+    let mut file = std::fs::File::create(path)?;
+    return file.write_all(&encode_dds(image));
+}
+
+// a little-endian bit cursor over a deflate stream
+struct BitReader<'a>
+{
+    data : &'a [u8],
+    bit_position : usize,
+}
+
+impl<'a> BitReader<'a>
+{
+    fn read_bit(&mut self) -> Result<u32, String>
+    {
+        let byte = *self.data.get(self.bit_position / 8).ok_or("deflate stream ends early")?;
+        let bit = (byte >> (self.bit_position % 8)) & 1;
+        self.bit_position = self.bit_position + 1;
+        return Ok(bit as u32);
+    }
+
+    fn read_bits(&mut self, count : u32) -> Result<u32, String>
+    {
+        let mut value : u32 = 0;
+        for idx in 0..count
+        {
+            value = value | (self.read_bit()? << idx);
+        }
+        return Ok(value);
+    }
+}
+
+// canonical Huffman table, the symbols are sorted by code length then by symbol
+struct HuffmanTable
+{
+    counts : [u16; 16],
+    symbols : Vec<u16>,
+}
+
+impl HuffmanTable
+{
+    fn new(code_lengths : &[u8]) -> HuffmanTable
+    {
+        let mut counts = [0u16; 16];
+        for x in code_lengths
+        {
+            counts[*x as usize] = counts[*x as usize] + 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for idx in 1..15
+        {
+            offsets[idx + 1] = offsets[idx] + counts[idx];
+        }
+
+        let mut symbols : Vec<u16> = vec![0; code_lengths.len()];
+        for (symbol, x) in code_lengths.iter().enumerate()
+        {
+            if *x != 0
+            {
+                symbols[offsets[*x as usize] as usize] = symbol as u16;
+                offsets[*x as usize] = offsets[*x as usize] + 1;
+            }
+        }
+
+        return HuffmanTable { counts, symbols };
+    }
+
+    // huffman codes are stored from the most significant bit, so decode them one bit at a time
+    fn decode(&self, reader : &mut BitReader) -> Result<u16, String>
+    {
+        let mut code : i32 = 0;
+        let mut first : i32 = 0;
+        let mut index : i32 = 0;
+        for length in 1..16
+        {
+            code = code | reader.read_bit()? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count
+            {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index = index + count;
+            first = (first + count) << 1;
+            code = code << 1;
+        }
+
+        return Err(String::from("invalid huffman code"));
+    }
+}
+
+const GLENGTH_BASE : [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const GLENGTH_EXTRA_BITS : [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const GDISTANCE_BASE : [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073
+    , 4097, 6145, 8193, 12289, 16385, 24577];
+const GDISTANCE_EXTRA_BITS : [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// function to inflate the compressed block with the literal / length and distance tables, the output stops at max_size bytes
+fn inflate_block(reader : &mut BitReader, output : &mut Vec<u8>, literal_table : &HuffmanTable, distance_table : &HuffmanTable
+    , max_size : usize) -> Result<(), String>
+{
+    loop
+    {
+        let symbol = literal_table.decode(reader)? as usize;
+        if symbol < 256
+        {
+            if output.len() >= max_size
+            {
+                return Err(String::from(GTOO_LARGE_ERROR));
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256
+        {
+            return Ok(());
+        }
+
+        let length_index = symbol - 257;
+        if length_index >= GLENGTH_BASE.len()
+        {
+            return Err(String::from("invalid length symbol"));
+        }
+        let length = GLENGTH_BASE[length_index] as usize + reader.read_bits(GLENGTH_EXTRA_BITS[length_index] as u32)? as usize;
+
+        let distance_index = distance_table.decode(reader)? as usize;
+        if distance_index >= GDISTANCE_BASE.len()
+        {
+            return Err(String::from("invalid distance symbol"));
+        }
+        let distance = GDISTANCE_BASE[distance_index] as usize + reader.read_bits(GDISTANCE_EXTRA_BITS[distance_index] as u32)? as usize;
+        if distance > output.len()
+        {
+            return Err(String::from("distance is too far back"));
+        }
+        if length > max_size - output.len()
+        {
+            return Err(String::from(GTOO_LARGE_ERROR));
+        }
+
+        // the copy can overlap itself, so copy byte by byte
+        let start = output.len() - distance;
+        for idx in 0..length
+        {
+            output.push(output[start + idx]);
+        }
+    }
+}
+
+// function to read the code lengths of a dynamic huffman block
+fn read_dynamic_tables(reader : &mut BitReader) -> Result<(HuffmanTable, HuffmanTable), String>
+{
+    const GCODE_LENGTH_ORDER : [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for idx in 0..code_length_count
+    {
+        code_length_lengths[GCODE_LENGTH_ORDER[idx]] = reader.read_bits(3)? as u8;
+    }
+    let code_length_table = HuffmanTable::new(&code_length_lengths);
+
+    // literal and distance code lengths are one sequence, a repeat can cross from one to the other
+    let mut lengths : Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count
+    {
+        let symbol = code_length_table.decode(reader)?;
+        let (value, repeat) = match symbol
+        {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("repeat without a previous length")?, 3 + reader.read_bits(2)?),
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+        for _ in 0..repeat
+        {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count
+    {
+        return Err(String::from("too many code lengths"));
+    }
+
+    return Ok((HuffmanTable::new(&lengths[..literal_count]), HuffmanTable::new(&lengths[literal_count..])));
+}
+
+const GTOO_LARGE_ERROR : &str = "inflated data is larger than expected";
+
+// function to decompress a zlib stream (RFC 1950 / 1951), the adler32 checksum is verified.
+// the stream comes from a file, so it's an error when it inflates to more than max_size bytes.
+pub fn zlib_decompress(data : &[u8], max_size : usize) -> Result<Vec<u8>, String>
+{
+    if data.len() < 6 || (data[0] & 0x0f) != 8 || !((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31)
+    {
+        return Err(String::from("invalid zlib header"));
+    }
+    if (data[1] & 0x20) != 0
+    {
+        return Err(String::from("zlib preset dictionary isn't supported"));
+    }
+
+    let mut reader = BitReader { data : &data[2..], bit_position : 0 };
+    let mut output : Vec<u8> = Vec::new();
+    loop
+    {
+        let is_final = reader.read_bits(1)?;
+        match reader.read_bits(2)?
+        {
+            0 =>
+            {
+                // stored block starts at the next byte boundary
+                let byte_position = reader.bit_position.div_ceil(8);
+                let header = reader.data.get(byte_position..byte_position + 4).ok_or("stored block ends early")?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]])
+                {
+                    return Err(String::from("stored block length doesn't match its complement"));
+                }
+                let length = length as usize;
+                if length > max_size - output.len()
+                {
+                    return Err(String::from(GTOO_LARGE_ERROR));
+                }
+                let block = reader.data.get(byte_position + 4..byte_position + 4 + length).ok_or("stored block ends early")?;
+                output.extend_from_slice(block);
+                reader.bit_position = (byte_position + 4 + length) * 8;
+            }
+            1 =>
+            {
+                // fixed huffman codes
+                let mut literal_lengths = [0u8; 288];
+                literal_lengths[0..144].fill(8);
+                literal_lengths[144..256].fill(9);
+                literal_lengths[256..280].fill(7);
+                literal_lengths[280..288].fill(8);
+                inflate_block(&mut reader, &mut output, &HuffmanTable::new(&literal_lengths), &HuffmanTable::new(&[5u8; 30]), max_size)?;
+            }
+            2 =>
+            {
+                let (literal_table, distance_table) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literal_table, &distance_table, max_size)?;
+            }
+            _ => return Err(String::from("invalid deflate block type")),
+        }
+
+        if is_final == 1
+        {
+            break;
+        }
+    }
+
+    let checksum_position = reader.bit_position.div_ceil(8);
+    let checksum = reader.data.get(checksum_position..checksum_position + 4).ok_or("zlib checksum is missing")?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&output)
+    {
+        return Err(String::from("zlib checksum mismatch"));
+    }
+
+    return Ok(output);
+}
+
+// the largest texture D3D12 can create (D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION), decoded images are uploaded as one
+pub const GMAX_IMAGE_DIMENSION : u32 = 16384;
+
+// function to check the size from a file header before anything is allocated for it
+fn check_image_size(width : u32, height : u32) -> Result<(), String>
+{
+    if width == 0 || height == 0 || width > GMAX_IMAGE_DIMENSION || height > GMAX_IMAGE_DIMENSION
+    {
+        return Err(format!("image size {}x{} must be from 1 to {} in each dimension", width, height, GMAX_IMAGE_DIMENSION));
+    }
+    return Ok(());
+}
+
+// paeth predictor of the PNG filter type 4
+fn paeth_predictor(a : u8, b : u8, c : u8) -> u8
+{
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc
+    {
+        return a;
+    }
+    return if pb <= pc { b } else { c };
+}
+
+// function to decode a PNG file to 8-bit RGBA. supports 8-bit gray, gray + alpha, RGB and RGBA without interlacing.
+// the pixel values are kept as they are, so the image format is Rgba8Unorm.
+pub fn decode_png(data : &[u8]) -> Result<Image, String>
+{
+    if data.len() < 8 || data[..8] != [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]
+    {
+        return Err(String::from("not a PNG file"));
+    }
+
+    let mut header : Option<&[u8]> = None;
+    let mut compressed : Vec<u8> = Vec::new();
+    let mut position = 8;
+    while position + 8 <= data.len()
+    {
+        let length = u32::from_be_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]]) as usize;
+        let chunk_type = &data[position + 4..position + 8];
+        let chunk_end = (position + 8).checked_add(length).ok_or("PNG chunk ends early")?;
+        let chunk_data = data.get(position + 8..chunk_end).ok_or("PNG chunk ends early")?;
+        match chunk_type
+        {
+            b"IHDR" => header = Some(chunk_data),
+            b"IDAT" => compressed.extend_from_slice(chunk_data),
+            b"IEND" => break,
+            _ => {}
+        }
+
+        // chunk length, type, data and CRC
+        position = chunk_end + 4;
+    }
+
+    let header = header.filter(|x| x.len() == 13).ok_or("PNG header is missing")?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let (bit_depth, color_type, interlace) = (header[8], header[9], header[12]);
+    let channel_count : usize = match color_type
+    {
+        0 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("PNG color type {} isn't supported", color_type)),
+    };
+    if bit_depth != 8 || interlace != 0
+    {
+        return Err(String::from("only 8-bit PNG without interlacing is supported"));
+    }
+    check_image_size(width, height)?;
+
+    // every scanline starts with its filter type
+    let row_size = width as usize * channel_count;
+    let scanlines_size = (row_size + 1).checked_mul(height as usize).ok_or("PNG image is too large")?;
+    let scanlines = zlib_decompress(&compressed, scanlines_size)?;
+    if scanlines.len() < scanlines_size
+    {
+        return Err(String::from("PNG image data is too short"));
+    }
+
+    // undo the filter of each scanline, a filter refers to the byte of the previous pixel and the row above
+    let mut raw : Vec<u8> = vec![0; row_size * height as usize];
+    for row in 0..height as usize
+    {
+        let filter_type = scanlines[row * (row_size + 1)];
+        let source = &scanlines[row * (row_size + 1) + 1..(row + 1) * (row_size + 1)];
+        for idx in 0..row_size
+        {
+            let left = if idx >= channel_count { raw[row * row_size + idx - channel_count] } else { 0 };
+            let up = if row > 0 { raw[(row - 1) * row_size + idx] } else { 0 };
+            let up_left = if row > 0 && idx >= channel_count { raw[(row - 1) * row_size + idx - channel_count] } else { 0 };
+            let prediction = match filter_type
+            {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth_predictor(left, up, up_left),
+                _ => return Err(format!("invalid PNG filter type {}", filter_type)),
+            };
+            raw[row * row_size + idx] = source[idx].wrapping_add(prediction);
+        }
+    }
+
+    let mut pixels : Vec<u8> = Vec::with_capacity(width as usize * height as usize * 4);
+    for x in raw.chunks_exact(channel_count)
+    {
+        let color = match channel_count
+        {
+            1 => [x[0], x[0], x[0], 255],
+            2 => [x[0], x[0], x[0], x[1]],
+            3 => [x[0], x[1], x[2], 255],
+            _ => [x[0], x[1], x[2], x[3]],
+        };
+        pixels.extend_from_slice(&color);
+    }
+
+    return Ok(Image
+    {
+        width,
+        height,
+        format : ImageFormat::Rgba8Unorm,
+        pixels,
+    });
+}
+
+fn read_u32(data : &[u8], offset : usize) -> u32
+{
+    return u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+}
+
+// function to decode an uncompressed DDS file, the formats of ImageFormat with a DX10 header or legacy 32-bit RGBA / BGRA.
+// only the top mip level of the first array slice is read.
+pub fn decode_dds(data : &[u8]) -> Result<Image, String>
+{
+    if data.len() < 128 || &data[..4] != b"DDS " || read_u32(data, 4) != 124
+    {
+        return Err(String::from("not a DDS file"));
+    }
+
+    let height = read_u32(data, 12);
+    let width = read_u32(data, 16);
+    check_image_size(width, height)?;
+
+    // DDS_PIXELFORMAT starts at byte 76
+    let pixel_format_flags = read_u32(data, 80);
+    let four_cc = &data[84..88];
+    let (format, data_offset) = if (pixel_format_flags & 0x4) != 0 && four_cc == b"DX10"
+    {
+        if data.len() < 148
+        {
+            return Err(String::from("DDS DX10 header is missing"));
+        }
+        let dxgi_format = read_u32(data, 128);
+        (ImageFormat::from_dxgi_format(dxgi_format).ok_or(format!("DXGI format {} isn't supported", dxgi_format))?, 148)
+    }
+    else if (pixel_format_flags & 0x40) != 0 && read_u32(data, 88) == 32
+    {
+        // legacy RGB with 32 bits per pixel, the red mask tells the channel order
+        match read_u32(data, 92)
+        {
+            0x000000ff => (ImageFormat::Rgba8Unorm, 128),
+            0x00ff0000 => (ImageFormat::Bgra8Unorm, 128),
+            _ => return Err(String::from("DDS channel layout isn't supported")),
+        }
+    }
+    else
+    {
+        return Err(String::from("compressed or legacy DDS formats aren't supported"));
+    };
+
+    let size = (width as usize * height as usize).checked_mul(format.bytes_per_pixel() as usize).ok_or("DDS image is too large")?;
+    let pixels = data.get(data_offset..).and_then(|x| x.get(..size)).ok_or("DDS image data is too short")?;
+
+    return Ok(Image
+    {
+        width,
+        height,
+        format,
+        pixels : pixels.to_vec(),
+    });
+}
+
+// function to read a PNG or DDS file, the format is picked by the extension
+pub fn read_image(path : &std::path::Path) -> Result<Image, String>
+{
+    let data = std::fs::read(path).map_err(|x| x.to_string())?;
+    let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("").to_ascii_lowercase();
+
+    return match extension.as_str()
+    {
+        "png" => decode_png(&data),
+        "dds" => decode_dds(&data),
+        _ => Err(format!("unknown image extension '{}'", extension)),
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // function to split a PNG into its chunks as (type, data), the CRC of every chunk is checked on the way
+    fn read_png_chunks(png : &[u8]) -> Vec<([u8; 4], Vec<u8>)>
+    {
+        let mut chunks : Vec<([u8; 4], Vec<u8>)> = Vec::new();
+        let mut position = 8;
+        while position < png.len()
+        {
+            let length = u32::from_be_bytes(png[position..position + 4].try_into().unwrap()) as usize;
+            let chunk_type : [u8; 4] = png[position + 4..position + 8].try_into().unwrap();
+            let crc = u32::from_be_bytes(png[position + 8 + length..position + 12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&png[position + 4..position + 8 + length]));
+            chunks.push((chunk_type, png[position + 8..position + 8 + length].to_vec()));
+            position = position + 12 + length;
+        }
+        return chunks;
+    }
+
+    #[test]
+    fn footprint_pads_all_rows_but_the_last()
+    {
+        let footprint = compute_footprint(10, 3, ImageFormat::Rgba8Unorm);
+        assert_eq!(footprint.row_size, 40);
+        assert_eq!(footprint.row_pitch, 256);
+        assert_eq!(footprint.total_bytes, 256 * 2 + 40);
+
+        // a row that is already aligned gets no padding
+        let footprint = compute_footprint(32, 2, ImageFormat::Rgba16Float);
+        assert_eq!((footprint.row_size, footprint.row_pitch, footprint.total_bytes), (256, 256, 512));
+        assert_eq!(compute_footprint(4, 0, ImageFormat::Rgba8Unorm).total_bytes, 0);
+    }
+
+    #[test]
+    fn pack_rows_drops_the_padding()
+    {
+        let footprint = compute_footprint(2, 3, ImageFormat::Rgba8Unorm);
+        let mut padded_data = vec![0xeeu8; footprint.total_bytes as usize];
+        for row in 0..3
+        {
+            for idx in 0..8
+            {
+                padded_data[row * 256 + idx] = (row * 8 + idx) as u8;
+            }
+        }
+
+        let packed = pack_rows(&padded_data, &footprint);
+        assert_eq!(packed, (0..24).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn half_to_f32_covers_every_class()
+    {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x3555), 1365.0 / 4096.0);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0000).to_bits(), 0.0f32.to_bits());
+        assert_eq!(half_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+        // the smallest subnormal is 2^-24
+        assert_eq!(half_to_f32(0x0001), 1.0 / 16777216.0);
+        assert_eq!(half_to_f32(0x83ff), -1023.0 / 16777216.0);
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(half_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn convert_to_rgba8_swizzles_and_tonemaps()
+    {
+        let bgra = Image { width : 1, height : 1, format : ImageFormat::Bgra8Srgb, pixels : vec![1, 2, 3, 4] };
+        assert_eq!(convert_to_rgba8(&bgra), vec![3, 2, 1, 4]);
+
+        // r = 1023, g = 0, b = 512, a = 3
+        let packed : u32 = 1023 | (512 << 20) | (3 << 30);
+        let rgb10a2 = Image { width : 1, height : 1, format : ImageFormat::Rgb10A2Unorm, pixels : packed.to_le_bytes().to_vec() };
+        assert_eq!(convert_to_rgba8(&rgb10a2), vec![255, 0, 128, 255]);
+
+        // 1.0 is 0.5 after Reinhard, which is 0.7354 in sRGB. negative and NaN colors go to black, infinity to white,
+        // alpha isn't tonemapped
+        let halves : [u16; 8] = [0x3c00, 0x0000, 0xbc00, 0x3c00, 0x7e00, 0x7c00, 0x3800, 0x3800];
+        let pixels : Vec<u8> = halves.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let hdr = Image { width : 2, height : 1, format : ImageFormat::Rgba16Float, pixels };
+        assert_eq!(convert_to_rgba8(&hdr), vec![188, 0, 0, 255, 0, 255, 156, 128]);
+    }
+
+    #[test]
+    fn linear_to_srgb_matches_the_curve()
+    {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(0.002) - 0.02584).abs() < 1e-6);
+        assert!((linear_to_srgb(0.5) - 0.735357).abs() < 1e-5);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn checksums_match_known_vectors()
+    {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        // long enough to need the modulo between the chunks
+        let data = vec![0xffu8; 100000];
+        let mut a : u64 = 1;
+        let mut b : u64 = 0;
+        for x in data.iter()
+        {
+            a = (a + *x as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&data), ((b << 16) | a) as u32);
+    }
+
+    #[test]
+    fn encode_png_writes_valid_chunks()
+    {
+        let rgba8 : Vec<u8> = (0..2 * 2 * 4).map(|x| x as u8).collect();
+        let png = encode_png(2, 2, &rgba8, true);
+        assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+
+        let chunks = read_png_chunks(&png);
+        let chunk_types : Vec<&[u8; 4]> = chunks.iter().map(|x| &x.0).collect();
+        assert_eq!(chunk_types, vec![b"IHDR", b"sRGB", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, vec![0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+
+        // one final stored block with the unfiltered scanlines, then the adler32 of them
+        let mut scanlines : Vec<u8> = vec![0];
+        scanlines.extend_from_slice(&rgba8[..8]);
+        scanlines.push(0);
+        scanlines.extend_from_slice(&rgba8[8..]);
+        let idat = &chunks[2].1;
+        assert_eq!(idat[..7], [0x78, 0x01, 1, 18, 0, !18u8, 0xff]);
+        assert_eq!(idat[7..7 + 18], scanlines[..]);
+        assert_eq!(idat[7 + 18..], adler32(&scanlines).to_be_bytes());
+
+        let linear_png = encode_png(2, 2, &rgba8, false);
+        assert!(read_png_chunks(&linear_png).iter().all(|x| &x.0 != b"sRGB"));
+    }
+
+    #[test]
+    fn encode_png_splits_large_images_into_stored_blocks()
+    {
+        // 2 rows of 40000 bytes plus the filter bytes need two stored blocks
+        let rgba8 = vec![7u8; 10000 * 2 * 4];
+        let chunks = read_png_chunks(&encode_png(10000, 2, &rgba8, false));
+        let idat = &chunks[1].1;
+        assert_eq!(idat[2], 0);
+        assert_eq!(u16::from_le_bytes([idat[3], idat[4]]), 65535);
+        let second_block = 2 + 5 + 65535;
+        assert_eq!(idat[second_block], 1);
+        assert_eq!(u16::from_le_bytes([idat[second_block + 1], idat[second_block + 2]]) as usize, 80002 - 65535);
+    }
+
+    #[test]
+    fn encode_dds_writes_the_dx10_header()
+    {
+        let image = Image { width : 3, height : 2, format : ImageFormat::Rgba16Float, pixels : (0..48).collect() };
+        let dds = encode_dds(&image);
+        let read = |offset : usize| u32::from_le_bytes(dds[offset..offset + 4].try_into().unwrap());
+
+        assert_eq!(dds.len(), 4 + 124 + 20 + 48);
+        assert_eq!(&dds[..4], b"DDS ");
+        assert_eq!(read(4), 124);
+        assert_eq!(read(8), 0x100f);
+        assert_eq!((read(12), read(16), read(20)), (2, 3, 24));
+        // pixel format at 76: size, FOURCC flag and "DX10"
+        assert_eq!((read(76), read(80)), (32, 0x4));
+        assert_eq!(&dds[84..88], b"DX10");
+        assert_eq!(read(108), 0x1000);
+        // DX10 header: format, TEXTURE2D, misc flag, array size
+        assert_eq!((read(128), read(132), read(136), read(140)), (10, 3, 0, 1));
+        assert_eq!(dds[148..], image.pixels[..]);
+    }
+
+    #[test]
+    fn zlib_decompress_reads_dynamic_huffman_blocks()
+    {
+        // 40 text lines compressed by Python's zlib.compress(data, 9), which picks a dynamic huffman block
+        let compressed : [u8; 170] =
+        [
+            0x78, 0xda, 0x9d, 0xd2, 0xc9, 0x0d, 0xc2, 0x40, 0x10, 0x44, 0xd1, 0x3b, 0x51, 0x74, 0x08, 0xbd, 0xb1, 0x66, 0xc3, 0x32, 0x80, 0xc1, 0x78, 0xc0,
+            0x60, 0xb6, 0xe8, 0x91, 0x20, 0x02, 0xfe, 0xb9, 0xf4, 0x2f, 0xa5, 0xd7, 0x36, 0x5d, 0x11, 0x55, 0x5d, 0xc8, 0x6d, 0x5f, 0xe4, 0x32, 0x34, 0xeb,
+            0xa3, 0xac, 0xfa, 0xfa, 0xe8, 0x64, 0x5b, 0x9f, 0x72, 0x18, 0x4e, 0xe7, 0xab, 0xd4, 0x7b, 0xe9, 0xbf, 0x73, 0xbb, 0x7c, 0xbf, 0x64, 0x53, 0x77,
+            0xa3, 0xf6, 0x57, 0x4d, 0x49, 0x65, 0x49, 0x2a, 0x37, 0x54, 0xcd, 0x48, 0x15, 0x63, 0x52, 0xa5, 0xa3, 0x6a, 0x8e, 0x9e, 0x9f, 0xa0, 0xe7, 0x03,
+            0x7d, 0x88, 0x6c, 0x38, 0xb2, 0x11, 0xc8, 0x46, 0x22, 0x1b, 0x89, 0x6c, 0x28, 0xb2, 0x61, 0xc8, 0x86, 0x21, 0x1b, 0x8e, 0x6c, 0x04, 0xb2, 0x91,
+            0xc8, 0x46, 0x22, 0x1b, 0x8a, 0x6c, 0x18, 0xb2, 0x61, 0xc8, 0x86, 0x23, 0x1b, 0x81, 0x6c, 0x04, 0xb2, 0x91, 0xc8, 0x86, 0x22, 0x1b, 0x86, 0x6c,
+            0x18, 0xb2, 0xe1, 0xc8, 0x46, 0x20, 0x1b, 0x81, 0x6c, 0x24, 0xb2, 0xa1, 0xc8, 0x86, 0x22, 0x1b, 0x86, 0x6c, 0xf8, 0x9f, 0x36, 0x3e, 0xdc, 0x63,
+            0xed, 0x69,
+        ];
+        let expected : String = (0..40).map(|x| format!("line {:03}: the quick brown fox jumps over the lazy dog\n", x * 7 % 50)).collect();
+        assert_eq!((compressed[2] >> 1) & 3, 2);
+        assert_eq!(zlib_decompress(&compressed, usize::MAX).unwrap(), expected.as_bytes());
+
+        // the size limit holds for the literals and the back references
+        assert_eq!(zlib_decompress(&compressed, expected.len()).unwrap(), expected.as_bytes());
+        assert_eq!(zlib_decompress(&compressed, expected.len() - 1), Err(String::from(GTOO_LARGE_ERROR)));
+        assert_eq!(zlib_decompress(&compressed, 10), Err(String::from(GTOO_LARGE_ERROR)));
+
+        let mut corrupted = compressed;
+        corrupted[169] ^= 1;
+        assert_eq!(zlib_decompress(&corrupted, usize::MAX), Err(String::from("zlib checksum mismatch")));
+        assert_eq!(zlib_decompress(&[0x78, 0x00, 0, 0, 0, 0], usize::MAX), Err(String::from("invalid zlib header")));
+    }
+
+    #[test]
+    fn zlib_decompress_checks_the_stored_block_length()
+    {
+        let data = b"stored";
+        let mut compressed : Vec<u8> = vec![0x78, 0x01, 1, 6, 0, !6u8, 0xff];
+        compressed.extend_from_slice(data);
+        compressed.extend_from_slice(&adler32(data).to_be_bytes());
+        assert_eq!(zlib_decompress(&compressed, usize::MAX).unwrap(), data);
+
+        compressed[5] = 0;
+        assert_eq!(zlib_decompress(&compressed, usize::MAX), Err(String::from("stored block length doesn't match its complement")));
+    }
+
+    #[test]
+    fn zlib_decompress_stops_at_the_size_limit()
+    {
+        let data = b"stored";
+        let mut compressed : Vec<u8> = vec![0x78, 0x01, 1, 6, 0, !6u8, 0xff];
+        compressed.extend_from_slice(data);
+        compressed.extend_from_slice(&adler32(data).to_be_bytes());
+        assert_eq!(zlib_decompress(&compressed, 6).unwrap(), data);
+        assert_eq!(zlib_decompress(&compressed, 5), Err(String::from(GTOO_LARGE_ERROR)));
+    }
+
+    #[test]
+    fn decode_png_undoes_every_filter_type()
+    {
+        // 3x5 RGB image written by Python, row y uses filter type y. the zlib stream is a fixed huffman block split over two IDAT chunks
+        let png : [u8; 115] =
+        [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x05,
+            0x08, 0x02, 0x00, 0x00, 0x00, 0x0f, 0x13, 0xc1, 0xf5, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x60, 0x60, 0xfd, 0x6f,
+            0x24, 0x79, 0x3d, 0xd3, 0x60, 0x26, 0x75, 0x00, 0x00, 0x00, 0x24, 0x49, 0x44, 0x41, 0x54, 0x45, 0x77, 0x3d, 0xa3, 0x9c, 0xf7, 0x37, 0x23, 0x91,
+            0x1b, 0x40, 0xc4, 0x24, 0xe7, 0xf6, 0x1d, 0x82, 0x98, 0x6d, 0xfa, 0xf3, 0x34, 0x74, 0x5f, 0x00, 0x11, 0x0b, 0x48, 0x40, 0x04, 0x84, 0x00, 0xbd,
+            0x6d, 0x12, 0x7d, 0x9d, 0x08, 0xde, 0xff, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        let image = decode_png(&png).unwrap();
+        assert_eq!((image.width, image.height, image.format), (3, 5, ImageFormat::Rgba8Unorm));
+        for y in 0..5u32
+        {
+            for x in 0..3u32
+            {
+                let expected = [((x * 50 + y * 30) % 256) as u8, ((x * 20 + y * 70 + 5) % 256) as u8, (255 - x * 40 - y * 9) as u8, 255];
+                let offset = ((y * 3 + x) * 4) as usize;
+                assert_eq!(image.pixels[offset..offset + 4], expected, "pixel {} {}", x, y);
+            }
+        }
+
+        let mut not_png = png;
+        not_png[1] = b'p';
+        assert_eq!(decode_png(&not_png).err(), Some(String::from("not a PNG file")));
+    }
+
+    #[test]
+    fn decode_png_rejects_crafted_headers()
+    {
+        let png = encode_png(2, 2, &[0; 16], false);
+        // the IHDR data starts at byte 16, the decoder doesn't check the CRC
+        let with_size = |width : u32, height : u32|
+        {
+            let mut crafted = png.clone();
+            crafted[16..20].copy_from_slice(&width.to_be_bytes());
+            crafted[20..24].copy_from_slice(&height.to_be_bytes());
+            return decode_png(&crafted).err();
+        };
+        assert_eq!(with_size(0, 2), Some(String::from("image size 0x2 must be from 1 to 16384 in each dimension")));
+        assert_eq!(with_size(2, u32::MAX), Some(format!("image size 2x{} must be from 1 to 16384 in each dimension", u32::MAX)));
+        assert_eq!(with_size(0x4000_0000, 0x4000_0000), Some(String::from("image size 1073741824x1073741824 must be from 1 to 16384 in each dimension")));
+        // a valid size the data doesn't cover is caught before anything of that size is allocated
+        assert_eq!(with_size(16384, 16384), Some(String::from("PNG image data is too short")));
+
+        // a chunk length that runs past the end of the file
+        let mut crafted = png.clone();
+        crafted[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(decode_png(&crafted).err(), Some(String::from("PNG chunk ends early")));
+
+        // more scanline data than the header says, the inflate stops at the expected size
+        let mut crafted = encode_png(2, 3, &[0; 24], false);
+        crafted[23] = 2;
+        assert_eq!(decode_png(&crafted).err(), Some(String::from(GTOO_LARGE_ERROR)));
+    }
+
+    #[test]
+    fn decode_png_reads_what_encode_png_writes()
+    {
+        let rgba8 : Vec<u8> = (0..5 * 3 * 4).map(|x| (x * 11) as u8).collect();
+        let image = decode_png(&encode_png(5, 3, &rgba8, true)).unwrap();
+        assert_eq!((image.width, image.height), (5, 3));
+        assert_eq!(image.pixels, rgba8);
+    }
+
+    #[test]
+    fn decode_dds_reads_what_encode_dds_writes()
+    {
+        let image = Image { width : 2, height : 3, format : ImageFormat::Rgba16Float, pixels : (0..48).collect() };
+        let dds = encode_dds(&image);
+        let decoded = decode_dds(&dds).unwrap();
+        assert_eq!((decoded.width, decoded.height, decoded.format), (2, 3, ImageFormat::Rgba16Float));
+        assert_eq!(decoded.pixels, image.pixels);
+
+        assert_eq!(decode_dds(&dds[..dds.len() - 1]).err(), Some(String::from("DDS image data is too short")));
+        assert_eq!(decode_dds(&dds[..100]).err(), Some(String::from("not a DDS file")));
+
+        // crafted sizes are rejected before the size of the data is computed
+        let with_size = |width : u32, height : u32|
+        {
+            let mut crafted = dds.clone();
+            crafted[12..16].copy_from_slice(&height.to_le_bytes());
+            crafted[16..20].copy_from_slice(&width.to_le_bytes());
+            return decode_dds(&crafted).err();
+        };
+        assert_eq!(with_size(2, 0), Some(String::from("image size 2x0 must be from 1 to 16384 in each dimension")));
+        assert_eq!(with_size(u32::MAX, u32::MAX), Some(format!("image size {}x{} must be from 1 to 16384 in each dimension", u32::MAX, u32::MAX)));
+        assert_eq!(with_size(16385, 1), Some(String::from("image size 16385x1 must be from 1 to 16384 in each dimension")));
+        assert_eq!(with_size(16384, 16384), Some(String::from("DDS image data is too short")));
+
+        // a legacy header with a BGRA channel mask instead of the DX10 header
+        let mut legacy = dds[..128].to_vec();
+        legacy[80..84].copy_from_slice(&0x41u32.to_le_bytes());
+        legacy[84..88].copy_from_slice(&[0; 4]);
+        legacy[88..92].copy_from_slice(&32u32.to_le_bytes());
+        legacy[92..96].copy_from_slice(&0x00ff0000u32.to_le_bytes());
+        legacy.extend_from_slice(&image.pixels[..24]);
+        let decoded = decode_dds(&legacy).unwrap();
+        assert_eq!(decoded.format, ImageFormat::Bgra8Unorm);
+        assert_eq!(decoded.pixels, image.pixels[..24]);
+    }
+}
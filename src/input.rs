@@ -0,0 +1,776 @@
+// input.rs - Keyboard and mouse input collected from the window messages into a per-frame state, plus the action mapping on top of it.
+// InputState and ActionMap only work on InputEvent, so they can be driven by synthetic events as well as by wnd_proc.
+
+#[cfg(windows)]
+use windows::Win32::Foundation::*;
+#[cfg(windows)]
+use windows::Win32::UI::Input::*;
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::*;
+use std::collections::HashMap;
+#[cfg(windows)]
+use std::ffi::c_void;
+use std::fs;
+#[cfg(windows)]
+use std::mem;
+use std::path::Path;
+
+#[cfg(windows)]
+use crate::window_events;
+#[cfg(windows)]
+use crate::window_events::{EventResponse, WindowEvent, WindowEventKind};
+
+// virtual key codes are the Win32 VK_ values, so there are 256 of them
+const GKEY_COUNT : usize = 256;
+const GMOUSE_BUTTON_COUNT : usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MouseButton
+{
+    Left,
+    Right,
+    Middle,
+    X1,
+    X2,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputEvent
+{
+    KeyDown(u8),
+    KeyUp(u8),
+    // cursor position in client coordinates
+    MouseMove { x : i32, y : i32 },
+    MouseButtonDown(MouseButton),
+    MouseButtonUp(MouseButton),
+    // in notches, positive is away from the user
+    MouseWheel(f32),
+    // relative motion from raw input, not affected by the cursor speed or clipping to the screen
+    RawMouseMotion { dx : i32, dy : i32 },
+    // the window lost the keyboard focus, every held key and button is released
+    FocusLost,
+}
+
+// the input of one frame, feed it with process_event and call end_frame after the frame is done
+#[derive(Clone)]
+pub struct InputState
+{
+    keys_held : [bool; GKEY_COUNT],
+    keys_pressed : [bool; GKEY_COUNT],
+    keys_released : [bool; GKEY_COUNT],
+    buttons_held : [bool; GMOUSE_BUTTON_COUNT],
+    buttons_pressed : [bool; GMOUSE_BUTTON_COUNT],
+    buttons_released : [bool; GMOUSE_BUTTON_COUNT],
+    mouse_position : Option<(i32, i32)>,
+    mouse_delta : (i32, i32),
+    raw_mouse_delta : (i32, i32),
+    wheel_delta : f32,
+}
+
+fn get_button_index(button : MouseButton) -> usize
+{
+    return match button
+    {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::X1 => 3,
+        MouseButton::X2 => 4,
+    };
+}
+
+impl Default for InputState
+{
+    fn default() -> InputState
+    {
+        return InputState
+        {
+            keys_held : [false; GKEY_COUNT],
+            keys_pressed : [false; GKEY_COUNT],
+            keys_released : [false; GKEY_COUNT],
+            buttons_held : [false; GMOUSE_BUTTON_COUNT],
+            buttons_pressed : [false; GMOUSE_BUTTON_COUNT],
+            buttons_released : [false; GMOUSE_BUTTON_COUNT],
+            mouse_position : None,
+            mouse_delta : (0, 0),
+            raw_mouse_delta : (0, 0),
+            wheel_delta : 0.0,
+        };
+    }
+}
+
+impl InputState
+{
+    // function to apply an event. a key can be pressed and released within the same frame, both are reported then.
+    pub fn process_event(&mut self, event : InputEvent)
+    {
+        match event
+        {
+            InputEvent::KeyDown(key) =>
+            {
+                // auto-repeat sends more key downs while the key is held, only the first one is a press
+                let key = key as usize;
+                self.keys_pressed[key] = self.keys_pressed[key] || !self.keys_held[key];
+                self.keys_held[key] = true;
+            }
+            InputEvent::KeyUp(key) =>
+            {
+                let key = key as usize;
+                self.keys_released[key] = self.keys_released[key] || self.keys_held[key];
+                self.keys_held[key] = false;
+            }
+            InputEvent::MouseMove { x, y } =>
+            {
+                // the first position after startup has nothing to compare with
+                if let Some((last_x, last_y)) = self.mouse_position
+                {
+                    self.mouse_delta = (self.mouse_delta.0 + x - last_x, self.mouse_delta.1 + y - last_y);
+                }
+                self.mouse_position = Some((x, y));
+            }
+            InputEvent::MouseButtonDown(button) =>
+            {
+                let idx = get_button_index(button);
+                self.buttons_pressed[idx] = self.buttons_pressed[idx] || !self.buttons_held[idx];
+                self.buttons_held[idx] = true;
+            }
+            InputEvent::MouseButtonUp(button) =>
+            {
+                let idx = get_button_index(button);
+                self.buttons_released[idx] = self.buttons_released[idx] || self.buttons_held[idx];
+                self.buttons_held[idx] = false;
+            }
+            InputEvent::MouseWheel(notches) => self.wheel_delta = self.wheel_delta + notches,
+            InputEvent::RawMouseMotion { dx, dy } =>
+            {
+                self.raw_mouse_delta = (self.raw_mouse_delta.0 + dx, self.raw_mouse_delta.1 + dy);
+            }
+            InputEvent::FocusLost =>
+            {
+                // the key ups go to another window, so release everything here or the keys get stuck
+                for idx in 0..GKEY_COUNT
+                {
+                    self.keys_released[idx] = self.keys_released[idx] || self.keys_held[idx];
+                    self.keys_held[idx] = false;
+                }
+                for idx in 0..GMOUSE_BUTTON_COUNT
+                {
+                    self.buttons_released[idx] = self.buttons_released[idx] || self.buttons_held[idx];
+                    self.buttons_held[idx] = false;
+                }
+            }
+        }
+    }
+
+    // function to clear the per-frame states, the held states stay until the release event
+    pub fn end_frame(&mut self)
+    {
+        self.keys_pressed = [false; GKEY_COUNT];
+        self.keys_released = [false; GKEY_COUNT];
+        self.buttons_pressed = [false; GMOUSE_BUTTON_COUNT];
+        self.buttons_released = [false; GMOUSE_BUTTON_COUNT];
+        self.mouse_delta = (0, 0);
+        self.raw_mouse_delta = (0, 0);
+        self.wheel_delta = 0.0;
+    }
+
+    pub fn is_key_held(&self, key : u8) -> bool
+    {
+        return self.keys_held[key as usize];
+    }
+
+    pub fn is_key_pressed(&self, key : u8) -> bool
+    {
+        return self.keys_pressed[key as usize];
+    }
+
+    pub fn is_key_released(&self, key : u8) -> bool
+    {
+        return self.keys_released[key as usize];
+    }
+
+    pub fn is_button_held(&self, button : MouseButton) -> bool
+    {
+        return self.buttons_held[get_button_index(button)];
+    }
+
+    pub fn is_button_pressed(&self, button : MouseButton) -> bool
+    {
+        return self.buttons_pressed[get_button_index(button)];
+    }
+
+    pub fn is_button_released(&self, button : MouseButton) -> bool
+    {
+        return self.buttons_released[get_button_index(button)];
+    }
+
+    pub fn get_mouse_position(&self) -> Option<(i32, i32)>
+    {
+        return self.mouse_position;
+    }
+
+    pub fn get_mouse_delta(&self) -> (i32, i32)
+    {
+        return self.mouse_delta;
+    }
+
+    pub fn get_raw_mouse_delta(&self) -> (i32, i32)
+    {
+        return self.raw_mouse_delta;
+    }
+
+    pub fn get_wheel_delta(&self) -> f32
+    {
+        return self.wheel_delta;
+    }
+}
+
+// something an action can be bound to
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InputBinding
+{
+    Key(u8),
+    Mouse(MouseButton),
+}
+
+// function to get the binding of a key name used in the config, e.g. "F2", "A", "Space" or "MouseLeft". case insensitive.
+pub fn get_binding_from_name(name : &str) -> Option<InputBinding>
+{
+    let upper_name = name.trim().to_ascii_uppercase();
+    let bytes = upper_name.as_bytes();
+
+    // letters and digits are the same as their ASCII codes
+    if bytes.len() == 1 && bytes[0].is_ascii_alphanumeric()
+    {
+        return Some(InputBinding::Key(bytes[0]));
+    }
+
+    // F1 - F24 are 0x70 - 0x87
+    if bytes.len() > 1 && bytes[0] == b'F'
+    {
+        if let Ok(x) = upper_name[1..].parse::<u8>()
+        {
+            return if (1..=24).contains(&x) { Some(InputBinding::Key(0x70 + x - 1)) } else { None };
+        }
+    }
+
+    let binding = match upper_name.as_str()
+    {
+        "BACKSPACE" => InputBinding::Key(0x08),
+        "TAB" => InputBinding::Key(0x09),
+        "ENTER" => InputBinding::Key(0x0D),
+        "SHIFT" => InputBinding::Key(0x10),
+        "CTRL" => InputBinding::Key(0x11),
+        "ALT" => InputBinding::Key(0x12),
+        "PAUSE" => InputBinding::Key(0x13),
+        "ESCAPE" => InputBinding::Key(0x1B),
+        "SPACE" => InputBinding::Key(0x20),
+        "PAGEUP" => InputBinding::Key(0x21),
+        "PAGEDOWN" => InputBinding::Key(0x22),
+        "END" => InputBinding::Key(0x23),
+        "HOME" => InputBinding::Key(0x24),
+        "LEFT" => InputBinding::Key(0x25),
+        "UP" => InputBinding::Key(0x26),
+        "RIGHT" => InputBinding::Key(0x27),
+        "DOWN" => InputBinding::Key(0x28),
+        "INSERT" => InputBinding::Key(0x2D),
+        "DELETE" => InputBinding::Key(0x2E),
+        "MOUSELEFT" => InputBinding::Mouse(MouseButton::Left),
+        "MOUSERIGHT" => InputBinding::Mouse(MouseButton::Right),
+        "MOUSEMIDDLE" => InputBinding::Mouse(MouseButton::Middle),
+        "MOUSEX1" => InputBinding::Mouse(MouseButton::X1),
+        "MOUSEX2" => InputBinding::Mouse(MouseButton::X2),
+        _ => return None,
+    };
+
+    return Some(binding);
+}
+
+// named actions bound to keys or mouse buttons, an action is triggered by any of its bindings
+#[derive(Clone, Default, Debug)]
+pub struct ActionMap
+{
+    bindings : HashMap<String, Vec<InputBinding>>,
+}
+
+// function to parse a quoted string value, returns None if it isn't quoted
+fn parse_quoted(value : &str) -> Option<&str>
+{
+    return value.trim().strip_prefix('"').and_then(|x| x.strip_suffix('"'));
+}
+
+impl ActionMap
+{
+    // function to get the built-in bindings, they are used when there is no binding file
+    pub fn get_default() -> ActionMap
+    {
+        let mut action_map = ActionMap::default();
+        action_map.bind("toggle_vsync", InputBinding::Key(0x71));
+        action_map.bind("screenshot", InputBinding::Key(0x7B));
+        return action_map;
+    }
+
+    // function to parse bindings. each line is an action with a key name or a list of key names, e.g.
+    //   toggle_vsync = "F2"
+    //   screenshot = ["F12", "Insert"]
+    // lines starting with # are comments. the error has the line number of the first invalid line.
+    pub fn parse(text : &str) -> Result<ActionMap, String>
+    {
+        let mut action_map = ActionMap::default();
+
+        for (line_index, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+
+            let line_number = line_index + 1;
+            let (action, value) = line.split_once('=').ok_or(format!("line {}: expected 'action = \"key\"'", line_number))?;
+            let action = action.trim();
+            if action.is_empty()
+            {
+                return Err(format!("line {}: missing action name", line_number));
+            }
+
+            let value = value.trim();
+            let key_names : Vec<Option<&str>> = match value.strip_prefix('[').and_then(|x| x.strip_suffix(']'))
+            {
+                Some(list) => list.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(parse_quoted).collect(),
+                None => vec![parse_quoted(value)],
+            };
+
+            for x in key_names
+            {
+                let x = x.ok_or(format!("line {}: key names of action '{}' must be quoted, e.g. \"F2\"", line_number, action))?;
+                let binding = get_binding_from_name(x).ok_or(format!("line {}: unknown key '{}' for action '{}'", line_number, x, action))?;
+                action_map.bind(action, binding);
+            }
+        }
+
+        return Ok(action_map);
+    }
+
+    // function to load bindings from a file, falls back to the default bindings if the file is missing or invalid
+    pub fn load(path : &Path) -> ActionMap
+    {
+        let text = match fs::read_to_string(path)
+        {
+            Ok(x) => x,
+            Err(_) => return ActionMap::get_default(),
+        };
+
+        return match ActionMap::parse(&text)
+        {
+            Ok(x) => x,
+            Err(x) =>
+            {
+                println!("Error during loading {}: {}, using the default bindings.", path.display(), x);
+                ActionMap::get_default()
+            }
+        };
+    }
+
+    pub fn bind(&mut self, action : &str, binding : InputBinding)
+    {
+        let bindings = self.bindings.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding)
+        {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn get_bindings(&self, action : &str) -> &[InputBinding]
+    {
+        return self.bindings.get(action).map(|x| x.as_slice()).unwrap_or(&[]);
+    }
+
+    pub fn is_action_held(&self, state : &InputState, action : &str) -> bool
+    {
+        return self.get_bindings(action).iter().any(|x| match x
+        {
+            InputBinding::Key(key) => state.is_key_held(*key),
+            InputBinding::Mouse(button) => state.is_button_held(*button),
+        });
+    }
+
+    pub fn is_action_pressed(&self, state : &InputState, action : &str) -> bool
+    {
+        return self.get_bindings(action).iter().any(|x| match x
+        {
+            InputBinding::Key(key) => state.is_key_pressed(*key),
+            InputBinding::Mouse(button) => state.is_button_pressed(*button),
+        });
+    }
+
+    pub fn is_action_released(&self, state : &InputState, action : &str) -> bool
+    {
+        return self.get_bindings(action).iter().any(|x| match x
+        {
+            InputBinding::Key(key) => state.is_key_released(*key),
+            InputBinding::Mouse(button) => state.is_button_released(*button),
+        });
+    }
+}
+
+// the input of the app, fed by wnd_proc
+static mut GINPUT_STATE : Option<InputState> = None;
+static mut GACTION_MAP : Option<ActionMap> = None;
+
+// events applied during the current frame, kept for input recording
+static mut GFRAME_EVENTS : Vec<InputEvent> = Vec::new();
+
+// when live input is off, window messages are ignored and only injected events reach the input state (e.g. a replay)
+static mut GLIVE_INPUT : bool = true;
+
+// HID usage page and usage of a generic mouse
+#[cfg(windows)]
+const GHID_USAGE_PAGE_GENERIC : u16 = 0x01;
+#[cfg(windows)]
+const GHID_USAGE_GENERIC_MOUSE : u16 = 0x02;
+
+// function to initialize the input state and load the action bindings
+pub fn initialize(bindings_path : &Path)
+{
+    unsafe
+    {
+        GINPUT_STATE = Some(InputState::default());
+        GACTION_MAP = Some(ActionMap::load(bindings_path));
+        GLIVE_INPUT = true;
+        (*std::ptr::addr_of_mut!(GFRAME_EVENTS)).clear();
+    }
+}
+
+// function to register the mouse for raw input (WM_INPUT), this gives unaccelerated relative motion
+#[cfg(windows)]
+pub fn register_raw_mouse(h_wnd : HWND) -> bool
+{
+    unsafe
+    {
+        let raw_input_device = RAWINPUTDEVICE
+        {
+            usUsagePage : GHID_USAGE_PAGE_GENERIC,
+            usUsage : GHID_USAGE_GENERIC_MOUSE,
+            dwFlags : RAWINPUTDEVICE_FLAGS::default(),
+            hwndTarget : h_wnd,
+        };
+
+        if let Err(x) = RegisterRawInputDevices(&[raw_input_device], mem::size_of::<RAWINPUTDEVICE>() as u32)
+        {
+            println!("Error during registering raw mouse input! {}", x.message());
+            return false;
+        }
+
+        return true;
+    }
+}
+
+// function to read the relative motion of a WM_INPUT message
+#[cfg(windows)]
+fn read_raw_mouse_motion(l_param : LPARAM) -> Option<InputEvent>
+{
+    unsafe
+    {
+        let mut raw_input = RAWINPUT::default();
+        let mut size = mem::size_of::<RAWINPUT>() as u32;
+        let read_size = GetRawInputData(HRAWINPUT(l_param.0 as *mut c_void), RID_INPUT, Some(&mut raw_input as *mut _ as *mut c_void)
+            , &mut size, mem::size_of::<RAWINPUTHEADER>() as u32);
+
+        if read_size == u32::MAX || raw_input.header.dwType != RIM_TYPEMOUSE.0
+        {
+            return None;
+        }
+
+        // absolute positions come from tablets and remote desktop, they aren't motion
+        let mouse = raw_input.data.mouse;
+        if (mouse.usFlags.0 & MOUSE_MOVE_ABSOLUTE.0) != 0 || (mouse.lLastX == 0 && mouse.lLastY == 0)
+        {
+            return None;
+        }
+
+        return Some(InputEvent::RawMouseMotion { dx : mouse.lLastX, dy : mouse.lLastY });
+    }
+}
+
+// function to translate a window message to an input event, returns None for messages that aren't input
+#[cfg(windows)]
+pub fn translate_message(message : u32, w_param : WPARAM, l_param : LPARAM) -> Option<InputEvent>
+{
+    // the coordinates in l_param are signed, they are negative left or above the client area on multiple monitors
+    let x = (l_param.0 & 0xffff) as i16 as i32;
+    let y = ((l_param.0 >> 16) & 0xffff) as i16 as i32;
+
+    return match message
+    {
+        WM_KEYDOWN | WM_SYSKEYDOWN => Some(InputEvent::KeyDown(w_param.0 as u8)),
+        WM_KEYUP | WM_SYSKEYUP => Some(InputEvent::KeyUp(w_param.0 as u8)),
+        WM_MOUSEMOVE => Some(InputEvent::MouseMove { x, y }),
+        WM_LBUTTONDOWN => Some(InputEvent::MouseButtonDown(MouseButton::Left)),
+        WM_LBUTTONUP => Some(InputEvent::MouseButtonUp(MouseButton::Left)),
+        WM_RBUTTONDOWN => Some(InputEvent::MouseButtonDown(MouseButton::Right)),
+        WM_RBUTTONUP => Some(InputEvent::MouseButtonUp(MouseButton::Right)),
+        WM_MBUTTONDOWN => Some(InputEvent::MouseButtonDown(MouseButton::Middle)),
+        WM_MBUTTONUP => Some(InputEvent::MouseButtonUp(MouseButton::Middle)),
+        WM_XBUTTONDOWN | WM_XBUTTONUP =>
+        {
+            // high word of w_param tells which X button it is
+            let button = if ((w_param.0 >> 16) & 0xffff) as u16 == XBUTTON1 { MouseButton::X1 } else { MouseButton::X2 };
+            if message == WM_XBUTTONDOWN { Some(InputEvent::MouseButtonDown(button)) } else { Some(InputEvent::MouseButtonUp(button)) }
+        }
+        WM_MOUSEWHEEL =>
+        {
+            let delta = ((w_param.0 >> 16) & 0xffff) as i16 as f32;
+            Some(InputEvent::MouseWheel(delta / WHEEL_DELTA as f32))
+        }
+        WM_INPUT => read_raw_mouse_motion(l_param),
+        WM_KILLFOCUS => Some(InputEvent::FocusLost),
+        _ => None,
+    };
+}
+
+// function to feed the input state from the window events, the events are ignored while live input is off
+#[cfg(windows)]
+pub fn subscribe_window_events()
+{
+    let handler = |_h_wnd : HWND, event : &WindowEvent|
+    {
+        unsafe
+        {
+            if !GLIVE_INPUT
+            {
+                return EventResponse::Continue;
+            }
+        }
+
+        match *event
+        {
+            WindowEvent::Key { key, down } => inject_event(if down { InputEvent::KeyDown(key) } else { InputEvent::KeyUp(key) }),
+            WindowEvent::Mouse(x) => inject_event(x),
+            WindowEvent::FocusChanged { focused : false } => inject_event(InputEvent::FocusLost),
+            _ => {}
+        }
+
+        return EventResponse::Continue;
+    };
+
+    window_events::subscribe(WindowEventKind::Key, Box::new(handler));
+    window_events::subscribe(WindowEventKind::Mouse, Box::new(handler));
+    window_events::subscribe(WindowEventKind::FocusChanged, Box::new(handler));
+}
+
+// function to apply an event that doesn't come from a window message
+pub fn inject_event(event : InputEvent)
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GINPUT_STATE)).as_mut()
+        {
+            x.process_event(event);
+            (*std::ptr::addr_of_mut!(GFRAME_EVENTS)).push(event);
+        }
+    }
+}
+
+// function to turn the window messages on or off as the input source
+pub fn set_live_input(live_input : bool)
+{
+    unsafe
+    {
+        GLIVE_INPUT = live_input;
+    }
+}
+
+// function to get the events applied since the last end_frame, in their order
+pub fn get_frame_events() -> &'static [InputEvent]
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GFRAME_EVENTS)).as_slice();
+    }
+}
+
+// function to clear the per-frame input, call it after the frame used the input
+pub fn end_frame()
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GINPUT_STATE)).as_mut()
+        {
+            x.end_frame();
+        }
+        (*std::ptr::addr_of_mut!(GFRAME_EVENTS)).clear();
+    }
+}
+
+pub fn get_input_state() -> &'static InputState
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GINPUT_STATE)).as_ref().unwrap();
+    }
+}
+
+pub fn get_action_map() -> &'static ActionMap
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GACTION_MAP)).as_ref().unwrap();
+    }
+}
+
+// function to check an action against the current input, e.g. is_action_pressed("toggle_vsync")
+pub fn is_action_pressed(action : &str) -> bool
+{
+    return get_action_map().is_action_pressed(get_input_state(), action);
+}
+
+pub fn is_action_held(action : &str) -> bool
+{
+    return get_action_map().is_action_held(get_input_state(), action);
+}
+
+pub fn is_action_released(action : &str) -> bool
+{
+    return get_action_map().is_action_released(get_input_state(), action);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const GKEY_A : u8 = 0x41;
+
+    #[test]
+    fn key_press_hold_and_release_over_frames()
+    {
+        let mut state = InputState::default();
+        state.process_event(InputEvent::KeyDown(GKEY_A));
+        assert!(state.is_key_pressed(GKEY_A) && state.is_key_held(GKEY_A) && !state.is_key_released(GKEY_A));
+
+        // auto-repeat in the next frame isn't another press
+        state.end_frame();
+        state.process_event(InputEvent::KeyDown(GKEY_A));
+        assert!(!state.is_key_pressed(GKEY_A) && state.is_key_held(GKEY_A));
+
+        state.end_frame();
+        state.process_event(InputEvent::KeyUp(GKEY_A));
+        assert!(state.is_key_released(GKEY_A) && !state.is_key_held(GKEY_A));
+
+        state.end_frame();
+        assert!(!state.is_key_released(GKEY_A));
+
+        // a key up without a key down (e.g. the key was held before the window got the focus) isn't a release
+        state.process_event(InputEvent::KeyUp(GKEY_A));
+        assert!(!state.is_key_released(GKEY_A));
+    }
+
+    #[test]
+    fn press_and_release_within_one_frame_reports_both()
+    {
+        let mut state = InputState::default();
+        state.process_event(InputEvent::MouseButtonDown(MouseButton::X1));
+        state.process_event(InputEvent::MouseButtonUp(MouseButton::X1));
+        assert!(state.is_button_pressed(MouseButton::X1));
+        assert!(state.is_button_released(MouseButton::X1));
+        assert!(!state.is_button_held(MouseButton::X1));
+        assert!(!state.is_button_pressed(MouseButton::X2));
+    }
+
+    #[test]
+    fn mouse_deltas_accumulate_per_frame()
+    {
+        let mut state = InputState::default();
+
+        // the first position has nothing to compare with
+        state.process_event(InputEvent::MouseMove { x : 100, y : 50 });
+        assert_eq!(state.get_mouse_delta(), (0, 0));
+
+        state.process_event(InputEvent::MouseMove { x : 110, y : 45 });
+        state.process_event(InputEvent::MouseMove { x : 90, y : 40 });
+        state.process_event(InputEvent::RawMouseMotion { dx : 3, dy : -1 });
+        state.process_event(InputEvent::RawMouseMotion { dx : 2, dy : -1 });
+        state.process_event(InputEvent::MouseWheel(1.0));
+        state.process_event(InputEvent::MouseWheel(-0.5));
+        assert_eq!(state.get_mouse_position(), Some((90, 40)));
+        assert_eq!(state.get_mouse_delta(), (-10, -10));
+        assert_eq!(state.get_raw_mouse_delta(), (5, -2));
+        assert_eq!(state.get_wheel_delta(), 0.5);
+
+        // the deltas are per frame, the position stays
+        state.end_frame();
+        assert_eq!(state.get_mouse_delta(), (0, 0));
+        assert_eq!(state.get_raw_mouse_delta(), (0, 0));
+        assert_eq!(state.get_wheel_delta(), 0.0);
+        assert_eq!(state.get_mouse_position(), Some((90, 40)));
+        state.process_event(InputEvent::MouseMove { x : 91, y : 40 });
+        assert_eq!(state.get_mouse_delta(), (1, 0));
+    }
+
+    #[test]
+    fn focus_lost_releases_everything_held()
+    {
+        let mut state = InputState::default();
+        state.process_event(InputEvent::KeyDown(GKEY_A));
+        state.process_event(InputEvent::MouseButtonDown(MouseButton::Left));
+        state.end_frame();
+
+        state.process_event(InputEvent::FocusLost);
+        assert!(!state.is_key_held(GKEY_A) && state.is_key_released(GKEY_A));
+        assert!(!state.is_button_held(MouseButton::Left) && state.is_button_released(MouseButton::Left));
+        assert!(!state.is_button_released(MouseButton::Right));
+    }
+
+    #[test]
+    fn actions_follow_any_of_their_bindings()
+    {
+        let action_map = ActionMap::parse("# comment\n\nfire = [\"Space\", \"MouseLeft\"]\n  jump = \"f5\"\n").unwrap();
+        assert_eq!(action_map.get_bindings("fire"), &[InputBinding::Key(0x20), InputBinding::Mouse(MouseButton::Left)]);
+        assert_eq!(action_map.get_bindings("jump"), &[InputBinding::Key(0x74)]);
+        assert!(action_map.get_bindings("missing").is_empty());
+
+        let mut state = InputState::default();
+        state.process_event(InputEvent::MouseButtonDown(MouseButton::Left));
+        assert!(action_map.is_action_pressed(&state, "fire"));
+        assert!(action_map.is_action_held(&state, "fire"));
+        assert!(!action_map.is_action_pressed(&state, "jump"));
+
+        state.end_frame();
+        state.process_event(InputEvent::MouseButtonUp(MouseButton::Left));
+        assert!(action_map.is_action_released(&state, "fire"));
+        assert!(!action_map.is_action_held(&state, "fire"));
+    }
+
+    #[test]
+    fn binding_names()
+    {
+        assert_eq!(get_binding_from_name("a"), Some(InputBinding::Key(b'A')));
+        assert_eq!(get_binding_from_name("7"), Some(InputBinding::Key(b'7')));
+        assert_eq!(get_binding_from_name("F1"), Some(InputBinding::Key(0x70)));
+        assert_eq!(get_binding_from_name("F24"), Some(InputBinding::Key(0x87)));
+        assert_eq!(get_binding_from_name(" pageDown "), Some(InputBinding::Key(0x22)));
+        assert_eq!(get_binding_from_name("MouseX2"), Some(InputBinding::Mouse(MouseButton::X2)));
+        assert_eq!(get_binding_from_name("F25"), None);
+        assert_eq!(get_binding_from_name("F0"), None);
+        assert_eq!(get_binding_from_name(""), None);
+        assert_eq!(get_binding_from_name("Hyper"), None);
+    }
+
+    #[test]
+    fn action_map_parse_errors_have_the_line_number()
+    {
+        let errors = [
+            ("fire \"Space\"", "line 1: expected 'action = \"key\"'"),
+            ("\n = \"Space\"", "line 2: missing action name"),
+            ("fire = \"Space\"\n\njump = \"Hyper\"", "line 3: unknown key 'Hyper' for action 'jump'"),
+            ("fire = Space", "line 1: key names of action 'fire' must be quoted, e.g. \"F2\""),
+            ("fire = [\"Space\", Enter]", "line 1: key names of action 'fire' must be quoted, e.g. \"F2\""),
+        ];
+
+        for (text, error) in errors
+        {
+            assert_eq!(ActionMap::parse(text).unwrap_err(), error);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+// input_replay.rs - Recording of the per-frame input events and frame delta times to a compact binary file, and playing it back.
+// a replay drives the demo clock with the recorded delta times, so the same frames come out again, also on the headless path.
+//
+// file layout, all values are little endian:
+//   header : "RDIR" magic, u16 version
+//   frame  : u32 delta time in microseconds, u16 event count, events
+//   event  : u8 tag, followed by the payload of the tag (see write_event)
+
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::input::{InputEvent, MouseButton};
+
+const GREPLAY_MAGIC : &[u8; 4] = b"RDIR";
+const GREPLAY_VERSION : u16 = 1;
+
+// one recorded frame, the events are applied before the frame renders
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReplayFrame
+{
+    pub delta_time : Duration,
+    pub events : Vec<InputEvent>,
+}
+
+fn get_button_code(button : MouseButton) -> u8
+{
+    return match button
+    {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::X1 => 3,
+        MouseButton::X2 => 4,
+    };
+}
+
+fn get_button_from_code(code : u8) -> Option<MouseButton>
+{
+    return match code
+    {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Right),
+        2 => Some(MouseButton::Middle),
+        3 => Some(MouseButton::X1),
+        4 => Some(MouseButton::X2),
+        _ => None,
+    };
+}
+
+fn write_event(output : &mut Vec<u8>, event : &InputEvent)
+{
+    match *event
+    {
+        InputEvent::KeyDown(key) => output.extend_from_slice(&[0, key]),
+        InputEvent::KeyUp(key) => output.extend_from_slice(&[1, key]),
+        InputEvent::MouseMove { x, y } =>
+        {
+            output.push(2);
+            output.extend_from_slice(&x.to_le_bytes());
+            output.extend_from_slice(&y.to_le_bytes());
+        }
+        InputEvent::MouseButtonDown(button) => output.extend_from_slice(&[3, get_button_code(button)]),
+        InputEvent::MouseButtonUp(button) => output.extend_from_slice(&[4, get_button_code(button)]),
+        InputEvent::MouseWheel(notches) =>
+        {
+            output.push(5);
+            output.extend_from_slice(&notches.to_le_bytes());
+        }
+        InputEvent::RawMouseMotion { dx, dy } =>
+        {
+            output.push(6);
+            output.extend_from_slice(&dx.to_le_bytes());
+            output.extend_from_slice(&dy.to_le_bytes());
+        }
+        InputEvent::FocusLost => output.push(7),
+    }
+}
+
+// function to encode one frame, a frame has at most 65535 events and the rest are dropped
+pub fn encode_frame(delta_time : Duration, events : &[InputEvent]) -> Vec<u8>
+{
+    let delta_us = delta_time.as_micros().min(u32::MAX as u128) as u32;
+    let event_count = events.len().min(u16::MAX as usize);
+
+    let mut output : Vec<u8> = Vec::new();
+    output.extend_from_slice(&delta_us.to_le_bytes());
+    output.extend_from_slice(&(event_count as u16).to_le_bytes());
+    for x in &events[..event_count]
+    {
+        write_event(&mut output, x);
+    }
+
+    return output;
+}
+
+// a cursor over the replay bytes, every read fails at the end of the data
+struct ReplayReader<'a>
+{
+    bytes : &'a [u8],
+    offset : usize,
+}
+
+impl<'a> ReplayReader<'a>
+{
+    fn read_bytes<const N : usize>(&mut self) -> Result<[u8; N], String>
+    {
+        let end = self.offset + N;
+        if end > self.bytes.len()
+        {
+            return Err(format!("unexpected end of data at byte {}", self.offset));
+        }
+
+        let mut value = [0u8; N];
+        value.copy_from_slice(&self.bytes[self.offset..end]);
+        self.offset = end;
+        return Ok(value);
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String>
+    {
+        return Ok(self.read_bytes::<1>()?[0]);
+    }
+
+    fn read_i32(&mut self) -> Result<i32, String>
+    {
+        return Ok(i32::from_le_bytes(self.read_bytes::<4>()?));
+    }
+
+    fn read_button(&mut self) -> Result<MouseButton, String>
+    {
+        let code = self.read_u8()?;
+        return get_button_from_code(code).ok_or(format!("invalid mouse button {} at byte {}", code, self.offset - 1));
+    }
+
+    fn read_event(&mut self) -> Result<InputEvent, String>
+    {
+        let tag = self.read_u8()?;
+        let event = match tag
+        {
+            0 => InputEvent::KeyDown(self.read_u8()?),
+            1 => InputEvent::KeyUp(self.read_u8()?),
+            2 => InputEvent::MouseMove { x : self.read_i32()?, y : self.read_i32()? },
+            3 => InputEvent::MouseButtonDown(self.read_button()?),
+            4 => InputEvent::MouseButtonUp(self.read_button()?),
+            5 => InputEvent::MouseWheel(f32::from_le_bytes(self.read_bytes::<4>()?)),
+            6 => InputEvent::RawMouseMotion { dx : self.read_i32()?, dy : self.read_i32()? },
+            7 => InputEvent::FocusLost,
+            _ => return Err(format!("invalid event tag {} at byte {}", tag, self.offset - 1)),
+        };
+
+        return Ok(event);
+    }
+}
+
+// function to get the file header
+pub fn get_replay_header() -> Vec<u8>
+{
+    let mut header = GREPLAY_MAGIC.to_vec();
+    header.extend_from_slice(&GREPLAY_VERSION.to_le_bytes());
+    return header;
+}
+
+// function to decode a whole replay file, the error tells where the data went wrong
+pub fn decode_replay(bytes : &[u8]) -> Result<Vec<ReplayFrame>, String>
+{
+    let mut reader = ReplayReader { bytes, offset : 0 };
+    if &reader.read_bytes::<4>()? != GREPLAY_MAGIC
+    {
+        return Err(String::from("not an input replay file"));
+    }
+
+    let version = u16::from_le_bytes(reader.read_bytes::<2>()?);
+    if version != GREPLAY_VERSION
+    {
+        return Err(format!("unsupported replay version {}", version));
+    }
+
+    let mut frames : Vec<ReplayFrame> = Vec::new();
+    while reader.offset < bytes.len()
+    {
+        let delta_us = u32::from_le_bytes(reader.read_bytes::<4>()?);
+        let event_count = u16::from_le_bytes(reader.read_bytes::<2>()?);
+
+        let mut events : Vec<InputEvent> = Vec::with_capacity(event_count as usize);
+        for _ in 0..event_count
+        {
+            events.push(reader.read_event()?);
+        }
+
+        frames.push(ReplayFrame
+        {
+            delta_time : Duration::from_micros(delta_us as u64),
+            events,
+        });
+    }
+
+    return Ok(frames);
+}
+
+// writes the frames as they come, so a crash still leaves the frames before it
+pub struct InputRecorder
+{
+    writer : BufWriter<File>,
+    frame_count : u32,
+}
+
+impl InputRecorder
+{
+    pub fn new(output_path : &Path) -> std::io::Result<InputRecorder>
+    {
+        if let Some(x) = output_path.parent().filter(|x| !x.as_os_str().is_empty())
+        {
+            fs::create_dir_all(x)?;
+        }
+
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        writer.write_all(&get_replay_header())?;
+
+        return Ok(InputRecorder
+        {
+            writer,
+            frame_count : 0,
+        });
+    }
+
+    // function to append a frame, call it once per frame with the events of that frame
+    pub fn record_frame(&mut self, delta_time : Duration, events : &[InputEvent]) -> std::io::Result<()>
+    {
+        self.writer.write_all(&encode_frame(delta_time, events))?;
+        self.frame_count = self.frame_count + 1;
+        return Ok(());
+    }
+
+    // function to flush everything to disk, returns the number of recorded frames
+    pub fn finish(mut self) -> std::io::Result<u32>
+    {
+        self.writer.flush()?;
+        return Ok(self.frame_count);
+    }
+}
+
+// plays the frames back in order
+pub struct InputPlayback
+{
+    frames : Vec<ReplayFrame>,
+    next_frame_index : usize,
+    elapsed_time : Duration,
+}
+
+impl InputPlayback
+{
+    pub fn new(frames : Vec<ReplayFrame>) -> InputPlayback
+    {
+        return InputPlayback
+        {
+            frames,
+            next_frame_index : 0,
+            elapsed_time : Duration::ZERO,
+        };
+    }
+
+    pub fn load(path : &Path) -> Result<InputPlayback, String>
+    {
+        let bytes = fs::read(path).map_err(|x| x.to_string())?;
+        return Ok(InputPlayback::new(decode_replay(&bytes)?));
+    }
+
+    // function to get the next frame, the elapsed time includes its delta time. returns None at the end.
+    pub fn next_frame(&mut self) -> Option<&ReplayFrame>
+    {
+        let frame = self.frames.get(self.next_frame_index)?;
+        self.next_frame_index = self.next_frame_index + 1;
+        self.elapsed_time = self.elapsed_time + frame.delta_time;
+        return Some(frame);
+    }
+
+    // the sum of the delta times of the played frames, the demo clock is set to it
+    pub fn get_elapsed_time(&self) -> Duration
+    {
+        return self.elapsed_time;
+    }
+
+    pub fn get_frame_count(&self) -> u32
+    {
+        return self.frames.len() as u32;
+    }
+
+    pub fn is_finished(&self) -> bool
+    {
+        return self.next_frame_index >= self.frames.len();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // function to build a replay file from the header and the given frame bytes
+    fn to_replay(frames : &[Vec<u8>]) -> Vec<u8>
+    {
+        let mut bytes = get_replay_header();
+        for x in frames.iter()
+        {
+            bytes.extend_from_slice(x);
+        }
+        return bytes;
+    }
+
+    #[test]
+    fn decode_replay_reads_what_encode_frame_writes()
+    {
+        let frames = vec!
+        [
+            ReplayFrame
+            {
+                delta_time : Duration::from_micros(16667),
+                events : vec!
+                [
+                    InputEvent::KeyDown(0x41),
+                    InputEvent::KeyUp(0x41),
+                    InputEvent::MouseMove { x : -12, y : 1080 },
+                    InputEvent::MouseButtonDown(MouseButton::Left),
+                    InputEvent::MouseButtonUp(MouseButton::X2),
+                    InputEvent::MouseWheel(-1.5),
+                    InputEvent::RawMouseMotion { dx : i32::MIN, dy : i32::MAX },
+                    InputEvent::FocusLost,
+                ],
+            },
+            ReplayFrame { delta_time : Duration::ZERO, events : Vec::new() },
+            ReplayFrame { delta_time : Duration::from_micros(33333), events : vec![InputEvent::MouseButtonDown(MouseButton::Middle)] },
+        ];
+        let bytes = to_replay(&frames.iter().map(|x| encode_frame(x.delta_time, &x.events)).collect::<Vec<Vec<u8>>>());
+        assert_eq!(&bytes[..6], b"RDIR\x01\x00");
+        assert_eq!(decode_replay(&bytes).unwrap(), frames);
+
+        // a replay without any frame is fine
+        assert_eq!(decode_replay(&get_replay_header()).unwrap(), Vec::new());
+
+        // the playback sums the delta times of the played frames
+        let mut playback = InputPlayback::new(frames);
+        assert_eq!(playback.get_frame_count(), 3);
+        assert_eq!(playback.next_frame().unwrap().events.len(), 8);
+        assert!(playback.next_frame().is_some());
+        assert!(!playback.is_finished());
+        assert!(playback.next_frame().is_some());
+        assert!(playback.is_finished());
+        assert!(playback.next_frame().is_none());
+        assert_eq!(playback.get_elapsed_time(), Duration::from_micros(16667 + 33333));
+    }
+
+    #[test]
+    fn encode_frame_clamps_the_delta_time()
+    {
+        let bytes = encode_frame(Duration::from_secs(10000), &[]);
+        assert_eq!(bytes, vec![0xff, 0xff, 0xff, 0xff, 0, 0]);
+    }
+
+    #[test]
+    fn decode_replay_rejects_bad_headers()
+    {
+        assert_eq!(decode_replay(b"RDIX\x01\x00"), Err(String::from("not an input replay file")));
+        assert_eq!(decode_replay(b"RDIR\x02\x00"), Err(String::from("unsupported replay version 2")));
+        assert_eq!(decode_replay(b"RD"), Err(String::from("unexpected end of data at byte 0")));
+        assert_eq!(decode_replay(b"RDIR\x01"), Err(String::from("unexpected end of data at byte 4")));
+    }
+
+    #[test]
+    fn decode_replay_rejects_truncated_frames()
+    {
+        // the frame header is cut
+        assert_eq!(decode_replay(&to_replay(&[vec![1, 0, 0]])), Err(String::from("unexpected end of data at byte 6")));
+
+        // the y of the mouse move at byte 17 is cut, the frame header is at 6 and the event tag at 12
+        let mut frame = encode_frame(Duration::from_millis(16), &[InputEvent::MouseMove { x : 1, y : 2 }]);
+        frame.pop();
+        assert_eq!(decode_replay(&to_replay(&[frame])), Err(String::from("unexpected end of data at byte 17")));
+
+        // fewer events than the count says
+        let mut frame = encode_frame(Duration::from_millis(16), &[InputEvent::FocusLost]);
+        frame[4] = 2;
+        assert_eq!(decode_replay(&to_replay(&[frame])), Err(String::from("unexpected end of data at byte 13")));
+    }
+
+    #[test]
+    fn decode_replay_rejects_invalid_events()
+    {
+        let frame_header = encode_frame(Duration::from_millis(16), &[InputEvent::FocusLost])[..6].to_vec();
+
+        let invalid_tag = [frame_header.clone(), vec![8]].concat();
+        assert_eq!(decode_replay(&to_replay(&[invalid_tag])), Err(String::from("invalid event tag 8 at byte 12")));
+
+        let invalid_button = [frame_header.clone(), vec![3, 5]].concat();
+        assert_eq!(decode_replay(&to_replay(&[invalid_button])), Err(String::from("invalid mouse button 5 at byte 13")));
+
+        let invalid_button = [frame_header, vec![4, 0xff]].concat();
+        assert_eq!(decode_replay(&to_replay(&[invalid_button])), Err(String::from("invalid mouse button 255 at byte 13")));
+    }
+}
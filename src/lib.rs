@@ -0,0 +1,59 @@
+// lib.rs - The library side of the project, so the renderer can be embedded by other applications (see embed.rs).
+// main.rs is just one user of it that owns its own windows and message loop.
+// the modules touching D3D12 or Win32 only exist on Windows, the rest also build on the other platforms so their tests can run there.
+
+// the code spells out `return x;` and `a = a + 1`, clippy shouldn't fight that style
+#![allow(clippy::needless_return, clippy::assign_op_pattern)]
+
+#[cfg(windows)]
+pub mod graphic_device;
+#[cfg(windows)]
+pub mod hello_world_triangle;
+pub mod frame_limiter;
+#[cfg(windows)]
+pub mod embed;
+#[cfg(windows)]
+pub mod headless;
+pub mod image_io;
+#[cfg(windows)]
+pub mod screenshot;
+pub mod recording;
+#[cfg(windows)]
+pub mod window;
+#[cfg(windows)]
+pub mod render_scale;
+pub mod input;
+pub mod input_replay;
+#[cfg(windows)]
+pub mod window_events;
+pub mod mesh_io;
+#[cfg(windows)]
+pub mod asset_drop;
+#[cfg(windows)]
+pub mod render_thread;
+pub mod app_config;
+#[cfg(windows)]
+pub mod buffer;
+pub mod frame_ring;
+pub mod upload_allocator;
+pub mod descriptor_allocator;
+#[cfg(windows)]
+pub mod descriptor_heap;
+#[cfg(windows)]
+pub mod bindless;
+pub mod state_tracker;
+#[cfg(windows)]
+pub mod resource_states;
+pub mod barrier_mapping;
+#[cfg(windows)]
+pub mod barriers;
+pub mod memory_allocator;
+#[cfg(windows)]
+pub mod gpu_memory;
+pub mod residency_policy;
+#[cfg(windows)]
+pub mod residency;
+#[cfg(windows)]
+pub mod depth_buffer;
+#[cfg(windows)]
+pub mod msaa;
@@ -2,40 +2,484 @@
 // Feel free to use it if you want start graphics programming with Rust!
 
 // main.rs - The entry point of the app, mainly for window initialization and setup game loop.
+// the main thread owns the windows and pumps their messages, the game loop runs on the render thread.
 
+// same house style as lib.rs
+#![allow(clippy::needless_return, clippy::assign_op_pattern)]
+
+#[cfg(windows)]
 use windows::core::PCWSTR;
+#[cfg(windows)]
 use windows::Win32::Foundation::*;
+#[cfg(windows)]
 use windows::Win32::System::LibraryLoader::*;
+#[cfg(windows)]
 use windows::Win32::UI::WindowsAndMessaging::*;
+#[cfg(windows)]
 use windows::Win32::Graphics::Gdi::*;
+#[cfg(windows)]
 use windows_sys::*;
+#[cfg(windows)]
 use std::mem;
+#[cfg(windows)]
+use std::time::Duration;
+
+#[cfg(windows)]
+use rust_d3d12::graphic_device;
+#[cfg(windows)]
+use rust_d3d12::hello_world_triangle;
+#[cfg(windows)]
+use rust_d3d12::frame_limiter;
+#[cfg(windows)]
+use rust_d3d12::headless;
+#[cfg(windows)]
+use rust_d3d12::screenshot;
+#[cfg(windows)]
+use rust_d3d12::screenshot::ScreenshotFormat;
+#[cfg(windows)]
+use rust_d3d12::recording::{FrameRecorder, RecordingFormat};
+#[cfg(windows)]
+use rust_d3d12::window;
+#[cfg(windows)]
+use rust_d3d12::render_scale;
+#[cfg(windows)]
+use rust_d3d12::input;
+#[cfg(windows)]
+use rust_d3d12::asset_drop;
+#[cfg(windows)]
+use rust_d3d12::window_events;
+#[cfg(windows)]
+use rust_d3d12::window_events::{EventResponse, WindowEvent, WindowEventKind};
+#[cfg(windows)]
+use rust_d3d12::render_thread;
+#[cfg(windows)]
+use rust_d3d12::render_thread::{RenderAck, RenderCommand, RenderContext, RenderThread, WindowId};
+#[cfg(windows)]
+use rust_d3d12::input_replay::{InputPlayback, InputRecorder};
+#[cfg(windows)]
+use rust_d3d12::window::{WindowConfig, WindowPosition};
+#[cfg(windows)]
+use rust_d3d12::app_config;
+#[cfg(windows)]
+use rust_d3d12::upload_allocator;
+#[cfg(windows)]
+use rust_d3d12::descriptor_heap;
+#[cfg(windows)]
+use rust_d3d12::bindless;
+#[cfg(windows)]
+use rust_d3d12::depth_buffer;
+#[cfg(windows)]
+use rust_d3d12::msaa;
+#[cfg(windows)]
+use rust_d3d12::app_config::{AppConfig, SampleChoice};
+#[cfg(windows)]
+use rust_d3d12::headless::SampleFunctions;
+
+// a window with its own surface, plus the states that decide whether the game loop renders, throttles or pauses it
+#[cfg(windows)]
+struct AppWindow
+{
+    window_id : WindowId,
+    surface : graphic_device::Surface,
+    active : bool,
+    minimized : bool,
+    occluded : bool,
+    capture_request : Option<ScreenshotFormat>,
+    // intermediate target when the render scale isn't 1, upscaled to the back buffer
+    scaled_target : Option<render_scale::ScaledTarget>,
+}
+
+// all windows with a surface, the first one is the main window. only the render thread touches them.
+#[cfg(windows)]
+static mut GWINDOWS : Vec<AppWindow> = Vec::new();
+
+// the render thread and the main window, only the message thread touches them
+#[cfg(windows)]
+static mut GRENDER_THREAD : Option<RenderThread> = None;
+#[cfg(windows)]
+static mut GMAIN_WINDOW : Option<HWND> = None;
+
+// the longest time the message thread waits for the render thread to handle a resize or a closed window
+#[cfg(windows)]
+const GRENDER_HANDSHAKE_TIMEOUT : Duration = Duration::from_millis(250);
+
+// function to find the window entry of a HWND
+#[cfg(windows)]
+unsafe fn find_window(h_wnd : HWND) -> Option<&'static mut AppWindow>
+{
+    return (*std::ptr::addr_of_mut!(GWINDOWS)).iter_mut().find(|x| x.window_id == render_thread::get_window_id(h_wnd));
+}
+
+// function to register the handlers of the message thread, they forward the events to the render thread
+#[cfg(windows)]
+fn subscribe_message_thread_events()
+{
+    // WM_SIZE also comes during CreateWindowExW, before the render thread exists. the render thread reads the size itself then.
+    window_events::subscribe(WindowEventKind::Resized, Box::new(|h_wnd, event|
+    {
+        if let (Some(x), WindowEvent::Resized { minimized, .. }) = (unsafe { (*std::ptr::addr_of!(GRENDER_THREAD)).as_ref() }, event)
+        {
+            // wait until a frame with the new size is presented while dragging the border, nothing to wait for when minimized
+            if *minimized
+            {
+                x.send_event(h_wnd, event);
+            }
+            else
+            {
+                let _ = x.resize_and_wait(h_wnd, event, GRENDER_HANDSHAKE_TIMEOUT);
+            }
+        }
+        return EventResponse::Continue;
+    }));
+
+    let forward = |h_wnd, event : &WindowEvent|
+    {
+        if let Some(x) = unsafe { (*std::ptr::addr_of!(GRENDER_THREAD)).as_ref() }
+        {
+            x.send_event(h_wnd, event);
+        }
+        return EventResponse::Continue;
+    };
+    window_events::subscribe(WindowEventKind::FocusChanged, Box::new(forward));
+    window_events::subscribe(WindowEventKind::Key, Box::new(forward));
+    window_events::subscribe(WindowEventKind::Mouse, Box::new(forward));
+    window_events::subscribe(WindowEventKind::FileDropped, Box::new(forward));
+
+    window_events::subscribe(WindowEventKind::DpiChanged, Box::new(|h_wnd, event|
+    {
+        // the window is moved to another monitor or the scaling is changed, resize to the suggested rect
+        if let WindowEvent::DpiChanged { suggested_rect, .. } = event
+        {
+            window::handle_dpi_changed(h_wnd, suggested_rect);
+        }
+        return EventResponse::Consumed;
+    }));
+}
+
+// function to register the handlers of the render thread for the forwarded events
+#[cfg(windows)]
+fn subscribe_render_thread_events()
+{
+    window_events::subscribe(WindowEventKind::FocusChanged, Box::new(|h_wnd, event|
+    {
+        if let (Some(x), WindowEvent::FocusChanged { focused }) = (unsafe { find_window(h_wnd) }, event)
+        {
+            x.active = *focused;
+        }
+        return EventResponse::Continue;
+    }));
 
-mod graphic_device;
-mod hello_world_triangle;
+    window_events::subscribe(WindowEventKind::Resized, Box::new(|h_wnd, event|
+    {
+        if let (Some(x), WindowEvent::Resized { width, height, minimized }) = (unsafe { find_window(h_wnd) }, event)
+        {
+            x.minimized = *minimized;
+            if !x.minimized
+            {
+                x.surface.resize(*width, *height);
+            }
+        }
+        return EventResponse::Continue;
+    }));
+}
 
-// define window proc function for the Win32 messages
+// define window proc function for the Win32 messages, most of them go through the window event bus
+#[cfg(windows)]
 unsafe extern "system" fn wnd_proc(h_wnd : HWND, message : u32, w_param : WPARAM, l_param : LPARAM) -> LRESULT
 {
+    if let Some(x) = window_events::handle_message(h_wnd, message, w_param, l_param)
+    {
+        return x;
+    }
+
     match message
     {
-        WM_DESTROY => 
+        WM_DESTROY =>
         {
-            PostQuitMessage(0);
+            // the surface must be released while the window still exists
+            if GMAIN_WINDOW == Some(h_wnd)
+            {
+                if let Some(x) = (*std::ptr::addr_of_mut!(GRENDER_THREAD)).take()
+                {
+                    x.shutdown();
+                }
+                PostQuitMessage(0);
+            }
+            else if let Some(x) = (*std::ptr::addr_of!(GRENDER_THREAD)).as_ref()
+            {
+                let _ = x.remove_window(h_wnd, GRENDER_HANDSHAKE_TIMEOUT);
+            }
             LRESULT::default()
         }
         _ => return DefWindowProcW(h_wnd,message,w_param,l_param),
     }
 }
 
+// function to create a window, the surface for it is created by the render thread
+#[cfg(windows)]
+unsafe fn create_app_window(app_instance : HINSTANCE, app_class_name : PCWSTR, config : &WindowConfig) -> Option<HWND>
+{
+    return match window::create_window(config, app_instance, app_class_name)
+    {
+        Ok(x) => Some(x),
+        Err(x) =>
+        {
+            println!("Error during window creation! {}", x.message());
+            None
+        }
+    };
+}
+
+// function to create the surface of a window on the render thread, sized from the real client rect so the image is never scaled.
+// with a render scale the scene is drawn into the scaled target, so the surface is only a scene target at scale 1.
+#[cfg(windows)]
+unsafe fn create_app_surface(window_id : WindowId) -> bool
+{
+    let h_wnd = render_thread::get_hwnd(window_id);
+    let (width, height) = window::get_client_size(h_wnd);
+    let surface = match graphic_device::create_surface(h_wnd, width.max(1), height.max(1), render_scale::get_render_scale() == 1.0)
+    {
+        Some(x) => x,
+        None =>
+        {
+            println!("Error during swapchain creation!");
+            return false;
+        }
+    };
+
+    (*std::ptr::addr_of_mut!(GWINDOWS)).push(AppWindow
+    {
+        window_id,
+        surface,
+        active : false,
+        minimized : false,
+        occluded : false,
+        capture_request : None,
+        scaled_target : None,
+    });
+
+    return true;
+}
+
+// function to get the value that follows a command line switch
+#[cfg(windows)]
+fn get_arg_value<'a>(args : &'a [String], name : &str) -> Option<&'a String>
+{
+    return args.iter().position(|x| x == name).and_then(|x| args.get(x + 1));
+}
+
+// function to get the entry points of the sample picked with "--sample <name>", new samples get an arm here
+#[cfg(windows)]
+fn get_sample_functions(sample : SampleChoice) -> SampleFunctions
+{
+    return match sample
+    {
+        SampleChoice::HelloWorldTriangle => SampleFunctions
+        {
+            create_pipeline : hello_world_triangle::create_pipeline,
+            render : hello_world_triangle::render,
+        },
+    };
+}
+
+// function to record the demo offscreen with a fixed clock step, the frames are written by the recorder as they come
+#[cfg(windows)]
+fn record(args : &[String], config : &AppConfig, record_path : &std::path::Path)
+{
+    let is_y4m = record_path.extension().is_some_and(|x| x.eq_ignore_ascii_case("y4m"));
+    let format = match get_arg_value(args, "--record-format").map(|x| x.as_str())
+    {
+        Some("png") => RecordingFormat::PngSequence,
+        Some("y4m") => RecordingFormat::Y4m,
+        _ => if is_y4m { RecordingFormat::Y4m } else { RecordingFormat::PngSequence },
+    };
+
+    // one second of animation by default
+    let frame_rate = get_arg_value(args, "--record-fps").and_then(|x| x.parse::<u32>().ok()).filter(|x| *x > 0).unwrap_or(60);
+    let frame_count = config.frames.unwrap_or(frame_rate);
+
+    let mut recorder = match FrameRecorder::new(record_path, format, frame_rate)
+    {
+        Ok(x) => x,
+        Err(x) =>
+        {
+            println!("Error during recording setup: {}", x);
+            return;
+        }
+    };
+
+    // drive the demo clock with the frame index instead of the wall clock
+    hello_world_triangle::set_fixed_time_step(Some(std::time::Duration::from_secs(1) / frame_rate));
+
+    let mut write_error : Option<std::io::Error> = None;
+    let succeeded = headless::run(&get_sample_functions(config.sample), config.width, config.height, frame_count, |frame_index, image|
+    {
+        if write_error.is_some()
+        {
+            return;
+        }
+
+        match recorder.write_frame(image)
+        {
+            Ok(()) => println!("Recorded frame {}/{}", frame_index + 1, frame_count),
+            Err(x) => write_error = Some(x),
+        }
+    });
+
+    if !succeeded
+    {
+        println!("Error during headless rendering!");
+    }
+    if let Some(x) = write_error
+    {
+        println!("Error during writing frames: {}", x);
+    }
+
+    match recorder.finish()
+    {
+        Ok(x) => println!("Recording finished, {} frames written to {}", x, record_path.display()),
+        Err(x) => println!("Error during finishing the recording: {}", x),
+    }
+}
+
+// function to replay recorded input offscreen, the frames are rendered with the recorded delta times.
+// the screenshot action of the replay saves the frame, so a replay can produce the images for a regression run.
+#[cfg(windows)]
+fn replay_headless(config : &AppConfig, replay_path : &std::path::Path)
+{
+    let mut playback = match InputPlayback::load(replay_path)
+    {
+        Ok(x) => x,
+        Err(x) =>
+        {
+            println!("Error during loading the replay {}: {}", replay_path.display(), x);
+            return;
+        }
+    };
+
+    input::initialize(std::path::Path::new("input_bindings.toml"));
+    input::set_live_input(false);
+
+    let frame_count = config.frames.unwrap_or(u32::MAX).min(playback.get_frame_count());
+    let succeeded = headless::run_with_update(&get_sample_functions(config.sample), config.width, config.height, frame_count, |_|
+    {
+        if let Some(frame) = playback.next_frame()
+        {
+            for x in frame.events.iter()
+            {
+                input::inject_event(*x);
+            }
+        }
+        hello_world_triangle::set_manual_time(Some(playback.get_elapsed_time()));
+    }
+    , |frame_index, image|
+    {
+        let checksum = image.pixels.iter().fold(0u32, |sum, x| sum.wrapping_mul(31).wrapping_add(*x as u32));
+        println!("Replay frame {}: checksum {:08x}", frame_index, checksum);
+
+        if input::is_action_pressed("screenshot")
+        {
+            let path = std::path::PathBuf::from(format!("replay_frame_{:06}.png", frame_index));
+            if screenshot::save(image, ScreenshotFormat::Png, &path)
+            {
+                println!("Replay screenshot saved: {}", path.display());
+            }
+        }
+        input::end_frame();
+    });
+
+    if !succeeded
+    {
+        println!("Error during headless rendering!");
+    }
+}
+
+// the app needs D3D12, the other platforms only build the library for its tests
+#[cfg(not(windows))]
+fn main()
+{
+    println!("RustD3D12 only runs on Windows.");
+}
+
 // entry point of the app
+#[cfg(windows)]
 fn main()
 {
-    unsafe 
+    let args : Vec<String> = std::env::args().collect();
+
+    // the settings come from config.toml (or "--config <path>") with the command line switches on top, see app_config.rs
+    let config = match app_config::load(&args)
+    {
+        Ok(x) => x,
+        Err(errors) =>
+        {
+            println!("Error during loading the config:");
+            for x in errors.iter()
+            {
+                println!("  {}", x);
+            }
+            return;
+        }
+    };
+
+    // "--print-config" dumps the resolved settings in the config file format and exits
+    if args.iter().any(|x| x == "--print-config")
+    {
+        print!("{}", config.to_toml());
+        return;
+    }
+
+    // the device settings must be in place before any initialize_d3d12()
+    graphic_device::set_debug_layer(config.debug_layer);
+    graphic_device::set_adapter_choice(config.adapter.clone());
+    graphic_device::set_back_buffer_format(config.back_buffer_format);
+    depth_buffer::set_depth_format(config.depth_format);
+    depth_buffer::set_reverse_z(config.reverse_z);
+    msaa::set_sample_count(config.msaa);
+    graphic_device::set_vsync(config.vsync);
+
+    // "--record <path>" renders a frame-exact sequence offscreen, as numbered PNG files in a directory or a .y4m stream.
+    // "--record-format png|y4m" overrides the format from the extension, "--record-fps <rate>" sets the clock step.
+    if let Some(record_path) = get_arg_value(&args, "--record")
+    {
+        record(&args, &config, std::path::Path::new(record_path));
+        return;
+    }
+
+    // "--headless --replay <file>" plays recorded input offscreen instead of opening a window
+    if let (true, Some(replay_path)) = (config.headless, get_arg_value(&args, "--replay"))
+    {
+        replay_headless(&config, std::path::Path::new(replay_path));
+        return;
+    }
+
+    // "--headless" renders offscreen without creating any window, "--frames <count>" sets how many frames to render
+    if config.headless
+    {
+        let frame_count = config.frames.unwrap_or(1);
+
+        // the read back frames stay in CPU memory, print a checksum of each one to tell them apart
+        let succeeded = headless::run(&get_sample_functions(config.sample), config.width, config.height, frame_count, |frame_index, image|
+        {
+            let checksum = image.pixels.iter().fold(0u32, |sum, x| sum.wrapping_mul(31).wrapping_add(*x as u32));
+            println!("Headless frame {}: {} bytes, checksum {:08x}", frame_index, image.pixels.len(), checksum);
+        });
+
+        if !succeeded
+        {
+            println!("Error during headless rendering!");
+        }
+        return;
+    }
+
+    unsafe
     {
         // get app instance, register class and create window
         // we don't have int WINAPI wWinMain(HINSTANCE hInstance, HINSTANCE hPrevInstance, PWSTR pCmdLine, int nCmdShow); in Rust
         // so it's necessary to get the HINSTANCE from module handle directly.
+        // declare per-monitor DPI awareness before any window exists, so the client size is in physical pixels
+        window::enable_per_monitor_dpi_awareness();
+
         let app_instance : HINSTANCE = GetModuleHandleW(None).unwrap().into();
         let app_class_name = PCWSTR::from_raw(w!("Rust D3D12"));
 
@@ -58,44 +502,373 @@ fn main()
 
         RegisterClassExW(&app_class);
 
-        // fixed at 1080p and disabled window resizing and maximizing for now
-        let render_width : u32 = 1920;
-        let render_height : u32 = 1080;
-        let app_window = CreateWindowExW(WINDOW_EX_STYLE::default(), app_class_name, PCWSTR::from_raw(w!("Rust D3D12"))
-        , WS_OVERLAPPED | WS_MINIMIZEBOX | WS_SYSMENU, 0, 0, render_width as i32, render_height as i32, None, None, app_instance, None).unwrap();
+        // the message thread handlers are registered before any window exists, so no message is missed
+        subscribe_message_thread_events();
+
+        // the size in WindowConfig is the client area, it's centered on the primary monitor without resizing
+        let main_window_config = WindowConfig
+        {
+            client_width : config.width,
+            client_height : config.height,
+            ..WindowConfig::default()
+        };
+        let main_window = match create_app_window(app_instance, app_class_name, &main_window_config)
+        {
+            Some(x) => x,
+            None => return,
+        };
+        GMAIN_WINDOW = Some(main_window);
+        let mut window_ids : Vec<WindowId> = vec![render_thread::get_window_id(main_window)];
+
+        // additional viewports can be opened with "--windows <count>", each of them renders with its own swapchain
+        for idx in 1..config.windows
+        {
+            let offset = 64 * idx as i32;
+            let viewport_config = WindowConfig
+            {
+                title : format!("{} - Viewport {}", main_window_config.title, idx + 1),
+                client_width : main_window_config.client_width / 2,
+                client_height : main_window_config.client_height / 2,
+                position : WindowPosition::At { x : offset, y : offset },
+                resizable : true,
+                ..main_window_config.clone()
+            };
+            if let Some(x) = create_app_window(app_instance, app_class_name, &viewport_config)
+            {
+                window_ids.push(render_thread::get_window_id(x));
+            }
+        }
+
+        // everything that touches D3D12 runs on the render thread, this thread only pumps the window messages.
+        // so dragging or resizing a window, which runs a modal loop here, doesn't stop the animation.
+        let render_args = args.clone();
+        let render_config = config.clone();
+        let render_window_ids = window_ids.clone();
+        let render_thread = match RenderThread::spawn(move |context| render_main(context, render_window_ids, render_args, render_config))
+        {
+            Ok(x) => x,
+            Err(x) =>
+            {
+                println!("Error during render thread creation! {}", x);
+                return;
+            }
+        };
 
-        // initialize graphic device
-        if !graphic_device::initialize_d3d12(app_window, render_width, render_height)
+        if !render_thread.wait_for_start()
         {
+            render_thread.shutdown();
+            for x in window_ids.iter()
+            {
+                let _ = DestroyWindow(render_thread::get_hwnd(*x));
+            }
             return;
         }
+        GRENDER_THREAD = Some(render_thread);
 
-        // initialize demo resources
-        hello_world_triangle::create_pipeline();
+        // show the windows after windows and graphic device are created.
+        for x in window_ids.iter()
+        {
+            let _ = ShowWindow(render_thread::get_hwnd(*x), SW_SHOW);
+        }
 
-        // show the window and enter the game loop after window and graphic device are created.
-        let _ = ShowWindow(app_window, SW_SHOW);
+        // raw mouse motion goes to the main window
+        let _ = input::register_raw_mouse(main_window);
+
+        // the message pump can block now, the render thread paces itself
         let mut msg = MSG::default();
-        
-        while msg.message != WM_QUIT
+        while GetMessageW(&mut msg, None, 0, 0).0 > 0
+        {
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+
+        // the render thread is normally stopped by WM_DESTROY of the main window
+        if let Some(x) = (*std::ptr::addr_of_mut!(GRENDER_THREAD)).take()
+        {
+            x.shutdown();
+        }
+        window_events::clear_subscribers();
+    }
+}
+
+// the render thread, it creates the device and the surfaces of the windows and runs the game loop until Shutdown.
+// the window events arrive over the channel and are dispatched on the event bus of this thread.
+#[cfg(windows)]
+fn render_main(context : RenderContext, window_ids : Vec<WindowId>, args : Vec<String>, config : AppConfig)
+{
+    unsafe
+    {
+        // keyboard and mouse input, the action bindings are loaded from input_bindings.toml next to the working directory
+        input::initialize(std::path::Path::new("input_bindings.toml"));
+
+        subscribe_render_thread_events();
+        input::subscribe_window_events();
+
+        // drop a .hlsl file onto a window to replace the demo pixel shader, or an image / mesh to load it
+        asset_drop::register_default_loaders();
+        asset_drop::subscribe_window_events();
+
+        // "--render-scale <scale>" renders at a scaled resolution and upscales it to the window, e.g. 0.5 for half resolution.
+        // it's set before the surfaces are created, they don't need depth buffers or MSAA targets of their own when the scene is scaled
+        render_scale::set_render_scale(config.render_scale);
+
+        // initialize graphic device, it's shared by all windows
+        if !graphic_device::initialize_d3d12() || !upload_allocator::initialize(upload_allocator::GDEFAULT_CAPACITY) || !window_ids.iter().all(|x| create_app_surface(*x))
         {
-            if PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE).as_bool()
+            context.acknowledge(RenderAck::Started(false));
+            window_events::clear_subscribers();
+            (*std::ptr::addr_of_mut!(GWINDOWS)).clear();
+            return;
+        }
+
+        // initialize demo resources
+        let sample = get_sample_functions(config.sample);
+        (sample.create_pipeline)();
+
+        if !render_scale::create_pipeline()
+        {
+            render_scale::set_render_scale(1.0);
+        }
+
+        // bindless resources are optional, dropped images are shown with the bindless blit when the device supports SM 6.6
+        if bindless::initialize()
+        {
+            bindless::create_blit_pipeline();
+        }
+
+        // present() runs without vsync by default, so pace the loop ourselves instead of spinning at thousands of FPS.
+        // target frame rates for focused, unfocused and minimized window, 0 means unlimited.
+        frame_limiter::initialize(config.focused_fps, config.unfocused_fps, config.minimized_fps);
+
+        // "--record-input <file>" records the input and frame times, "--replay <file>" plays them back instead of the live input.
+        // both drive the demo clock with the frame times, so the replay shows the same frames. the app quits when a replay ends.
+        let mut input_recorder : Option<InputRecorder> = None;
+        if let Some(x) = get_arg_value(&args, "--record-input")
+        {
+            match InputRecorder::new(std::path::Path::new(x))
             {
-                let _ = TranslateMessage(&msg);
-                DispatchMessageW(&msg);
+                Ok(recorder) => input_recorder = Some(recorder),
+                Err(error) => println!("Error during creating the input recording {}: {}", x, error),
             }
-            else
-            {    
+        }
+
+        let mut input_playback : Option<InputPlayback> = None;
+        if let Some(x) = get_arg_value(&args, "--replay")
+        {
+            match InputPlayback::load(std::path::Path::new(x))
+            {
+                Ok(playback) =>
+                {
+                    input::set_live_input(false);
+                    input_playback = Some(playback);
+                }
+                Err(error) => println!("Error during loading the replay {}: {}", x, error),
+            }
+        }
+
+        // the message thread waits for this before it shows the windows
+        context.acknowledge(RenderAck::Started(true));
+
+        let main_window_id = window_ids[0];
+        let mut demo_time = std::time::Duration::ZERO;
+        let mut last_frame_time = std::time::Instant::now();
+        loop
+        {
+            let mut running = true;
+
+            // the resizes are acknowledged after the frame with the new size is presented
+            let mut resized_windows : Vec<WindowId> = Vec::new();
+            for command in context.poll_commands()
+            {
+                match command
+                {
+                    RenderCommand::Event { window_id, event } =>
+                    {
+                        if let WindowEvent::Resized { .. } = event
+                        {
+                            resized_windows.push(window_id);
+                        }
+                        window_events::dispatch(render_thread::get_hwnd(window_id), &event);
+                    }
+                    RenderCommand::RemoveWindow { window_id } =>
+                    {
+                        // the surface might still be used by the GPU
+                        graphic_device::wait_for_gpu();
+                        (*std::ptr::addr_of_mut!(GWINDOWS)).retain(|x| x.window_id != window_id);
+                        context.acknowledge(RenderAck::WindowRemoved(window_id));
+                    }
+                    RenderCommand::Shutdown => running = false,
+                }
+            }
+            if !running
+            {
+                break;
+            }
+
+            let now = std::time::Instant::now();
+            let mut delta_time = now - last_frame_time;
+            last_frame_time = now;
+
+            // a replay feeds the recorded events and frame time instead of the live ones
+            if let Some(playback) = input_playback.as_mut()
+            {
+                match playback.next_frame()
+                {
+                    Some(frame) =>
+                    {
+                        delta_time = frame.delta_time;
+                        for x in frame.events.iter()
+                        {
+                            input::inject_event(*x);
+                        }
+                    }
+                    None =>
+                    {
+                        // close the main window, the message thread shuts this thread down then
+                        println!("Replay finished.");
+                        let _ = PostMessageW(render_thread::get_hwnd(main_window_id), WM_CLOSE, WPARAM(0), LPARAM(0));
+                        input_playback = None;
+                    }
+                }
+            }
+            if let Some(x) = input_recorder.as_mut()
+            {
+                if let Err(error) = x.record_frame(delta_time, input::get_frame_events())
+                {
+                    println!("Error during recording input: {}, the recording is stopped.", error);
+                    input_recorder = None;
+                }
+            }
+            if input_recorder.is_some() || input_playback.is_some()
+            {
+                demo_time = demo_time + delta_time;
+                hello_world_triangle::set_manual_time(Some(demo_time));
+            }
+
+            // nothing is visible when a window is minimized or fully covered, skip it.
+            // the occlusion is reported by the last present, keep testing it until the window shows up again.
+            let windows = &mut *std::ptr::addr_of_mut!(GWINDOWS);
+            let mut any_visible = false;
+            let mut any_active = false;
+            for x in windows.iter_mut()
+            {
+                if x.occluded
+                {
+                    x.occluded = x.surface.is_occluded();
+                }
+                any_visible = any_visible || (!x.minimized && !x.occluded);
+                any_active = any_active || x.active;
+            }
+
+            // actions of this frame. the screenshot goes to the focused window, hold shift for DDS with the original back buffer format.
+            if input::is_action_pressed("toggle_vsync")
+            {
+                graphic_device::set_vsync(!graphic_device::get_vsync());
+                println!("VSync: {}", if graphic_device::get_vsync() { "on" } else { "off" });
+            }
+            if input::is_action_pressed("screenshot")
+            {
+                let shift_down = input::get_input_state().is_key_held(0x10);
+                if let Some(x) = windows.iter_mut().find(|x| x.active)
+                {
+                    x.capture_request = Some(if shift_down { ScreenshotFormat::Dds } else { ScreenshotFormat::Png });
+                }
+            }
+            input::end_frame();
+
+            // pause the demo clock as well when no window is visible
+            let paused = !any_visible;
+            hello_world_triangle::set_paused(paused);
+
+            if !paused
+            {
                 graphic_device::update();
-                hello_world_triangle::render(render_width, render_height);
+                graphic_device::begin_frame();
+                upload_allocator::begin_frame();
+                descriptor_heap::begin_frame();
+                bindless::begin_frame();
 
-                // present and wait GPU fence. just for demo, it's not the best way to do this.
+                for x in windows.iter_mut()
+                {
+                    if x.minimized || x.occluded
+                    {
+                        continue;
+                    }
+
+                    let mut render_target = x.surface.get_render_target();
+                    if !asset_drop::render_image_view(&render_target)
+                    {
+                        // the back buffer only gets the scene buffers when the scene isn't drawn into a scaled target
+                        let scaled_render_target = render_scale::prepare_target(&mut x.scaled_target, render_target.width, render_target.height);
+                        x.surface.set_scene_target(scaled_render_target.is_none());
+                        render_target = x.surface.get_render_target();
+                        match scaled_render_target
+                        {
+                            Some(scaled_render_target) =>
+                            {
+                                (sample.render)(&scaled_render_target);
+                                render_scale::blit(x.scaled_target.as_ref().unwrap(), &render_target);
+                            }
+                            None => (sample.render)(&render_target),
+                        }
+                    }
+
+                    // the back buffer is only valid until present, so capture it right here
+                    if let Some(format) = x.capture_request.take()
+                    {
+                        let _ = screenshot::capture_to_file(&render_target, format);
+                    }
+                    x.occluded = !x.surface.present();
+                }
+
+                // wait GPU fence once for all windows. just for demo, it's not the best way to do this.
                 // doing a ring-buffer workflow for frame resources is the way for better CPU-GPU efficiency.
-                graphic_device::present();
+                upload_allocator::end_frame();
+                descriptor_heap::end_frame();
                 graphic_device::wait_for_gpu();
             }
+
+            for x in resized_windows.iter()
+            {
+                context.acknowledge(RenderAck::Resized(*x));
+            }
+
+            // keep the loop alive at a lower rate while unfocused or paused, so the commands are still handled
+            let limiter_mode = if paused
+            {
+                frame_limiter::LimiterMode::Minimized
+            }
+            else if !any_active
+            {
+                frame_limiter::LimiterMode::Unfocused
+            }
+            else
+            {
+                frame_limiter::LimiterMode::Focused
+            };
+            frame_limiter::wait_for_next_frame(limiter_mode);
         }
 
+        if let Some(x) = input_recorder.take()
+        {
+            match x.finish()
+            {
+                Ok(frame_count) => println!("Input recording finished, {} frames.", frame_count),
+                Err(error) => println!("Error during finishing the input recording: {}", error),
+            }
+        }
+
+        window_events::clear_subscribers();
+        frame_limiter::shutdown();
+
+        // release the assets and the surfaces after the GPU is idle. their placed resources must go back to the heaps
+        // and be unregistered before the state tracking, the heaps and the residency are shut down
+        graphic_device::wait_for_gpu();
+        asset_drop::shutdown();
+        (*std::ptr::addr_of_mut!(GWINDOWS)).clear();
+        upload_allocator::shutdown();
         graphic_device::shutdown();
+        descriptor_heap::shutdown();
+        bindless::shutdown();
     }
 }
@@ -0,0 +1,621 @@
+// memory_allocator.rs - A TLSF allocator for the offsets in a GPU heap, gpu_memory.rs places the resources at them
+
+const GSECOND_LEVEL_LOG2 : u32 = 4;
+const GSECOND_LEVEL_COUNT : usize = 1 << GSECOND_LEVEL_LOG2;
+const GFIRST_LEVEL_COUNT : usize = 64 - GSECOND_LEVEL_LOG2 as usize + 1;
+
+// a block of the heap, free or allocated, it knows its physical neighbours to merge them and its neighbours in the free list
+#[derive(Clone, Copy, Debug)]
+struct TlsfBlock
+{
+    offset : u64,
+    size : u64,
+    is_free : bool,
+    prev_physical : Option<u32>,
+    next_physical : Option<u32>,
+    prev_free : Option<u32>,
+    next_free : Option<u32>,
+    // it changes every time the block is freed or the slot is reused, to catch an allocation that was freed already
+    generation : u32,
+}
+
+// an allocation in the heap, offset and size are aligned to what was asked for
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MemoryAllocation
+{
+    pub offset : u64,
+    pub size : u64,
+    // the slot of the block and its generation when it was allocated
+    pub block : u32,
+    pub generation : u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct MemoryStatistics
+{
+    pub capacity : u64,
+    pub used_size : u64,
+    pub allocation_count : u32,
+    pub free_block_count : u32,
+    pub largest_free_block : u64,
+}
+
+impl MemoryStatistics
+{
+    pub fn get_free_size(&self) -> u64
+    {
+        return self.capacity - self.used_size;
+    }
+
+    // function to get how much of the free memory can't be used for one allocation, 0 when it's all in one block
+    pub fn get_fragmentation(&self) -> f32
+    {
+        let free_size = self.get_free_size();
+        if free_size == 0
+        {
+            return 0.0;
+        }
+
+        return 1.0 - self.largest_free_block as f32 / free_size as f32;
+    }
+}
+
+pub struct TlsfAllocator
+{
+    capacity : u64,
+    // every size and offset is a multiple of it
+    granularity : u64,
+    blocks : Vec<TlsfBlock>,
+    // the unused slots in blocks
+    unused_blocks : Vec<u32>,
+    // a bit per first level with a free block, and a bit per second level of each first level
+    first_level_bitmap : u64,
+    second_level_bitmaps : [u32; GFIRST_LEVEL_COUNT],
+    free_heads : [[Option<u32>; GSECOND_LEVEL_COUNT]; GFIRST_LEVEL_COUNT],
+    used_size : u64,
+    allocation_count : u32,
+}
+
+fn align_up(value : u64, alignment : u64) -> u64
+{
+    return value.div_ceil(alignment) * alignment;
+}
+
+// function to get the size class of a block, sizes below 16 go linearly in the first class
+fn get_mapping(size : u64) -> (usize, usize)
+{
+    if size < GSECOND_LEVEL_COUNT as u64
+    {
+        return (0, size as usize);
+    }
+
+    let first_level = 63 - size.leading_zeros();
+    let second_level = (size >> (first_level - GSECOND_LEVEL_LOG2)) as usize ^ GSECOND_LEVEL_COUNT;
+    return ((first_level - GSECOND_LEVEL_LOG2 + 1) as usize, second_level);
+}
+
+impl TlsfAllocator
+{
+    // function to create an allocator over capacity bytes, the capacity is rounded down to the granularity
+    pub fn new(capacity : u64, granularity : u64) -> TlsfAllocator
+    {
+        let granularity = granularity.max(1);
+        let mut allocator = TlsfAllocator
+        {
+            capacity : capacity / granularity * granularity,
+            granularity,
+            blocks : Vec::new(),
+            unused_blocks : Vec::new(),
+            first_level_bitmap : 0,
+            second_level_bitmaps : [0; GFIRST_LEVEL_COUNT],
+            free_heads : [[None; GSECOND_LEVEL_COUNT]; GFIRST_LEVEL_COUNT],
+            used_size : 0,
+            allocation_count : 0,
+        };
+
+        if allocator.capacity > 0
+        {
+            let block = allocator.create_block(0, allocator.capacity);
+            allocator.insert_free_block(block);
+        }
+
+        return allocator;
+    }
+
+    fn create_block(&mut self, offset : u64, size : u64) -> u32
+    {
+        let block = TlsfBlock
+        {
+            offset,
+            size,
+            is_free : false,
+            prev_physical : None,
+            next_physical : None,
+            prev_free : None,
+            next_free : None,
+            generation : 0,
+        };
+
+        match self.unused_blocks.pop()
+        {
+            Some(x) =>
+            {
+                let generation = self.blocks[x as usize].generation.wrapping_add(1);
+                self.blocks[x as usize] = TlsfBlock { generation, ..block };
+                return x;
+            }
+            None =>
+            {
+                self.blocks.push(block);
+                return self.blocks.len() as u32 - 1;
+            }
+        }
+    }
+
+    fn destroy_block(&mut self, block : u32)
+    {
+        self.blocks[block as usize].generation = self.blocks[block as usize].generation.wrapping_add(1);
+        self.unused_blocks.push(block);
+    }
+
+    fn insert_free_block(&mut self, block : u32)
+    {
+        let (first_level, second_level) = get_mapping(self.blocks[block as usize].size);
+        let head = self.free_heads[first_level][second_level];
+
+        let x = &mut self.blocks[block as usize];
+        x.is_free = true;
+        x.prev_free = None;
+        x.next_free = head;
+        if let Some(y) = head
+        {
+            self.blocks[y as usize].prev_free = Some(block);
+        }
+
+        self.free_heads[first_level][second_level] = Some(block);
+        self.first_level_bitmap = self.first_level_bitmap | (1 << first_level);
+        self.second_level_bitmaps[first_level] = self.second_level_bitmaps[first_level] | (1 << second_level);
+    }
+
+    fn remove_free_block(&mut self, block : u32)
+    {
+        let x = self.blocks[block as usize];
+        let (first_level, second_level) = get_mapping(x.size);
+
+        match x.prev_free
+        {
+            Some(y) => self.blocks[y as usize].next_free = x.next_free,
+            None => self.free_heads[first_level][second_level] = x.next_free,
+        }
+        if let Some(y) = x.next_free
+        {
+            self.blocks[y as usize].prev_free = x.prev_free;
+        }
+
+        if self.free_heads[first_level][second_level].is_none()
+        {
+            self.second_level_bitmaps[first_level] = self.second_level_bitmaps[first_level] & !(1 << second_level);
+            if self.second_level_bitmaps[first_level] == 0
+            {
+                self.first_level_bitmap = self.first_level_bitmap & !(1 << first_level);
+            }
+        }
+
+        let x = &mut self.blocks[block as usize];
+        x.is_free = false;
+        x.prev_free = None;
+        x.next_free = None;
+    }
+
+    // function to find a free block of at least size bytes, the size is rounded up to the next class so any block in it fits
+    fn find_free_block(&self, size : u64) -> Option<u32>
+    {
+        let rounded_size = if size < GSECOND_LEVEL_COUNT as u64 { size } else { size.checked_add((1 << (63 - size.leading_zeros() - GSECOND_LEVEL_LOG2)) - 1)? };
+        let (mut first_level, second_level) = get_mapping(rounded_size);
+        if first_level >= GFIRST_LEVEL_COUNT
+        {
+            return None;
+        }
+
+        // the classes from second_level on in the same first level, then any class of a larger first level
+        let mut second_level_map = self.second_level_bitmaps[first_level] & (!0u32 << second_level);
+        if second_level_map == 0
+        {
+            let first_level_map = if first_level + 1 < 64 { self.first_level_bitmap & (!0u64 << (first_level + 1)) } else { 0 };
+            if first_level_map == 0
+            {
+                return None;
+            }
+
+            first_level = first_level_map.trailing_zeros() as usize;
+            second_level_map = self.second_level_bitmaps[first_level];
+        }
+
+        return self.free_heads[first_level][second_level_map.trailing_zeros() as usize];
+    }
+
+    // function to check whether a free block can hold size bytes at an aligned offset
+    fn fits(&self, block : u32, size : u64, alignment : u64) -> bool
+    {
+        let x = &self.blocks[block as usize];
+        return align_up(x.offset, alignment) + size <= x.offset + x.size;
+    }
+
+    // function to split the end of a block off as a free block, the block keeps the first size bytes
+    fn split_block(&mut self, block : u32, size : u64)
+    {
+        let x = self.blocks[block as usize];
+        if x.size <= size
+        {
+            return;
+        }
+
+        let remainder = self.create_block(x.offset + size, x.size - size);
+        self.blocks[remainder as usize].prev_physical = Some(block);
+        self.blocks[remainder as usize].next_physical = x.next_physical;
+        if let Some(y) = x.next_physical
+        {
+            self.blocks[y as usize].prev_physical = Some(remainder);
+        }
+        self.blocks[block as usize].next_physical = Some(remainder);
+        self.blocks[block as usize].size = size;
+        self.insert_free_block(remainder);
+    }
+
+    // function to merge a block with the next physical one, which is released
+    fn merge_with_next(&mut self, block : u32)
+    {
+        let next = self.blocks[block as usize].next_physical.unwrap();
+        let y = self.blocks[next as usize];
+        self.blocks[block as usize].size += y.size;
+        self.blocks[block as usize].next_physical = y.next_physical;
+        if let Some(z) = y.next_physical
+        {
+            self.blocks[z as usize].prev_physical = Some(block);
+        }
+        self.destroy_block(next);
+    }
+
+    // function to allocate size bytes at an offset aligned to alignment, None if no free block can hold it
+    pub fn allocate(&mut self, size : u64, alignment : u64) -> Option<MemoryAllocation>
+    {
+        if size == 0 || size > self.capacity
+        {
+            return None;
+        }
+
+        let size = align_up(size, self.granularity);
+        let alignment = align_up(alignment.max(1), self.granularity);
+
+        // a block of the right class is usually aligned already, otherwise look for one that has room for the padding
+        let block = match self.find_free_block(size)
+        {
+            Some(x) if self.fits(x, size, alignment) => x,
+            _ =>
+            {
+                let x = self.find_free_block(size.checked_add(alignment - self.granularity)?)?;
+                if !self.fits(x, size, alignment)
+                {
+                    return None;
+                }
+                x
+            }
+        };
+
+        self.remove_free_block(block);
+
+        // the padding in front of an aligned offset becomes a free block of its own
+        let padding = align_up(self.blocks[block as usize].offset, alignment) - self.blocks[block as usize].offset;
+        let block = if padding > 0
+        {
+            self.split_block(block, padding);
+            let aligned_block = self.blocks[block as usize].next_physical.unwrap();
+            self.remove_free_block(aligned_block);
+            self.insert_free_block(block);
+            aligned_block
+        }
+        else
+        {
+            block
+        };
+
+        self.split_block(block, size);
+
+        self.used_size += size;
+        self.allocation_count += 1;
+        let x = &self.blocks[block as usize];
+        return Some(MemoryAllocation
+        {
+            offset : x.offset,
+            size : x.size,
+            block,
+            generation : x.generation,
+        });
+    }
+
+    // function to check that an allocation is still alive in this allocator
+    pub fn validate(&self, allocation : &MemoryAllocation) -> Result<(), String>
+    {
+        let x = match self.blocks.get(allocation.block as usize)
+        {
+            Some(x) => x,
+            None => return Err(format!("Allocation at {:#x} doesn't belong to this heap.", allocation.offset)),
+        };
+
+        if x.generation != allocation.generation || x.is_free
+        {
+            return Err(format!("Allocation at {:#x} was freed already.", allocation.offset));
+        }
+        if x.offset != allocation.offset || x.size != allocation.size
+        {
+            return Err(format!("Allocation at {:#x} doesn't match its block.", allocation.offset));
+        }
+
+        return Ok(());
+    }
+
+    // function to free an allocation, it's merged with its free neighbours
+    pub fn free(&mut self, allocation : &MemoryAllocation) -> Result<(), String>
+    {
+        self.validate(allocation)?;
+
+        let mut block = allocation.block;
+        self.used_size -= allocation.size;
+        self.allocation_count -= 1;
+
+        // the block can be handed out again as it is or merged, either way the old allocation must not match it anymore
+        self.blocks[block as usize].generation = self.blocks[block as usize].generation.wrapping_add(1);
+
+        if let Some(x) = self.blocks[block as usize].next_physical
+        {
+            if self.blocks[x as usize].is_free
+            {
+                self.remove_free_block(x);
+                self.merge_with_next(block);
+            }
+        }
+
+        if let Some(x) = self.blocks[block as usize].prev_physical
+        {
+            if self.blocks[x as usize].is_free
+            {
+                self.remove_free_block(x);
+                self.merge_with_next(x);
+                block = x;
+            }
+        }
+
+        self.insert_free_block(block);
+        return Ok(());
+    }
+
+    pub fn get_capacity(&self) -> u64
+    {
+        return self.capacity;
+    }
+
+    pub fn get_used_size(&self) -> u64
+    {
+        return self.used_size;
+    }
+
+    pub fn get_allocation_count(&self) -> u32
+    {
+        return self.allocation_count;
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        return self.allocation_count == 0;
+    }
+
+    // function to get the largest free block, it's in the highest non empty class
+    pub fn get_largest_free_block(&self) -> u64
+    {
+        if self.first_level_bitmap == 0
+        {
+            return 0;
+        }
+
+        let first_level = 63 - self.first_level_bitmap.leading_zeros() as usize;
+        let second_level = 31 - self.second_level_bitmaps[first_level].leading_zeros() as usize;
+
+        let mut largest_size = 0;
+        let mut block = self.free_heads[first_level][second_level];
+        while let Some(x) = block
+        {
+            largest_size = largest_size.max(self.blocks[x as usize].size);
+            block = self.blocks[x as usize].next_free;
+        }
+
+        return largest_size;
+    }
+
+    pub fn get_statistics(&self) -> MemoryStatistics
+    {
+        // the unused slots are never free, they were taken out of the free lists before they were merged
+        let free_block_count = self.blocks.iter().filter(|x| x.is_free).count();
+        return MemoryStatistics
+        {
+            capacity : self.capacity,
+            used_size : self.used_size,
+            allocation_count : self.allocation_count,
+            free_block_count : free_block_count as u32,
+            largest_free_block : self.get_largest_free_block(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const KB : u64 = 1024;
+    const MB : u64 = 1024 * 1024;
+
+    // a small LCG, so the fuzz test is the same on every run
+    struct Random(u64);
+
+    impl Random
+    {
+        fn next(&mut self, range : u64) -> u64
+        {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            return (self.0 >> 33) % range;
+        }
+    }
+
+    fn check_no_overlap(allocations : &[(MemoryAllocation, u64)], capacity : u64)
+    {
+        let mut sorted : Vec<&MemoryAllocation> = allocations.iter().map(|x| &x.0).collect();
+        sorted.sort_by_key(|x| x.offset);
+        for x in sorted.windows(2)
+        {
+            assert!(x[0].offset + x[0].size <= x[1].offset, "{:?} overlaps {:?}", x[0], x[1]);
+        }
+        if let Some(x) = sorted.last()
+        {
+            assert!(x.offset + x.size <= capacity);
+        }
+    }
+
+    #[test]
+    fn random_allocations_never_overlap_and_merge_back()
+    {
+        let capacity = 64 * MB;
+        let mut allocator = TlsfAllocator::new(capacity, 256);
+        let mut random = Random(7);
+        let alignments = [1, 256, 4 * KB, 64 * KB, 4 * MB];
+        let mut allocations : Vec<(MemoryAllocation, u64)> = Vec::new();
+
+        for step in 0..4000
+        {
+            if allocations.is_empty() || random.next(3) != 0
+            {
+                // mostly small blocks with a few large ones
+                let size_range = if random.next(8) == 0 { 4 * MB } else { 64 * KB };
+                let size = 1 + random.next(size_range);
+                let alignment = alignments[random.next(alignments.len() as u64) as usize];
+                if let Some(x) = allocator.allocate(size, alignment)
+                {
+                    assert_eq!(x.offset % alignment, 0);
+                    assert_eq!(x.offset % 256, 0);
+                    assert!(x.size >= size && x.size % 256 == 0);
+                    allocations.push((x, alignment));
+                }
+            }
+            else
+            {
+                let (x, _) = allocations.swap_remove(random.next(allocations.len() as u64) as usize);
+                allocator.free(&x).unwrap();
+            }
+
+            if step % 100 == 0
+            {
+                check_no_overlap(&allocations, capacity);
+                assert_eq!(allocator.get_used_size(), allocations.iter().map(|x| x.0.size).sum::<u64>());
+                assert_eq!(allocator.get_allocation_count(), allocations.len() as u32);
+            }
+        }
+        check_no_overlap(&allocations, capacity);
+
+        while !allocations.is_empty()
+        {
+            let (x, _) = allocations.swap_remove(random.next(allocations.len() as u64) as usize);
+            allocator.free(&x).unwrap();
+        }
+
+        // everything is merged back into the one block it started with
+        let statistics = allocator.get_statistics();
+        assert!(allocator.is_empty());
+        assert_eq!(statistics.used_size, 0);
+        assert_eq!(statistics.free_block_count, 1);
+        assert_eq!(statistics.largest_free_block, capacity);
+        assert_eq!(allocator.allocate(capacity, 1).map(|x| (x.offset, x.size)), Some((0, capacity)));
+    }
+
+    #[test]
+    fn large_alignments_split_off_the_padding()
+    {
+        let mut allocator = TlsfAllocator::new(16 * MB, 64 * KB);
+        let first = allocator.allocate(64 * KB, 64 * KB).unwrap();
+        assert_eq!(first.offset, 0);
+
+        // the free block starts at 64 KB, the padding up to 4 MB stays free on its own
+        let aligned = allocator.allocate(MB, 4 * MB).unwrap();
+        assert_eq!((aligned.offset, aligned.size), (4 * MB, MB));
+        assert_eq!(allocator.get_statistics().free_block_count, 2);
+        assert_eq!(allocator.get_used_size(), MB + 64 * KB);
+
+        // the padding is used by the next allocation that fits in it
+        let in_padding = allocator.allocate(2 * MB, 64 * KB).unwrap();
+        assert_eq!(in_padding.offset, 64 * KB);
+    }
+
+    #[test]
+    fn large_alignments_skip_a_free_block_without_room_for_the_padding()
+    {
+        let mut allocator = TlsfAllocator::new(16 * MB, 64 * KB);
+        let _first = allocator.allocate(64 * KB, 1).unwrap();
+        let hole = allocator.allocate(MB, 1).unwrap();
+        let _last = allocator.allocate(64 * KB, 1).unwrap();
+        allocator.free(&hole).unwrap();
+
+        // the 1 MB hole has the right size class but no 4 MB aligned offset, the block after it is taken
+        let aligned = allocator.allocate(MB, 4 * MB).unwrap();
+        assert_eq!(aligned.offset, 4 * MB);
+        assert_eq!(allocator.allocate(MB, 1).unwrap().offset, hole.offset);
+
+        assert!(allocator.allocate(MB, 32 * MB).is_none());
+        assert!(allocator.allocate(17 * MB, 1).is_none());
+        assert!(allocator.allocate(0, 1).is_none());
+    }
+
+    #[test]
+    fn freed_allocations_are_rejected_by_generation()
+    {
+        let mut allocator = TlsfAllocator::new(MB, 256);
+        let first = allocator.allocate(4 * KB, 256).unwrap();
+        allocator.free(&first).unwrap();
+        assert_eq!(allocator.free(&first), Err(String::from("Allocation at 0x0 was freed already.")));
+
+        // the slot and the offset are reused by the next allocation, the old handle still doesn't free it
+        let second = allocator.allocate(4 * KB, 256).unwrap();
+        assert_eq!(second.offset, first.offset);
+        assert!(allocator.free(&first).is_err());
+        assert!(allocator.validate(&second).is_ok());
+        assert_eq!(allocator.get_allocation_count(), 1);
+
+        let foreign = MemoryAllocation { offset : 0x100, size : 256, block : 999, generation : 0 };
+        assert_eq!(allocator.free(&foreign), Err(String::from("Allocation at 0x100 doesn't belong to this heap.")));
+        let mismatched = MemoryAllocation { size : 8 * KB, ..second };
+        assert_eq!(allocator.free(&mismatched), Err(String::from("Allocation at 0x0 doesn't match its block.")));
+
+        allocator.free(&second).unwrap();
+        assert!(allocator.is_empty());
+    }
+
+    #[test]
+    fn statistics_report_the_fragmentation()
+    {
+        let mut allocator = TlsfAllocator::new(1024, 1);
+        let allocations : Vec<MemoryAllocation> = (0..4).map(|_| allocator.allocate(256, 1).unwrap()).collect();
+        let full = allocator.get_statistics();
+        assert_eq!(full, MemoryStatistics { capacity : 1024, used_size : 1024, allocation_count : 4, free_block_count : 0, largest_free_block : 0 });
+        assert_eq!(full.get_fragmentation(), 0.0);
+
+        // two separate holes of 256, only half of the free memory fits in one allocation
+        allocator.free(&allocations[0]).unwrap();
+        allocator.free(&allocations[2]).unwrap();
+        let holes = allocator.get_statistics();
+        assert_eq!((holes.get_free_size(), holes.free_block_count, holes.largest_free_block), (512, 2, 256));
+        assert_eq!(holes.get_fragmentation(), 0.5);
+        assert!(allocator.allocate(512, 1).is_none());
+
+        allocator.free(&allocations[1]).unwrap();
+        let merged = allocator.get_statistics();
+        assert_eq!((merged.free_block_count, merged.largest_free_block), (1, 768));
+        assert_eq!(merged.get_fragmentation(), 0.0);
+    }
+}
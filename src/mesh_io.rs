@@ -0,0 +1,138 @@
+// mesh_io.rs - CPU side mesh loading. only Wavefront OBJ positions and faces for now, polygons are triangulated as fans.
+
+use std::path::Path;
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Mesh
+{
+    pub positions : Vec<[f32; 3]>,
+    // triangle list, 3 indices per triangle
+    pub indices : Vec<u32>,
+}
+
+impl Mesh
+{
+    pub fn get_triangle_count(&self) -> u32
+    {
+        return (self.indices.len() / 3) as u32;
+    }
+}
+
+// function to resolve an OBJ index, they are 1-based and negative values count back from the last position
+fn resolve_obj_index(token : &str, position_count : usize, line_number : usize) -> Result<u32, String>
+{
+    // "v", "v/vt", "v//vn" or "v/vt/vn", only the position index is used
+    let position_token = token.split('/').next().unwrap_or("");
+    let index = position_token.parse::<i64>().map_err(|_| format!("line {}: invalid face index '{}'", line_number, token))?;
+
+    let resolved = if index < 0 { position_count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= position_count as i64
+    {
+        return Err(format!("line {}: face index {} is out of range", line_number, index));
+    }
+
+    return Ok(resolved as u32);
+}
+
+// function to parse an OBJ file, the lines other than v and f are ignored
+pub fn parse_obj(text : &str) -> Result<Mesh, String>
+{
+    let mut mesh = Mesh::default();
+
+    for (line_index, line) in text.lines().enumerate()
+    {
+        let line_number = line_index + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next()
+        {
+            Some("v") =>
+            {
+                let mut position = [0.0f32; 3];
+                for x in position.iter_mut()
+                {
+                    let token = tokens.next().ok_or(format!("line {}: vertex needs 3 coordinates", line_number))?;
+                    *x = token.parse::<f32>().map_err(|_| format!("line {}: invalid coordinate '{}'", line_number, token))?;
+                }
+                mesh.positions.push(position);
+            }
+            Some("f") =>
+            {
+                let mut face : Vec<u32> = Vec::new();
+                for x in tokens
+                {
+                    face.push(resolve_obj_index(x, mesh.positions.len(), line_number)?);
+                }
+                if face.len() < 3
+                {
+                    return Err(format!("line {}: face needs at least 3 vertices", line_number));
+                }
+
+                for idx in 1..face.len() - 1
+                {
+                    mesh.indices.extend_from_slice(&[face[0], face[idx], face[idx + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    return Ok(mesh);
+}
+
+// function to read a mesh file, the format is picked by the extension
+pub fn read_mesh(path : &Path) -> Result<Mesh, String>
+{
+    let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("").to_ascii_lowercase();
+    return match extension.as_str()
+    {
+        "obj" => parse_obj(&std::fs::read_to_string(path).map_err(|x| x.to_string())?),
+        _ => Err(format!("unknown mesh extension '{}'", extension)),
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const SQUARE_POSITIONS : &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0.5 1.5 0\n";
+
+    #[test]
+    fn polygons_are_triangulated_as_fans()
+    {
+        let triangle = parse_obj(&format!("{}f 1 2 3\n", SQUARE_POSITIONS)).unwrap();
+        assert_eq!(triangle.indices, vec![0, 1, 2]);
+
+        let quad = parse_obj(&format!("{}f 1 2 3 4\n", SQUARE_POSITIONS)).unwrap();
+        assert_eq!(quad.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad.get_triangle_count(), 2);
+
+        let pentagon = parse_obj(&format!("{}f 1 2 3 5 4\n", SQUARE_POSITIONS)).unwrap();
+        assert_eq!(pentagon.indices, vec![0, 1, 2, 0, 2, 4, 0, 4, 3]);
+        assert_eq!(pentagon.positions.len(), 5);
+        assert_eq!(pentagon.positions[4], [0.5, 1.5, 0.0]);
+    }
+
+    #[test]
+    fn face_indices_accept_every_form()
+    {
+        // negative indices count back from the last position read so far, not from the end of the file
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 1 1 0\nf 2/1 4/2/1 3//1\n# comment\nvn 0 0 1\nvt 0 0\ns off\n";
+        let mesh = parse_obj(text).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 1, 3, 2]);
+        assert_eq!(mesh.positions.len(), 4);
+    }
+
+    #[test]
+    fn invalid_lines_report_their_number()
+    {
+        let error = |text : &str| parse_obj(text).unwrap_err();
+        assert_eq!(error("v 0 0 0\nv 1 2\n"), "line 2: vertex needs 3 coordinates");
+        assert_eq!(error("v 0 x 0\n"), "line 1: invalid coordinate 'x'");
+        assert_eq!(error("v 0 0 0\nf 1 a 1\n"), "line 2: invalid face index 'a'");
+        assert_eq!(error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"), "line 4: face index 4 is out of range");
+        assert_eq!(error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 0\n"), "line 4: face index 0 is out of range");
+        assert_eq!(error("v 0 0 0\nf -2 1 1\n"), "line 2: face index -2 is out of range");
+        assert_eq!(error("v 0 0 0\nv 1 0 0\nf 1 2\n"), "line 3: face needs at least 3 vertices");
+    }
+}
@@ -0,0 +1,226 @@
+// msaa.rs - Multisample anti-aliasing of the scene. the scene is drawn into a multisampled color target and depth buffer,
+// then resolved into the single sampled target it belongs to, e.g. the back buffer before it's presented. the swapchains
+// stay single sampled since flip model swapchains can't be multisampled. the sample count is picked once before the
+// device is created and lowered to what the device supports for the back buffer and depth formats.
+
+use std::ffi::c_void;
+use std::mem;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows::Win32::Graphics::Dxgi::Common::*;
+
+use crate::depth_buffer;
+use crate::descriptor_allocator::{DescriptorHandle, DescriptorHeapType};
+use crate::descriptor_heap;
+use crate::gpu_memory;
+use crate::gpu_memory::GpuAllocation;
+use crate::graphic_device;
+use crate::resource_states;
+use crate::resource_states::CommandListStates;
+
+static mut GSAMPLE_COUNT : u32 = 1;
+
+// function to pick the samples per pixel, 1 disables MSAA. call it before initialize_d3d12()
+pub fn set_sample_count(sample_count : u32)
+{
+    unsafe
+    {
+        GSAMPLE_COUNT = sample_count.max(1);
+    }
+}
+
+pub fn get_sample_count() -> u32
+{
+    unsafe
+    {
+        return GSAMPLE_COUNT;
+    }
+}
+
+pub fn is_enabled() -> bool
+{
+    return get_sample_count() > 1;
+}
+
+// the sample desc of the scene targets and the pipelines drawing into them
+pub fn get_sample_desc() -> DXGI_SAMPLE_DESC
+{
+    return DXGI_SAMPLE_DESC
+    {
+        Count : get_sample_count(),
+        Quality : 0,
+    };
+}
+
+// function to get the number of quality levels of a format at a sample count, 0 means the count isn't supported
+fn get_quality_level_count(format : DXGI_FORMAT, sample_count : u32) -> u32
+{
+    unsafe
+    {
+        let mut quality_levels = D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS
+        {
+            Format : format,
+            SampleCount : sample_count,
+            Flags : D3D12_MULTISAMPLE_QUALITY_LEVELS_FLAG_NONE,
+            NumQualityLevels : 0,
+        };
+
+        if graphic_device::get_device().CheckFeatureSupport(D3D12_FEATURE_MULTISAMPLE_QUALITY_LEVELS, &mut quality_levels as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS>() as u32).is_err()
+        {
+            return 0;
+        }
+
+        return quality_levels.NumQualityLevels;
+    }
+}
+
+// function to check whether the back buffer format can be the destination of ResolveSubresource
+fn is_resolve_supported(format : DXGI_FORMAT) -> bool
+{
+    unsafe
+    {
+        let mut format_support = D3D12_FEATURE_DATA_FORMAT_SUPPORT
+        {
+            Format : format,
+            ..D3D12_FEATURE_DATA_FORMAT_SUPPORT::default()
+        };
+
+        if graphic_device::get_device().CheckFeatureSupport(D3D12_FEATURE_FORMAT_SUPPORT, &mut format_support as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_FORMAT_SUPPORT>() as u32).is_err()
+        {
+            return false;
+        }
+
+        return (format_support.Support1 & D3D12_FORMAT_SUPPORT1_MULTISAMPLE_RESOLVE) == D3D12_FORMAT_SUPPORT1_MULTISAMPLE_RESOLVE;
+    }
+}
+
+// function to lower the sample count to the highest one the device supports for both the back buffer and the depth format.
+// it's called by graphic_device::initialize_d3d12() after the device is created.
+pub fn initialize()
+{
+    unsafe
+    {
+        let requested_sample_count = GSAMPLE_COUNT;
+        let back_buffer_format = graphic_device::get_back_buffer_format();
+        let dsv_format = depth_buffer::get_dsv_format();
+
+        let mut sample_count = if is_resolve_supported(back_buffer_format) { requested_sample_count } else { 1 };
+        while sample_count > 1 && (get_quality_level_count(back_buffer_format, sample_count) == 0 || get_quality_level_count(dsv_format, sample_count) == 0)
+        {
+            sample_count /= 2;
+        }
+
+        if sample_count != requested_sample_count
+        {
+            println!("{}x MSAA is not supported with the back buffer and depth formats, using {}x instead.", requested_sample_count, sample_count);
+        }
+        GSAMPLE_COUNT = sample_count;
+    }
+}
+
+// a multisampled color target the scene is drawn into. it rests in RESOLVE_SOURCE state since every pass drawing into it
+// ends with a resolve, so only the barrier to RENDER_TARGET is needed at the start of the next pass.
+pub struct MsaaTarget
+{
+    resource : ID3D12Resource,
+    // it's declared after the resource so it's released after it
+    _memory : GpuAllocation,
+    rtv : DescriptorHandle,
+    width : u32,
+    height : u32,
+}
+
+// function to create a multisampled color target with the back buffer format, None when MSAA is disabled
+pub fn create_msaa_target(width : u32, height : u32) -> Option<MsaaTarget>
+{
+    if !is_enabled()
+    {
+        return None;
+    }
+
+    unsafe
+    {
+        let texture_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width : width as u64,
+            Height : height,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : graphic_device::get_back_buffer_format(),
+            SampleDesc : get_sample_desc(),
+            Layout : D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags : D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        let (resource, memory) = match gpu_memory::create_resource(D3D12_HEAP_TYPE_DEFAULT, &texture_desc, D3D12_RESOURCE_STATE_RESOLVE_SOURCE, None)
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("Error during MSAA target creation!");
+                return None;
+            }
+        };
+
+        let rtv = descriptor_heap::allocate(DescriptorHeapType::Rtv, 1)?;
+        graphic_device::get_device().CreateRenderTargetView(&resource, None, descriptor_heap::get_cpu_handle(&rtv, 0));
+        resource_states::register_resource(&resource, D3D12_RESOURCE_STATE_RESOLVE_SOURCE);
+
+        return Some(MsaaTarget
+        {
+            resource,
+            _memory : memory,
+            rtv,
+            width,
+            height,
+        });
+    }
+}
+
+impl Drop for MsaaTarget
+{
+    fn drop(&mut self)
+    {
+        resource_states::unregister_resource(&self.resource);
+        descriptor_heap::free(&self.rtv);
+    }
+}
+
+impl MsaaTarget
+{
+    pub fn get_resource(&self) -> &ID3D12Resource
+    {
+        return &self.resource;
+    }
+
+    pub fn get_rtv(&self) -> D3D12_CPU_DESCRIPTOR_HANDLE
+    {
+        return descriptor_heap::get_cpu_handle(&self.rtv, 0);
+    }
+
+    pub fn get_width(&self) -> u32
+    {
+        return self.width;
+    }
+
+    pub fn get_height(&self) -> u32
+    {
+        return self.height;
+    }
+}
+
+// function to record the resolve of a multisampled target into a single sampled target of the same size and format.
+// the source is left in RESOLVE_SOURCE, its resting state, and the dest in RESOLVE_DEST until it's required otherwise.
+pub fn resolve(command_list : &ID3D12GraphicsCommandList, resource_states : &mut CommandListStates, source : &ID3D12Resource, dest : &ID3D12Resource)
+{
+    unsafe
+    {
+        resource_states.use_as_resolve_source(source);
+        resource_states.use_as_resolve_dest(dest);
+        resource_states.flush(command_list);
+        command_list.ResolveSubresource(dest, 0, source, 0, graphic_device::get_back_buffer_format());
+    }
+}
@@ -0,0 +1,168 @@
+// recording.rs - Frame sequence recording, writes numbered PNG files or a raw Y4M stream that ffmpeg can read.
+// combined with the fixed time step of the demo clock, the output is frame-exact and reproducible.
+
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::image_io;
+use crate::image_io::Image;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordingFormat
+{
+    // output path is a directory, frames are saved as frame_000000.png, frame_000001.png...
+    PngSequence,
+    // output path is a single .y4m file
+    Y4m,
+}
+
+pub struct FrameRecorder
+{
+    format : RecordingFormat,
+    output_path : PathBuf,
+    frame_rate : u32,
+    frame_count : u32,
+    y4m_writer : Option<BufWriter<File>>,
+}
+
+// function to convert 8-bit RGBA pixels to planar 8-bit YUV 4:4:4 (BT.601, limited range), which is what ffmpeg assumes
+// for a Y4M stream without any color tags.
+pub fn rgba8_to_yuv444(rgba8 : &[u8]) -> Vec<u8>
+{
+    let pixel_count = rgba8.len() / 4;
+    let mut yuv : Vec<u8> = vec![0; pixel_count * 3];
+    let (y_plane, uv_planes) = yuv.split_at_mut(pixel_count);
+    let (u_plane, v_plane) = uv_planes.split_at_mut(pixel_count);
+
+    for (idx, x) in rgba8.chunks_exact(4).enumerate()
+    {
+        let r = x[0] as f32;
+        let g = x[1] as f32;
+        let b = x[2] as f32;
+
+        let y = 16.0 + 0.256788 * r + 0.504129 * g + 0.097906 * b;
+        let u = 128.0 - 0.148223 * r - 0.290993 * g + 0.439216 * b;
+        let v = 128.0 + 0.439216 * r - 0.367788 * g - 0.071427 * b;
+
+        y_plane[idx] = (y + 0.5).clamp(0.0, 255.0) as u8;
+        u_plane[idx] = (u + 0.5).clamp(0.0, 255.0) as u8;
+        v_plane[idx] = (v + 0.5).clamp(0.0, 255.0) as u8;
+    }
+
+    return yuv;
+}
+
+// function to build the Y4M stream header
+pub fn get_y4m_header(width : u32, height : u32, frame_rate : u32) -> String
+{
+    return format!("YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444\n", width, height, frame_rate);
+}
+
+impl FrameRecorder
+{
+    // function to start a recording, frame_rate is only written to the Y4M header. the demo clock step should match it.
+    pub fn new(output_path : &Path, format : RecordingFormat, frame_rate : u32) -> std::io::Result<FrameRecorder>
+    {
+        match format
+        {
+            RecordingFormat::PngSequence => fs::create_dir_all(output_path)?,
+            RecordingFormat::Y4m =>
+            {
+                if let Some(x) = output_path.parent().filter(|x| !x.as_os_str().is_empty())
+                {
+                    fs::create_dir_all(x)?;
+                }
+            }
+        }
+
+        return Ok(FrameRecorder
+        {
+            format,
+            output_path : output_path.to_path_buf(),
+            frame_rate,
+            frame_count : 0,
+            y4m_writer : None,
+        });
+    }
+
+    // function to append a frame, all frames of a Y4M stream must have the same size
+    pub fn write_frame(&mut self, image : &Image) -> std::io::Result<()>
+    {
+        match self.format
+        {
+            RecordingFormat::PngSequence =>
+            {
+                let path = self.output_path.join(format!("frame_{:06}.png", self.frame_count));
+                image_io::write_png(&path, image)?;
+            }
+            RecordingFormat::Y4m =>
+            {
+                // the header needs the frame size, so the file is created with the first frame
+                if self.y4m_writer.is_none()
+                {
+                    let mut writer = BufWriter::new(File::create(&self.output_path)?);
+                    writer.write_all(get_y4m_header(image.width, image.height, self.frame_rate).as_bytes())?;
+                    self.y4m_writer = Some(writer);
+                }
+
+                let writer = self.y4m_writer.as_mut().unwrap();
+                writer.write_all(b"FRAME\n")?;
+                writer.write_all(&rgba8_to_yuv444(&image_io::convert_to_rgba8(image)))?;
+            }
+        }
+
+        self.frame_count = self.frame_count + 1;
+        return Ok(());
+    }
+
+    // function to flush everything to disk, returns the number of recorded frames
+    pub fn finish(mut self) -> std::io::Result<u32>
+    {
+        if let Some(x) = self.y4m_writer.as_mut()
+        {
+            x.flush()?;
+        }
+
+        return Ok(self.frame_count);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn rgba8_to_yuv444_uses_bt601_limited_range()
+    {
+        // black, white, red and blue, the alpha is ignored
+        let rgba8 = [0, 0, 0, 255, 255, 255, 255, 0, 255, 0, 0, 255, 0, 0, 255, 255];
+        let yuv = rgba8_to_yuv444(&rgba8);
+
+        // the planes come one after the other, Y then U then V
+        assert_eq!(yuv[0..4], [16, 235, 81, 41]);
+        assert_eq!(yuv[4..8], [128, 128, 90, 240]);
+        assert_eq!(yuv[8..12], [128, 128, 240, 110]);
+    }
+
+    #[test]
+    fn rgba8_to_yuv444_keeps_grays_neutral()
+    {
+        let rgba8 : Vec<u8> = (0..=255u8).flat_map(|x| [x, x, x, 255]).collect();
+        let yuv = rgba8_to_yuv444(&rgba8);
+        assert_eq!(yuv.len(), 256 * 3);
+        assert!(yuv[256..].iter().all(|x| *x == 128));
+        // the luma grows with the gray level from 16 to 235
+        assert!(yuv[..256].windows(2).all(|x| x[0] <= x[1]));
+        assert_eq!((yuv[0], yuv[128], yuv[255]), (16, 126, 235));
+    }
+
+    #[test]
+    fn y4m_header_describes_the_stream()
+    {
+        assert_eq!(get_y4m_header(1920, 1080, 60), "YUV4MPEG2 W1920 H1080 F60:1 Ip A1:1 C444\n");
+        assert_eq!(get_y4m_header(3, 5, 24), "YUV4MPEG2 W3 H5 F24:1 Ip A1:1 C444\n");
+    }
+}
@@ -0,0 +1,416 @@
+// render_scale.rs - Render at an internal resolution that differs from the window size, then upscale blit to the back buffer.
+// the window size follows the DPI of the monitor, while the render scale decides how many pixels are actually shaded.
+
+use std::mem::ManuallyDrop;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Direct3D::*;
+use windows::Win32::Graphics::Direct3D::Fxc::*;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows::Win32::Graphics::Dxgi::Common::*;
+use std::path::PathBuf;
+use std::fs;
+
+use crate::descriptor_allocator::{DescriptorHandle, DescriptorHeapType};
+use crate::descriptor_heap;
+use crate::depth_buffer;
+use crate::depth_buffer::DepthBuffer;
+use crate::gpu_memory;
+use crate::gpu_memory::GpuAllocation;
+use crate::graphic_device;
+use crate::graphic_device::RenderTarget;
+use crate::msaa;
+use crate::msaa::MsaaTarget;
+use crate::resource_states;
+use crate::resource_states::CommandListStates;
+
+static mut GRENDER_SCALE : f32 = 1.0;
+static mut GBLIT_ROOT_SIGNATURE : Option<ID3D12RootSignature> = None;
+static mut GBLIT_STATE : Option<ID3D12PipelineState> = None;
+
+// an internal render target, it rests in PIXEL_SHADER_RESOURCE state so the blit can sample it any time
+pub struct ScaledTarget
+{
+    resource : ID3D12Resource,
+    // it's declared after the resource so it's released after it, the memory is reused when the render scale changes
+    _memory : GpuAllocation,
+    rtv : DescriptorHandle,
+    srv : DescriptorHandle,
+    depth_buffer : Option<DepthBuffer>,
+    msaa_target : Option<MsaaTarget>,
+    width : u32,
+    height : u32,
+}
+
+// function to set the render scale, 1.0 renders at window resolution. the scale is clamped to [0.25, 2.0].
+pub fn set_render_scale(scale : f32)
+{
+    unsafe
+    {
+        GRENDER_SCALE = scale.clamp(0.25, 2.0);
+    }
+}
+
+pub fn get_render_scale() -> f32
+{
+    unsafe
+    {
+        return GRENDER_SCALE;
+    }
+}
+
+// function to compute the internal resolution of an output size
+pub fn get_scaled_size(output_width : u32, output_height : u32, scale : f32) -> (u32, u32)
+{
+    let width = ((output_width as f32 * scale).round() as u32).max(1);
+    let height = ((output_height as f32 * scale).round() as u32).max(1);
+    return (width, height);
+}
+
+// function to create the blit pipeline, it samples the internal target with a static linear clamp sampler
+pub fn create_pipeline() -> bool
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+
+        // one SRV table for the source texture
+        let srv_range = D3D12_DESCRIPTOR_RANGE
+        {
+            RangeType : D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
+            NumDescriptors : 1,
+            BaseShaderRegister : 0,
+            RegisterSpace : 0,
+            OffsetInDescriptorsFromTableStart : 0,
+        };
+
+        let root_parameter_table = D3D12_ROOT_PARAMETER
+        {
+            ParameterType : D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE,
+            Anonymous : D3D12_ROOT_PARAMETER_0
+            {
+                DescriptorTable : D3D12_ROOT_DESCRIPTOR_TABLE
+                {
+                    NumDescriptorRanges : 1,
+                    pDescriptorRanges : &srv_range,
+                }
+            },
+            ShaderVisibility : D3D12_SHADER_VISIBILITY_PIXEL,
+        };
+
+        let linear_clamp_sampler = D3D12_STATIC_SAMPLER_DESC
+        {
+            Filter : D3D12_FILTER_MIN_MAG_MIP_LINEAR,
+            AddressU : D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+            AddressV : D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+            AddressW : D3D12_TEXTURE_ADDRESS_MODE_CLAMP,
+            MaxAnisotropy : 1,
+            ComparisonFunc : D3D12_COMPARISON_FUNC_NEVER,
+            BorderColor : D3D12_STATIC_BORDER_COLOR_OPAQUE_BLACK,
+            MaxLOD : f32::MAX,
+            ShaderVisibility : D3D12_SHADER_VISIBILITY_PIXEL,
+            ..D3D12_STATIC_SAMPLER_DESC::default()
+        };
+
+        let root_signature_desc = D3D12_ROOT_SIGNATURE_DESC
+        {
+            NumParameters : 1,
+            pParameters : &root_parameter_table,
+            NumStaticSamplers : 1,
+            pStaticSamplers : &linear_clamp_sampler,
+            ..D3D12_ROOT_SIGNATURE_DESC::default()
+        };
+
+        let mut root_signature_blob : Option<ID3DBlob> = None;
+        let _ = D3D12SerializeRootSignature(&root_signature_desc, D3D_ROOT_SIGNATURE_VERSION_1, &mut root_signature_blob, None);
+        if root_signature_blob.is_none()
+        {
+            println!("Error during blit root signature serialization!");
+            return false;
+        }
+
+        let root_blob_data = std::slice::from_raw_parts(root_signature_blob.as_ref().unwrap().GetBufferPointer() as *const u8, root_signature_blob.as_ref().unwrap().GetBufferSize());
+        GBLIT_ROOT_SIGNATURE = device.CreateRootSignature::<ID3D12RootSignature>(0, root_blob_data).ok();
+        if GBLIT_ROOT_SIGNATURE.is_none()
+        {
+            println!("Error during blit root signature creation!");
+            return false;
+        }
+
+        let shader_path = match fs::canonicalize(PathBuf::from("./shaders/upscale_blit.hlsl"))
+        {
+            Ok(x) => x,
+            Err(_) =>
+            {
+                println!("Error during blit shader creation! shaders/upscale_blit.hlsl is not found.");
+                return false;
+            }
+        };
+        let shader_file_name = HSTRING::from(shader_path.as_path());
+        let compile_flag = D3DCOMPILE_DEBUG | D3DCOMPILE_SKIP_OPTIMIZATION;
+        let mut vs_blob : Option<ID3DBlob> = None;
+        let mut ps_blob : Option<ID3DBlob> = None;
+
+        let _ = D3DCompileFromFile(&shader_file_name, None, None, s!("BlitVS"), s!("vs_5_1"), compile_flag, 0, &mut vs_blob, None);
+        let _ = D3DCompileFromFile(&shader_file_name, None, None, s!("BlitPS"), s!("ps_5_1"), compile_flag, 0, &mut ps_blob, None);
+        if vs_blob.is_none() || ps_blob.is_none()
+        {
+            println!("Error during blit shader creation!");
+            return false;
+        }
+
+        let render_target_blend_desc = D3D12_RENDER_TARGET_BLEND_DESC
+        {
+            RenderTargetWriteMask : D3D12_COLOR_WRITE_ENABLE_ALL.0 as u8,
+            ..D3D12_RENDER_TARGET_BLEND_DESC::default()
+        };
+
+        let mut rtv_format_list = [DXGI_FORMAT_UNKNOWN; 8];
+        rtv_format_list[0] = graphic_device::get_back_buffer_format();
+
+        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC
+        {
+            pRootSignature : ManuallyDrop::new(GBLIT_ROOT_SIGNATURE.clone()),
+            VS : D3D12_SHADER_BYTECODE
+            {
+                pShaderBytecode : vs_blob.as_ref().unwrap().GetBufferPointer(),
+                BytecodeLength : vs_blob.as_ref().unwrap().GetBufferSize(),
+            },
+            PS : D3D12_SHADER_BYTECODE
+            {
+                pShaderBytecode : ps_blob.as_ref().unwrap().GetBufferPointer(),
+                BytecodeLength : ps_blob.as_ref().unwrap().GetBufferSize(),
+            },
+            RasterizerState : D3D12_RASTERIZER_DESC
+            {
+                FillMode : D3D12_FILL_MODE_SOLID,
+                CullMode : D3D12_CULL_MODE_NONE,
+                ..D3D12_RASTERIZER_DESC::default()
+            },
+            BlendState : D3D12_BLEND_DESC
+            {
+                RenderTarget : [render_target_blend_desc; 8],
+                ..D3D12_BLEND_DESC::default()
+            },
+            DepthStencilState : D3D12_DEPTH_STENCIL_DESC::default(),
+            SampleMask : u32::MAX,
+            PrimitiveTopologyType : D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
+            NumRenderTargets : 1,
+            RTVFormats : rtv_format_list,
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+        };
+
+        GBLIT_STATE = device.CreateGraphicsPipelineState::<ID3D12PipelineState>(&pso_desc).ok();
+        if GBLIT_STATE.is_none()
+        {
+            println!("Error during blit pipeline state creation!");
+            return false;
+        }
+
+        return true;
+    }
+}
+
+// function to create an internal render target with its RTV and a shader visible SRV
+fn create_scaled_target(width : u32, height : u32) -> Option<ScaledTarget>
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+
+        let texture_desc = D3D12_RESOURCE_DESC
+        {
+            Dimension : D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width : width as u64,
+            Height : height,
+            DepthOrArraySize : 1,
+            MipLevels : 1,
+            Format : graphic_device::get_back_buffer_format(),
+            SampleDesc : DXGI_SAMPLE_DESC
+            {
+                Count : 1,
+                Quality : 0,
+            },
+            Layout : D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            Flags : D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+            ..D3D12_RESOURCE_DESC::default()
+        };
+
+        let (resource, memory) = match gpu_memory::create_resource(D3D12_HEAP_TYPE_DEFAULT, &texture_desc, D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE, None)
+        {
+            Some(x) => x,
+            None =>
+            {
+                println!("Error during scaled render target creation!");
+                return None;
+            }
+        };
+
+        let rtv = descriptor_heap::allocate(DescriptorHeapType::Rtv, 1)?;
+        device.CreateRenderTargetView(&resource, None, descriptor_heap::get_cpu_handle(&rtv, 0));
+
+        // the SRV is staged in the CPU heap, the blit copies it to a table of the frame
+        let srv = match descriptor_heap::allocate(DescriptorHeapType::CbvSrvUav, 1)
+        {
+            Some(x) => x,
+            None =>
+            {
+                descriptor_heap::free(&rtv);
+                return None;
+            }
+        };
+        device.CreateShaderResourceView(&resource, None, descriptor_heap::get_cpu_handle(&srv, 0));
+        resource_states::register_resource(&resource, D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE);
+
+        return Some(ScaledTarget
+        {
+            resource,
+            _memory : memory,
+            rtv,
+            srv,
+            depth_buffer : depth_buffer::create_depth_buffer(width, height),
+            msaa_target : msaa::create_msaa_target(width, height),
+            width,
+            height,
+        });
+    }
+}
+
+impl Drop for ScaledTarget
+{
+    fn drop(&mut self)
+    {
+        resource_states::unregister_resource(&self.resource);
+        descriptor_heap::free(&self.rtv);
+        descriptor_heap::free(&self.srv);
+    }
+}
+
+impl ScaledTarget
+{
+    pub fn get_render_target(&self) -> RenderTarget
+    {
+        return RenderTarget
+        {
+            resource : Some(self.resource.clone()),
+            rtv : descriptor_heap::get_cpu_handle(&self.rtv, 0),
+            depth_resource : self.depth_buffer.as_ref().map(|x| x.get_resource().clone()),
+            dsv : self.depth_buffer.as_ref().map(|x| x.get_dsv()),
+            msaa_resource : self.msaa_target.as_ref().map(|x| x.get_resource().clone()),
+            msaa_rtv : self.msaa_target.as_ref().map(|x| x.get_rtv()),
+            width : self.width,
+            height : self.height,
+            resting_state : D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        };
+    }
+}
+
+// function to make sure the internal target matches the output size and the current render scale.
+// returns the target to render into, None means rendering directly to the output because the scale is 1.
+pub fn prepare_target(scaled_target : &mut Option<ScaledTarget>, output_width : u32, output_height : u32) -> Option<RenderTarget>
+{
+    unsafe
+    {
+        if GRENDER_SCALE == 1.0 || GBLIT_STATE.is_none()
+        {
+            if scaled_target.is_some()
+            {
+                graphic_device::wait_for_gpu();
+                *scaled_target = None;
+            }
+            return None;
+        }
+
+        let (width, height) = get_scaled_size(output_width, output_height, GRENDER_SCALE);
+        if scaled_target.as_ref().map(|x| (x.width, x.height)) != Some((width, height))
+        {
+            // the old target might still be used by the GPU
+            graphic_device::wait_for_gpu();
+            *scaled_target = create_scaled_target(width, height);
+        }
+
+        return scaled_target.as_ref().map(|x| x.get_render_target());
+    }
+}
+
+// function to stretch the internal target over the output target with bilinear filtering
+pub fn blit(source : &ScaledTarget, dest : &RenderTarget)
+{
+    let dest_rect = RECT
+    {
+        left : 0,
+        top : 0,
+        right : dest.width as i32,
+        bottom : dest.height as i32,
+    };
+    blit_texture(&source.resource, &source.srv, dest, &dest_rect);
+}
+
+// function to draw the texture of a SRV in the CPU descriptor heap into a rect of the output target.
+// the area outside of the rect is cleared to black.
+pub fn blit_texture(source : &ID3D12Resource, srv : &DescriptorHandle, dest : &RenderTarget, dest_rect : &RECT)
+{
+    unsafe
+    {
+        let srv_table = match descriptor_heap::stage_table(&[*srv])
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        let command_list = graphic_device::get_command_list();
+        let _ = command_list.Reset(graphic_device::get_command_allocator(), None);
+
+        let dest_resource = dest.resource.as_ref().unwrap();
+        let mut resource_states = CommandListStates::new();
+        resource_states.use_as_shader_resource(source);
+        resource_states.use_as_render_target(dest_resource);
+        resource_states.flush(command_list);
+        command_list.OMSetRenderTargets(1, Some(&dest.rtv), FALSE, None);
+
+        let covers_target = dest_rect.left <= 0 && dest_rect.top <= 0 && dest_rect.right >= dest.width as i32 && dest_rect.bottom >= dest.height as i32;
+        if !covers_target
+        {
+            command_list.ClearRenderTargetView(dest.rtv, &[0.0, 0.0, 0.0, 1.0], None);
+        }
+
+        command_list.SetPipelineState(GBLIT_STATE.as_ref().unwrap());
+        command_list.SetGraphicsRootSignature(GBLIT_ROOT_SIGNATURE.as_ref().unwrap());
+        command_list.SetDescriptorHeaps(&[descriptor_heap::get_shader_visible_heap().cloned()]);
+        command_list.SetGraphicsRootDescriptorTable(0, srv_table.gpu_handle);
+
+        let viewport_desc = D3D12_VIEWPORT
+        {
+            Width : (dest_rect.right - dest_rect.left) as f32,
+            Height : (dest_rect.bottom - dest_rect.top) as f32,
+            MinDepth : 0.0,
+            MaxDepth : 1.0,
+            TopLeftX : dest_rect.left as f32,
+            TopLeftY : dest_rect.top as f32,
+        };
+        command_list.RSSetViewports(&[viewport_desc; 1]);
+        command_list.RSSetScissorRects(&[*dest_rect; 1]);
+
+        // full screen triangle
+        command_list.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        command_list.DrawInstanced(3, 1, 0, 0);
+
+        // back to the resting state, e.g. present state for back buffer
+        resource_states.require(dest_resource, None, dest.resting_state);
+        resource_states.execute(command_list);
+    }
+}
+
+// function to check whether the blit pipeline is ready
+pub fn is_pipeline_ready() -> bool
+{
+    unsafe
+    {
+        return GBLIT_STATE.is_some();
+    }
+}
@@ -0,0 +1,212 @@
+// render_thread.rs - A dedicated render thread that receives the window events over a channel, so the Win32 message pump
+// can block in modal loops (window dragging and resizing) without stalling the rendering.
+// the thread that owns the windows keeps pumping messages, while everything that touches D3D12 runs on the render thread.
+
+use windows::Win32::Foundation::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::window_events::WindowEvent;
+
+// HWND can't be sent to another thread as it is, so the windows are identified by the handle value
+pub type WindowId = isize;
+
+pub fn get_window_id(h_wnd : HWND) -> WindowId
+{
+    return h_wnd.0 as WindowId;
+}
+
+pub fn get_hwnd(window_id : WindowId) -> HWND
+{
+    return HWND(window_id as *mut _);
+}
+
+// commands from the message thread to the render thread
+#[derive(Clone, Debug)]
+pub enum RenderCommand
+{
+    Event { window_id : WindowId, event : WindowEvent },
+    // the window is being destroyed, release its surface and acknowledge with WindowRemoved
+    RemoveWindow { window_id : WindowId },
+    // leave the render loop, release everything and end the thread
+    Shutdown,
+}
+
+// answers from the render thread, used for the handshakes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderAck
+{
+    // the render thread finished its initialization, false if it failed and the thread ends
+    Started(bool),
+    // a resize is handled and a frame with the new size is presented (or the window isn't visible)
+    Resized(WindowId),
+    WindowRemoved(WindowId),
+}
+
+// the render thread side of the channels
+pub struct RenderContext
+{
+    command_receiver : Receiver<RenderCommand>,
+    ack_sender : Sender<RenderAck>,
+}
+
+impl RenderContext
+{
+    // function to take the commands that arrived since the last call, it never blocks.
+    // a closed channel means the message thread is gone, that's treated as a shutdown.
+    pub fn poll_commands(&self) -> Vec<RenderCommand>
+    {
+        let mut commands : Vec<RenderCommand> = Vec::new();
+        loop
+        {
+            match self.command_receiver.try_recv()
+            {
+                Ok(x) => commands.push(x),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) =>
+                {
+                    commands.push(RenderCommand::Shutdown);
+                    break;
+                }
+            }
+        }
+        return commands;
+    }
+
+    pub fn acknowledge(&self, ack : RenderAck)
+    {
+        let _ = self.ack_sender.send(ack);
+    }
+}
+
+// the message thread side, it owns the render thread
+pub struct RenderThread
+{
+    command_sender : Sender<RenderCommand>,
+    ack_receiver : Receiver<RenderAck>,
+    join_handle : Option<JoinHandle<()>>,
+}
+
+// function to process the messages sent from other threads without touching the posted ones.
+// the render thread may send messages to the windows (e.g. SetWindowTextW), so a waiting message thread must keep doing this.
+pub fn pump_sent_messages()
+{
+    unsafe
+    {
+        let mut msg = MSG::default();
+        let _ = PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE | PM_QS_SENDMESSAGE);
+    }
+}
+
+impl RenderThread
+{
+    // function to start the render thread, the body runs the render loop until it receives Shutdown
+    pub fn spawn(body : impl FnOnce(RenderContext) + Send + 'static) -> std::io::Result<RenderThread>
+    {
+        let (command_sender, command_receiver) = mpsc::channel::<RenderCommand>();
+        let (ack_sender, ack_receiver) = mpsc::channel::<RenderAck>();
+
+        let join_handle = thread::Builder::new().name(String::from("Render Thread")).spawn(move ||
+        {
+            body(RenderContext
+            {
+                command_receiver,
+                ack_sender,
+            });
+        })?;
+
+        return Ok(RenderThread
+        {
+            command_sender,
+            ack_receiver,
+            join_handle : Some(join_handle),
+        });
+    }
+
+    // function to wait for an acknowledgement while pumping the sent messages, returns false on timeout.
+    // other acknowledgements that arrive meanwhile are dropped, e.g. a late one after an earlier timeout.
+    fn wait_for_ack(&self, expected : RenderAck, timeout : Duration) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        loop
+        {
+            pump_sent_messages();
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero()
+            {
+                return false;
+            }
+
+            match self.ack_receiver.recv_timeout(remaining.min(Duration::from_millis(1)))
+            {
+                Ok(x) if x == expected => return true,
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+        }
+    }
+
+    // function to wait until the render thread is initialized, returns false if it failed
+    pub fn wait_for_start(&self) -> bool
+    {
+        loop
+        {
+            pump_sent_messages();
+            match self.ack_receiver.recv_timeout(Duration::from_millis(1))
+            {
+                Ok(RenderAck::Started(x)) => return x,
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+        }
+    }
+
+    // function to forward a window event, it doesn't wait for the render thread
+    pub fn send_event(&self, h_wnd : HWND, event : &WindowEvent)
+    {
+        let _ = self.command_sender.send(RenderCommand::Event { window_id : get_window_id(h_wnd), event : event.clone() });
+    }
+
+    // function to forward a resize and wait until a frame with the new size is presented, so the window doesn't show
+    // a stretched old frame. the wait is bounded, so a busy render thread can't freeze the message pump.
+    pub fn resize_and_wait(&self, h_wnd : HWND, event : &WindowEvent, timeout : Duration) -> bool
+    {
+        self.send_event(h_wnd, event);
+        return self.wait_for_ack(RenderAck::Resized(get_window_id(h_wnd)), timeout);
+    }
+
+    // function to release the surface of a window before the window is destroyed
+    pub fn remove_window(&self, h_wnd : HWND, timeout : Duration) -> bool
+    {
+        let window_id = get_window_id(h_wnd);
+        let _ = self.command_sender.send(RenderCommand::RemoveWindow { window_id });
+        return self.wait_for_ack(RenderAck::WindowRemoved(window_id), timeout);
+    }
+
+    // function to stop the render thread and wait until it has released everything
+    pub fn shutdown(mut self)
+    {
+        let _ = self.command_sender.send(RenderCommand::Shutdown);
+
+        if let Some(x) = self.join_handle.take()
+        {
+            while !x.is_finished()
+            {
+                pump_sent_messages();
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            if x.join().is_err()
+            {
+                println!("The render thread ended with a panic!");
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+// residency.rs - Residency management of the video memory with residency_policy.rs. the heaps of gpu_memory.rs in local
+// video memory are registered here, the submissions touch the ones they use, and when the usage polled by
+// graphic_device::update() is over the budget the least recently used ones are evicted. an evicted heap is made
+// resident again right before a submission uses it.
+
+use std::collections::HashMap;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows_core::Interface;
+
+use crate::graphic_device;
+use crate::residency_policy::{LruResidencySet, ResidencyId};
+
+struct Residency
+{
+    residency_set : LruResidencySet,
+    pageables : HashMap<ResidencyId, ID3D12Pageable>,
+}
+
+static mut GRESIDENCY : Option<Residency> = None;
+
+// function to create the residency set, it's called by graphic_device::initialize_d3d12()
+pub fn initialize()
+{
+    unsafe
+    {
+        GRESIDENCY = Some(Residency
+        {
+            residency_set : LruResidencySet::new(),
+            pageables : HashMap::new(),
+        });
+    }
+}
+
+pub fn shutdown()
+{
+    unsafe
+    {
+        GRESIDENCY = None;
+    }
+}
+
+// function to manage a heap or a committed resource, it's resident when it's created
+pub fn register(pageable : &ID3D12Pageable, size : u64) -> ResidencyId
+{
+    unsafe
+    {
+        let residency_id = ResidencyId(pageable.as_raw() as u64);
+        if let Some(x) = (*std::ptr::addr_of_mut!(GRESIDENCY)).as_mut()
+        {
+            x.residency_set.register(residency_id, size);
+            x.pageables.insert(residency_id, pageable.clone());
+        }
+
+        return residency_id;
+    }
+}
+
+// function to stop managing an object, call it before the object is released
+pub fn unregister(residency_id : ResidencyId)
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GRESIDENCY)).as_mut()
+        {
+            x.residency_set.unregister(residency_id);
+            x.pageables.remove(&residency_id);
+        }
+    }
+}
+
+// function to mark objects as used by the next submission, the evicted ones are made resident before it's executed
+pub fn touch(residency_ids : &[ResidencyId])
+{
+    unsafe
+    {
+        let residency = match (*std::ptr::addr_of_mut!(GRESIDENCY)).as_mut()
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        let fence_value = graphic_device::get_next_fence_value();
+        let evicted_objects : Vec<Option<ID3D12Pageable>> = residency_ids.iter()
+            .filter(|x| residency.residency_set.touch(**x, fence_value))
+            .map(|x| residency.pageables.get(x).cloned())
+            .collect();
+
+        // MakeResident blocks until the memory is paged in, it fails when the memory can't be found even after trimming
+        if !evicted_objects.is_empty() && graphic_device::get_device().MakeResident(&evicted_objects).is_err()
+        {
+            println!("Error during MakeResident! {} objects", evicted_objects.len());
+        }
+    }
+}
+
+// function to keep an object resident until unpin() is called, for objects that are used without being touched
+// like the ones bindless slots point to
+pub fn pin(residency_id : ResidencyId)
+{
+    unsafe
+    {
+        let residency = match (*std::ptr::addr_of_mut!(GRESIDENCY)).as_mut()
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        if residency.residency_set.pin(residency_id, graphic_device::get_next_fence_value())
+        {
+            let evicted_objects = [residency.pageables.get(&residency_id).cloned()];
+            if graphic_device::get_device().MakeResident(&evicted_objects).is_err()
+            {
+                println!("Error during MakeResident! 1 objects");
+            }
+        }
+    }
+}
+
+// function to release a pin, the object can be evicted once the next submission is completed
+pub fn unpin(residency_id : ResidencyId)
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GRESIDENCY)).as_mut()
+        {
+            x.residency_set.unpin(residency_id, graphic_device::get_next_fence_value());
+        }
+    }
+}
+
+// function to evict the least recently used objects while the local video memory usage is over the budget.
+// it's called by graphic_device::update() after the budget is polled.
+pub fn update_budget(usage : u64, budget : u64)
+{
+    unsafe
+    {
+        let residency = match (*std::ptr::addr_of_mut!(GRESIDENCY)).as_mut()
+        {
+            Some(x) => x,
+            None => return,
+        };
+
+        let evictions = residency.residency_set.select_evictions(usage, budget, graphic_device::get_completed_fence_value());
+        if evictions.is_empty()
+        {
+            return;
+        }
+
+        let evicted_objects : Vec<Option<ID3D12Pageable>> = evictions.iter().map(|x| residency.pageables.get(x).cloned()).collect();
+        if graphic_device::get_device().Evict(&evicted_objects).is_err()
+        {
+            println!("Error during Evict! {} objects", evicted_objects.len());
+            return;
+        }
+
+        println!("Video memory usage is over the budget ({} MB of {} MB), evicted {} objects.", usage / (1024 * 1024), budget / (1024 * 1024), evicted_objects.len());
+    }
+}
+
+pub fn get_resident_size() -> u64
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GRESIDENCY)).as_ref().map_or(0, |x| x.residency_set.get_resident_size());
+    }
+}
+
+pub fn get_evicted_count() -> usize
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GRESIDENCY)).as_ref().map_or(0, |x| x.residency_set.get_evicted_count());
+    }
+}
@@ -0,0 +1,312 @@
+// residency_policy.rs - The least recently used eviction policy of residency.rs
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ResidencyId(pub u64);
+
+struct ResidencyEntry
+{
+    size : u64,
+    last_used_fence_value : u64,
+    // the order of the last use, the key in the LRU list
+    stamp : u64,
+    is_resident : bool,
+    // pinned objects are never evicted, e.g. the ones a live bindless slot points to
+    pin_count : u32,
+}
+
+pub struct LruResidencySet
+{
+    entries : HashMap<ResidencyId, ResidencyEntry>,
+    // the resident objects by the stamp of their last use, the first one is the least recently used
+    lru : BTreeMap<u64, ResidencyId>,
+    next_stamp : u64,
+    resident_size : u64,
+}
+
+impl LruResidencySet
+{
+    pub fn new() -> LruResidencySet
+    {
+        return LruResidencySet
+        {
+            entries : HashMap::new(),
+            lru : BTreeMap::new(),
+            next_stamp : 0,
+            resident_size : 0,
+        };
+    }
+
+    // function to add an object, objects are created resident and count as used by nothing yet
+    pub fn register(&mut self, id : ResidencyId, size : u64)
+    {
+        self.unregister(id);
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.entries.insert(id, ResidencyEntry
+        {
+            size,
+            last_used_fence_value : 0,
+            stamp,
+            is_resident : true,
+            pin_count : 0,
+        });
+        self.lru.insert(stamp, id);
+        self.resident_size += size;
+    }
+
+    pub fn unregister(&mut self, id : ResidencyId)
+    {
+        if let Some(x) = self.entries.remove(&id)
+        {
+            if x.is_resident
+            {
+                self.lru.remove(&x.stamp);
+                self.resident_size -= x.size;
+            }
+        }
+    }
+
+    // function to mark an object as used by the submission that completes with fence_value.
+    // it returns true when the object was evicted, it must be made resident before the submission.
+    pub fn touch(&mut self, id : ResidencyId, fence_value : u64) -> bool
+    {
+        let x = match self.entries.get_mut(&id)
+        {
+            Some(x) => x,
+            None => return false,
+        };
+
+        let was_evicted = !x.is_resident;
+        if was_evicted
+        {
+            x.is_resident = true;
+            self.resident_size += x.size;
+        }
+        else
+        {
+            self.lru.remove(&x.stamp);
+        }
+
+        x.stamp = self.next_stamp;
+        x.last_used_fence_value = x.last_used_fence_value.max(fence_value);
+        self.next_stamp += 1;
+        self.lru.insert(x.stamp, id);
+        return was_evicted;
+    }
+
+    // function to keep an object resident until it's unpinned, for objects that are used without being touched.
+    // it returns true when the object was evicted, it must be made resident before the next submission.
+    pub fn pin(&mut self, id : ResidencyId, fence_value : u64) -> bool
+    {
+        if let Some(x) = self.entries.get_mut(&id)
+        {
+            x.pin_count = x.pin_count + 1;
+            return self.touch(id, fence_value);
+        }
+
+        return false;
+    }
+
+    // function to release a pin, the object counts as used by the submission that completes with fence_value
+    pub fn unpin(&mut self, id : ResidencyId, fence_value : u64)
+    {
+        if let Some(x) = self.entries.get_mut(&id)
+        {
+            x.pin_count = x.pin_count.saturating_sub(1);
+            self.touch(id, fence_value);
+        }
+    }
+
+    // function to pick the objects to evict so the usage gets under the budget. only the objects whose last use
+    // is completed can be picked, so it can evict less than needed. the picked objects are marked as evicted.
+    pub fn select_evictions(&mut self, usage : u64, budget : u64, completed_fence_value : u64) -> Vec<ResidencyId>
+    {
+        let mut evictions : Vec<(u64, ResidencyId)> = Vec::new();
+        let mut excess_size = usage.saturating_sub(budget);
+
+        for (&stamp, &id) in self.lru.iter()
+        {
+            if excess_size == 0
+            {
+                break;
+            }
+
+            let x = &self.entries[&id];
+            if x.pin_count > 0
+            {
+                continue;
+            }
+
+            // the later ones were used after it, so they can't be completed either
+            if x.last_used_fence_value > completed_fence_value
+            {
+                break;
+            }
+
+            excess_size = excess_size.saturating_sub(x.size);
+            evictions.push((stamp, id));
+        }
+
+        for (stamp, id) in evictions.iter()
+        {
+            self.lru.remove(stamp);
+            let x = self.entries.get_mut(id).unwrap();
+            x.is_resident = false;
+            self.resident_size -= x.size;
+        }
+
+        return evictions.into_iter().map(|x| x.1).collect();
+    }
+
+    pub fn is_resident(&self, id : ResidencyId) -> bool
+    {
+        return self.entries.get(&id).is_some_and(|x| x.is_resident);
+    }
+
+    pub fn get_resident_size(&self) -> u64
+    {
+        return self.resident_size;
+    }
+
+    pub fn get_evicted_count(&self) -> usize
+    {
+        return self.entries.len() - self.lru.len();
+    }
+
+    pub fn get_object_count(&self) -> usize
+    {
+        return self.entries.len();
+    }
+}
+
+impl Default for LruResidencySet
+{
+    fn default() -> LruResidencySet
+    {
+        return LruResidencySet::new();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const A : ResidencyId = ResidencyId(1);
+    const B : ResidencyId = ResidencyId(2);
+    const C : ResidencyId = ResidencyId(3);
+
+    fn create_set() -> LruResidencySet
+    {
+        let mut set = LruResidencySet::new();
+        set.register(A, 100);
+        set.register(B, 200);
+        set.register(C, 300);
+        return set;
+    }
+
+    #[test]
+    fn least_recently_used_objects_are_evicted_first()
+    {
+        let mut set = create_set();
+        assert_eq!(set.get_resident_size(), 600);
+        set.touch(A, 1);
+        set.touch(C, 2);
+
+        // 250 bytes over the budget, B was never used and A before C
+        assert_eq!(set.select_evictions(600, 350, 2), vec![B, A]);
+        assert!(!set.is_resident(A) && !set.is_resident(B) && set.is_resident(C));
+        assert_eq!(set.get_resident_size(), 300);
+        assert_eq!(set.get_evicted_count(), 2);
+
+        // under the budget nothing is picked
+        assert_eq!(set.select_evictions(300, 350, 2), vec![]);
+    }
+
+    #[test]
+    fn eviction_stops_at_an_object_in_flight()
+    {
+        let mut set = create_set();
+        set.touch(A, 5);
+        set.touch(B, 6);
+        set.touch(C, 7);
+
+        // B is still used by the submission of fence 6, so it stops there even though it's over the budget
+        assert_eq!(set.select_evictions(600, 0, 5), vec![A]);
+        assert_eq!(set.select_evictions(500, 0, 5), vec![]);
+        assert_eq!(set.get_resident_size(), 500);
+
+        assert_eq!(set.select_evictions(500, 0, 7), vec![B, C]);
+        assert_eq!(set.get_resident_size(), 0);
+    }
+
+    #[test]
+    fn touching_an_evicted_object_makes_it_resident_again()
+    {
+        let mut set = create_set();
+        assert_eq!(set.select_evictions(600, 400, 0), vec![A, B]);
+        assert_eq!(set.get_resident_size(), 300);
+
+        assert!(set.touch(A, 3));
+        assert!(set.is_resident(A));
+        assert_eq!(set.get_resident_size(), 400);
+        assert_eq!(set.get_evicted_count(), 1);
+
+        // it's resident now, and the most recently used one
+        assert!(!set.touch(A, 4));
+        assert!(!set.touch(ResidencyId(99), 4));
+        assert_eq!(set.get_resident_size(), 400);
+        assert_eq!(set.select_evictions(400, 0, 4), vec![C, A]);
+    }
+
+    #[test]
+    fn unregistering_keeps_the_sizes_consistent()
+    {
+        let mut set = create_set();
+        assert_eq!(set.select_evictions(600, 500, 0), vec![A]);
+
+        // an evicted object doesn't count in the resident size anymore
+        set.unregister(A);
+        assert_eq!((set.get_resident_size(), set.get_evicted_count(), set.get_object_count()), (500, 0, 2));
+        set.unregister(B);
+        assert_eq!((set.get_resident_size(), set.get_evicted_count(), set.get_object_count()), (300, 0, 1));
+        set.unregister(B);
+        assert_eq!(set.get_object_count(), 1);
+
+        // registering an id again replaces the old object
+        set.register(C, 50);
+        assert_eq!((set.get_resident_size(), set.get_object_count()), (50, 1));
+        assert_eq!(set.select_evictions(50, 0, 0), vec![C]);
+    }
+
+    #[test]
+    fn pinned_objects_are_never_evicted()
+    {
+        let mut set = create_set();
+        assert_eq!(set.select_evictions(600, 500, 0), vec![A]);
+
+        // pinning an evicted object makes it resident again, and it's skipped while it's the least recently used
+        assert!(set.pin(A, 1));
+        assert!(!set.pin(B, 1));
+        set.touch(C, 1);
+        assert_eq!(set.select_evictions(600, 0, 1), vec![C]);
+        assert!(set.is_resident(A) && set.is_resident(B));
+
+        // A is pinned twice, it stays pinned after the first unpin
+        assert!(!set.pin(A, 2));
+        set.unpin(A, 2);
+        set.unpin(B, 2);
+        assert_eq!(set.select_evictions(300, 0, 2), vec![B]);
+
+        // the last unpin counts as a use by the fence, it can't be evicted before that's completed
+        set.unpin(A, 3);
+        assert_eq!(set.select_evictions(100, 0, 2), vec![]);
+        assert_eq!(set.select_evictions(100, 0, 3), vec![A]);
+        set.unpin(ResidencyId(99), 3);
+        assert_eq!(set.get_resident_size(), 0);
+    }
+}
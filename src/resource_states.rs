@@ -0,0 +1,281 @@
+// resource_states.rs - Automatic resource state tracking on top of state_tracker.rs. resources are registered with the state
+// they are created in, then a CommandListStates records how each command list uses them and inserts the batched transitions.
+// when the list is executed the states it starts with are resolved against the states left by the previous lists,
+// the fixup barriers go into a small command list that runs right before it.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::mem;
+use windows::Win32::Graphics::Direct3D12::*;
+use windows_core::Interface;
+
+use crate::barriers;
+use crate::gpu_memory;
+use crate::graphic_device;
+use crate::state_tracker::{CommandListStateTracker, GlobalStateTracker, ResourceId, ResourceState};
+
+static mut GGLOBAL_STATES : Option<GlobalStateTracker> = None;
+static mut GFIXUP_COMMAND_ALLOCATOR : Option<ID3D12CommandAllocator> = None;
+static mut GFIXUP_COMMAND_LIST : Option<ID3D12GraphicsCommandList> = None;
+
+// function to create the global states and the fixup command list, it's called by graphic_device::initialize_d3d12()
+pub fn initialize() -> bool
+{
+    unsafe
+    {
+        let device = graphic_device::get_device();
+        barriers::initialize();
+        GGLOBAL_STATES = Some(GlobalStateTracker::new());
+        GFIXUP_COMMAND_ALLOCATOR = device.CreateCommandAllocator::<ID3D12CommandAllocator>(D3D12_COMMAND_LIST_TYPE_DIRECT).ok();
+        if GFIXUP_COMMAND_ALLOCATOR.is_none()
+        {
+            return false;
+        }
+
+        GFIXUP_COMMAND_LIST = device.CreateCommandList(0, D3D12_COMMAND_LIST_TYPE_DIRECT, GFIXUP_COMMAND_ALLOCATOR.as_ref(), None).ok();
+        match GFIXUP_COMMAND_LIST.as_ref()
+        {
+            // command lists are created in recording state, close it so it can be reset like the main one
+            Some(x) =>
+            {
+                let _ = x.Close();
+                return true;
+            }
+            None => return false,
+        }
+    }
+}
+
+// function to release the tracking, the resources unregistered afterwards are ignored
+pub fn shutdown()
+{
+    unsafe
+    {
+        GFIXUP_COMMAND_LIST = None;
+        GFIXUP_COMMAND_ALLOCATOR = None;
+        GGLOBAL_STATES = None;
+    }
+}
+
+// function to reset the fixup allocator, it's called by graphic_device::begin_frame() while the GPU is idle
+pub fn begin_frame()
+{
+    unsafe
+    {
+        if let Some(x) = GFIXUP_COMMAND_ALLOCATOR.as_ref()
+        {
+            let _ = x.Reset();
+        }
+    }
+}
+
+pub fn get_resource_id(resource : &ID3D12Resource) -> ResourceId
+{
+    return ResourceId(resource.as_raw() as u64);
+}
+
+pub fn to_d3d12_state(state : ResourceState) -> D3D12_RESOURCE_STATES
+{
+    return D3D12_RESOURCE_STATES(state.0 as i32);
+}
+
+pub fn from_d3d12_state(state : D3D12_RESOURCE_STATES) -> ResourceState
+{
+    return ResourceState(state.0 as u32);
+}
+
+// function to get the number of subresources, mips * array slices * planes, depth stencil formats have 2 planes
+pub fn get_subresource_count(resource : &ID3D12Resource) -> u32
+{
+    unsafe
+    {
+        let resource_desc = resource.GetDesc();
+        if resource_desc.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER
+        {
+            return 1;
+        }
+
+        let array_size = if resource_desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE3D { 1 } else { resource_desc.DepthOrArraySize as u32 };
+        let mut format_info = D3D12_FEATURE_DATA_FORMAT_INFO
+        {
+            Format : resource_desc.Format,
+            PlaneCount : 1,
+        };
+        let _ = graphic_device::get_device().CheckFeatureSupport(D3D12_FEATURE_FORMAT_INFO, &mut format_info as *mut _ as *mut c_void
+            , mem::size_of::<D3D12_FEATURE_DATA_FORMAT_INFO>() as u32);
+
+        return resource_desc.MipLevels.max(1) as u32 * array_size * format_info.PlaneCount.max(1) as u32;
+    }
+}
+
+// function to start tracking a resource in the state it was created in
+pub fn register_resource(resource : &ID3D12Resource, state : D3D12_RESOURCE_STATES)
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GGLOBAL_STATES)).as_mut()
+        {
+            x.register(get_resource_id(resource), get_subresource_count(resource), from_d3d12_state(state));
+        }
+    }
+}
+
+// function to stop tracking a resource, call it before the resource is released since its address can be reused
+pub fn unregister_resource(resource : &ID3D12Resource)
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GGLOBAL_STATES)).as_mut()
+        {
+            x.unregister(get_resource_id(resource));
+        }
+    }
+}
+
+// function to get the state a subresource is left in by the submitted command lists
+pub fn get_resource_state(resource : &ID3D12Resource, subresource : u32) -> Option<D3D12_RESOURCE_STATES>
+{
+    unsafe
+    {
+        return (*std::ptr::addr_of!(GGLOBAL_STATES)).as_ref()?.get_state(get_resource_id(resource), subresource).map(to_d3d12_state);
+    }
+}
+
+// the states of the resources used by one command list recording. declare each use with require() or a use_as_*() function,
+// call flush() before the commands that need the new states, then execute() instead of ExecuteCommandLists.
+pub struct CommandListStates
+{
+    tracker : CommandListStateTracker,
+    // the used resources stay alive until the list is submitted, the barriers borrow them
+    resources : HashMap<ResourceId, ID3D12Resource>,
+}
+
+impl CommandListStates
+{
+    pub fn new() -> CommandListStates
+    {
+        return CommandListStates
+        {
+            tracker : CommandListStateTracker::new(),
+            resources : HashMap::new(),
+        };
+    }
+
+    // function to use a resource in a state from now on, subresource None means all subresources
+    pub fn require(&mut self, resource : &ID3D12Resource, subresource : Option<u32>, state : D3D12_RESOURCE_STATES)
+    {
+        let resource_id = get_resource_id(resource);
+        if !self.resources.contains_key(&resource_id)
+        {
+            self.resources.insert(resource_id, resource.clone());
+        }
+        self.tracker.require(resource_id, get_subresource_count(resource), subresource, from_d3d12_state(state));
+    }
+
+    pub fn use_as_render_target(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_RENDER_TARGET);
+    }
+
+    // readable from any shader stage
+    pub fn use_as_shader_resource(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE | D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE);
+    }
+
+    pub fn use_as_unordered_access(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_UNORDERED_ACCESS);
+    }
+
+    pub fn use_as_copy_source(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_COPY_SOURCE);
+    }
+
+    pub fn use_as_copy_dest(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_COPY_DEST);
+    }
+
+    pub fn use_as_resolve_source(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_RESOLVE_SOURCE);
+    }
+
+    pub fn use_as_resolve_dest(&mut self, resource : &ID3D12Resource)
+    {
+        self.require(resource, None, D3D12_RESOURCE_STATE_RESOLVE_DEST);
+    }
+
+    // function to wait for the UAV writes to a resource before the following accesses
+    pub fn unordered_access_barrier(&mut self, resource : &ID3D12Resource)
+    {
+        let resource_id = get_resource_id(resource);
+        if !self.resources.contains_key(&resource_id)
+        {
+            self.resources.insert(resource_id, resource.clone());
+        }
+        self.tracker.unordered_access_barrier(resource_id);
+    }
+
+    // function to record the batched barriers in one call, enhanced or legacy depending on the device
+    pub fn flush(&mut self, command_list : &ID3D12GraphicsCommandList)
+    {
+        barriers::record_barriers(command_list, &self.tracker.flush(), &self.resources);
+    }
+
+    // function to close the command list and execute it on the main queue, after the fixups of the states it starts with
+    pub fn execute(mut self, command_list : &ID3D12GraphicsCommandList)
+    {
+        self.flush(command_list);
+
+        unsafe
+        {
+            let _ = command_list.Close();
+
+            // the evicted heaps the list uses are paged in before it's executed
+            gpu_memory::touch_resources(&self.resources.values().collect::<Vec<_>>());
+
+            let global_states = match (*std::ptr::addr_of_mut!(GGLOBAL_STATES)).as_mut()
+            {
+                Some(x) => x,
+                None =>
+                {
+                    graphic_device::get_command_queue().ExecuteCommandLists(&[Some(command_list.cast().unwrap())]);
+                    return;
+                }
+            };
+
+            if cfg!(debug_assertions)
+            {
+                for x in global_states.get_unknown_resources(&self.tracker)
+                {
+                    println!("Resource {:#x} isn't registered for state tracking, it's assumed to be in the state of its first use.", x.0);
+                }
+            }
+
+            let fixups = global_states.submit(&self.tracker);
+            if fixups.is_empty()
+            {
+                graphic_device::get_command_queue().ExecuteCommandLists(&[Some(command_list.cast().unwrap())]);
+                return;
+            }
+
+            let fixup_command_list = GFIXUP_COMMAND_LIST.as_ref().unwrap();
+            let _ = fixup_command_list.Reset(GFIXUP_COMMAND_ALLOCATOR.as_ref().unwrap(), None);
+            barriers::record_barriers(fixup_command_list, &fixups, &self.resources);
+            let _ = fixup_command_list.Close();
+
+            graphic_device::get_command_queue().ExecuteCommandLists(&[Some(fixup_command_list.cast().unwrap()), Some(command_list.cast().unwrap())]);
+        }
+    }
+}
+
+impl Default for CommandListStates
+{
+    fn default() -> CommandListStates
+    {
+        return CommandListStates::new();
+    }
+}
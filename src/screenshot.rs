@@ -0,0 +1,89 @@
+// screenshot.rs - Capture a render target to a PNG or DDS file, the encoding itself lives in image_io.rs
+
+use windows::Win32::System::SystemInformation::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::graphic_device;
+use crate::graphic_device::RenderTarget;
+use crate::image_io;
+use crate::image_io::Image;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScreenshotFormat
+{
+    Png,
+    Dds,
+}
+
+// screenshots are saved under the working directory
+const GSCREENSHOT_DIRECTORY : &str = "screenshots";
+
+// function to copy a render target to CPU memory, call it after the rendering is submitted and before presenting.
+// it waits for the GPU, so don't call it every frame.
+pub fn capture(target : &RenderTarget) -> Option<Image>
+{
+    let resource = target.resource.as_ref()?;
+    let readback = graphic_device::create_texture_readback(resource)?;
+    readback.copy_from(resource, target.resting_state);
+    graphic_device::wait_for_gpu();
+
+    return Some(readback.read());
+}
+
+// function to save an image to the file path, PNG converts the image to 8-bit RGBA while DDS keeps the original format
+pub fn save(image : &Image, format : ScreenshotFormat, path : &Path) -> bool
+{
+    let result = match format
+    {
+        ScreenshotFormat::Png => image_io::write_png(path, image),
+        ScreenshotFormat::Dds => image_io::write_dds(path, image),
+    };
+
+    if let Err(x) = result
+    {
+        println!("Error during saving {}: {}", path.display(), x);
+        return false;
+    }
+
+    return true;
+}
+
+// function to build a file name from the local time, e.g. screenshots/screenshot_20240101_120000_000.png
+pub fn get_timestamped_path(format : ScreenshotFormat) -> PathBuf
+{
+    unsafe
+    {
+        let local_time = GetLocalTime();
+        let extension = match format
+        {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Dds => "dds",
+        };
+
+        let file_name = format!("screenshot_{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}.{}", local_time.wYear, local_time.wMonth, local_time.wDay
+            , local_time.wHour, local_time.wMinute, local_time.wSecond, local_time.wMilliseconds, extension);
+
+        return Path::new(GSCREENSHOT_DIRECTORY).join(file_name);
+    }
+}
+
+// function to capture a render target and write it to a timestamped file, returns the file path if succeeded
+pub fn capture_to_file(target : &RenderTarget, format : ScreenshotFormat) -> Option<PathBuf>
+{
+    let image = capture(target)?;
+    if fs::create_dir_all(GSCREENSHOT_DIRECTORY).is_err()
+    {
+        println!("Error during creating the screenshot directory!");
+        return None;
+    }
+
+    let path = get_timestamped_path(format);
+    if !save(&image, format, &path)
+    {
+        return None;
+    }
+
+    println!("Screenshot saved: {}", path.display());
+    return Some(path);
+}
@@ -0,0 +1,546 @@
+// state_tracker.rs - The per subresource bookkeeping of resource states and the barriers between them
+
+use std::collections::HashMap;
+
+// the bits of D3D12_RESOURCE_STATES, with the same values so they convert as they are
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ResourceState(pub u32);
+
+impl ResourceState
+{
+    pub const COMMON : ResourceState = ResourceState(0);
+    pub const PRESENT : ResourceState = ResourceState(0);
+    pub const VERTEX_AND_CONSTANT_BUFFER : ResourceState = ResourceState(0x1);
+    pub const INDEX_BUFFER : ResourceState = ResourceState(0x2);
+    pub const RENDER_TARGET : ResourceState = ResourceState(0x4);
+    pub const UNORDERED_ACCESS : ResourceState = ResourceState(0x8);
+    pub const DEPTH_WRITE : ResourceState = ResourceState(0x10);
+    pub const DEPTH_READ : ResourceState = ResourceState(0x20);
+    pub const NON_PIXEL_SHADER_RESOURCE : ResourceState = ResourceState(0x40);
+    pub const PIXEL_SHADER_RESOURCE : ResourceState = ResourceState(0x80);
+    pub const STREAM_OUT : ResourceState = ResourceState(0x100);
+    pub const INDIRECT_ARGUMENT : ResourceState = ResourceState(0x200);
+    pub const COPY_DEST : ResourceState = ResourceState(0x400);
+    pub const COPY_SOURCE : ResourceState = ResourceState(0x800);
+    pub const RESOLVE_DEST : ResourceState = ResourceState(0x1000);
+    pub const RESOLVE_SOURCE : ResourceState = ResourceState(0x2000);
+    pub const GENERIC_READ : ResourceState = ResourceState(0x1 | 0x2 | 0x40 | 0x80 | 0x200 | 0x800);
+
+    // the states the GPU writes in, they can't be combined with any other state
+    const WRITE_BITS : u32 = 0x4 | 0x8 | 0x10 | 0x100 | 0x400 | 0x1000;
+
+    pub fn is_read_only(self) -> bool
+    {
+        return self.0 & ResourceState::WRITE_BITS == 0;
+    }
+
+    pub fn contains(self, other : ResourceState) -> bool
+    {
+        return self.0 & other.0 == other.0;
+    }
+
+    // function to check whether a resource in this state can be used in the required state without a transition.
+    // a combined read state covers each of its read states, COMMON only covers itself.
+    pub fn satisfies(self, required : ResourceState) -> bool
+    {
+        if self == required
+        {
+            return true;
+        }
+        return required != ResourceState::COMMON && self.is_read_only() && self.contains(required);
+    }
+}
+
+// the key of a resource, the D3D12 side uses the address of the interface
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ResourceId(pub u64);
+
+// a barrier to record, subresource None means all subresources
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StateBarrier
+{
+    Transition
+    {
+        resource : ResourceId,
+        subresource : Option<u32>,
+        before : ResourceState,
+        after : ResourceState,
+    },
+    UnorderedAccess
+    {
+        resource : ResourceId,
+    },
+}
+
+// a resource as seen by one command list
+struct ListResource
+{
+    // the state each subresource needs at its first use in the list, None if it isn't used
+    first_states : Vec<Option<ResourceState>>,
+    // the state of each subresource after the barriers recorded so far
+    current_states : Vec<Option<ResourceState>>,
+}
+
+// the states of the resources a command list uses, one tracker per command list recording
+pub struct CommandListStateTracker
+{
+    resources : HashMap<ResourceId, ListResource>,
+    // the resources in the order of their first use, so the fixups are resolved in a stable order
+    resource_order : Vec<ResourceId>,
+    pending_barriers : Vec<StateBarrier>,
+}
+
+// function to pick the state to transition to, read states are combined so the earlier reads stay valid
+fn get_state_after(current : ResourceState, required : ResourceState) -> ResourceState
+{
+    if current != ResourceState::COMMON && required != ResourceState::COMMON && current.is_read_only() && required.is_read_only()
+    {
+        return ResourceState(current.0 | required.0);
+    }
+    return required;
+}
+
+// function to turn the transitions of all subresources into one barrier when they are all the same.
+// transitions holds (subresource, before, after) and is never empty.
+fn coalesce_transitions(resource : ResourceId, subresource_count : u32, transitions : &[(u32, ResourceState, ResourceState)]) -> Vec<StateBarrier>
+{
+    let (_, first_before, first_after) = transitions[0];
+    if transitions.len() == subresource_count as usize && transitions.iter().all(|x| x.1 == first_before && x.2 == first_after)
+    {
+        return vec![StateBarrier::Transition { resource, subresource : None, before : first_before, after : first_after }];
+    }
+
+    return transitions.iter().map(|x| StateBarrier::Transition { resource, subresource : Some(x.0), before : x.1, after : x.2 }).collect();
+}
+
+impl CommandListStateTracker
+{
+    pub fn new() -> CommandListStateTracker
+    {
+        return CommandListStateTracker
+        {
+            resources : HashMap::new(),
+            resource_order : Vec::new(),
+            pending_barriers : Vec::new(),
+        };
+    }
+
+    // function to declare that the following commands use a resource in a state, subresource None means all of them.
+    // the needed transitions are batched until flush().
+    pub fn require(&mut self, resource : ResourceId, subresource_count : u32, subresource : Option<u32>, state : ResourceState)
+    {
+        if !self.resources.contains_key(&resource)
+        {
+            self.resource_order.push(resource);
+            self.resources.insert(resource, ListResource
+            {
+                first_states : vec![None; subresource_count as usize],
+                current_states : vec![None; subresource_count as usize],
+            });
+        }
+
+        let list_resource = self.resources.get_mut(&resource).unwrap();
+        let subresources = match subresource
+        {
+            Some(x) => x..x + 1,
+            None => 0..list_resource.current_states.len() as u32,
+        };
+
+        let mut transitions : Vec<(u32, ResourceState, ResourceState)> = Vec::new();
+        for x in subresources
+        {
+            let idx = x as usize;
+            match list_resource.current_states[idx]
+            {
+                // the first use, the state before it is resolved at submit
+                None =>
+                {
+                    list_resource.first_states[idx] = Some(state);
+                    list_resource.current_states[idx] = Some(state);
+                }
+                Some(current) =>
+                {
+                    if !current.satisfies(state)
+                    {
+                        let after = get_state_after(current, state);
+                        transitions.push((x, current, after));
+                        list_resource.current_states[idx] = Some(after);
+                    }
+                }
+            }
+        }
+
+        if transitions.is_empty()
+        {
+            return;
+        }
+
+        let subresource_count = list_resource.current_states.len() as u32;
+        let barriers = if subresource.is_none() { coalesce_transitions(resource, subresource_count, &transitions) }
+            else { transitions.iter().map(|x| StateBarrier::Transition { resource, subresource : Some(x.0), before : x.1, after : x.2 }).collect() };
+        for x in barriers
+        {
+            self.push_transition(x);
+        }
+    }
+
+    // function to batch a transition, a pending transition of the same subresources is folded into it instead.
+    // e.g. A->B then B->C is recorded as A->C, and A->B then B->A cancels out.
+    fn push_transition(&mut self, barrier : StateBarrier)
+    {
+        if let StateBarrier::Transition { resource, subresource, before, after } = barrier
+        {
+            // only fold with the last barrier of the resource, an UAV barrier or another subresource in between keeps the order
+            let last_index = self.pending_barriers.iter().rposition(|x| match x
+            {
+                StateBarrier::Transition { resource : y, .. } => *y == resource,
+                StateBarrier::UnorderedAccess { resource : y } => *y == resource,
+            });
+
+            if let Some(idx) = last_index
+            {
+                if let StateBarrier::Transition { subresource : pending_subresource, before : pending_before, after : pending_after, .. } = self.pending_barriers[idx]
+                {
+                    if pending_subresource == subresource && pending_after == before
+                    {
+                        if pending_before == after
+                        {
+                            self.pending_barriers.remove(idx);
+                        }
+                        else
+                        {
+                            self.pending_barriers[idx] = StateBarrier::Transition { resource, subresource, before : pending_before, after };
+                        }
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.pending_barriers.push(barrier);
+    }
+
+    // function to order the UAV accesses before and after this point, e.g. between two dispatches writing the same resource
+    pub fn unordered_access_barrier(&mut self, resource : ResourceId)
+    {
+        self.pending_barriers.push(StateBarrier::UnorderedAccess { resource });
+    }
+
+    // function to take the batched barriers, record them before the commands that need the new states
+    pub fn flush(&mut self) -> Vec<StateBarrier>
+    {
+        return std::mem::take(&mut self.pending_barriers);
+    }
+
+    pub fn has_pending_barriers(&self) -> bool
+    {
+        return !self.pending_barriers.is_empty();
+    }
+
+    // the state of a subresource after the barriers recorded so far, None if the list doesn't use it
+    pub fn get_current_state(&self, resource : ResourceId, subresource : u32) -> Option<ResourceState>
+    {
+        return self.resources.get(&resource).and_then(|x| x.current_states.get(subresource as usize).copied().flatten());
+    }
+
+    pub fn get_resources(&self) -> &[ResourceId]
+    {
+        return &self.resource_order;
+    }
+}
+
+impl Default for CommandListStateTracker
+{
+    fn default() -> CommandListStateTracker
+    {
+        return CommandListStateTracker::new();
+    }
+}
+
+// the states of the resources between command lists, i.e. the state each subresource is left in by the lists submitted so far
+pub struct GlobalStateTracker
+{
+    states : HashMap<ResourceId, Vec<ResourceState>>,
+}
+
+impl GlobalStateTracker
+{
+    pub fn new() -> GlobalStateTracker
+    {
+        return GlobalStateTracker
+        {
+            states : HashMap::new(),
+        };
+    }
+
+    // function to start tracking a resource in the state it was created in
+    pub fn register(&mut self, resource : ResourceId, subresource_count : u32, state : ResourceState)
+    {
+        self.states.insert(resource, vec![state; subresource_count as usize]);
+    }
+
+    // function to stop tracking a resource, its id can be reused by a new resource afterwards
+    pub fn unregister(&mut self, resource : ResourceId) -> bool
+    {
+        return self.states.remove(&resource).is_some();
+    }
+
+    pub fn is_registered(&self, resource : ResourceId) -> bool
+    {
+        return self.states.contains_key(&resource);
+    }
+
+    pub fn get_state(&self, resource : ResourceId, subresource : u32) -> Option<ResourceState>
+    {
+        return self.states.get(&resource).and_then(|x| x.get(subresource as usize).copied());
+    }
+
+    // the resources a list uses without being registered, their state before the list is unknown
+    pub fn get_unknown_resources(&self, list : &CommandListStateTracker) -> Vec<ResourceId>
+    {
+        return list.resource_order.iter().filter(|x| !self.states.contains_key(x)).copied().collect();
+    }
+
+    // function to resolve a list at submit time. returns the fixup barriers that bring the resources from the states
+    // left by the previous lists to the states the list starts with, they must execute right before the list.
+    // the states the list ends with become the current states. a resource that isn't registered is assumed to be
+    // in the state of its first use and is registered with the state it ends in.
+    pub fn submit(&mut self, list : &CommandListStateTracker) -> Vec<StateBarrier>
+    {
+        let mut fixups : Vec<StateBarrier> = Vec::new();
+        for resource in list.resource_order.iter()
+        {
+            let list_resource = &list.resources[resource];
+            let subresource_count = list_resource.current_states.len();
+            let states = self.states.entry(*resource).or_insert_with(||
+            {
+                list_resource.first_states.iter().map(|x| x.unwrap_or(ResourceState::COMMON)).collect()
+            });
+
+            // a resource can be seen with another subresource count if its id was reused without unregistering
+            if states.len() != subresource_count
+            {
+                *states = list_resource.first_states.iter().map(|x| x.unwrap_or(ResourceState::COMMON)).collect();
+            }
+
+            let mut transitions : Vec<(u32, ResourceState, ResourceState)> = Vec::new();
+            for (idx, state) in states.iter_mut().enumerate()
+            {
+                // the exact state is needed, the before state of the barriers in the list must match
+                if let Some(first_state) = list_resource.first_states[idx]
+                {
+                    if *state != first_state
+                    {
+                        transitions.push((idx as u32, *state, first_state));
+                    }
+                }
+                if let Some(current_state) = list_resource.current_states[idx]
+                {
+                    *state = current_state;
+                }
+            }
+
+            if !transitions.is_empty()
+            {
+                fixups.extend(coalesce_transitions(*resource, subresource_count as u32, &transitions));
+            }
+        }
+
+        return fixups;
+    }
+
+    pub fn get_resource_count(&self) -> usize
+    {
+        return self.states.len();
+    }
+}
+
+impl Default for GlobalStateTracker
+{
+    fn default() -> GlobalStateTracker
+    {
+        return GlobalStateTracker::new();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const RESOURCE : ResourceId = ResourceId(0x1000);
+    const OTHER_RESOURCE : ResourceId = ResourceId(0x2000);
+    const SHADER_RESOURCE : ResourceState = ResourceState(ResourceState::PIXEL_SHADER_RESOURCE.0 | ResourceState::NON_PIXEL_SHADER_RESOURCE.0);
+
+    fn transition(resource : ResourceId, subresource : Option<u32>, before : ResourceState, after : ResourceState) -> StateBarrier
+    {
+        return StateBarrier::Transition { resource, subresource, before, after };
+    }
+
+    #[test]
+    fn first_use_records_no_barrier()
+    {
+        let mut list = CommandListStateTracker::new();
+        list.require(RESOURCE, 1, None, ResourceState::RENDER_TARGET);
+        assert!(!list.has_pending_barriers());
+        assert_eq!(list.get_current_state(RESOURCE, 0), Some(ResourceState::RENDER_TARGET));
+        assert_eq!(list.get_current_state(OTHER_RESOURCE, 0), None);
+    }
+
+    #[test]
+    fn pending_transitions_are_folded()
+    {
+        // A -> B -> C is recorded as A -> C
+        let mut list = CommandListStateTracker::new();
+        list.require(RESOURCE, 1, None, ResourceState::RENDER_TARGET);
+        list.require(RESOURCE, 1, None, ResourceState::COPY_SOURCE);
+        list.require(RESOURCE, 1, None, ResourceState::UNORDERED_ACCESS);
+        assert_eq!(list.flush(), vec![transition(RESOURCE, None, ResourceState::RENDER_TARGET, ResourceState::UNORDERED_ACCESS)]);
+        assert!(!list.has_pending_barriers());
+
+        // A -> B -> A cancels out
+        list.require(RESOURCE, 1, None, ResourceState::COPY_DEST);
+        list.require(RESOURCE, 1, None, ResourceState::UNORDERED_ACCESS);
+        assert_eq!(list.flush(), vec![]);
+        assert_eq!(list.get_current_state(RESOURCE, 0), Some(ResourceState::UNORDERED_ACCESS));
+
+        // an UAV barrier in between keeps both transitions
+        list.require(RESOURCE, 1, None, ResourceState::COPY_SOURCE);
+        list.unordered_access_barrier(RESOURCE);
+        list.require(RESOURCE, 1, None, ResourceState::COPY_DEST);
+        assert_eq!(list.flush(), vec!
+        [
+            transition(RESOURCE, None, ResourceState::UNORDERED_ACCESS, ResourceState::COPY_SOURCE),
+            StateBarrier::UnorderedAccess { resource : RESOURCE },
+            transition(RESOURCE, None, ResourceState::COPY_SOURCE, ResourceState::COPY_DEST),
+        ]);
+
+        // transitions of other resources don't stop the folding
+        list.require(OTHER_RESOURCE, 1, None, ResourceState::COPY_SOURCE);
+        list.require(RESOURCE, 1, None, ResourceState::RENDER_TARGET);
+        list.require(OTHER_RESOURCE, 1, None, ResourceState::COPY_DEST);
+        list.require(RESOURCE, 1, None, ResourceState::PIXEL_SHADER_RESOURCE);
+        assert_eq!(list.flush(), vec!
+        [
+            transition(RESOURCE, None, ResourceState::COPY_DEST, ResourceState::PIXEL_SHADER_RESOURCE),
+            transition(OTHER_RESOURCE, None, ResourceState::COPY_SOURCE, ResourceState::COPY_DEST),
+        ]);
+    }
+
+    #[test]
+    fn read_states_are_merged()
+    {
+        let mut list = CommandListStateTracker::new();
+        list.require(RESOURCE, 1, None, ResourceState::PIXEL_SHADER_RESOURCE);
+        list.require(RESOURCE, 1, None, ResourceState::NON_PIXEL_SHADER_RESOURCE);
+        assert_eq!(list.flush(), vec![transition(RESOURCE, None, ResourceState::PIXEL_SHADER_RESOURCE, SHADER_RESOURCE)]);
+
+        // each read of the combined state is covered without a barrier
+        list.require(RESOURCE, 1, None, ResourceState::PIXEL_SHADER_RESOURCE);
+        list.require(RESOURCE, 1, None, ResourceState::NON_PIXEL_SHADER_RESOURCE);
+        assert!(!list.has_pending_barriers());
+
+        // a write replaces the combined state, and a read after a write isn't merged with it
+        list.require(RESOURCE, 1, None, ResourceState::RENDER_TARGET);
+        list.require(RESOURCE, 1, None, ResourceState::COPY_SOURCE);
+        assert_eq!(list.flush(), vec![transition(RESOURCE, None, SHADER_RESOURCE, ResourceState::COPY_SOURCE)]);
+
+        // COMMON is neither merged nor covered by a read state
+        list.require(RESOURCE, 1, None, ResourceState::COMMON);
+        assert_eq!(list.flush(), vec![transition(RESOURCE, None, ResourceState::COPY_SOURCE, ResourceState::COMMON)]);
+        assert!(!ResourceState::GENERIC_READ.satisfies(ResourceState::COMMON));
+        assert!(ResourceState::GENERIC_READ.satisfies(ResourceState::COPY_SOURCE));
+        assert!(!ResourceState::GENERIC_READ.satisfies(ResourceState::DEPTH_READ));
+    }
+
+    #[test]
+    fn transitions_are_coalesced_per_subresource()
+    {
+        let mut list = CommandListStateTracker::new();
+        list.require(RESOURCE, 3, None, ResourceState::RENDER_TARGET);
+        list.require(RESOURCE, 3, Some(1), ResourceState::COPY_DEST);
+        assert_eq!(list.flush(), vec![transition(RESOURCE, Some(1), ResourceState::RENDER_TARGET, ResourceState::COPY_DEST)]);
+
+        // the subresources come from different states, each one gets its own barrier
+        list.require(RESOURCE, 3, None, ResourceState::PIXEL_SHADER_RESOURCE);
+        assert_eq!(list.flush(), vec!
+        [
+            transition(RESOURCE, Some(0), ResourceState::RENDER_TARGET, ResourceState::PIXEL_SHADER_RESOURCE),
+            transition(RESOURCE, Some(1), ResourceState::COPY_DEST, ResourceState::PIXEL_SHADER_RESOURCE),
+            transition(RESOURCE, Some(2), ResourceState::RENDER_TARGET, ResourceState::PIXEL_SHADER_RESOURCE),
+        ]);
+
+        // the same transition on all of them is one barrier
+        list.require(RESOURCE, 3, None, ResourceState::COPY_DEST);
+        assert_eq!(list.flush(), vec![transition(RESOURCE, None, ResourceState::PIXEL_SHADER_RESOURCE, ResourceState::COPY_DEST)]);
+
+        // only the subresources that aren't in the state yet are transitioned
+        list.require(RESOURCE, 3, Some(2), ResourceState::UNORDERED_ACCESS);
+        list.flush();
+        list.require(RESOURCE, 3, None, ResourceState::UNORDERED_ACCESS);
+        assert_eq!(list.flush(), vec!
+        [
+            transition(RESOURCE, Some(0), ResourceState::COPY_DEST, ResourceState::UNORDERED_ACCESS),
+            transition(RESOURCE, Some(1), ResourceState::COPY_DEST, ResourceState::UNORDERED_ACCESS),
+        ]);
+    }
+
+    #[test]
+    fn submit_resolves_the_first_states_of_each_list()
+    {
+        let mut global = GlobalStateTracker::new();
+        global.register(RESOURCE, 2, ResourceState::COMMON);
+        assert!(global.is_registered(RESOURCE));
+
+        let mut first_list = CommandListStateTracker::new();
+        first_list.require(RESOURCE, 2, None, ResourceState::RENDER_TARGET);
+        first_list.require(RESOURCE, 2, Some(0), ResourceState::PIXEL_SHADER_RESOURCE);
+        assert_eq!(global.submit(&first_list), vec![transition(RESOURCE, None, ResourceState::COMMON, ResourceState::RENDER_TARGET)]);
+        assert_eq!(global.get_state(RESOURCE, 0), Some(ResourceState::PIXEL_SHADER_RESOURCE));
+        assert_eq!(global.get_state(RESOURCE, 1), Some(ResourceState::RENDER_TARGET));
+
+        // the second list only knows the states it needs, the fixups come from the states the first one left.
+        // the other resource isn't registered, it's taken to be in the state of its first use
+        let mut second_list = CommandListStateTracker::new();
+        second_list.require(RESOURCE, 2, None, ResourceState::COPY_SOURCE);
+        second_list.require(OTHER_RESOURCE, 1, None, ResourceState::UNORDERED_ACCESS);
+        second_list.require(OTHER_RESOURCE, 1, None, ResourceState::NON_PIXEL_SHADER_RESOURCE);
+        assert_eq!(global.get_unknown_resources(&second_list), vec![OTHER_RESOURCE]);
+        assert_eq!(global.submit(&second_list), vec!
+        [
+            transition(RESOURCE, Some(0), ResourceState::PIXEL_SHADER_RESOURCE, ResourceState::COPY_SOURCE),
+            transition(RESOURCE, Some(1), ResourceState::RENDER_TARGET, ResourceState::COPY_SOURCE),
+        ]);
+        assert_eq!(global.get_state(OTHER_RESOURCE, 0), Some(ResourceState::NON_PIXEL_SHADER_RESOURCE));
+        assert_eq!(global.get_resource_count(), 2);
+
+        // the exact state is needed at submit, a combined read state isn't kept for one of its reads
+        let mut third_list = CommandListStateTracker::new();
+        third_list.require(OTHER_RESOURCE, 1, None, ResourceState::NON_PIXEL_SHADER_RESOURCE);
+        third_list.require(RESOURCE, 2, Some(1), ResourceState::COPY_SOURCE);
+        assert_eq!(global.get_unknown_resources(&third_list), vec![]);
+        assert_eq!(global.submit(&third_list), vec![]);
+
+        global.register(RESOURCE, 2, SHADER_RESOURCE);
+        let mut fourth_list = CommandListStateTracker::new();
+        fourth_list.require(RESOURCE, 2, None, ResourceState::PIXEL_SHADER_RESOURCE);
+        assert_eq!(global.submit(&fourth_list), vec![transition(RESOURCE, None, SHADER_RESOURCE, ResourceState::PIXEL_SHADER_RESOURCE)]);
+
+        assert!(global.unregister(OTHER_RESOURCE));
+        assert!(!global.unregister(OTHER_RESOURCE));
+        assert_eq!(global.get_state(OTHER_RESOURCE, 0), None);
+    }
+
+    #[test]
+    fn submit_resets_a_reused_id_with_another_subresource_count()
+    {
+        let mut global = GlobalStateTracker::new();
+        global.register(RESOURCE, 1, ResourceState::RENDER_TARGET);
+
+        let mut list = CommandListStateTracker::new();
+        list.require(RESOURCE, 3, Some(2), ResourceState::COPY_DEST);
+        assert_eq!(global.submit(&list), vec![]);
+        assert_eq!(global.get_state(RESOURCE, 0), Some(ResourceState::COMMON));
+        assert_eq!(global.get_state(RESOURCE, 2), Some(ResourceState::COPY_DEST));
+    }
+}
@@ -0,0 +1,170 @@
+// upload_allocator.rs - A ring allocator over a persistently mapped upload buffer for per-frame dynamic data
+
+#[cfg(windows)]
+use windows::Win32::Graphics::Direct3D12::*;
+#[cfg(windows)]
+use std::ffi::c_void;
+
+#[cfg(windows)]
+use crate::buffer;
+#[cfg(windows)]
+use crate::graphic_device;
+#[cfg(windows)]
+use crate::frame_ring::{align_up, FrameRing};
+
+// an allocation in the upload buffer, cpu_pointer is valid until the frame is reclaimed
+#[derive(Clone, Copy, Debug)]
+pub struct UploadAllocation
+{
+    pub cpu_pointer : *mut u8,
+    pub gpu_address : u64,
+    pub offset : u64,
+    pub size : u64,
+}
+
+// the ring with its upload buffer
+#[cfg(windows)]
+pub struct UploadAllocator
+{
+    resource : ID3D12Resource,
+    cpu_base : *mut u8,
+    gpu_base : u64,
+    ring : FrameRing,
+}
+
+#[cfg(windows)]
+impl UploadAllocator
+{
+    pub fn new(capacity : u64) -> Option<UploadAllocator>
+    {
+        let resource = buffer::create_buffer_resource(capacity, D3D12_HEAP_TYPE_UPLOAD, D3D12_RESOURCE_FLAG_NONE, D3D12_RESOURCE_STATE_GENERIC_READ)?;
+
+        unsafe
+        {
+            // upload heaps can stay mapped for their whole life
+            let mut mapped_data : *mut c_void = std::ptr::null_mut();
+            if resource.Map(0, None, Some(&mut mapped_data)).is_err()
+            {
+                println!("Error during upload buffer mapping!");
+                return None;
+            }
+
+            let gpu_base = resource.GetGPUVirtualAddress();
+            return Some(UploadAllocator
+            {
+                resource,
+                cpu_base : mapped_data as *mut u8,
+                gpu_base,
+                ring : FrameRing::new(capacity),
+            });
+        }
+    }
+
+    pub fn allocate(&mut self, size : u64, alignment : u64) -> Option<UploadAllocation>
+    {
+        let offset = self.ring.allocate(size, alignment)?;
+        unsafe
+        {
+            return Some(UploadAllocation
+            {
+                cpu_pointer : self.cpu_base.add(offset as usize),
+                gpu_address : self.gpu_base + offset,
+                offset,
+                size,
+            });
+        }
+    }
+
+    pub fn get_resource(&self) -> &ID3D12Resource
+    {
+        return &self.resource;
+    }
+
+    pub fn get_ring(&self) -> &FrameRing
+    {
+        return &self.ring;
+    }
+}
+
+#[cfg(windows)]
+static mut GUPLOAD_ALLOCATOR : Option<UploadAllocator> = None;
+
+// the default size of the per-frame upload memory
+pub const GDEFAULT_CAPACITY : u64 = 4 * 1024 * 1024;
+
+// function to create the per-frame allocator, call it after graphic_device::initialize_d3d12()
+#[cfg(windows)]
+pub fn initialize(capacity : u64) -> bool
+{
+    unsafe
+    {
+        GUPLOAD_ALLOCATOR = UploadAllocator::new(capacity);
+        return GUPLOAD_ALLOCATOR.is_some();
+    }
+}
+
+// function to release the upload buffer, call it while the GPU is idle
+#[cfg(windows)]
+pub fn shutdown()
+{
+    unsafe
+    {
+        GUPLOAD_ALLOCATOR = None;
+    }
+}
+
+// function to reclaim the space of the finished frames, called once per frame before anything is allocated
+#[cfg(windows)]
+pub fn begin_frame()
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GUPLOAD_ALLOCATOR)).as_mut()
+        {
+            x.ring.reclaim(graphic_device::get_completed_fence_value());
+        }
+    }
+}
+
+// function to close the allocations of this frame, called after the command lists of the frame are executed.
+// they are reclaimed after the next fence signal of the main queue is completed.
+#[cfg(windows)]
+pub fn end_frame()
+{
+    unsafe
+    {
+        if let Some(x) = (*std::ptr::addr_of_mut!(GUPLOAD_ALLOCATOR)).as_mut()
+        {
+            x.ring.end_frame(graphic_device::get_next_fence_value());
+        }
+    }
+}
+
+// function to allocate upload memory for this frame, None if the allocator isn't initialized or is full
+#[cfg(windows)]
+pub fn allocate(size : u64, alignment : u64) -> Option<UploadAllocation>
+{
+    unsafe
+    {
+        let allocation = (*std::ptr::addr_of_mut!(GUPLOAD_ALLOCATOR)).as_mut()?.allocate(size, alignment);
+        if allocation.is_none()
+        {
+            println!("The upload allocator is out of memory! {} bytes requested", size);
+        }
+        return allocation;
+    }
+}
+
+// function to copy a constant struct to upload memory, returns the GPU address for a root CBV or a CBV.
+// the address is aligned to D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT.
+#[cfg(windows)]
+pub fn allocate_constants<T : Copy>(constants : &T) -> Option<u64>
+{
+    let size = align_up(std::mem::size_of::<T>() as u64, D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as u64);
+    let allocation = allocate(size, D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT as u64)?;
+    unsafe
+    {
+        std::ptr::copy_nonoverlapping(constants as *const T as *const u8, allocation.cpu_pointer, std::mem::size_of::<T>());
+    }
+    return Some(allocation.gpu_address);
+}
@@ -0,0 +1,245 @@
+// window.rs - Window creation from a WindowConfig, the size in the config is the client area so it matches the swapchain
+
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use windows::Win32::UI::HiDpi::*;
+use windows::Win32::UI::Shell::*;
+use std::path::PathBuf;
+
+// where to place the window, monitors are indexed with the primary monitor first
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowPosition
+{
+    Centered { monitor_index : usize },
+    At { x : i32, y : i32 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig
+{
+    pub title : String,
+    pub client_width : u32,
+    pub client_height : u32,
+    pub position : WindowPosition,
+    pub resizable : bool,
+    pub borderless : bool,
+    // an .ico file, the default application icon is used if it's None or fails to load
+    pub icon_path : Option<PathBuf>,
+}
+
+impl Default for WindowConfig
+{
+    fn default() -> WindowConfig
+    {
+        return WindowConfig
+        {
+            title : String::from("Rust D3D12"),
+            client_width : 1920,
+            client_height : 1080,
+            position : WindowPosition::Centered { monitor_index : 0 },
+            resizable : false,
+            borderless : false,
+            icon_path : None,
+        };
+    }
+}
+
+// function to get the window styles of a config
+pub fn get_window_style(config : &WindowConfig) -> (WINDOW_STYLE, WINDOW_EX_STYLE)
+{
+    if config.borderless
+    {
+        return (WS_POPUP, WINDOW_EX_STYLE::default());
+    }
+
+    let mut style = WS_OVERLAPPED | WS_CAPTION | WS_MINIMIZEBOX | WS_SYSMENU;
+    if config.resizable
+    {
+        style = style | WS_THICKFRAME | WS_MAXIMIZEBOX;
+    }
+
+    return (style, WINDOW_EX_STYLE::default());
+}
+
+// a monitor with the values needed for window placement
+#[derive(Clone, Copy, Debug)]
+pub struct MonitorInfo
+{
+    pub handle : HMONITOR,
+    pub work_area : RECT,
+    pub is_primary : bool,
+    pub dpi : u32,
+}
+
+// function to get the effective DPI of a monitor, 96 is 100% scaling
+fn get_monitor_dpi(h_monitor : HMONITOR) -> u32
+{
+    unsafe
+    {
+        let mut dpi_x : u32 = USER_DEFAULT_SCREEN_DPI;
+        let mut dpi_y : u32 = USER_DEFAULT_SCREEN_DPI;
+        let _ = GetDpiForMonitor(h_monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+        return dpi_x;
+    }
+}
+
+// callback of EnumDisplayMonitors, collects the monitors into the Vec passed by l_param
+unsafe extern "system" fn collect_monitor(h_monitor : HMONITOR, _hdc : HDC, _rect : *mut RECT, l_param : LPARAM) -> BOOL
+{
+    let monitors = &mut *(l_param.0 as *mut Vec<MonitorInfo>);
+    let mut monitor_info = MONITORINFO
+    {
+        cbSize : std::mem::size_of::<MONITORINFO>() as u32,
+        ..MONITORINFO::default()
+    };
+
+    if GetMonitorInfoW(h_monitor, &mut monitor_info).as_bool()
+    {
+        monitors.push(MonitorInfo
+        {
+            handle : h_monitor,
+            work_area : monitor_info.rcWork,
+            is_primary : (monitor_info.dwFlags & MONITORINFOF_PRIMARY) != 0,
+            dpi : get_monitor_dpi(h_monitor),
+        });
+    }
+
+    return TRUE;
+}
+
+// function to get all monitors, the primary monitor is the first one
+pub fn get_monitors() -> Vec<MonitorInfo>
+{
+    unsafe
+    {
+        let mut monitors : Vec<MonitorInfo> = Vec::new();
+        let _ = EnumDisplayMonitors(None, None, Some(collect_monitor), LPARAM(&mut monitors as *mut _ as isize));
+
+        // stable sort keeps the enumeration order of the other monitors
+        monitors.sort_by_key(|x| !x.is_primary);
+        return monitors;
+    }
+}
+
+// function to compute the outer window rect, so that the client area has exactly the configured size.
+// the client size is in physical pixels, the borders are computed with the DPI of the monitor the window goes to.
+pub fn compute_window_rect(config : &WindowConfig) -> RECT
+{
+    unsafe
+    {
+        let (style, ex_style) = get_window_style(config);
+        let monitors = get_monitors();
+        let monitor = match config.position
+        {
+            WindowPosition::Centered { monitor_index } => monitors.get(monitor_index).or(monitors.first()).copied(),
+            WindowPosition::At { x, y } =>
+            {
+                let h_monitor = MonitorFromPoint(POINT { x, y }, MONITOR_DEFAULTTOPRIMARY);
+                monitors.iter().find(|m| m.handle == h_monitor).copied()
+            }
+        };
+        let dpi = monitor.map(|x| x.dpi).unwrap_or(USER_DEFAULT_SCREEN_DPI);
+
+        let mut window_rect = RECT
+        {
+            left : 0,
+            top : 0,
+            right : config.client_width as i32,
+            bottom : config.client_height as i32,
+        };
+        let _ = AdjustWindowRectExForDpi(&mut window_rect, style, FALSE, ex_style, dpi);
+
+        let window_width = window_rect.right - window_rect.left;
+        let window_height = window_rect.bottom - window_rect.top;
+        let (x, y) = match (&config.position, monitor)
+        {
+            (WindowPosition::At { x, y }, _) => (*x, *y),
+            (WindowPosition::Centered { .. }, Some(monitor)) =>
+            {
+                // center in the work area (excluding taskbar) of the monitor
+                let work_area = monitor.work_area;
+                (work_area.left + (work_area.right - work_area.left - window_width) / 2
+                    , work_area.top + ((work_area.bottom - work_area.top - window_height) / 2).max(0))
+            }
+            (WindowPosition::Centered { .. }, None) => (0, 0),
+        };
+
+        return RECT
+        {
+            left : x,
+            top : y,
+            right : x + window_width,
+            bottom : y + window_height,
+        };
+    }
+}
+
+// function to opt in per-monitor DPI awareness (v2), so Windows doesn't bitmap-stretch the window on high DPI displays.
+// must be called before any window is created.
+pub fn enable_per_monitor_dpi_awareness()
+{
+    unsafe
+    {
+        if SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2).is_err()
+        {
+            println!("Per-monitor DPI awareness v2 is not available, the window may be scaled by the system.");
+        }
+    }
+}
+
+// function to handle a DPI change, the window is moved to the rect suggested by the system.
+// the new client size arrives with the WM_SIZE that follows, which resizes the swapchain.
+pub fn handle_dpi_changed(h_wnd : HWND, suggested_rect : &RECT)
+{
+    unsafe
+    {
+        let _ = SetWindowPos(h_wnd, None, suggested_rect.left, suggested_rect.top, suggested_rect.right - suggested_rect.left
+            , suggested_rect.bottom - suggested_rect.top, SWP_NOZORDER | SWP_NOACTIVATE);
+    }
+}
+
+// function to create a window from a config, the window is not shown yet
+pub fn create_window(config : &WindowConfig, app_instance : HINSTANCE, app_class_name : PCWSTR) -> Result<HWND>
+{
+    unsafe
+    {
+        let (style, ex_style) = get_window_style(config);
+        let window_rect = compute_window_rect(config);
+
+        let h_wnd = CreateWindowExW(ex_style, app_class_name, &HSTRING::from(config.title.as_str()), style
+            , window_rect.left, window_rect.top, window_rect.right - window_rect.left, window_rect.bottom - window_rect.top
+            , None, None, app_instance, None)?;
+
+        // dropped files are reported as FileDropped window events
+        DragAcceptFiles(h_wnd, TRUE);
+
+        if let Some(x) = config.icon_path.as_ref()
+        {
+            match LoadImageW(None, &HSTRING::from(x.as_path()), IMAGE_ICON, 0, 0, LR_LOADFROMFILE | LR_DEFAULTSIZE)
+            {
+                Ok(icon) =>
+                {
+                    let _ = SendMessageW(h_wnd, WM_SETICON, WPARAM(ICON_BIG as usize), LPARAM(icon.0 as isize));
+                    let _ = SendMessageW(h_wnd, WM_SETICON, WPARAM(ICON_SMALL as usize), LPARAM(icon.0 as isize));
+                }
+                Err(_) => println!("Failed to load window icon: {}", x.display()),
+            }
+        }
+
+        return Ok(h_wnd);
+    }
+}
+
+// function to get the real client size of a window, the swapchain should be created with it
+pub fn get_client_size(h_wnd : HWND) -> (u32, u32)
+{
+    unsafe
+    {
+        let mut client_rect = RECT::default();
+        let _ = GetClientRect(h_wnd, &mut client_rect);
+
+        return ((client_rect.right - client_rect.left).max(0) as u32, (client_rect.bottom - client_rect.top).max(0) as u32);
+    }
+}
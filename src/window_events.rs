@@ -0,0 +1,251 @@
+// window_events.rs - An event bus for the window messages. wnd_proc translates the Win32 messages to typed events,
+// and the subsystems subscribe to the events they care about instead of editing the match in wnd_proc.
+
+use windows::Win32::Foundation::*;
+use windows::Win32::UI::Shell::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::input;
+use crate::input::InputEvent;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum WindowEvent
+{
+    // new client size in pixels, the size is 0 when minimized
+    Resized { width : u32, height : u32, minimized : bool },
+    FocusChanged { focused : bool },
+    // new DPI of the window (96 is 100% scaling) and the window rect suggested by the system for it
+    DpiChanged { dpi : u32, suggested_rect : RECT },
+    FileDropped { paths : Vec<PathBuf> },
+    // virtual key code, down is false for a key up
+    Key { key : u8, down : bool },
+    // mouse move, buttons, wheel or raw mouse motion
+    Mouse(InputEvent),
+    // the user wants to close the window, a handler can veto it
+    CloseRequested,
+}
+
+// the kind of an event without its payload, used when subscribing
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum WindowEventKind
+{
+    Resized,
+    FocusChanged,
+    DpiChanged,
+    FileDropped,
+    Key,
+    Mouse,
+    CloseRequested,
+}
+
+impl WindowEvent
+{
+    pub fn get_kind(&self) -> WindowEventKind
+    {
+        return match self
+        {
+            WindowEvent::Resized { .. } => WindowEventKind::Resized,
+            WindowEvent::FocusChanged { .. } => WindowEventKind::FocusChanged,
+            WindowEvent::DpiChanged { .. } => WindowEventKind::DpiChanged,
+            WindowEvent::FileDropped { .. } => WindowEventKind::FileDropped,
+            WindowEvent::Key { .. } => WindowEventKind::Key,
+            WindowEvent::Mouse(_) => WindowEventKind::Mouse,
+            WindowEvent::CloseRequested => WindowEventKind::CloseRequested,
+        };
+    }
+}
+
+// what a handler did with an event
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventResponse
+{
+    // let the next handlers and the default handling see it
+    Continue,
+    // stop here, the handlers after this one and the default handling don't see it
+    Consumed,
+    // only for CloseRequested, the window stays open
+    VetoClose,
+}
+
+pub type EventHandler = Box<dyn FnMut(HWND, &WindowEvent) -> EventResponse>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SubscriptionId(u32);
+
+struct Subscriber
+{
+    id : SubscriptionId,
+    kind : WindowEventKind,
+    // shared with the snapshot taken by dispatch, so subscribing or unsubscribing inside a handler is fine
+    handler : Rc<RefCell<EventHandler>>,
+    active : Rc<Cell<bool>>,
+}
+
+// handlers are called in the order they subscribed. every thread has its own bus, the message thread dispatches the
+// translated messages and the render thread dispatches the events it receives over its channel (see render_thread.rs).
+thread_local!
+{
+    static GSUBSCRIBERS : RefCell<Vec<Subscriber>> = const { RefCell::new(Vec::new()) };
+    static GNEXT_SUBSCRIPTION_ID : Cell<u32> = const { Cell::new(0) };
+}
+
+// function to register a handler for one kind of event on the bus of the current thread, returns the id to unsubscribe it
+pub fn subscribe(kind : WindowEventKind, handler : EventHandler) -> SubscriptionId
+{
+    let id = SubscriptionId(GNEXT_SUBSCRIPTION_ID.with(|x| x.replace(x.get() + 1)));
+
+    GSUBSCRIBERS.with_borrow_mut(|x| x.push(Subscriber
+    {
+        id,
+        kind,
+        handler : Rc::new(RefCell::new(handler)),
+        active : Rc::new(Cell::new(true)),
+    }));
+
+    return id;
+}
+
+pub fn unsubscribe(id : SubscriptionId)
+{
+    GSUBSCRIBERS.with_borrow_mut(|subscribers|
+    {
+        for x in subscribers.iter().filter(|x| x.id == id)
+        {
+            // a dispatch in progress may still hold the handler, the flag stops it from being called
+            x.active.set(false);
+        }
+        subscribers.retain(|x| x.id != id);
+    });
+}
+
+// function to remove every handler of the current thread, e.g. before shutting down
+pub fn clear_subscribers()
+{
+    let subscribers = GSUBSCRIBERS.with_borrow_mut(std::mem::take);
+    for x in subscribers.iter()
+    {
+        x.active.set(false);
+    }
+}
+
+// function to send an event to its handlers, returns the first response that isn't Continue.
+// a handler may cause messages that dispatch again (e.g. SetWindowPos sends WM_SIZE), a handler that is already running isn't re-entered.
+pub fn dispatch(h_wnd : HWND, event : &WindowEvent) -> EventResponse
+{
+    // take a snapshot, so the handlers can subscribe and unsubscribe while they run
+    let kind = event.get_kind();
+    let handlers : Vec<_> = GSUBSCRIBERS.with_borrow(|subscribers| subscribers.iter()
+        .filter(|x| x.kind == kind)
+        .map(|x| (x.handler.clone(), x.active.clone()))
+        .collect());
+
+    for (handler, active) in handlers
+    {
+        if !active.get()
+        {
+            continue;
+        }
+
+        let response = match handler.try_borrow_mut()
+        {
+            Ok(mut x) => x(h_wnd, event),
+            Err(_) => EventResponse::Continue,
+        };
+
+        // a close can only be vetoed on CloseRequested, treat it as consumed for everything else
+        match response
+        {
+            EventResponse::Continue => continue,
+            EventResponse::VetoClose if kind != WindowEventKind::CloseRequested => return EventResponse::Consumed,
+            _ => return response,
+        }
+    }
+
+    return EventResponse::Continue;
+}
+
+// function to get the paths of a WM_DROPFILES message, the drop handle is released here
+fn read_dropped_files(w_param : WPARAM) -> Vec<PathBuf>
+{
+    unsafe
+    {
+        let h_drop = HDROP(w_param.0 as *mut _);
+        let file_count = DragQueryFileW(h_drop, u32::MAX, None);
+
+        let mut paths : Vec<PathBuf> = Vec::new();
+        for idx in 0..file_count
+        {
+            // the length doesn't include the null terminator
+            let length = DragQueryFileW(h_drop, idx, None) as usize;
+            let mut buffer : Vec<u16> = vec![0; length + 1];
+            let copied = DragQueryFileW(h_drop, idx, Some(&mut buffer)) as usize;
+            paths.push(PathBuf::from(String::from_utf16_lossy(&buffer[..copied])));
+        }
+
+        DragFinish(h_drop);
+        return paths;
+    }
+}
+
+// function to translate a window message to an event, returns None for messages that have no event
+pub fn translate_message(message : u32, w_param : WPARAM, l_param : LPARAM) -> Option<WindowEvent>
+{
+    unsafe
+    {
+        let event = match message
+        {
+            WM_SIZE => WindowEvent::Resized
+            {
+                // low word and high word of l_param are the new client width and height
+                width : (l_param.0 & 0xffff) as u32,
+                height : ((l_param.0 >> 16) & 0xffff) as u32,
+                minimized : w_param.0 as u32 == SIZE_MINIMIZED,
+            },
+            // low word of w_param is the activation state
+            WM_ACTIVATE => WindowEvent::FocusChanged { focused : (w_param.0 & 0xffff) as u32 != WA_INACTIVE },
+            WM_DPICHANGED => WindowEvent::DpiChanged
+            {
+                dpi : (w_param.0 & 0xffff) as u32,
+                suggested_rect : *(l_param.0 as *const RECT),
+            },
+            WM_DROPFILES => WindowEvent::FileDropped { paths : read_dropped_files(w_param) },
+            WM_CLOSE => WindowEvent::CloseRequested,
+            _ => match input::translate_message(message, w_param, l_param)?
+            {
+                InputEvent::KeyDown(key) => WindowEvent::Key { key, down : true },
+                InputEvent::KeyUp(key) => WindowEvent::Key { key, down : false },
+                // focus changes come with WM_ACTIVATE
+                InputEvent::FocusLost => return None,
+                x => WindowEvent::Mouse(x),
+            },
+        };
+
+        return Some(event);
+    }
+}
+
+// function for wnd_proc, translates and dispatches a message. returns None if the message should go to the default handling,
+// otherwise the result for wnd_proc to return.
+pub fn handle_message(h_wnd : HWND, message : u32, w_param : WPARAM, l_param : LPARAM) -> Option<LRESULT>
+{
+    unsafe
+    {
+        let event = translate_message(message, w_param, l_param)?;
+        let response = dispatch(h_wnd, &event);
+
+        // these always need the default proc: activation sets the keyboard focus, system keys drive alt + F4 and the window menu,
+        // and WM_INPUT cleans up the raw input buffer there.
+        let needs_default = matches!(message, WM_ACTIVATE | WM_SYSKEYDOWN | WM_SYSKEYUP | WM_INPUT);
+
+        return match response
+        {
+            EventResponse::Continue => None,
+            _ if needs_default => Some(DefWindowProcW(h_wnd, message, w_param, l_param)),
+            // a consumed or vetoed WM_CLOSE doesn't reach DefWindowProcW, so the window isn't destroyed
+            _ => Some(LRESULT::default()),
+        };
+    }
+}